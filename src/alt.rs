@@ -3,6 +3,7 @@ use core::ops;
 use core::slice;
 
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(C)]
 pub struct BGR<T> {
     /// Blue
     pub b: T,
@@ -13,6 +14,7 @@ pub struct BGR<T> {
 }
 
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(C)]
 pub struct BGRA<T, TA = T> {
     /// Blue
     pub b: T,
@@ -26,6 +28,7 @@ pub struct BGRA<T, TA = T> {
 
 #[cfg(feature = "argb")]
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(C)]
 pub struct ABGR<T, TA = T> {
     /// Alpha
     pub a: TA,
@@ -39,6 +42,7 @@ pub struct ABGR<T, TA = T> {
 
 #[cfg(feature = "argb")]
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(C)]
 pub struct ARGB<T, TA = T> {
     /// Alpha
     pub a: TA,
@@ -80,6 +84,7 @@ pub type ARGB16 = ARGB<u16>;
 
 #[cfg(feature = "grb")]
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(C)]
 pub struct GRB<T> {
     /// Green
     pub g: T,
@@ -93,12 +98,40 @@ pub struct GRB<T> {
 #[cfg(feature = "grb")]
 pub type GRB8 = GRB<u8>;
 
+/// Radiance RGBE: 8-bit mantissas that share an exponent `e`, for HDR images.
+///
+/// Converts to and from `RGB<f32>`. An `RGBE8` whose largest mantissa is at least 128,
+/// which is how Radiance writes them, converts to `RGB<f32>` and back without loss.
+/// Other pixels come back normalized, e.g. `RGBE8::new(1, 0, 0, 200)` becomes
+/// `RGBE8::new(128, 0, 0, 193)`, and any pixel with `e == 0` becomes black.
+/// `RGB<f32>` is rounded down to 8 significant bits, relative to its largest component.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[repr(C)]
+pub struct RGBE8 {
+    /// Red mantissa
+    pub r: u8,
+    /// Green mantissa
+    pub g: u8,
+    /// Blue mantissa
+    pub b: u8,
+    /// Exponent + 128, or 0 for black
+    pub e: u8,
+}
+
+#[cfg(feature = "as-bytes")]
+unsafe impl crate::Pod for RGBE8 {}
+
+#[cfg(feature = "as-bytes")]
+unsafe impl crate::Zeroable for RGBE8 {}
+
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(C)]
 /// Grayscale. Use `.0` or `*` (deref) to access the value.
 /// brightness level
 pub struct Gray<T>(pub T);
 
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(C)]
 /// Grayscale with alpha. Use `.0`/`.1` to access.
 pub struct GrayAlpha<T, TA = T>(pub T, pub TA);
 
@@ -299,6 +332,15 @@ impl<T> ComponentSlice<T> for [GrayAlpha<T>] {
 #[cfg(feature = "as-bytes")]
 impl<T: crate::Pod> ComponentBytes<T> for [GrayAlpha<T>] {}
 
+impl<T: MathComponent> PixelMath<T> for GrayAlpha<T> {
+    #[inline]
+    fn mix(self, other: Self, t: f64) -> Self {
+        let mut c = [self.0];
+        let a = mix_premultiplied(&mut c, [other.0], self.1, other.1, t);
+        GrayAlpha(c[0], a)
+    }
+}
+
 impl<T> ComponentSlice<T> for Gray<T> {
     #[inline(always)]
     fn as_slice(&self) -> &[T] {
@@ -326,6 +368,8 @@ impl<T> ComponentSlice<T> for [Gray<T>] {
 #[cfg(feature = "as-bytes")]
 impl<T: crate::Pod> ComponentBytes<T> for [Gray<T>] {}
 
+impl<T: MathComponent> PixelMath<T> for Gray<T> {}
+
 /// Assumes 255 is opaque
 impl<T: Copy> From<Gray<T>> for GrayAlpha<T, u8> {
     #[inline(always)]
@@ -342,9 +386,116 @@ impl<T: Copy> From<Gray<T>> for GrayAlpha<T, u16> {
     }
 }
 
+impl RGBE8 {
+    #[inline(always)]
+    pub const fn new(r: u8, g: u8, b: u8, e: u8) -> Self {
+        Self { r, g, b, e }
+    }
+}
+
+/// `2^exp`, exact for every exponent RGBE uses
+#[inline]
+fn exp2(exp: i32) -> f64 {
+    f64::from_bits(((exp + 1023) as u64) << 52)
+}
+
+impl From<RGBE8> for crate::RGB<f32> {
+    /// Mantissa × 2^(e - 136), like Radiance's `rgbe2float`
+    #[inline]
+    fn from(px: RGBE8) -> Self {
+        if px.e == 0 {
+            return Self::default();
+        }
+        let f = exp2(px.e as i32 - 136);
+        crate::RGB {
+            r: (px.r as f64 * f) as f32,
+            g: (px.g as f64 * f) as f32,
+            b: (px.b as f64 * f) as f32,
+        }
+    }
+}
+
+impl From<crate::RGB<f32>> for RGBE8 {
+    /// Negative and NaN components become 0, values too large for RGBE become the
+    /// largest one
+    fn from(px: crate::RGB<f32>) -> Self {
+        let [r, g, b] = [px.r, px.g, px.b].map(|c| c.max(0.) as f64);
+        let max = r.max(g).max(b);
+        if max == 0. {
+            return Self::default();
+        }
+        // frexp: max = m × 2^exp, 0.5 <= m < 1
+        let exp = ((max.to_bits() >> 52) & 0x7FF) as i32 - 1022;
+        if exp < -127 {
+            return Self::default();
+        }
+        if exp > 127 {
+            return Self::new(255, 255, 255, 255);
+        }
+        let scale = exp2(8 - exp);
+        Self {
+            r: (r * scale) as u8,
+            g: (g * scale) as u8,
+            b: (b * scale) as u8,
+            e: (exp + 128) as u8,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::alt::GrayAlpha;
+    use crate::alt::{GrayAlpha, RGBE8};
+    use crate::RGB;
+
+    #[test]
+    fn rgbe() {
+        let f = |r, g, b| RGB::<f32> { r, g, b };
+        assert_eq!(RGBE8::from(f(1., 0.5, 0.25)), RGBE8::new(128, 64, 32, 129));
+        assert_eq!(RGB::from(RGBE8::new(128, 64, 32, 129)), f(1., 0.5, 0.25));
+        assert_eq!(RGBE8::from(f(0., 0., 0.)), RGBE8::default());
+        assert_eq!(RGB::from(RGBE8::new(9, 9, 9, 0)), f(0., 0., 0.));
+        assert_eq!(
+            RGBE8::from(f(-1., f32::NAN, 3.)),
+            RGBE8::new(0, 0, 192, 130)
+        );
+        assert_eq!(
+            RGBE8::from(f(f32::MAX, 0., 0.)),
+            RGBE8::new(255, 255, 255, 255)
+        );
+        assert_eq!(RGBE8::from(f(1e-40, 0., 0.)), RGBE8::default());
+
+        // 最大尾数不小于 128 的 RGBE8 都能转换成 f32 再原样转换回来
+        for e in 1..=255u8 {
+            for m in [128u8, 129, 200, 255] {
+                for px in [
+                    RGBE8::new(m, 0, 1, e),
+                    RGBE8::new(3, m, 127, e),
+                    RGBE8::new(m, m, m, e),
+                ] {
+                    assert_eq!(RGBE8::from(RGB::<f32>::from(px)), px);
+                }
+            }
+        }
+        // 没有规范化的尾数转换回来会被规范化, 值不变
+        let odd = RGBE8::new(1, 0, 0, 200);
+        assert_eq!(
+            RGBE8::from(RGB::<f32>::from(odd)),
+            RGBE8::new(128, 0, 0, 193)
+        );
+        let odd = RGBE8::new(3, 100, 127, 140);
+        assert_eq!(
+            RGBE8::from(RGB::<f32>::from(odd)),
+            RGBE8::new(6, 200, 254, 139)
+        );
+        assert_eq!(
+            RGB::<f32>::from(RGBE8::new(6, 200, 254, 139)),
+            RGB::<f32>::from(odd)
+        );
+        // f32 只保留最大分量的 8 位有效数字
+        let px = RGBE8::from(f(1000., 1.5, 0.001));
+        assert_eq!((px.r, px.g, px.b, px.e), (250, 0, 0, 138));
+        assert_eq!(RGB::<f32>::from(px).r, 1000.);
+    }
 
     #[test]
     fn t() {
@@ -0,0 +1,891 @@
+//! Windows bitmaps: BMP files, and DIBs as the clipboard stores them (a BMP without its
+//! 14-byte file header).
+//!
+//! Decoding supports 1/2/4/8-bit palettes, RLE4 and RLE8, 16- and 32-bit bitfields
+//! (RGB555 by default), 24-bit BGR and 32-bit BGRA, bottom-up and top-down.
+//! Images with an alpha mask decode to `BGRA8`, everything else to `BGR8`.
+//!
+//! ```rust
+//! use cr::alt::BGR8;
+//! use cr::formats::bmp;
+//! use cr::{DynamicImage, Image};
+//!
+//! let image = DynamicImage::from(Image::filled(BGR8 { b: 1, g: 2, r: 3 }, 3, 2));
+//! let file = bmp::encode(&image).unwrap();
+//! assert_eq!(&file[..2], b"BM");
+//! assert_eq!(bmp::decode(&file).unwrap(), image);
+//! ```
+
+use crate::alt::{BGR8, BGRA8};
+use crate::{AlphaKind, DynamicImage, Image};
+use core::fmt;
+
+/// Size of the `BITMAPFILEHEADER` in front of the DIB
+const FILE_HEADER: usize = 14;
+
+const BI_RGB: u32 = 0;
+const BI_RLE8: u32 = 1;
+const BI_RLE4: u32 = 2;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+/// `LCS_sRGB`, the color space written to V4 headers
+const LCS_SRGB: u32 = 0x7352_4742;
+
+/// Why a BMP file couldn't be decoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// Doesn't start with `BM`
+    BadMagic,
+    /// Malformed header, zero size, or fields that don't fit together
+    BadHeader,
+    /// Compression (JPEG, PNG, ...) or bit depth that isn't supported
+    Unsupported,
+    /// Pixel refers to a color past the end of the palette
+    BadPalette,
+    /// RLE data writes outside of the image
+    BadRle,
+    /// The file ends before all pixels, or RLE data is far too short for a large image
+    UnexpectedEof,
+    /// Width × height doesn't fit in memory
+    TooLarge,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::BadMagic => "not a BMP file",
+            Error::BadHeader => "invalid BMP header",
+            Error::Unsupported => "unsupported BMP compression or bit depth",
+            Error::BadPalette => "palette index out of range",
+            Error::BadRle => "invalid RLE data",
+            Error::UnexpectedEof => "file is truncated",
+            Error::TooLarge => "image is too large",
+        })
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[inline]
+fn u16_at(data: &[u8], pos: usize) -> Result<u16, Error> {
+    data.get(pos..pos + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or(Error::UnexpectedEof)
+}
+
+#[inline]
+fn u32_at(data: &[u8], pos: usize) -> Result<u32, Error> {
+    data.get(pos..pos + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(Error::UnexpectedEof)
+}
+
+/// One channel of a bitfield mask
+#[derive(Debug, Copy, Clone)]
+struct Mask {
+    mask: u32,
+    shift: u32,
+    bits: u32,
+}
+
+impl Mask {
+    fn new(mask: u32) -> Result<Option<Self>, Error> {
+        if mask == 0 {
+            return Ok(None);
+        }
+        let shift = mask.trailing_zeros();
+        let bits = (mask >> shift).trailing_ones();
+        // 掩码的位必须连续
+        if mask.count_ones() != bits {
+            return Err(Error::BadHeader);
+        }
+        Ok(Some(Self { mask, shift, bits }))
+    }
+
+    /// The channel scaled to 8 bits
+    #[inline]
+    fn get(self, px: u32) -> u8 {
+        let v = (px & self.mask) >> self.shift;
+        if self.bits >= 8 {
+            (v >> (self.bits - 8)) as u8
+        } else {
+            let max = (1u32 << self.bits) - 1;
+            ((v * 255 + max / 2) / max) as u8
+        }
+    }
+}
+
+struct Masks {
+    r: Option<Mask>,
+    g: Option<Mask>,
+    b: Option<Mask>,
+    a: Option<Mask>,
+}
+
+impl Masks {
+    fn new([r, g, b, a]: [u32; 4]) -> Result<Self, Error> {
+        Ok(Self {
+            r: Mask::new(r)?,
+            g: Mask::new(g)?,
+            b: Mask::new(b)?,
+            a: Mask::new(a)?,
+        })
+    }
+
+    #[inline]
+    fn get(&self, px: u32) -> BGRA8 {
+        let c = |m: Option<Mask>| m.map_or(0, |m| m.get(px));
+        BGRA8 {
+            b: c(self.b),
+            g: c(self.g),
+            r: c(self.r),
+            a: self.a.map_or(255, |m| m.get(px)),
+        }
+    }
+}
+
+/// Everything from the DIB header that's needed to read the pixels
+struct Info {
+    width: usize,
+    height: usize,
+    top_down: bool,
+    bpp: u16,
+    compression: u32,
+    masks: Masks,
+    palette: Vec<BGR8>,
+    /// Size of the header, masks and palette, where pixels of a packed DIB start
+    len: usize,
+}
+
+fn read_info(dib: &[u8]) -> Result<Info, Error> {
+    let header_len = u32_at(dib, 0)? as usize;
+    let core = header_len == 12;
+    let (width, height, planes, bpp, compression, colors_used) = if core {
+        // BITMAPCOREHEADER: 16-bit sizes, always bottom-up
+        (
+            u16_at(dib, 4)? as i64,
+            u16_at(dib, 6)? as i64,
+            u16_at(dib, 8)?,
+            u16_at(dib, 10)?,
+            BI_RGB,
+            0,
+        )
+    } else if header_len >= 40 {
+        (
+            u32_at(dib, 4)? as i32 as i64,
+            u32_at(dib, 8)? as i32 as i64,
+            u16_at(dib, 12)?,
+            u16_at(dib, 14)?,
+            u32_at(dib, 16)?,
+            u32_at(dib, 32)? as usize,
+        )
+    } else {
+        return Err(Error::BadHeader);
+    };
+    if dib.len() < header_len {
+        return Err(Error::UnexpectedEof);
+    }
+    if width <= 0 || height == 0 || height == i32::MIN as i64 || planes != 1 {
+        return Err(Error::BadHeader);
+    }
+
+    let mut len = header_len;
+    let masks = match (compression, bpp) {
+        (BI_RGB, 16) => [0x7C00, 0x03E0, 0x001F, 0],
+        (BI_RGB, 32) => [0xFF_0000, 0xFF00, 0xFF, 0],
+        (BI_BITFIELDS | BI_ALPHABITFIELDS, 16 | 32) => {
+            // BITMAPINFOHEADER 之后紧跟着掩码; V2 及以上的头部自带掩码
+            let count = if compression == BI_ALPHABITFIELDS {
+                4
+            } else {
+                3
+            };
+            if header_len == 40 {
+                len += 4 * count;
+            }
+            let mut masks = [0; 4];
+            for (i, m) in masks.iter_mut().enumerate().take(count) {
+                *m = u32_at(dib, 40 + 4 * i)?;
+            }
+            if header_len >= 56 {
+                masks[3] = u32_at(dib, 52)?;
+            }
+            masks
+        }
+        (BI_RGB, 1 | 2 | 4 | 8 | 24) | (BI_RLE8, 8) | (BI_RLE4, 4) => [0; 4],
+        (BI_RLE8 | BI_RLE4 | BI_BITFIELDS | BI_ALPHABITFIELDS, _) => return Err(Error::BadHeader),
+        _ => return Err(Error::Unsupported),
+    };
+
+    let mut palette = Vec::new();
+    if bpp <= 8 {
+        let max = 1 << bpp;
+        let count = if colors_used == 0 { max } else { colors_used };
+        if count > max {
+            return Err(Error::BadHeader);
+        }
+        let entry = if core { 3 } else { 4 };
+        let data = dib
+            .get(len..len + count * entry)
+            .ok_or(Error::UnexpectedEof)?;
+        palette.extend(data.chunks_exact(entry).map(|c| BGR8 {
+            b: c[0],
+            g: c[1],
+            r: c[2],
+        }));
+        len += count * entry;
+    }
+
+    let top_down = height < 0;
+    if top_down && matches!(compression, BI_RLE8 | BI_RLE4) {
+        return Err(Error::BadHeader);
+    }
+    Ok(Info {
+        width: width as usize,
+        height: height.unsigned_abs() as usize,
+        top_down,
+        bpp,
+        compression,
+        masks: Masks::new(masks)?,
+        palette,
+        len,
+    })
+}
+
+/// Decode a `.bmp` file
+pub fn decode(data: &[u8]) -> Result<DynamicImage, Error> {
+    if !data.starts_with(b"BM") {
+        return Err(Error::BadMagic);
+    }
+    let offset = u32_at(data, 10)? as usize;
+    let dib = &data[FILE_HEADER..];
+    let info = read_info(dib)?;
+    let offset = offset
+        .checked_sub(FILE_HEADER)
+        .filter(|&o| o >= info.len)
+        .ok_or(Error::BadHeader)?;
+    let pixels = dib.get(offset..).ok_or(Error::UnexpectedEof)?;
+    decode_pixels(&info, pixels)
+}
+
+/// Decode a packed DIB, i.e. `CF_DIB` or `CF_DIBV5` from the clipboard,
+/// where the pixels follow the header and palette directly
+pub fn decode_dib(dib: &[u8]) -> Result<DynamicImage, Error> {
+    let info = read_info(dib)?;
+    decode_pixels(&info, &dib[info.len..])
+}
+
+fn decode_pixels(info: &Info, data: &[u8]) -> Result<DynamicImage, Error> {
+    let (width, height) = (info.width, info.height);
+    let count = width.checked_mul(height).ok_or(Error::TooLarge)?;
+    if count > isize::MAX as usize / 4 {
+        return Err(Error::TooLarge);
+    }
+
+    if matches!(info.compression, BI_RLE8 | BI_RLE4) {
+        // 一个游程 2 字节最多 255 个像素, 但行尾和跳转能跳过任意多, 空白的图可以只有 2 字节.
+        // 所以只限制大图: 每字节最多 4096 个像素, 挡住几十字节的文件声明巨大的尺寸
+        if count / 4096 > data.len().max(4096) {
+            return Err(Error::UnexpectedEof);
+        }
+        let indices = decode_rle(data, width, height, info.compression == BI_RLE4)?;
+        let mut pixels = Vec::with_capacity(count);
+        for y in 0..height {
+            let row = &indices[(height - 1 - y) * width..][..width];
+            for &i in row {
+                pixels.push(lookup(&info.palette, i)?);
+            }
+        }
+        return Ok(DynamicImage::from(Image::new(pixels, width, height)));
+    }
+
+    let bpp = info.bpp as usize;
+    let row_len = width
+        .checked_mul(bpp)
+        .map(|bits| bits.div_ceil(8))
+        .ok_or(Error::TooLarge)?;
+    let stride = row_len.div_ceil(4) * 4;
+    // 最后一行的填充字节常被省略
+    let len = stride
+        .checked_mul(height - 1)
+        .and_then(|n| n.checked_add(row_len))
+        .ok_or(Error::TooLarge)?;
+    let data = data.get(..len).ok_or(Error::UnexpectedEof)?;
+    let mut pixels = Vec::with_capacity(count);
+
+    let rows = (0..height).map(|y| {
+        let y = if info.top_down { y } else { height - 1 - y };
+        &data[y * stride..][..row_len]
+    });
+    match bpp {
+        1 | 2 | 4 | 8 => {
+            let mask = ((1u16 << bpp) - 1) as u8;
+            for row in rows {
+                for x in 0..width {
+                    let bit = x * bpp;
+                    let i = (row[bit / 8] >> (8 - bpp - bit % 8)) & mask;
+                    pixels.push(lookup(&info.palette, i)?);
+                }
+            }
+        }
+        24 => {
+            for row in rows {
+                pixels.extend(row.chunks_exact(3).map(|c| BGR8 {
+                    b: c[0],
+                    g: c[1],
+                    r: c[2],
+                }));
+            }
+        }
+        _ => {
+            let size = bpp / 8;
+            let mut bgra = Vec::with_capacity(count);
+            for row in rows {
+                bgra.extend(row.chunks_exact(size).map(|c| {
+                    let px = c.iter().rev().fold(0u32, |px, &b| (px << 8) | b as u32);
+                    info.masks.get(px)
+                }));
+            }
+            if info.masks.a.is_some() {
+                return Ok(DynamicImage::from(Image::new(bgra, width, height)));
+            }
+            pixels.extend(bgra.into_iter().map(|px| BGR8 {
+                b: px.b,
+                g: px.g,
+                r: px.r,
+            }));
+        }
+    }
+    Ok(DynamicImage::from(Image::new(pixels, width, height)))
+}
+
+#[inline]
+fn lookup(palette: &[BGR8], index: u8) -> Result<BGR8, Error> {
+    palette
+        .get(index as usize)
+        .copied()
+        .ok_or(Error::BadPalette)
+}
+
+/// Palette indices in file order (bottom row first). Pixels skipped by deltas
+/// or early line ends get index 0.
+fn decode_rle(data: &[u8], width: usize, height: usize, four: bool) -> Result<Vec<u8>, Error> {
+    let mut indices = vec![0u8; width * height];
+    let (mut x, mut y, mut pos) = (0usize, 0usize, 0usize);
+    let next = |pos: &mut usize| {
+        let b = data.get(*pos).copied().ok_or(Error::UnexpectedEof);
+        *pos += 1;
+        b
+    };
+    let mut put = |x: &mut usize, y: usize, count: usize, pixel: &dyn Fn(usize) -> u8| {
+        if y >= height || *x + count > width {
+            return Err(Error::BadRle);
+        }
+        let row = &mut indices[y * width + *x..][..count];
+        for (i, p) in row.iter_mut().enumerate() {
+            *p = pixel(i);
+        }
+        *x += count;
+        Ok(())
+    };
+
+    loop {
+        // 有些编码器在最后一行之后直接结束, 省略了 end-of-bitmap
+        if pos >= data.len() && y >= height {
+            break;
+        }
+        let count = next(&mut pos)? as usize;
+        let value = next(&mut pos)?;
+        if count > 0 {
+            put(&mut x, y, count, &|i| match (four, i % 2) {
+                (false, _) => value,
+                (true, 0) => value >> 4,
+                (true, _) => value & 0x0F,
+            })?;
+            continue;
+        }
+        match value {
+            0 => {
+                x = 0;
+                y += 1;
+            }
+            1 => break,
+            2 => {
+                x += next(&mut pos)? as usize;
+                y += next(&mut pos)? as usize;
+            }
+            n => {
+                // 绝对模式: n 个未压缩的像素, 按 16 位对齐
+                let n = n as usize;
+                let len = if four { n.div_ceil(2) } else { n };
+                let run = data.get(pos..pos + len).ok_or(Error::UnexpectedEof)?;
+                put(&mut x, y, n, &|i| match (four, i % 2) {
+                    (false, _) => run[i],
+                    (true, 0) => run[i / 2] >> 4,
+                    (true, _) => run[i / 2] & 0x0F,
+                })?;
+                pos += len + len % 2;
+            }
+        }
+    }
+    Ok(indices)
+}
+
+/// Size of the DIB header [`encode_dib`] writes for this image
+#[inline]
+fn header_len(has_alpha: bool) -> usize {
+    if has_alpha {
+        108
+    } else {
+        40
+    }
+}
+
+/// Encode a `.bmp` file, bottom-up. Images with alpha are stored as 32-bit BGRA
+/// with a V4 header, everything else as 24-bit BGR.
+pub fn encode(image: &DynamicImage) -> Result<Vec<u8>, Error> {
+    let has_alpha = image.format().alpha != AlphaKind::None;
+    let dib = encode_dib(image)?;
+    // 文件头里的总大小也是 32 位
+    if dib.len() > u32::MAX as usize - FILE_HEADER {
+        return Err(Error::TooLarge);
+    }
+    let mut out = Vec::with_capacity(FILE_HEADER + dib.len());
+    out.extend_from_slice(b"BM");
+    out.extend_from_slice(&((FILE_HEADER + dib.len()) as u32).to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&((FILE_HEADER + header_len(has_alpha)) as u32).to_le_bytes());
+    out.extend_from_slice(&dib);
+    Ok(out)
+}
+
+/// Encode a packed DIB for the clipboard, in the same layout as [`encode`]
+pub fn encode_dib(image: &DynamicImage) -> Result<Vec<u8>, Error> {
+    let has_alpha = image.format().alpha != AlphaKind::None;
+    let (width, height) = (image.width(), image.height());
+    let bpp = if has_alpha { 32 } else { 24 };
+    let header = header_len(has_alpha);
+    // 宽高在头部是有符号的 32 位, 像素数据的大小是无符号 32 位
+    if width > i32::MAX as usize || height > i32::MAX as usize {
+        return Err(Error::TooLarge);
+    }
+    let stride = (width as u64 * bpp as u64 / 8).div_ceil(4) * 4;
+    if stride * height as u64 + header as u64 > u32::MAX as u64 {
+        return Err(Error::TooLarge);
+    }
+    let stride = stride as usize;
+
+    let mut out = Vec::with_capacity(header + stride * height);
+    let mut put = |v: u32| out.extend_from_slice(&v.to_le_bytes());
+    put(header as u32);
+    put(width as u32);
+    put(height as u32);
+    put(1 | ((bpp as u32) << 16));
+    put(if has_alpha { BI_BITFIELDS } else { BI_RGB });
+    put((stride * height) as u32);
+    // 72 DPI
+    put(2835);
+    put(2835);
+    put(0);
+    put(0);
+    if has_alpha {
+        for mask in [0xFF_0000, 0xFF00, 0xFF, 0xFF00_0000, LCS_SRGB] {
+            put(mask);
+        }
+        // 色彩空间端点和 gamma, sRGB 时忽略
+        for _ in 0..12 {
+            put(0);
+        }
+    }
+
+    let pad = stride - width * bpp / 8;
+    if has_alpha {
+        let image = image.to_bgra8();
+        for row in (0..height).rev().filter_map(|y| image.row(y)) {
+            out.extend(row.iter().flat_map(|px| [px.b, px.g, px.r, px.a]));
+            out.extend(core::iter::repeat_n(0, pad));
+        }
+    } else {
+        let image = image.to_image::<BGR8>();
+        for row in (0..height).rev().filter_map(|y| image.row(y)) {
+            out.extend(row.iter().flat_map(|px| [px.b, px.g, px.r]));
+            out.extend(core::iter::repeat_n(0, pad));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RGB8, RGBA8};
+
+    /// File with a `BITMAPINFOHEADER`, then `extra` (masks or palette), then pixels
+    fn file(
+        width: i32,
+        height: i32,
+        bpp: u16,
+        compression: u32,
+        extra: &[u8],
+        pixels: &[u8],
+    ) -> Vec<u8> {
+        let offset = 14 + 40 + extra.len();
+        let mut out = b"BM".to_vec();
+        out.extend(((offset + pixels.len()) as u32).to_le_bytes());
+        out.extend([0; 4]);
+        out.extend((offset as u32).to_le_bytes());
+        out.extend(40u32.to_le_bytes());
+        out.extend(width.to_le_bytes());
+        out.extend(height.to_le_bytes());
+        out.extend(1u16.to_le_bytes());
+        out.extend(bpp.to_le_bytes());
+        out.extend(compression.to_le_bytes());
+        out.extend([0; 12]);
+        // 调色板的颜色数
+        let colors = if bpp <= 8 { extra.len() / 4 } else { 0 };
+        out.extend((colors as u32).to_le_bytes());
+        out.extend([0; 4]);
+        out.extend_from_slice(extra);
+        out.extend_from_slice(pixels);
+        out
+    }
+
+    /// Black, white, red, green
+    const PALETTE: [u8; 16] = [0, 0, 0, 0, 255, 255, 255, 0, 0, 0, 255, 0, 0, 255, 0, 0];
+
+    fn bgr(image: &DynamicImage) -> Vec<BGR8> {
+        image.as_image::<BGR8>().unwrap().buf().to_vec()
+    }
+
+    const K: BGR8 = BGR8 { b: 0, g: 0, r: 0 };
+    const W: BGR8 = BGR8 {
+        b: 255,
+        g: 255,
+        r: 255,
+    };
+    const R: BGR8 = BGR8 { b: 0, g: 0, r: 255 };
+    const G: BGR8 = BGR8 { b: 0, g: 255, r: 0 };
+
+    #[test]
+    fn round_trips() {
+        let rgb: Vec<_> = (0..15u8).map(|i| RGB8::new(i * 16, 255 - i, i)).collect();
+        let rgb = DynamicImage::from(Image::new(rgb, 5, 3));
+        let bgr = DynamicImage::from(rgb.to_image::<BGR8>());
+        assert_eq!(decode(&encode(&rgb).unwrap()).unwrap(), bgr);
+        assert_eq!(decode_dib(&encode_dib(&rgb).unwrap()).unwrap(), bgr);
+
+        let rgba: Vec<_> = (0..6u8).map(|i| RGBA8::new(i, 2, 3, i * 40)).collect();
+        let rgba = DynamicImage::from(Image::new(rgba, 3, 2));
+        let bgra = DynamicImage::from(rgba.to_bgra8());
+        let file = encode(&rgba).unwrap();
+        assert_eq!(file.len(), 14 + 108 + 6 * 4);
+        assert_eq!(decode(&file).unwrap(), bgra);
+        assert_eq!(decode_dib(&encode_dib(&rgba).unwrap()).unwrap(), bgra);
+    }
+
+    #[test]
+    fn palettes_and_orientation() {
+        // 1-bit, 2×2, bottom-up: 每行填充到 4 字节
+        let img = decode(&file(
+            2,
+            2,
+            1,
+            BI_RGB,
+            &PALETTE[..8],
+            &[0x40, 0, 0, 0, 0x80, 0, 0, 0],
+        ))
+        .unwrap();
+        assert_eq!(bgr(&img), [W, K, K, W]);
+
+        // 4-bit, top-down, 3 colors used
+        let img = decode(&file(3, -1, 4, BI_RGB, &PALETTE[..12], &[0x12, 0x00, 0, 0])).unwrap();
+        assert_eq!(bgr(&img), [W, R, K]);
+
+        let img = decode(&file(2, 1, 8, BI_RGB, &PALETTE, &[3, 1, 0, 0])).unwrap();
+        assert_eq!(bgr(&img), [G, W]);
+        assert_eq!(
+            decode(&file(1, 1, 4, BI_RGB, &PALETTE, &[0xF0, 0, 0, 0])),
+            Err(Error::BadPalette)
+        );
+
+        // BITMAPCOREHEADER, 3-byte palette
+        let mut core = b"BM\0\0\0\0\0\0\0\0\x20\0\0\0".to_vec();
+        core.extend([12, 0, 0, 0, 2, 0, 1, 0, 1, 0, 1, 0]);
+        core.extend([0, 0, 255, 255, 0, 0]);
+        core.extend([0x80, 0, 0, 0]);
+        let img = decode(&core).unwrap();
+        assert_eq!(bgr(&img), [BGR8 { b: 255, g: 0, r: 0 }, R]);
+    }
+
+    #[test]
+    fn bitfields() {
+        // 16-bit 默认 555
+        let img = decode(&file(2, 1, 16, BI_RGB, &[], &[0x00, 0x7C, 0x1F, 0x00])).unwrap();
+        assert_eq!(bgr(&img), [R, BGR8 { b: 255, g: 0, r: 0 }]);
+
+        let masks: Vec<u8> = [0xF800u32, 0x07E0, 0x001F]
+            .iter()
+            .flat_map(|m| m.to_le_bytes())
+            .collect();
+        let img = decode(&file(1, 1, 16, BI_BITFIELDS, &masks, &[0xE0, 0x07, 0, 0])).unwrap();
+        assert_eq!(bgr(&img), [G]);
+
+        // ARGB1555
+        let masks: Vec<u8> = [0x7C00u32, 0x03E0, 0x001F, 0x8000]
+            .iter()
+            .flat_map(|m| m.to_le_bytes())
+            .collect();
+        let img = decode(&file(
+            2,
+            1,
+            16,
+            BI_ALPHABITFIELDS,
+            &masks,
+            &[0x1F, 0x80, 0x1F, 0x00],
+        ))
+        .unwrap();
+        assert_eq!(
+            img.as_image::<BGRA8>().unwrap().buf(),
+            [
+                BGRA8 {
+                    b: 255,
+                    g: 0,
+                    r: 0,
+                    a: 255
+                },
+                BGRA8 {
+                    b: 255,
+                    g: 0,
+                    r: 0,
+                    a: 0
+                }
+            ]
+        );
+
+        // 32-bit BI_RGB: 第四个字节被忽略
+        let img = decode(&file(1, 1, 32, BI_RGB, &[], &[1, 2, 3, 4])).unwrap();
+        assert_eq!(bgr(&img), [BGR8 { b: 1, g: 2, r: 3 }]);
+
+        let img = decode(&file(1, 1, 24, BI_RGB, &[], &[1, 2, 3])).unwrap();
+        assert_eq!(bgr(&img), [BGR8 { b: 1, g: 2, r: 3 }]);
+
+        let masks: Vec<u8> = [0xF0u32, 0x0F00, 0x00]
+            .iter()
+            .flat_map(|m| m.to_le_bytes())
+            .collect();
+        assert!(decode(&file(1, 1, 16, BI_BITFIELDS, &masks, &[0, 0])).is_ok());
+        let masks: Vec<u8> = [0x0Fu32, 0xF00F, 0x00]
+            .iter()
+            .flat_map(|m| m.to_le_bytes())
+            .collect();
+        assert_eq!(
+            decode(&file(1, 1, 16, BI_BITFIELDS, &masks, &[0, 0])),
+            Err(Error::BadHeader)
+        );
+    }
+
+    #[test]
+    fn rle() {
+        // 第 0 行 (底部): 1 个 run, 绝对模式 3 个像素; 第 1 行: delta 跳过 1 个
+        let rle8 = [1, 1, 0, 3, 1, 1, 2, 0, 0, 0, 0, 2, 1, 0, 1, 3, 0, 1];
+        let img = decode(&file(4, 2, 8, BI_RLE8, &PALETTE, &rle8)).unwrap();
+        assert_eq!(bgr(&img), [K, G, K, K, W, W, W, R]);
+
+        // RLE4: 高低半字节交替
+        let rle4 = [5, 0x12, 0, 0, 0, 3, 0x32, 0x10, 0, 1];
+        let img = decode(&file(5, 2, 4, BI_RLE4, &PALETTE, &rle4)).unwrap();
+        assert_eq!(bgr(&img), [G, R, W, K, K, W, R, W, R, W]);
+
+        assert_eq!(
+            decode(&file(2, 1, 8, BI_RLE8, &PALETTE, &[3, 1, 0, 1])),
+            Err(Error::BadRle)
+        );
+        assert_eq!(
+            decode(&file(2, 1, 8, BI_RLE8, &PALETTE, &[2, 1])),
+            Err(Error::UnexpectedEof)
+        );
+        assert_eq!(
+            decode(&file(2, -1, 8, BI_RLE8, &PALETTE, &[0, 1])),
+            Err(Error::BadHeader)
+        );
+        // 省略 end-of-bitmap 也能解码
+        assert!(decode(&file(2, 1, 8, BI_RLE8, &PALETTE, &[2, 1, 0, 0])).is_ok());
+    }
+
+    #[test]
+    fn encode_too_large() {
+        // 没有像素的图像也有尺寸; 头部的宽高是 i32
+        let wide = DynamicImage::from(Image::<BGR8>::new(vec![], 1 << 31, 0));
+        assert_eq!(encode_dib(&wide), Err(Error::TooLarge));
+        let tall = DynamicImage::from(Image::<BGRA8>::new(vec![], 0, 1 << 31));
+        assert_eq!(encode(&tall), Err(Error::TooLarge));
+        let edge = DynamicImage::from(Image::<BGR8>::new(vec![], i32::MAX as usize, 0));
+        assert!(encode_dib(&edge).is_ok());
+    }
+
+    #[test]
+    fn rle_huge_size_tiny_data() {
+        // 声明 10⁹ × 10⁹, 只有一个 end-of-bitmap: 不能先分配再读
+        let bomb = file(1_000_000_000, 1_000_000_000, 8, BI_RLE8, &PALETTE, &[0, 1]);
+        assert_eq!(bomb.len(), 14 + 40 + PALETTE.len() + 2);
+        assert_eq!(decode(&bomb), Err(Error::UnexpectedEof));
+        let bomb = file(100_000, 100_000, 4, BI_RLE4, &PALETTE, &[0; 1000]);
+        assert_eq!(decode(&bomb), Err(Error::UnexpectedEof));
+
+        // 空白的中等大小图像仍然可以只有 end-of-bitmap
+        let blank = decode(&file(1000, 1000, 8, BI_RLE8, &PALETTE, &[0, 1])).unwrap();
+        assert_eq!(blank.width(), 1000);
+        assert!(bgr(&blank).iter().all(|&c| c == K));
+    }
+
+    #[test]
+    fn bad_headers() {
+        assert_eq!(decode(b"PNG"), Err(Error::BadMagic));
+        assert_eq!(decode(b"BM\0\0"), Err(Error::UnexpectedEof));
+        assert_eq!(
+            decode(&file(0, 1, 24, BI_RGB, &[], &[])),
+            Err(Error::BadHeader)
+        );
+        assert_eq!(
+            decode(&file(1, 0, 24, BI_RGB, &[], &[])),
+            Err(Error::BadHeader)
+        );
+        assert_eq!(
+            decode(&file(1, i32::MIN, 24, BI_RGB, &[], &[])),
+            Err(Error::BadHeader)
+        );
+        // planes 必须是 1
+        let mut bad = file(1, 1, 24, BI_RGB, &[], &[0; 4]);
+        bad[26] = 2;
+        assert_eq!(decode(&bad), Err(Error::BadHeader));
+        // 像素偏移量落在头部里面
+        let mut bad = file(1, 1, 24, BI_RGB, &[], &[0; 4]);
+        bad[10] = 20;
+        assert_eq!(decode(&bad), Err(Error::BadHeader));
+        // 头部长度不认识, 或者比数据长
+        let mut bad = file(1, 1, 24, BI_RGB, &[], &[0; 4]);
+        bad[14] = 20;
+        assert_eq!(decode(&bad), Err(Error::BadHeader));
+        assert_eq!(decode_dib(&bad[14..30]), Err(Error::BadHeader));
+        assert_eq!(
+            decode_dib(&[124, 0, 0, 0, 1, 0, 0, 0]),
+            Err(Error::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn unsupported_compression() {
+        assert_eq!(
+            decode(&file(1, 1, 24, 4, &[], &[0; 4])),
+            Err(Error::Unsupported)
+        );
+        assert_eq!(
+            decode(&file(1, 1, 24, 5, &[], &[0; 4])),
+            Err(Error::Unsupported)
+        );
+        assert_eq!(
+            decode(&file(1, 1, 64, BI_RGB, &[], &[0; 8])),
+            Err(Error::Unsupported)
+        );
+        // 压缩方式和位数不匹配
+        assert_eq!(
+            decode(&file(1, 1, 4, BI_RLE8, &PALETTE, &[0, 1])),
+            Err(Error::BadHeader)
+        );
+        assert_eq!(
+            decode(&file(1, 1, 24, BI_BITFIELDS, &[0; 12], &[0; 4])),
+            Err(Error::BadHeader)
+        );
+    }
+
+    #[test]
+    fn palette_overflow() {
+        // 2 色的图声明 4 色
+        assert_eq!(
+            decode(&file(1, 1, 1, BI_RGB, &PALETTE, &[0; 4])),
+            Err(Error::BadHeader)
+        );
+        // 调色板被截断
+        let mut short = file(1, 1, 8, BI_RGB, &PALETTE, &[]);
+        short.truncate(short.len() - 2);
+        assert_eq!(decode(&short), Err(Error::UnexpectedEof));
+        // 索引超出调色板, 包括 RLE 的
+        assert_eq!(
+            decode(&file(1, 1, 8, BI_RGB, &PALETTE, &[4, 0, 0, 0])),
+            Err(Error::BadPalette)
+        );
+        assert_eq!(
+            decode(&file(2, 1, 8, BI_RLE8, &PALETTE, &[2, 9, 0, 1])),
+            Err(Error::BadPalette)
+        );
+    }
+
+    #[test]
+    fn rle_bounds() {
+        // 游程, 绝对模式和跳转都不能越过行尾或图像顶部
+        let rle = |data: &[u8]| decode(&file(2, 2, 8, BI_RLE8, &PALETTE, data));
+        assert_eq!(rle(&[3, 1]), Err(Error::BadRle));
+        assert_eq!(rle(&[0, 3, 1, 1, 1, 0]), Err(Error::BadRle));
+        assert_eq!(rle(&[0, 2, 3, 0, 1, 1]), Err(Error::BadRle));
+        assert_eq!(rle(&[0, 2, 0, 2, 1, 1]), Err(Error::BadRle));
+        assert_eq!(rle(&[0, 0, 0, 0, 1, 1]), Err(Error::BadRle));
+        let rle4 = |data: &[u8]| decode(&file(3, 1, 4, BI_RLE4, &PALETTE, data));
+        assert_eq!(rle4(&[4, 0x12]), Err(Error::BadRle));
+        assert_eq!(rle4(&[0, 4, 0x12, 0x30]), Err(Error::BadRle));
+        // 被截断的绝对模式和跳转
+        assert_eq!(rle(&[0, 2, 1]), Err(Error::UnexpectedEof));
+        assert_eq!(rle(&[0, 3, 1]), Err(Error::UnexpectedEof));
+        assert_eq!(rle(&[1, 1, 0]), Err(Error::UnexpectedEof));
+    }
+
+    #[test]
+    fn truncated_pixels() {
+        assert_eq!(
+            decode(&file(2, 2, 24, BI_RGB, &[], &[0; 9])),
+            Err(Error::UnexpectedEof)
+        );
+        // 最后一行可以没有填充
+        assert!(decode(&file(2, 2, 24, BI_RGB, &[], &[0; 14])).is_ok());
+        assert_eq!(
+            decode(&file(2, 2, 24, BI_RGB, &[], &[0; 13])),
+            Err(Error::UnexpectedEof)
+        );
+        let masks: Vec<u8> = [0xF800u32, 0x07E0, 0x001F]
+            .iter()
+            .flat_map(|m| m.to_le_bytes())
+            .collect();
+        let mut short = file(1, 1, 16, BI_BITFIELDS, &masks, &[]);
+        short.truncate(short.len() - 4);
+        assert_eq!(decode(&short), Err(Error::UnexpectedEof));
+    }
+
+    #[test]
+    fn huge_header_tiny_data() {
+        for bpp in [1, 4, 8, 16, 24, 32] {
+            let extra = match bpp {
+                1 => &PALETTE[..8],
+                4 | 8 => &PALETTE[..],
+                _ => &[],
+            };
+            assert_eq!(
+                decode(&file(0x10000, 0x10000, bpp, BI_RGB, extra, &[])),
+                Err(Error::UnexpectedEof),
+                "{bpp}"
+            );
+            assert_eq!(
+                decode(&file(i32::MAX, -1000, bpp, BI_RGB, extra, &[0; 16])),
+                Err(Error::UnexpectedEof),
+                "{bpp}"
+            );
+            // 像素数超过内存能放下的
+            assert_eq!(
+                decode(&file(i32::MAX, -i32::MAX, bpp, BI_RGB, extra, &[0; 16])),
+                Err(Error::TooLarge),
+                "{bpp}"
+            );
+        }
+        let dib = &file(i32::MAX, 1000, 24, BI_RGB, &[], &[0; 16])[14..];
+        assert_eq!(decode_dib(dib), Err(Error::UnexpectedEof));
+    }
+}
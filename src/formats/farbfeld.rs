@@ -0,0 +1,150 @@
+//! Farbfeld: a 16-byte header and 16-bit big-endian RGBA pixels, nothing else.
+//!
+//! ```rust
+//! use cr::formats::farbfeld;
+//! use cr::{Image, RGBA16};
+//!
+//! let image = Image::filled(RGBA16::new(1, 2, 3, 65535), 2, 2);
+//! let file = farbfeld::encode(image.view()).unwrap();
+//! assert_eq!(file.len(), 16 + 4 * 8);
+//! assert_eq!(farbfeld::decode(&file).unwrap(), image);
+//! ```
+
+use crate::{Image, ImageRef, RGBA16};
+use core::fmt;
+
+const MAGIC: &[u8; 8] = b"farbfeld";
+const HEADER: usize = 16;
+
+/// Why a farbfeld file couldn't be decoded or encoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// Doesn't start with `farbfeld`
+    BadMagic,
+    /// The file ends before all pixels
+    UnexpectedEof,
+    /// Width × height doesn't fit in memory, or in the header's 32 bits
+    TooLarge,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::BadMagic => "not a farbfeld file",
+            Error::UnexpectedEof => "file is truncated",
+            Error::TooLarge => "image is too large",
+        })
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Decode a `.ff` file
+pub fn decode(data: &[u8]) -> Result<Image<RGBA16>, Error> {
+    let header = data.get(..HEADER).ok_or(Error::UnexpectedEof)?;
+    if &header[..8] != MAGIC {
+        return Err(Error::BadMagic);
+    }
+    let width = u32::from_be_bytes([header[8], header[9], header[10], header[11]]) as usize;
+    let height = u32::from_be_bytes([header[12], header[13], header[14], header[15]]) as usize;
+    let len = width
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(8))
+        .ok_or(Error::TooLarge)?;
+    let pixels = data[HEADER..].get(..len).ok_or(Error::UnexpectedEof)?;
+    let be = |c: &[u8]| u16::from_be_bytes([c[0], c[1]]);
+    let buf = pixels
+        .chunks_exact(8)
+        .map(|c| RGBA16::new(be(&c[0..]), be(&c[2..]), be(&c[4..]), be(&c[6..])))
+        .collect();
+    Ok(Image::new(buf, width, height))
+}
+
+/// Encode a `.ff` file. Convert other pixel types with `DynamicImage::to_rgba16`.
+pub fn encode(image: ImageRef<'_, RGBA16>) -> Result<Vec<u8>, Error> {
+    let (width, height) = (image.width(), image.height());
+    if width > u32::MAX as usize || height > u32::MAX as usize {
+        return Err(Error::TooLarge);
+    }
+    let mut out = Vec::with_capacity(HEADER + width * height * 8);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&(width as u32).to_be_bytes());
+    out.extend_from_slice(&(height as u32).to_be_bytes());
+    for px in image.rows().flatten() {
+        for c in [px.r, px.g, px.b, px.a] {
+            out.extend_from_slice(&c.to_be_bytes());
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let buf: Vec<_> = (0..12u16)
+            .map(|i| RGBA16::new(i, i * 1000, 0xFF00 | i, 65535 - i))
+            .collect();
+        let image = Image::new(buf, 4, 3);
+        let file = encode(image.view()).unwrap();
+        assert_eq!(&file[8..16], [0, 0, 0, 4, 0, 0, 0, 3]);
+        // big-endian
+        assert_eq!(&file[16..24], [0, 0, 0, 0, 0xFF, 0, 0xFF, 0xFF]);
+        assert_eq!(decode(&file).unwrap(), image);
+
+        // 子图按行写出, 不带 stride 的填充
+        let crop = image.crop_ref(1, 1, 2, 2);
+        let decoded = decode(&encode(crop).unwrap()).unwrap();
+        assert_eq!(
+            decoded.buf(),
+            [image[(1, 1)], image[(2, 1)], image[(1, 2)], image[(2, 2)]]
+        );
+
+        let empty = decode(&encode(ImageRef::new(&[], 0, 5)).unwrap()).unwrap();
+        assert_eq!((empty.width(), empty.height()), (0, 5));
+    }
+
+    #[test]
+    fn bad_magic() {
+        let file = encode(Image::filled(RGBA16::default(), 2, 2).view()).unwrap();
+        let mut bad = file.clone();
+        bad[0] = b'F';
+        assert_eq!(decode(&bad), Err(Error::BadMagic));
+        assert_eq!(decode(b"farbfel\0\0\0\0\0\0\0\0\0"), Err(Error::BadMagic));
+    }
+
+    #[test]
+    fn truncated() {
+        let file = encode(Image::filled(RGBA16::default(), 2, 2).view()).unwrap();
+        assert_eq!(decode(&file[..0]), Err(Error::UnexpectedEof));
+        assert_eq!(decode(&file[..10]), Err(Error::UnexpectedEof));
+        assert_eq!(decode(&file[..file.len() - 1]), Err(Error::UnexpectedEof));
+        // 多余的数据忽略
+        let mut long = file.clone();
+        long.push(0);
+        assert_eq!(decode(&long).unwrap().width(), 2);
+    }
+
+    #[test]
+    fn huge_header_tiny_data() {
+        let mut huge = b"farbfeld".to_vec();
+        huge.extend([0xFF; 8]);
+        assert_eq!(decode(&huge), Err(Error::TooLarge));
+        // 声明的尺寸放得下, 但数据不够: 不分配内存
+        huge[8..12].copy_from_slice(&[0, 1, 0, 0]);
+        assert_eq!(decode(&huge), Err(Error::UnexpectedEof));
+        huge.extend([0; 64]);
+        assert_eq!(decode(&huge), Err(Error::UnexpectedEof));
+    }
+
+    #[test]
+    fn encode_too_large() {
+        // 没有像素的图像也有尺寸, 头部只有 32 位
+        let wide = ImageRef::<RGBA16>::new(&[], 1 << 32, 0);
+        assert_eq!(encode(wide), Err(Error::TooLarge));
+        let tall = ImageRef::<RGBA16>::new(&[], 0, 1 << 32);
+        assert_eq!(encode(tall), Err(Error::TooLarge));
+    }
+}
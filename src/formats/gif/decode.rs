@@ -0,0 +1,585 @@
+use super::{lzw, Disposal, Error, Frame};
+use super::{APPLICATION, EXTENSION, GRAPHIC_CONTROL, IMAGE, TRAILER};
+use crate::{RGB8, RGBA8};
+
+/// Largest canvas the decoder accepts, and the most pixels [`decode`] returns over all
+/// frames together. The same as QOI's.
+pub const MAX_PIXELS: usize = 400_000_000;
+
+/// The logical screen, from the start of the file
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Info {
+    /// Canvas width
+    pub width: u16,
+    /// Canvas height
+    pub height: u16,
+    /// From the `NETSCAPE2.0` extension, if it comes before the first frame.
+    /// `Some(0)` loops forever, `None` plays once.
+    pub loop_count: Option<u16>,
+}
+
+/// Reads a GIF frame by frame. Each item is the whole canvas after drawing that frame.
+///
+/// Every frame is a new copy of the canvas, width × height × 4 bytes, however small its
+/// part of the file is. Keeping all of them can take far more memory than the file size
+/// suggests, so process frames as they come when that matters; [`decode`] stops with
+/// `TooLarge` after [`MAX_PIXELS`].
+///
+/// After an error the iterator ends.
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    info: Info,
+    global: Option<Vec<RGB8>>,
+    canvas: Vec<RGBA8>,
+    /// The last frame's disposal, its area, and the canvas from before it if needed
+    dispose: Option<(Disposal, Rect, Option<Vec<RGBA8>>)>,
+    done: bool,
+}
+
+/// Frame position and size, in canvas pixels
+#[derive(Debug, Copy, Clone)]
+struct Rect {
+    left: usize,
+    top: usize,
+    width: usize,
+    height: usize,
+}
+
+/// Graphic control extension fields that apply to the next frame
+#[derive(Debug, Copy, Clone)]
+struct Control {
+    disposal: Disposal,
+    delay: u16,
+    transparent: Option<u8>,
+}
+
+/// 3 bytes per color, `len` colors
+fn color_table(data: &[u8], pos: usize, len: usize) -> Result<Vec<RGB8>, Error> {
+    let bytes = data.get(pos..pos + len * 3).ok_or(Error::UnexpectedEof)?;
+    Ok(bytes
+        .chunks_exact(3)
+        .map(|c| RGB8::new(c[0], c[1], c[2]))
+        .collect())
+}
+
+/// Position after a run of sub-blocks, including the empty one that ends them
+fn skip_sub_blocks(data: &[u8], mut pos: usize) -> Result<usize, Error> {
+    loop {
+        let len = *data.get(pos).ok_or(Error::UnexpectedEof)? as usize;
+        pos += 1 + len;
+        if len == 0 {
+            return Ok(pos);
+        }
+    }
+}
+
+impl<'a> Decoder<'a> {
+    /// Reads the header and the global color table
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let header = data.get(..13).ok_or(Error::UnexpectedEof)?;
+        if &header[..6] != b"GIF87a" && &header[..6] != b"GIF89a" {
+            return Err(Error::BadSignature);
+        }
+        let width = u16::from_le_bytes([header[6], header[7]]);
+        let height = u16::from_le_bytes([header[8], header[9]]);
+        if width == 0 || height == 0 {
+            return Err(Error::BadHeader);
+        }
+        let flags = header[10];
+        let mut pos = 13;
+        let global = if flags & 0x80 != 0 {
+            let len = 2 << (flags & 7);
+            let table = color_table(data, pos, len)?;
+            pos += len * 3;
+            Some(table)
+        } else {
+            None
+        };
+
+        let pixels = width as usize * height as usize;
+        // 每个像素至少要占一点压缩数据, 挡住用很小的文件申请巨大画布
+        if pixels / 4096 > data.len() {
+            return Err(Error::UnexpectedEof);
+        }
+        if pixels > MAX_PIXELS {
+            return Err(Error::TooLarge);
+        }
+        let info = Info {
+            width,
+            height,
+            loop_count: Self::loop_count(data, pos)?,
+        };
+        Ok(Self {
+            data,
+            pos,
+            info,
+            global,
+            canvas: vec![RGBA8::default(); pixels],
+            dispose: None,
+            done: false,
+        })
+    }
+
+    /// Looks for the `NETSCAPE2.0` extension among the blocks before the first frame
+    fn loop_count(data: &[u8], mut pos: usize) -> Result<Option<u16>, Error> {
+        while data.get(pos) == Some(&EXTENSION) {
+            let label = *data.get(pos + 1).ok_or(Error::UnexpectedEof)?;
+            pos += 2;
+            if label == APPLICATION && data.get(pos) == Some(&11) {
+                let id = data.get(pos + 1..pos + 12).ok_or(Error::UnexpectedEof)?;
+                let sub = data.get(pos + 12..pos + 16);
+                if (id == b"NETSCAPE2.0" || id == b"ANIMEXTS1.0")
+                    && matches!(sub, Some([3, 1, _, _]))
+                {
+                    return Ok(Some(u16::from_le_bytes([data[pos + 14], data[pos + 15]])));
+                }
+            }
+            pos = skip_sub_blocks(data, pos)?;
+        }
+        Ok(None)
+    }
+
+    /// The logical screen and loop count
+    #[inline]
+    pub fn info(&self) -> Info {
+        self.info
+    }
+
+    #[inline]
+    fn byte(&mut self) -> Result<u8, Error> {
+        let byte = *self.data.get(self.pos).ok_or(Error::UnexpectedEof)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn read_frame(&mut self) -> Result<Option<Frame>, Error> {
+        let mut control = Control {
+            disposal: Disposal::Keep,
+            delay: 0,
+            transparent: None,
+        };
+        loop {
+            match self.byte()? {
+                TRAILER => return Ok(None),
+                EXTENSION => {
+                    let label = self.byte()?;
+                    if label == GRAPHIC_CONTROL && self.data.get(self.pos) == Some(&4) {
+                        let block = self
+                            .data
+                            .get(self.pos + 1..self.pos + 5)
+                            .ok_or(Error::UnexpectedEof)?;
+                        control = Control {
+                            disposal: Disposal::from_bits(block[0] >> 2 & 7),
+                            delay: u16::from_le_bytes([block[1], block[2]]),
+                            transparent: (block[0] & 1 != 0).then_some(block[3]),
+                        };
+                    }
+                    // 注释, 纯文本和其它应用扩展都跳过
+                    self.pos = skip_sub_blocks(self.data, self.pos)?;
+                }
+                IMAGE => return self.read_image(control).map(Some),
+                _ => return Err(Error::BadHeader),
+            }
+        }
+    }
+
+    fn read_image(&mut self, control: Control) -> Result<Frame, Error> {
+        let d = self
+            .data
+            .get(self.pos..self.pos + 9)
+            .ok_or(Error::UnexpectedEof)?;
+        let le = |i: usize| u16::from_le_bytes([d[i], d[i + 1]]) as usize;
+        let rect = Rect {
+            left: le(0),
+            top: le(2),
+            width: le(4),
+            height: le(6),
+        };
+        let flags = d[8];
+        self.pos += 9;
+        let local = if flags & 0x80 != 0 {
+            let len = 2 << (flags & 7);
+            let table = color_table(self.data, self.pos, len)?;
+            self.pos += len * 3;
+            Some(table)
+        } else {
+            None
+        };
+        let interlaced = flags & 0x40 != 0;
+
+        let min_size = self.byte()?;
+        let start = self.pos;
+        self.pos = skip_sub_blocks(self.data, start)?;
+        let len = rect.width * rect.height;
+        if len / 4096 > self.pos - start {
+            return Err(Error::UnexpectedEof);
+        }
+        let mut lzw_data = Vec::with_capacity(self.pos - start);
+        let mut p = start;
+        while self.data[p] != 0 {
+            let n = self.data[p] as usize;
+            lzw_data.extend_from_slice(&self.data[p + 1..p + 1 + n]);
+            p += 1 + n;
+        }
+        let indices = lzw::decode(&lzw_data, min_size, len)?;
+
+        // 先处理上一帧的处置方式, 再画这一帧
+        match self.dispose.take() {
+            Some((Disposal::Background, r, _)) => self.fill(r, RGBA8::default()),
+            Some((Disposal::Previous, _, Some(saved))) => self.canvas = saved,
+            _ => {}
+        }
+        let saved = (control.disposal == Disposal::Previous).then(|| self.canvas.clone());
+
+        let palette = local
+            .as_deref()
+            .or(self.global.as_deref())
+            .ok_or(Error::BadPalette)?;
+        let rows = interlace_rows(rect.height, interlaced);
+        let (width, height) = (self.info.width as usize, self.info.height as usize);
+        for (row, &y) in indices.chunks_exact(rect.width.max(1)).zip(&rows) {
+            for (x, &index) in row.iter().enumerate() {
+                if Some(index) == control.transparent {
+                    continue;
+                }
+                let c = *palette.get(index as usize).ok_or(Error::BadPalette)?;
+                let (cx, cy) = (rect.left + x, rect.top + y);
+                if cx < width && cy < height {
+                    self.canvas[cy * width + cx] = c.alpha(255);
+                }
+            }
+        }
+        self.dispose = Some((control.disposal, rect, saved));
+        Ok(Frame {
+            pixels: self.canvas.clone(),
+            delay: control.delay,
+        })
+    }
+
+    /// Fills the part of `r` that's on the canvas
+    fn fill(&mut self, r: Rect, color: RGBA8) {
+        let (width, height) = (self.info.width as usize, self.info.height as usize);
+        let x_end = (r.left + r.width).min(width);
+        for y in r.top..(r.top + r.height).min(height) {
+            if r.left < x_end {
+                self.canvas[y * width + r.left..y * width + x_end].fill(color);
+            }
+        }
+    }
+}
+
+/// Frame rows in the order they're stored
+fn interlace_rows(height: usize, interlaced: bool) -> Vec<usize> {
+    if !interlaced {
+        return (0..height).collect();
+    }
+    [(0, 8), (4, 8), (2, 4), (1, 2)]
+        .iter()
+        .flat_map(|&(start, step)| (start..height).step_by(step))
+        .collect()
+}
+
+impl Iterator for Decoder<'_> {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_frame() {
+            Ok(Some(frame)) => Some(Ok(frame)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Decode all frames at once.
+///
+/// Fails with `TooLarge` if the frames together have more than [`MAX_PIXELS`] pixels.
+pub fn decode(data: &[u8]) -> Result<(Info, Vec<Frame>), Error> {
+    decode_max(data, MAX_PIXELS)
+}
+
+fn decode_max(data: &[u8], max_pixels: usize) -> Result<(Info, Vec<Frame>), Error> {
+    let decoder = Decoder::new(data)?;
+    let info = decoder.info();
+    let canvas = info.width as usize * info.height as usize;
+    let mut frames = Vec::new();
+    for frame in decoder {
+        // 每帧都是整张画布的拷贝, 几个字节的帧也一样, 所以总数要有上限
+        if (frames.len() + 1) * canvas > max_pixels {
+            return Err(Error::TooLarge);
+        }
+        frames.push(frame?);
+    }
+    Ok((info, frames))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: RGBA8 = RGBA8::new(255, 0, 0, 255);
+    const GREEN: RGBA8 = RGBA8::new(0, 255, 0, 255);
+    const BLUE: RGBA8 = RGBA8::new(0, 0, 255, 255);
+    const CLEAR: RGBA8 = RGBA8::new(0, 0, 0, 0);
+
+    /// Header with a 4-color global table: red, green, blue, black
+    fn file(width: u16, height: u16, blocks: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = b"GIF89a".to_vec();
+        out.extend_from_slice(&width.to_le_bytes());
+        out.extend_from_slice(&height.to_le_bytes());
+        out.extend_from_slice(&[0x80 | 0x70 | 1, 0, 0]);
+        out.extend_from_slice(&[255, 0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0]);
+        for b in blocks {
+            out.extend_from_slice(b);
+        }
+        out.push(TRAILER);
+        out
+    }
+
+    fn control(disposal: u8, delay: u16, transparent: Option<u8>) -> Vec<u8> {
+        let [lo, hi] = delay.to_le_bytes();
+        let flags = disposal << 2 | transparent.is_some() as u8;
+        vec![
+            EXTENSION,
+            GRAPHIC_CONTROL,
+            4,
+            flags,
+            lo,
+            hi,
+            transparent.unwrap_or(0),
+            0,
+        ]
+    }
+
+    fn image(rect: [u16; 4], local: Option<&[u8]>, interlaced: bool, indices: &[u8]) -> Vec<u8> {
+        let mut out = vec![IMAGE];
+        for v in rect {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        let mut flags = (interlaced as u8) << 6;
+        if let Some(t) = local {
+            flags |= 0x80 | ((t.len() / 3).trailing_zeros() - 1) as u8;
+        }
+        out.push(flags);
+        out.extend_from_slice(local.unwrap_or_default());
+        out.push(2);
+        for chunk in lzw::encode(indices, 2).chunks(255) {
+            out.push(chunk.len() as u8);
+            out.extend_from_slice(chunk);
+        }
+        out.push(0);
+        out
+    }
+
+    #[test]
+    fn disposal() {
+        let gif = file(
+            3,
+            1,
+            &[
+                // 循环扩展, 注释扩展都应被跳过
+                [
+                    &[EXTENSION, APPLICATION, 11][..],
+                    b"NETSCAPE2.0",
+                    &[3, 1, 5, 0, 0],
+                ]
+                .concat(),
+                vec![EXTENSION, 0xFE, 2, b'h', b'i', 0],
+                control(1, 10, None),
+                image([0, 0, 3, 1], None, false, &[0, 0, 0]),
+                control(3, 20, None),
+                image([1, 0, 1, 1], None, false, &[1]),
+                control(2, 30, Some(3)),
+                image([0, 0, 2, 1], None, false, &[2, 3]),
+                control(0, 40, None),
+                image([2, 0, 1, 1], None, false, &[1]),
+            ],
+        );
+        let decoder = Decoder::new(&gif).unwrap();
+        assert_eq!(
+            decoder.info(),
+            Info {
+                width: 3,
+                height: 1,
+                loop_count: Some(5)
+            }
+        );
+        let frames: Vec<_> = decoder.map(Result::unwrap).collect();
+        let expected = [
+            (vec![RED, RED, RED], 10),
+            (vec![RED, GREEN, RED], 20),
+            // 上一帧恢复成之前的样子, 透明像素不覆盖
+            (vec![BLUE, RED, RED], 30),
+            // 上一帧的区域清成透明
+            (vec![CLEAR, CLEAR, GREEN], 40),
+        ];
+        assert_eq!(frames.len(), expected.len());
+        for (frame, (pixels, delay)) in frames.iter().zip(expected) {
+            assert_eq!((&frame.pixels, frame.delay), (&pixels, delay));
+        }
+    }
+
+    #[test]
+    fn palettes_and_interlace() {
+        let indices: Vec<u8> = (0..10).map(|y| y % 4).collect();
+        let rows = interlace_rows(10, true);
+        assert_eq!(rows, [0, 8, 4, 2, 6, 1, 3, 5, 7, 9]);
+        let stored: Vec<u8> = rows.iter().map(|&y| indices[y]).collect();
+        // 本地调色板只在这一帧有效; 画在画布外的部分被裁掉
+        let local = [9, 9, 9, 8, 8, 8, 7, 7, 7, 6, 6, 6];
+        let gif = file(
+            2,
+            10,
+            &[
+                image([1, 0, 1, 10], Some(&local), true, &stored),
+                image([0, 0, 2, 1], None, false, &[1, 2]),
+                image([1, 9, 2, 2], None, false, &[0, 0, 0, 0]),
+            ],
+        );
+        let (info, frames) = decode(&gif).unwrap();
+        assert_eq!(info.loop_count, None);
+        assert_eq!(frames.len(), 3);
+        for y in 0..10 {
+            let c = local[indices[y] as usize * 3];
+            assert_eq!(frames[0].pixels[y * 2], CLEAR);
+            assert_eq!(frames[0].pixels[y * 2 + 1], RGBA8::new(c, c, c, 255));
+        }
+        assert_eq!(frames[1].pixels[..2], [GREEN, BLUE]);
+        assert_eq!(frames[2].pixels[19], RED);
+        assert_eq!(frames[2].pixels[2..19], frames[1].pixels[2..19]);
+    }
+
+    #[test]
+    fn bad_headers() {
+        let good = file(1, 1, &[image([0, 0, 1, 1], None, false, &[0])]);
+        assert!(decode(&good).is_ok());
+        let mut bad = good.clone();
+        bad[3] = b'X';
+        assert_eq!(decode(&bad).unwrap_err(), Error::BadSignature);
+        let mut bad = good.clone();
+        bad[6] = 0;
+        assert_eq!(decode(&bad).unwrap_err(), Error::BadHeader);
+        let mut bad = good.clone();
+        bad[8] = 0;
+        assert_eq!(decode(&bad).unwrap_err(), Error::BadHeader);
+        // 帧之间出现未知的块
+        let gif = file(1, 1, &[vec![0x99]]);
+        assert_eq!(decode(&gif).unwrap_err(), Error::BadHeader);
+    }
+
+    #[test]
+    fn palette_errors() {
+        // 没有全局调色板, 帧也没有局部调色板
+        let mut bad = file(1, 1, &[image([0, 0, 1, 1], None, false, &[0])]);
+        bad[10] = 0;
+        bad.drain(13..25);
+        assert_eq!(decode(&bad).unwrap_err(), Error::BadPalette);
+
+        // 索引超出局部调色板, 即使全局调色板够大
+        let past_end = [0x80, 0, 0, 0, 0, 0];
+        let gif = file(1, 1, &[image([0, 0, 1, 1], Some(&past_end), false, &[3])]);
+        assert_eq!(decode(&gif).unwrap_err(), Error::BadPalette);
+
+        // 全局调色板被截断
+        let gif = file(1, 1, &[]);
+        assert_eq!(decode(&gif[..20]).unwrap_err(), Error::UnexpectedEof);
+        // 局部调色板被截断
+        let gif = file(1, 1, &[image([0, 0, 1, 1], Some(&past_end), false, &[0])]);
+        assert_eq!(
+            decode(&gif[..25 + 10 + 3]).unwrap_err(),
+            Error::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn truncated_blocks() {
+        let good = file(1, 1, &[image([0, 0, 1, 1], None, false, &[0])]);
+        assert_eq!(decode(&good[..5]).unwrap_err(), Error::UnexpectedEof);
+        // 缺少结尾标记
+        assert_eq!(
+            decode(&good[..good.len() - 1]).unwrap_err(),
+            Error::UnexpectedEof
+        );
+        // 图像描述符不完整
+        assert_eq!(decode(&good[..30]).unwrap_err(), Error::UnexpectedEof);
+        // LZW 子块不完整
+        assert_eq!(
+            decode(&good[..good.len() - 3]).unwrap_err(),
+            Error::UnexpectedEof
+        );
+
+        // 图形控制扩展和注释扩展被截断
+        let gif = file(1, 1, &[control(0, 0, None)]);
+        assert_eq!(decode(&gif[..25 + 5]).unwrap_err(), Error::UnexpectedEof);
+        let comment = vec![EXTENSION, 0xFE, 5, b'h', b'i'];
+        let gif = file(1, 1, &[comment]);
+        assert_eq!(decode(&gif).unwrap_err(), Error::UnexpectedEof);
+
+        // 循环次数扩展被截断时 new 就会失败
+        let mut netscape = vec![EXTENSION, APPLICATION, 11];
+        netscape.extend_from_slice(b"NETSCAPE");
+        let gif = file(1, 1, &[netscape]);
+        assert_eq!(Decoder::new(&gif).err(), Some(Error::UnexpectedEof));
+
+        // 出错之后迭代器就停下
+        let mut decoder = Decoder::new(&good[..good.len() - 3]).unwrap();
+        assert!(decoder.next().unwrap().is_err());
+        assert!(decoder.next().is_none());
+    }
+
+    #[test]
+    fn many_tiny_frames() {
+        // 每帧只有十几个字节, 输出却是整张画布
+        let frame = image([0, 0, 1, 1], None, false, &[0]);
+        let gif = file(4, 4, &vec![frame.clone(); 10]);
+        assert_eq!(decode_max(&gif, 160).unwrap().1.len(), 10);
+        assert_eq!(decode_max(&gif, 159).unwrap_err(), Error::TooLarge);
+        assert_eq!(decode_max(&gif, 16).unwrap_err(), Error::TooLarge);
+        assert_eq!(decode_max(&file(4, 4, &[frame]), 16).unwrap().1.len(), 1);
+        // 逐帧读取不受限制
+        assert_eq!(Decoder::new(&gif).unwrap().count(), 10);
+    }
+
+    #[test]
+    fn huge_header_tiny_data() {
+        let good = file(1, 1, &[image([0, 0, 1, 1], None, false, &[0])]);
+        // 巨大画布配上很小的文件
+        let mut huge = good.clone();
+        huge[6..10].fill(0xFF);
+        assert_eq!(decode(&huge).unwrap_err(), Error::UnexpectedEof);
+
+        // 画布大小刚好在界限上时可以解码, 再大一行就不行
+        let rows = good.len() as u16;
+        assert!(decode(&file(4096, rows, &[image([0, 0, 1, 1], None, false, &[0])])).is_ok());
+        let gif = file(4096, rows + 1, &[image([0, 0, 1, 1], None, false, &[0])]);
+        assert_eq!(decode(&gif).unwrap_err(), Error::UnexpectedEof);
+
+        // 画布很小, 帧的矩形很大, LZW 数据只有几个字节
+        let frame = image([0, 0, 0xFFFF, 0xFFFF], None, false, &[0]);
+        assert_eq!(
+            decode(&file(1, 1, &[frame])).unwrap_err(),
+            Error::UnexpectedEof
+        );
+        // 画布本身超过 MAX_PIXELS, 数据量够也不行
+        let mut huge = file(0xFFFF, 0xFFFF, &[]);
+        huge.resize(0xFFFF * 0xFFFF / 4096 + 1, 0);
+        assert_eq!(decode(&huge).unwrap_err(), Error::TooLarge);
+
+        // 帧的矩形刚好在界限上时 LZW 数据不够, 才由 LZW 报错
+        let frame = image([0, 0, 4096, 1], None, false, &[0]);
+        assert_eq!(
+            decode(&file(1, 1, &[frame])).unwrap_err(),
+            Error::UnexpectedEof
+        );
+    }
+}
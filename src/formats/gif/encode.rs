@@ -0,0 +1,422 @@
+use super::{lzw, quantize, Error, Frame};
+use super::{APPLICATION, EXTENSION, GRAPHIC_CONTROL, IMAGE, TRAILER};
+use crate::{RGB8, RGBA8};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// How to write a GIF
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Options {
+    /// Global color table for all frames, at most 256 colors. Pixels are mapped to the
+    /// closest color. `None` gives every frame its own table.
+    ///
+    /// Transparent pixels (alpha below 128) need one more entry, so with a full 256-color
+    /// palette frames that have any are rejected with [`Error::BadPalette`].
+    pub palette: Option<Vec<RGB8>>,
+    /// Number of times to loop. `Some(0)` loops forever (the default),
+    /// `None` leaves out the `NETSCAPE2.0` extension so the animation plays once.
+    pub loop_count: Option<u16>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            palette: None,
+            loop_count: Some(0),
+        }
+    }
+}
+
+/// Writes frames one by one
+pub struct Encoder<W: Write> {
+    out: W,
+    width: u16,
+    height: u16,
+    /// The palette from [`Options`], and its transparent index if it has room for one
+    global: Option<(Vec<RGB8>, Option<u8>)>,
+    buf: Vec<u8>,
+}
+
+/// Bits per index for a color table of `len` colors, as stored in the flags plus one
+#[inline]
+fn table_bits(len: usize) -> u32 {
+    usize::BITS - (len.max(2) - 1).leading_zeros()
+}
+
+/// The color table padded to a power of two
+fn write_table(out: &mut Vec<u8>, palette: &[RGB8]) {
+    for c in palette {
+        out.extend_from_slice(&[c.r, c.g, c.b]);
+    }
+    let padding = (1 << table_bits(palette.len())) - palette.len();
+    out.resize(out.len() + padding * 3, 0);
+}
+
+fn check(width: usize, height: usize, options: &Options) -> Result<(), Error> {
+    if width == 0 || height == 0 {
+        return Err(Error::BadHeader);
+    }
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(Error::TooLarge);
+    }
+    if options
+        .palette
+        .as_ref()
+        .is_some_and(|p| p.is_empty() || p.len() > 256)
+    {
+        return Err(Error::BadPalette);
+    }
+    Ok(())
+}
+
+impl<W: Write> Encoder<W> {
+    /// Writes the header, the global color table if there is one, and the loop count
+    pub fn new(mut out: W, width: usize, height: usize, options: &Options) -> io::Result<Self> {
+        check(width, height, options)?;
+        let mut buf = b"GIF89a".to_vec();
+        buf.extend_from_slice(&(width as u16).to_le_bytes());
+        buf.extend_from_slice(&(height as u16).to_le_bytes());
+        // 8 位颜色精度
+        let mut flags = 0x70;
+        let global = options.palette.clone().map(|mut palette| {
+            let transparent = (palette.len() < 256).then_some(palette.len() as u8);
+            if transparent.is_some() {
+                palette.push(RGB8::default());
+            }
+            flags |= 0x80 | (table_bits(palette.len()) - 1) as u8;
+            (palette, transparent)
+        });
+        buf.extend_from_slice(&[flags, 0, 0]);
+        if let Some((palette, _)) = &global {
+            write_table(&mut buf, palette);
+        }
+        if let Some(count) = options.loop_count {
+            buf.extend_from_slice(&[EXTENSION, APPLICATION, 11]);
+            buf.extend_from_slice(b"NETSCAPE2.0");
+            buf.extend_from_slice(&[3, 1]);
+            buf.extend_from_slice(&count.to_le_bytes());
+            buf.push(0);
+        }
+        out.write_all(&buf)?;
+        Ok(Self {
+            out,
+            width: width as u16,
+            height: height as u16,
+            global,
+            buf,
+        })
+    }
+
+    /// Next frame, width × height pixels covering the whole canvas.
+    /// `delay` is in 1/100 s.
+    pub fn write_frame(&mut self, pixels: &[RGBA8], delay: u16) -> io::Result<()> {
+        if pixels.len() != self.width as usize * self.height as usize {
+            return Err(Error::BadHeader.into());
+        }
+        if needs_transparent_slot(&self.global, pixels) {
+            return Err(Error::BadPalette.into());
+        }
+        let local = self.global.is_none();
+        let (palette, transparent) = match &self.global {
+            Some((palette, transparent)) => (palette.clone(), *transparent),
+            None => frame_palette(pixels),
+        };
+
+        // 调色板里只有不透明的颜色, 透明色单独占一个位置
+        let opaque = &palette[..palette.len() - transparent.is_some() as usize];
+        let mut cache = HashMap::new();
+        let indices: Vec<u8> = pixels
+            .iter()
+            .map(|px| match transparent {
+                Some(t) if px.a < 128 => t,
+                _ => *cache
+                    .entry(px.rgb())
+                    .or_insert_with(|| quantize::nearest(opaque, px.rgb())),
+            })
+            .collect();
+
+        let buf = &mut self.buf;
+        buf.clear();
+        // 每帧都覆盖整个画布, 所以画完就清掉, 透明像素才不会透出上一帧
+        let [lo, hi] = delay.to_le_bytes();
+        let flags = 2 << 2 | transparent.is_some() as u8;
+        buf.extend_from_slice(&[EXTENSION, GRAPHIC_CONTROL, 4, flags, lo, hi]);
+        buf.extend_from_slice(&[transparent.unwrap_or(0), 0]);
+
+        buf.extend_from_slice(&[IMAGE, 0, 0, 0, 0]);
+        buf.extend_from_slice(&self.width.to_le_bytes());
+        buf.extend_from_slice(&self.height.to_le_bytes());
+        let bits = table_bits(palette.len());
+        if local {
+            buf.push(0x80 | (bits - 1) as u8);
+            write_table(buf, &palette);
+        } else {
+            buf.push(0);
+        }
+
+        let min_size = bits.max(2) as u8;
+        buf.push(min_size);
+        for block in lzw::encode(&indices, min_size).chunks(255) {
+            buf.push(block.len() as u8);
+            buf.extend_from_slice(block);
+        }
+        buf.push(0);
+        self.out.write_all(buf)
+    }
+
+    /// Writes the trailer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(&[TRAILER])?;
+        Ok(self.out)
+    }
+}
+
+/// The global palette is full, so transparent pixels have nowhere to go
+fn needs_transparent_slot(global: &Option<(Vec<RGB8>, Option<u8>)>, pixels: &[RGBA8]) -> bool {
+    matches!(global, Some((_, None))) && pixels.iter().any(|px| px.a < 128)
+}
+
+/// A frame's own palette, exact if possible, with a transparent entry at the end if needed
+fn frame_palette(pixels: &[RGBA8]) -> (Vec<RGB8>, Option<u8>) {
+    let mut counts: HashMap<RGB8, usize> = HashMap::new();
+    let mut colors: Vec<(RGB8, u32)> = Vec::new();
+    let mut has_transparent = false;
+    for px in pixels {
+        if px.a < 128 {
+            has_transparent = true;
+            continue;
+        }
+        let i = *counts.entry(px.rgb()).or_insert_with(|| {
+            colors.push((px.rgb(), 0));
+            colors.len() - 1
+        });
+        colors[i].1 = colors[i].1.saturating_add(1);
+    }
+    let mut palette = quantize::palette(&colors, 256 - has_transparent as usize);
+    if palette.is_empty() {
+        palette.push(RGB8::default());
+    }
+    let transparent = has_transparent.then_some(palette.len() as u8);
+    if has_transparent {
+        palette.push(RGB8::default());
+    }
+    (palette, transparent)
+}
+
+/// Encode a whole animation, or a still image as a single frame
+pub fn encode(
+    width: usize,
+    height: usize,
+    frames: &[Frame],
+    options: &Options,
+) -> Result<Vec<u8>, Error> {
+    check(width, height, options)?;
+    if frames.iter().any(|f| f.pixels.len() != width * height) {
+        return Err(Error::BadHeader);
+    }
+    // 参数已经检查过, 写入 Vec 不会失败
+    let mut encoder = Encoder::new(Vec::new(), width, height, options).expect("checked");
+    if frames
+        .iter()
+        .any(|f| needs_transparent_slot(&encoder.global, &f.pixels))
+    {
+        return Err(Error::BadPalette);
+    }
+    for frame in frames {
+        encoder
+            .write_frame(&frame.pixels, frame.delay)
+            .expect("checked");
+    }
+    Ok(encoder.finish().expect("checked"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{decode, Info};
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let clear = RGBA8::new(0, 0, 0, 0);
+        let pixels = |n: u8| -> Vec<RGBA8> {
+            (0..35u8)
+                .map(|i| match (i + n) % 9 {
+                    0 => clear,
+                    k => RGBA8::new(k * 20, 255 - k, n, 255),
+                })
+                .collect()
+        };
+        let frames: Vec<_> = (0..4)
+            .map(|n| Frame {
+                pixels: pixels(n),
+                delay: n as u16 * 7,
+            })
+            .collect();
+        let file = encode(7, 5, &frames, &Options::default()).unwrap();
+        let (info, decoded) = decode(&file).unwrap();
+        assert_eq!((info.width, info.height, info.loop_count), (7, 5, Some(0)));
+        assert_eq!(decoded, frames);
+
+        // 单色, 不透明
+        let still = [Frame {
+            pixels: vec![RGBA8::new(1, 2, 3, 255); 300 * 2],
+            delay: 0,
+        }];
+        let options = Options {
+            loop_count: None,
+            ..Options::default()
+        };
+        let file = encode(300, 2, &still, &options).unwrap();
+        assert!(!file.windows(8).any(|w| w == b"NETSCAPE"));
+        assert_eq!(
+            decode(&file).unwrap(),
+            (
+                Info {
+                    width: 300,
+                    height: 2,
+                    loop_count: None
+                },
+                still.to_vec()
+            )
+        );
+
+        // 给定调色板, 取最近的颜色
+        let options = Options {
+            palette: Some(vec![RGB8::new(0, 0, 0), RGB8::new(255, 255, 255)]),
+            loop_count: Some(3),
+        };
+        let frame = Frame {
+            pixels: vec![
+                RGBA8::new(10, 20, 30, 255),
+                RGBA8::new(200, 220, 240, 255),
+                RGBA8::new(200, 220, 240, 100),
+            ],
+            delay: 1,
+        };
+        let file = encode(3, 1, &[frame], &options).unwrap();
+        let (info, decoded) = decode(&file).unwrap();
+        assert_eq!(info.loop_count, Some(3));
+        assert_eq!(
+            decoded[0].pixels,
+            [
+                RGBA8::new(0, 0, 0, 255),
+                RGBA8::new(255, 255, 255, 255),
+                clear
+            ]
+        );
+    }
+
+    #[test]
+    fn quantizes() {
+        let pixels: Vec<_> = (0..64 * 64u32)
+            .map(|i| RGBA8::new((i % 64 * 4) as u8, (i / 64 * 4) as u8, 128, 255))
+            .collect();
+        let frame = Frame { pixels, delay: 0 };
+        let file = encode(64, 64, std::slice::from_ref(&frame), &Options::default()).unwrap();
+        let (_, decoded) = decode(&file).unwrap();
+        for (a, b) in frame.pixels.iter().zip(&decoded[0].pixels) {
+            assert_eq!(b.a, 255);
+            assert!(
+                a.r.abs_diff(b.r) <= 8 && a.g.abs_diff(b.g) <= 8 && a.b == b.b,
+                "{a:?} {b:?}"
+            );
+        }
+    }
+
+    fn frame() -> Frame {
+        Frame {
+            pixels: vec![RGBA8::default(); 4],
+            delay: 0,
+        }
+    }
+
+    #[test]
+    fn bad_dimensions() {
+        let frame = frame();
+        let options = Options::default();
+        assert_eq!(
+            encode(2, 2, std::slice::from_ref(&frame), &options).map(|_| ()),
+            Ok(())
+        );
+        assert_eq!(
+            encode(4, 2, std::slice::from_ref(&frame), &options),
+            Err(Error::BadHeader)
+        );
+        assert_eq!(encode(0, 2, &[], &options), Err(Error::BadHeader));
+        assert_eq!(encode(2, 0, &[], &options), Err(Error::BadHeader));
+        // GIF 的宽高只有 16 位
+        assert_eq!(encode(70_000, 1, &[], &options), Err(Error::TooLarge));
+        assert_eq!(encode(1, 65_536, &[], &options), Err(Error::TooLarge));
+    }
+
+    #[test]
+    fn palette_overflow() {
+        let frame = frame();
+        let options = Options {
+            palette: Some(vec![RGB8::default(); 257]),
+            ..Options::default()
+        };
+        assert_eq!(
+            encode(2, 2, std::slice::from_ref(&frame), &options),
+            Err(Error::BadPalette)
+        );
+        // 256 色刚好放得下不透明的帧
+        let opaque = Frame {
+            pixels: vec![RGBA8::new(0, 0, 0, 255); 4],
+            delay: 0,
+        };
+        let options = Options {
+            palette: Some(vec![RGB8::default(); 256]),
+            ..Options::default()
+        };
+        assert!(encode(2, 2, &[opaque], &options).is_ok());
+    }
+
+    #[test]
+    fn short_frame() {
+        let frame = frame();
+        let mut encoder = Encoder::new(Vec::new(), 2, 2, &Options::default()).unwrap();
+        assert!(encoder.write_frame(&frame.pixels[1..], 0).is_err());
+        assert!(encoder.write_frame(&[], 0).is_err());
+    }
+
+    #[test]
+    fn full_palette_transparency() {
+        let palette: Vec<_> = (0..=255).map(|i| RGB8::new(i, i, i)).collect();
+        let options = Options {
+            palette: Some(palette),
+            ..Options::default()
+        };
+        // 没有透明像素时 256 色都能用
+        let opaque = Frame {
+            pixels: vec![RGBA8::new(7, 7, 7, 255), RGBA8::new(250, 250, 250, 128)],
+            delay: 0,
+        };
+        let file = encode(2, 1, std::slice::from_ref(&opaque), &options).unwrap();
+        assert_eq!(
+            decode(&file).unwrap().1[0].pixels,
+            [RGBA8::new(7, 7, 7, 255), RGBA8::new(250, 250, 250, 255)]
+        );
+
+        // 透明像素不能悄悄变成最近的不透明颜色
+        let clear = Frame {
+            pixels: vec![RGBA8::new(7, 7, 7, 255), RGBA8::new(0, 0, 0, 0)],
+            delay: 0,
+        };
+        assert_eq!(
+            encode(2, 1, &[opaque, clear.clone()], &options),
+            Err(Error::BadPalette)
+        );
+        let mut encoder = Encoder::new(Vec::new(), 2, 1, &options).unwrap();
+        let err = encoder.write_frame(&clear.pixels, 0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // 255 色还有透明色的位置
+        let options = Options {
+            palette: options.palette.map(|p| p[..255].to_vec()),
+            ..options
+        };
+        let file = encode(2, 1, std::slice::from_ref(&clear), &options).unwrap();
+        assert_eq!(decode(&file).unwrap().1[0].pixels, clear.pixels);
+    }
+}
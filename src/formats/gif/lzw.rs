@@ -0,0 +1,240 @@
+//! GIF's variant of LZW: variable-length codes up to 12 bits, packed least significant first
+
+use super::Error;
+use std::collections::HashMap;
+
+const MAX_CODES: usize = 4096;
+
+/// Decode exactly `len` color indices. Data after them, including the end code, is ignored.
+pub(super) fn decode(data: &[u8], min_size: u8, len: usize) -> Result<Vec<u8>, Error> {
+    if !(2..=8).contains(&min_size) {
+        return Err(Error::BadLzw);
+    }
+    let clear = 1usize << min_size;
+    let end = clear + 1;
+
+    // 每个码是前缀码加一个字节, 从后往前展开
+    let mut prefix = [0u16; MAX_CODES];
+    let mut suffix = [0u8; MAX_CODES];
+    let mut first = [0u8; MAX_CODES];
+    let mut length = [0u16; MAX_CODES];
+    for i in 0..clear {
+        suffix[i] = i as u8;
+        first[i] = i as u8;
+        length[i] = 1;
+    }
+
+    let mut out = Vec::with_capacity(len);
+    let (mut buf, mut count, mut pos) = (0u32, 0u32, 0);
+    let mut size = min_size as u32 + 1;
+    let mut next = end + 1;
+    let mut prev: Option<usize> = None;
+    while out.len() < len {
+        while count < size {
+            let byte = *data.get(pos).ok_or(Error::UnexpectedEof)?;
+            buf |= (byte as u32) << count;
+            count += 8;
+            pos += 1;
+        }
+        let code = (buf & ((1 << size) - 1)) as usize;
+        buf >>= size;
+        count -= size;
+
+        if code == clear {
+            size = min_size as u32 + 1;
+            next = end + 1;
+            prev = None;
+            continue;
+        }
+        if code == end {
+            return Err(Error::UnexpectedEof);
+        }
+        let Some(p) = prev else {
+            if code >= clear {
+                return Err(Error::BadLzw);
+            }
+            out.push(code as u8);
+            prev = Some(code);
+            continue;
+        };
+        if code > next || (code == next && next == MAX_CODES) {
+            return Err(Error::BadLzw);
+        }
+        // code == next 时是 KwKwK 的情况: 前一个串加它自己的首字节
+        let head = if code < next { first[code] } else { first[p] };
+        if next < MAX_CODES {
+            prefix[next] = p as u16;
+            suffix[next] = head;
+            first[next] = first[p];
+            length[next] = length[p] + 1;
+            next += 1;
+            if next == 1 << size && size < 12 {
+                size += 1;
+            }
+        }
+
+        let n = length[code] as usize;
+        let start = out.len();
+        out.resize(start + n, 0);
+        let mut c = code;
+        for i in (0..n).rev() {
+            out[start + i] = suffix[c];
+            c = prefix[c] as usize;
+        }
+        prev = Some(code);
+    }
+    out.truncate(len);
+    Ok(out)
+}
+
+/// Writes codes least significant bit first
+struct Bits {
+    out: Vec<u8>,
+    buf: u32,
+    count: u32,
+}
+
+impl Bits {
+    #[inline]
+    fn put(&mut self, code: usize, size: u32) {
+        self.buf |= (code as u32) << self.count;
+        self.count += size;
+        while self.count >= 8 {
+            self.out.push(self.buf as u8);
+            self.buf >>= 8;
+            self.count -= 8;
+        }
+    }
+}
+
+/// Encode color indices below `1 << min_size`. Starts with a clear code, and clears
+/// again whenever the table is full.
+pub(super) fn encode(indices: &[u8], min_size: u8) -> Vec<u8> {
+    let clear = 1usize << min_size;
+    let end = clear + 1;
+    let mut bits = Bits {
+        out: Vec::with_capacity(indices.len() / 2 + 8),
+        buf: 0,
+        count: 0,
+    };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut size = min_size as u32 + 1;
+    let mut next = end + 1;
+    bits.put(clear, size);
+
+    let mut iter = indices.iter();
+    if let Some(&first) = iter.next() {
+        let mut prefix = first as usize;
+        for &byte in iter {
+            if let Some(&code) = table.get(&(prefix as u16, byte)) {
+                prefix = code as usize;
+                continue;
+            }
+            bits.put(prefix, size);
+            table.insert((prefix as u16, byte), next as u16);
+            next += 1;
+            // 解码器晚一个码才加表项, 所以这里用 > 而不是 ==
+            if next > 1 << size && size < 12 {
+                size += 1;
+            }
+            if next == MAX_CODES {
+                bits.put(clear, size);
+                table.clear();
+                size = min_size as u32 + 1;
+                next = end + 1;
+            }
+            prefix = byte as usize;
+        }
+        bits.put(prefix, size);
+        // 解码器读到最后一个码时也会加一个表项, 码长可能跟着变
+        if next < MAX_CODES {
+            next += 1;
+            if next > 1 << size && size < 12 {
+                size += 1;
+            }
+        }
+    }
+    bits.put(end, size);
+    if bits.count > 0 {
+        bits.out.push(bits.buf as u8);
+    }
+    bits.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let mut noise = 1u32;
+        let mut random = |bits: u8| {
+            noise = noise.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            ((noise >> 16) as u8) & ((1u16 << bits) - 1) as u8
+        };
+        for min_size in 2..=8u8 {
+            let inputs = [
+                vec![],
+                vec![1],
+                vec![0; 10_000],
+                (0..5000).map(|_| random(min_size)).collect::<Vec<_>>(),
+                (0..20_000)
+                    .map(|i| ((i / 7) % (1 << min_size)) as u8)
+                    .collect(),
+                (0..3000).map(|_| random(min_size.min(3))).collect(),
+            ];
+            for data in &inputs {
+                let lzw = encode(data, min_size);
+                assert_eq!(
+                    decode(&lzw, min_size, data.len()).unwrap(),
+                    *data,
+                    "{min_size}"
+                );
+            }
+        }
+        assert!(encode(&[0; 10_000], 2).len() < 200);
+    }
+
+    #[test]
+    fn known_stream() {
+        // GIF 规范附录里常见的 10×10 示例图像
+        let data = [
+            0x8C, 0x2D, 0x99, 0x87, 0x2A, 0x1C, 0xDC, 0x33, 0xA0, 0x02, 0x75, 0xEC, 0x95, 0xFA,
+            0xA8, 0xDE, 0x60, 0x8C, 0x04, 0x91, 0x4C, 0x01,
+        ];
+        let mut expected = Vec::new();
+        for row in [
+            "1111122222",
+            "1111122222",
+            "1111122222",
+            "1110000222",
+            "1110000222",
+            "2220000111",
+            "2220000111",
+            "2222211111",
+            "2222211111",
+            "2222211111",
+        ] {
+            expected.extend(row.bytes().map(|b| b - b'0'));
+        }
+        assert_eq!(decode(&data, 2, 100).unwrap(), expected);
+    }
+
+    #[test]
+    fn truncated_data() {
+        let lzw = encode(&[1, 2, 3, 1, 2, 3], 2);
+        // 要的像素比数据里的多
+        assert_eq!(decode(&lzw, 2, 7), Err(Error::UnexpectedEof));
+        assert_eq!(decode(&lzw[..1], 2, 6), Err(Error::UnexpectedEof));
+        assert_eq!(decode(&[], 2, 1), Err(Error::UnexpectedEof));
+    }
+
+    #[test]
+    fn bad_codes() {
+        let lzw = encode(&[1, 2, 3, 1, 2, 3], 2);
+        assert_eq!(decode(&lzw, 1, 6), Err(Error::BadLzw));
+        assert_eq!(decode(&lzw, 9, 6), Err(Error::BadLzw));
+        // 清除码之后直接用一个还不存在的码
+        assert_eq!(decode(&[0x04 | 0x07 << 3, 0], 2, 2), Err(Error::BadLzw));
+    }
+}
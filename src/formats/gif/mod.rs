@@ -0,0 +1,118 @@
+//! GIF, still and animated, with its own LZW implementation.
+//!
+//! [`Decoder`] is an iterator of frames, each the whole canvas in `RGBA8` after drawing
+//! the frame over the previous ones, so disposal methods, transparency, interlacing and
+//! local color tables are already taken care of. The canvas starts out transparent, and
+//! "restore to background" clears to transparent too, as browsers do.
+//!
+//! [`Encoder`] takes whole-canvas `RGBA8` frames. Each frame gets its own color table,
+//! exact if it has at most 256 colors and quantized otherwise, unless a palette is given in
+//! [`Options`]. Pixels with alpha below 128 become transparent.
+//!
+//! ```rust
+//! use cr::formats::gif::{self, Frame};
+//! use cr::RGBA8;
+//!
+//! let red = RGBA8::new(255, 0, 0, 255);
+//! let clear = RGBA8::new(0, 0, 0, 0);
+//! let frames = [
+//!     Frame { pixels: vec![red, clear, clear, red], delay: 50 },
+//!     Frame { pixels: vec![clear, red, red, clear], delay: 50 },
+//! ];
+//! let file = gif::encode(2, 2, &frames, &gif::Options::default()).unwrap();
+//! let (info, decoded) = gif::decode(&file).unwrap();
+//! assert_eq!((info.width, info.height, info.loop_count), (2, 2, Some(0)));
+//! assert_eq!(decoded, frames);
+//! ```
+
+use core::fmt;
+use std::io;
+
+mod decode;
+mod encode;
+mod lzw;
+mod quantize;
+
+pub use self::decode::*;
+pub use self::encode::*;
+
+/// Why a GIF file couldn't be decoded or encoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// Doesn't start with `GIF87a` or `GIF89a`
+    BadSignature,
+    /// Malformed or unknown block, or zero size.
+    /// When encoding: zero size, or a frame with the wrong number of pixels.
+    BadHeader,
+    /// No color table for a frame, a pixel refers to a color past its end,
+    /// or the encoder is given a palette of more than 256 colors, or of exactly 256 colors
+    /// for a frame with transparent pixels
+    BadPalette,
+    /// Invalid LZW code or code size
+    BadLzw,
+    /// The file ends early
+    UnexpectedEof,
+    /// More than [`MAX_PIXELS`] pixels in the canvas, or in all the frames from `decode`.
+    /// When encoding: over 65535 pixels wide or high.
+    TooLarge,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::BadSignature => "not a GIF file",
+            Error::BadHeader => "invalid GIF block or image size",
+            Error::BadPalette => "invalid color table or color index",
+            Error::BadLzw => "invalid LZW data",
+            Error::UnexpectedEof => "file is truncated",
+            Error::TooLarge => "image is too large",
+        })
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
+
+/// One frame of an animation: the whole canvas
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Frame {
+    /// Width × height pixels
+    pub pixels: Vec<crate::RGBA8>,
+    /// Time to show the frame, in 1/100 s
+    pub delay: u16,
+}
+
+/// What happens to a frame's area before the next frame is drawn
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Disposal {
+    /// Leave the frame in place
+    Keep,
+    /// Clear its area to transparent
+    Background,
+    /// Restore the area to what it was before the frame
+    Previous,
+}
+
+impl Disposal {
+    #[inline]
+    fn from_bits(bits: u8) -> Self {
+        match bits {
+            2 => Disposal::Background,
+            3 => Disposal::Previous,
+            // 0 (未指定) 和 1, 以及保留的值, 都当作保留
+            _ => Disposal::Keep,
+        }
+    }
+}
+
+/// Block introducers and labels
+const EXTENSION: u8 = 0x21;
+const IMAGE: u8 = 0x2C;
+const TRAILER: u8 = 0x3B;
+const GRAPHIC_CONTROL: u8 = 0xF9;
+const APPLICATION: u8 = 0xFF;
@@ -0,0 +1,121 @@
+//! Median cut color quantization
+
+use crate::RGB8;
+
+/// Reduce `colors`, with their pixel counts, to a palette of at most `max` colors.
+/// Returns the colors themselves if there are few enough.
+pub(super) fn palette(colors: &[(RGB8, u32)], max: usize) -> Vec<RGB8> {
+    if colors.len() <= max {
+        return colors.iter().map(|&(c, _)| c).collect();
+    }
+    let channel = |c: RGB8, ch: usize| [c.r, c.g, c.b][ch];
+    let mut boxes = vec![colors.to_vec()];
+    while boxes.len() < max {
+        // 切分跨度乘像素数最大的盒子, 沿着跨度最大的通道
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .flat_map(|(i, b)| {
+                let weight: u64 = b.iter().map(|&(_, n)| n as u64).sum();
+                (0..3).map(move |ch| {
+                    let (lo, hi) = b.iter().fold((255, 0), |(lo, hi), &(c, _)| {
+                        (channel(c, ch).min(lo), channel(c, ch).max(hi))
+                    });
+                    ((hi - lo) as u64 * weight.max(1), i, ch)
+                })
+            })
+            .max_by_key(|&(score, i, ch)| (score, usize::MAX - i, 2 - ch));
+        let Some((_, i, ch)) = widest else {
+            break;
+        };
+        let mut b = boxes.swap_remove(i);
+        b.sort_unstable_by_key(|&(c, _)| (channel(c, ch), c.r, c.g, c.b));
+        // 按像素数的中位数切开
+        let total: u64 = b.iter().map(|&(_, n)| n as u64).sum();
+        let mut acc = 0;
+        let mut split = b.len() / 2;
+        for (k, &(_, n)) in b.iter().enumerate() {
+            acc += n as u64;
+            if acc * 2 >= total {
+                split = k + 1;
+                break;
+            }
+        }
+        let rest = b.split_off(split.clamp(1, b.len() - 1));
+        boxes.push(b);
+        boxes.push(rest);
+    }
+    boxes
+        .iter()
+        .map(|b| {
+            let total: u64 = b.iter().map(|&(_, n)| n as u64).sum::<u64>().max(1);
+            let avg = |ch| {
+                let sum: u64 = b
+                    .iter()
+                    .map(|&(c, n)| channel(c, ch) as u64 * n as u64)
+                    .sum();
+                ((sum + total / 2) / total) as u8
+            };
+            RGB8::new(avg(0), avg(1), avg(2))
+        })
+        .collect()
+}
+
+/// Index of the closest palette color
+pub(super) fn nearest(palette: &[RGB8], c: RGB8) -> u8 {
+    let dist = |p: &RGB8| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(p.r, c.r) + d(p.g, c.g) + d(p.b, c.b)
+    };
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, p)| dist(p))
+        .map_or(0, |(i, _)| i as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn median_cut() {
+        let few = [(RGB8::new(1, 2, 3), 5), (RGB8::new(4, 5, 6), 1)];
+        assert_eq!(palette(&few, 2), [RGB8::new(1, 2, 3), RGB8::new(4, 5, 6)]);
+
+        // 两簇颜色分成两个
+        let mut colors = Vec::new();
+        for i in 0..10 {
+            colors.push((RGB8::new(i, 0, 0), 1));
+            colors.push((RGB8::new(200 + i, 250, 0), 1));
+        }
+        let mut p = palette(&colors, 2);
+        p.sort();
+        assert_eq!(p, [RGB8::new(5, 0, 0), RGB8::new(205, 250, 0)]);
+
+        // 全部颜色的渐变, 每个颜色都应该离调色板不远
+        let colors: Vec<_> = (0..4096u32)
+            .map(|i| {
+                (
+                    RGB8::new(
+                        (i % 16 * 17) as u8,
+                        (i / 16 % 16 * 17) as u8,
+                        (i / 256 * 17) as u8,
+                    ),
+                    1,
+                )
+            })
+            .collect();
+        let p = palette(&colors, 256);
+        assert_eq!(p.len(), 256);
+        for &(c, _) in &colors {
+            let q = p[nearest(&p, c) as usize];
+            let err = (c.r as i32 - q.r as i32).abs()
+                + (c.g as i32 - q.g as i32).abs()
+                + (c.b as i32 - q.b as i32).abs();
+            assert!(err <= 48, "{c:?} {q:?}");
+        }
+        assert_eq!(nearest(&p[..0], RGB8::new(1, 1, 1)), 0);
+    }
+}
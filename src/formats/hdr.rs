@@ -0,0 +1,553 @@
+//! Radiance `.hdr` (RGBE) images, for high dynamic range.
+//!
+//! Pixels are [`RGBE8`], and decode to `RGB<f32>`. Decoding supports flat scanlines,
+//! the old RLE (repeat markers) and the new per-channel RLE, mixed freely, and the
+//! `-Y`/`+Y` row and `+X`/`-X` column orders. Rotated images (`+X` first) and XYZE aren't
+//! supported. The encoder writes the new RLE where it's allowed, otherwise flat scanlines.
+//!
+//! ```rust
+//! use cr::formats::hdr;
+//! use cr::{Image, RGB};
+//!
+//! let image = Image::filled(RGB::<f32> { r: 100., g: 0.5, b: 0. }, 20, 2);
+//! let file = hdr::encode(image.view());
+//! assert!(file.starts_with(b"#?RADIANCE\n"));
+//! let (header, decoded) = hdr::decode(&file).unwrap();
+//! assert_eq!((header.width, header.height), (20, 2));
+//! assert_eq!(decoded.buf()[0], RGB { r: 100., g: 0.5, b: 0. });
+//! ```
+
+use crate::alt::RGBE8;
+use crate::{Image, ImageRef, RGB};
+use core::fmt;
+
+/// Largest width × height the decoder accepts, the same as QOI's. The old RLE can repeat
+/// a pixel millions of times with a few bytes, so the file size can't limit the image size.
+pub const MAX_PIXELS: usize = 400_000_000;
+
+/// Shortest and longest scanlines the new RLE can be used for
+const RLE_WIDTHS: core::ops::RangeInclusive<usize> = 8..=0x7FFF;
+
+/// Why a Radiance file couldn't be decoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// Doesn't start with `#?`
+    BadMagic,
+    /// Malformed header or resolution line
+    BadHeader,
+    /// XYZE pixels, or a rotated image
+    Unsupported,
+    /// RLE data is malformed or runs past the end of a scanline
+    BadRle,
+    /// The file ends before all pixels
+    UnexpectedEof,
+    /// More than [`MAX_PIXELS`], or width × height doesn't fit in memory
+    TooLarge,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::BadMagic => "not a Radiance HDR file",
+            Error::BadHeader => "invalid Radiance header",
+            Error::Unsupported => "unsupported Radiance pixel format or orientation",
+            Error::BadRle => "invalid RLE data",
+            Error::UnexpectedEof => "file is truncated",
+            Error::TooLarge => "image is too large",
+        })
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Header of a Radiance file
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Header {
+    pub width: usize,
+    pub height: usize,
+    /// Product of the `EXPOSURE` lines, or 1. The pixels are decoded as stored, which is
+    /// radiance multiplied by this: divide by it to get radiance in watts/steradian/m².
+    pub exposure: f32,
+}
+
+/// Where the pixels are stored, and where they start
+struct Layout {
+    header: Header,
+    bottom_up: bool,
+    right_to_left: bool,
+    start: usize,
+}
+
+fn parse(data: &[u8]) -> Result<Layout, Error> {
+    if !data.starts_with(b"#?") {
+        return Err(Error::BadMagic);
+    }
+    let mut lines = data.split(|&b| b == b'\n');
+    let mut start = 0;
+    let mut line = |start: &mut usize| {
+        let line = lines.next().ok_or(Error::UnexpectedEof)?;
+        *start += line.len() + 1;
+        if *start > data.len() {
+            return Err(Error::UnexpectedEof);
+        }
+        Ok(String::from_utf8_lossy(line).into_owned())
+    };
+
+    line(&mut start)?;
+    let mut exposure = 1.;
+    loop {
+        let l = line(&mut start)?;
+        let l = l.trim();
+        if l.is_empty() {
+            break;
+        }
+        if let Some(format) = l.strip_prefix("FORMAT=") {
+            match format.trim() {
+                "32-bit_rle_rgbe" => {}
+                "32-bit_rle_xyze" => return Err(Error::Unsupported),
+                _ => return Err(Error::BadHeader),
+            }
+        } else if let Some(value) = l.strip_prefix("EXPOSURE=") {
+            exposure *= value.trim().parse::<f32>().map_err(|_| Error::BadHeader)?;
+        }
+        // 其他变量和注释都忽略
+    }
+
+    let res = line(&mut start)?;
+    let parts: Vec<_> = res.split_ascii_whitespace().collect();
+    let [y, height, x, width] = parts[..] else {
+        return Err(Error::BadHeader);
+    };
+    let size = |s: &str| s.parse::<usize>().map_err(|_| Error::BadHeader);
+    let (height, width) = (size(height)?, size(width)?);
+    let (bottom_up, right_to_left) = match (y, x) {
+        ("-Y", "+X") => (false, false),
+        ("-Y", "-X") => (false, true),
+        ("+Y", "+X") => (true, false),
+        ("+Y", "-X") => (true, true),
+        ("-X" | "+X", "-Y" | "+Y") => return Err(Error::Unsupported),
+        _ => return Err(Error::BadHeader),
+    };
+    Ok(Layout {
+        header: Header {
+            width,
+            height,
+            exposure,
+        },
+        bottom_up,
+        right_to_left,
+        start,
+    })
+}
+
+/// Read the header without decoding the pixels
+pub fn read_header(data: &[u8]) -> Result<Header, Error> {
+    parse(data).map(|l| l.header)
+}
+
+/// Decode a `.hdr` file into floating-point RGB
+pub fn decode(data: &[u8]) -> Result<(Header, Image<RGB<f32>>), Error> {
+    let (header, image) = decode_rgbe(data)?;
+    let buf = image.buf().iter().map(|&px| RGB::from(px)).collect();
+    Ok((header, Image::new(buf, header.width, header.height)))
+}
+
+/// Decode a `.hdr` file, keeping the pixels as they're stored
+pub fn decode_rgbe(data: &[u8]) -> Result<(Header, Image<RGBE8>), Error> {
+    let layout = parse(data)?;
+    let Header { width, height, .. } = layout.header;
+    let count = width.checked_mul(height).ok_or(Error::TooLarge)?;
+    if count > MAX_PIXELS || count > isize::MAX as usize / 12 {
+        return Err(Error::TooLarge);
+    }
+    let data = &data[layout.start..];
+    // 每行至少 4 字节. 旧 RLE 的重复次数可以非常大, 数据量限制不了像素数,
+    // 所以像素数由 MAX_PIXELS 限制, 也不预先分配整张图, 截断的文件不会先申请一大块内存
+    if height > data.len() / 4 {
+        return Err(Error::UnexpectedEof);
+    }
+
+    let mut buf = Vec::new();
+    let mut pos = 0;
+    for _ in 0..height {
+        let row_start = buf.len();
+        let rest = &data[pos..];
+        pos += if RLE_WIDTHS.contains(&width) && rest.starts_with(&[2, 2]) && rest.len() >= 4 {
+            if rest[2] & 0x80 != 0 || ((rest[2] as usize) << 8 | rest[3] as usize) != width {
+                return Err(Error::BadRle);
+            }
+            decode_rle(&rest[4..], width, &mut buf)? + 4
+        } else {
+            decode_old(rest, width, &mut buf)?
+        };
+        if layout.right_to_left {
+            buf[row_start..].reverse();
+        }
+    }
+    if layout.bottom_up {
+        let rows: Vec<_> = buf
+            .chunks_exact(width.max(1))
+            .rev()
+            .flatten()
+            .copied()
+            .collect();
+        buf = rows;
+    }
+    Ok((layout.header, Image::new(buf, width, height)))
+}
+
+/// New RLE: each channel of the scanline in turn, as runs and literals.
+/// Returns the number of bytes read.
+fn decode_rle(data: &[u8], width: usize, out: &mut Vec<RGBE8>) -> Result<usize, Error> {
+    let mut channels = vec![0u8; width * 4];
+    let mut pos = 0;
+    for channel in channels.chunks_exact_mut(width) {
+        let mut x = 0;
+        while x < width {
+            let count = *data.get(pos).ok_or(Error::UnexpectedEof)? as usize;
+            if count > 128 {
+                let count = count - 128;
+                let value = *data.get(pos + 1).ok_or(Error::UnexpectedEof)?;
+                channel
+                    .get_mut(x..x + count)
+                    .ok_or(Error::BadRle)?
+                    .fill(value);
+                x += count;
+                pos += 2;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(Error::BadRle);
+                }
+                let src = data
+                    .get(pos + 1..pos + 1 + count)
+                    .ok_or(Error::UnexpectedEof)?;
+                channel[x..x + count].copy_from_slice(src);
+                x += count;
+                pos += 1 + count;
+            }
+        }
+    }
+    let (r, rest) = channels.split_at(width);
+    let (g, rest) = rest.split_at(width);
+    let (b, e) = rest.split_at(width);
+    out.extend((0..width).map(|x| RGBE8::new(r[x], g[x], b[x], e[x])));
+    Ok(pos)
+}
+
+/// Flat pixels, where `(1, 1, 1, n)` repeats the previous pixel `n` times, and consecutive
+/// repeats count in bigger units (`n << 8`, `n << 16`, ...). Returns the number of bytes read.
+fn decode_old(data: &[u8], width: usize, out: &mut Vec<RGBE8>) -> Result<usize, Error> {
+    let start = out.len();
+    let mut pos = 0;
+    let mut shift = 0;
+    while out.len() - start < width {
+        let px = data.get(pos..pos + 4).ok_or(Error::UnexpectedEof)?;
+        pos += 4;
+        if px[..3] == [1, 1, 1] {
+            let prev = *out[start..].last().ok_or(Error::BadRle)?;
+            let count = (px[3] as usize)
+                .checked_shl(shift)
+                .filter(|&n| shift < 32 && n <= width - (out.len() - start))
+                .ok_or(Error::BadRle)?;
+            out.extend(core::iter::repeat_n(prev, count));
+            shift += 8;
+        } else {
+            out.push(RGBE8::new(px[0], px[1], px[2], px[3]));
+            shift = 0;
+        }
+    }
+    Ok(pos)
+}
+
+/// Encode floating-point RGB as a `.hdr` file
+pub fn encode(image: ImageRef<'_, RGB<f32>>) -> Vec<u8> {
+    let buf: Vec<_> = image.rows().flatten().map(|&px| RGBE8::from(px)).collect();
+    encode_rgbe(ImageRef::new(&buf, image.width(), image.height()))
+}
+
+/// Encode RGBE pixels as a `.hdr` file
+pub fn encode_rgbe(image: ImageRef<'_, RGBE8>) -> Vec<u8> {
+    let (width, height) = (image.width(), image.height());
+    let mut out = Vec::with_capacity(64 + width * height * 4);
+    out.extend_from_slice(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n");
+    out.extend_from_slice(format!("-Y {height} +X {width}\n").as_bytes());
+
+    let mut channel = Vec::with_capacity(width);
+    for row in image.rows() {
+        if !RLE_WIDTHS.contains(&width) {
+            out.extend(row.iter().flat_map(|px| [px.r, px.g, px.b, px.e]));
+            continue;
+        }
+        out.extend_from_slice(&[2, 2, (width >> 8) as u8, width as u8]);
+        for c in 0..4 {
+            channel.clear();
+            channel.extend(row.iter().map(|px| [px.r, px.g, px.b, px.e][c]));
+            encode_rle(&channel, &mut out);
+        }
+    }
+    out
+}
+
+/// Runs of at least 3 bytes become run packets, everything else literals
+fn encode_rle(data: &[u8], out: &mut Vec<u8>) {
+    let run_at = |i: usize| {
+        data[i..]
+            .iter()
+            .take(127)
+            .take_while(|&&b| b == data[i])
+            .count()
+    };
+    let mut i = 0;
+    while i < data.len() {
+        let run = run_at(i);
+        if run >= 3 {
+            out.extend_from_slice(&[128 + run as u8, data[i]]);
+            i += run;
+            continue;
+        }
+        let start = i;
+        while i < data.len() && i - start < 128 && run_at(i) < 3 {
+            i += 1;
+        }
+        out.push((i - start) as u8);
+        out.extend_from_slice(&data[start..i]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(res: &str, pixels: &[u8]) -> Vec<u8> {
+        let mut f =
+            b"#?RGBE\n# comment\nFORMAT=32-bit_rle_rgbe\nEXPOSURE=2\nEXPOSURE= 0.5 \n\n".to_vec();
+        f.extend_from_slice(res.as_bytes());
+        f.push(b'\n');
+        f.extend_from_slice(pixels);
+        f
+    }
+
+    #[test]
+    fn round_trips() {
+        for (width, height) in [(1, 1), (7, 3), (8, 2), (300, 4), (32768, 1)] {
+            let buf: Vec<_> = (0..width * height)
+                .map(|i| {
+                    let v = (i / 5) as f32;
+                    RGB {
+                        r: v * 3.,
+                        g: 0.001 * v,
+                        b: if i % 7 == 0 { 1e6 } else { 0. },
+                    }
+                })
+                .collect();
+            let image = ImageRef::new(&buf, width, height);
+            let file = encode(image);
+            if width == 300 {
+                assert!(file.len() < width * height * 3);
+            }
+            let (header, decoded) = decode(&file).unwrap();
+            assert_eq!(
+                (header.width, header.height, header.exposure),
+                (width, height, 1.)
+            );
+            let expected: Vec<_> = buf.iter().map(|&px| RGB::from(RGBE8::from(px))).collect();
+            assert_eq!(decoded.buf(), expected);
+
+            let (_, rgbe) = decode_rgbe(&file).unwrap();
+            assert_eq!(encode_rgbe(rgbe.view()), file);
+        }
+    }
+
+    #[test]
+    fn old_rle_and_orientation() {
+        let (a, b) = ([10, 20, 30, 128], [1, 2, 3, 129]);
+        // 第一行 a b b b, 第二行用重复标记写成 a a b b
+        let mut pixels = Vec::new();
+        for px in [a, b, b, b, a, [1, 1, 1, 1], b, [1, 1, 1, 1]] {
+            pixels.extend_from_slice(&px);
+        }
+        let f = file("+Y 2 -X 4", &pixels);
+        let (header, image) = decode_rgbe(&f).unwrap();
+        assert_eq!(header.exposure, 1.);
+        let (a, b) = (RGBE8::new(10, 20, 30, 128), RGBE8::new(1, 2, 3, 129));
+        // 从下往上, 从右往左
+        assert_eq!(image.buf(), [b, b, a, a, b, b, b, a]);
+
+        // 连续的重复标记以 256 为单位
+        let mut pixels = vec![5, 5, 5, 130];
+        pixels.extend_from_slice(&[1, 1, 1, 4, 1, 1, 1, 1]);
+        let (_, image) = decode_rgbe(&file("-Y 1 +X 261", &pixels)).unwrap();
+        assert_eq!(image.buf(), [RGBE8::new(5, 5, 5, 130); 261]);
+
+        let f = b"#?RADIANCE\nEXPOSURE=4\n\n-Y 1 +X 1\n\x80\x80\x80\x81";
+        let (header, image) = decode(f).unwrap();
+        assert_eq!(header.exposure, 4.);
+        assert_eq!(
+            image.buf(),
+            [RGB {
+                r: 1.,
+                g: 1.,
+                b: 1.
+            }]
+        );
+    }
+
+    #[test]
+    fn new_rle() {
+        let mut row = vec![2, 2, 0, 10];
+        // r: 10 个 7; g: 0..10 原样; b: 5 个 1 和 5 个 2; e: 10 个 128
+        row.extend_from_slice(&[138, 7, 10, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        row.extend_from_slice(&[133, 1, 5, 2, 2, 2, 2, 2, 138, 128]);
+        let (_, image) = decode_rgbe(&file("-Y 1 +X 10", &row)).unwrap();
+        let expected: Vec<_> = (0..10)
+            .map(|x| RGBE8::new(7, x, 1 + (x >= 5) as u8, 128))
+            .collect();
+        assert_eq!(image.buf(), expected);
+
+        let mut out = Vec::new();
+        encode_rle(&[1, 2, 3, 3, 3, 3, 4, 4, 5], &mut out);
+        assert_eq!(out, [2, 1, 2, 132, 3, 3, 4, 4, 5]);
+        out.clear();
+        encode_rle(&[9; 300], &mut out);
+        assert_eq!(out, [255, 9, 255, 9, 128 + 46, 9]);
+    }
+
+    #[test]
+    fn bad_headers() {
+        assert_eq!(decode(b"P6\n"), Err(Error::BadMagic));
+        assert_eq!(
+            decode(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n"),
+            Err(Error::UnexpectedEof)
+        );
+        assert_eq!(
+            decode(b"#?RADIANCE\nFORMAT=16-bit\n\n-Y 1 +X 1\n\0\0\0\0"),
+            Err(Error::BadHeader)
+        );
+        assert_eq!(
+            decode(b"#?RADIANCE\nEXPOSURE=x\n\n-Y 1 +X 1\n\0\0\0\0"),
+            Err(Error::BadHeader)
+        );
+        let bad = |res: &str| decode_rgbe(&file(res, &[0; 4])).map(|_| ());
+        assert_eq!(bad("-Y 1 +X"), Err(Error::BadHeader));
+        assert_eq!(bad("-Y 1 *X 1"), Err(Error::BadHeader));
+        assert_eq!(bad("-Y 1 +X 1 +Z 1"), Err(Error::BadHeader));
+        assert_eq!(bad("-Y -1 +X 1"), Err(Error::BadHeader));
+        assert_eq!(
+            bad("-Y 1 +X 99999999999999999999999"),
+            Err(Error::BadHeader)
+        );
+        // 没有分辨率行
+        assert_eq!(
+            decode(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n"),
+            Err(Error::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        let bad = |res: &str| decode_rgbe(&file(res, &[0; 4])).map(|_| ());
+        assert_eq!(bad("+X 1 -Y 1"), Err(Error::Unsupported));
+        assert_eq!(bad("-X 1 +Y 1"), Err(Error::Unsupported));
+        assert_eq!(
+            decode(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\0\0\0\0"),
+            Err(Error::Unsupported)
+        );
+    }
+
+    #[test]
+    fn old_rle_errors() {
+        let bad = |res: &str, px: &[u8]| decode_rgbe(&file(res, px)).map(|_| ());
+        // 行首的重复没有上一个像素
+        assert_eq!(
+            bad("-Y 1 +X 2", &[1, 1, 1, 1, 0, 0, 0, 0]),
+            Err(Error::BadRle)
+        );
+        // 重复超出行尾, 包括连续重复的放大
+        assert_eq!(
+            bad("-Y 1 +X 2", &[3, 3, 3, 3, 1, 1, 1, 2]),
+            Err(Error::BadRle)
+        );
+        assert_eq!(
+            bad("-Y 1 +X 300", &[3, 3, 3, 3, 1, 1, 1, 2, 1, 1, 1, 2]),
+            Err(Error::BadRle)
+        );
+        assert!(bad("-Y 1 +X 514", &[3, 3, 3, 3, 1, 1, 1, 1, 1, 1, 1, 2]).is_ok());
+    }
+
+    #[test]
+    fn new_rle_bounds() {
+        let bad = |res: &str, px: &[u8]| decode_rgbe(&file(res, px)).map(|_| ());
+        // 宽度不符, 长度为 0, 超出行尾
+        assert_eq!(
+            bad("-Y 1 +X 8", &[2, 2, 0, 9, 0, 0, 0, 0]),
+            Err(Error::BadRle)
+        );
+        assert_eq!(
+            bad("-Y 1 +X 8", &[2, 2, 0x80, 8, 0, 0, 0, 0]),
+            Err(Error::BadRle)
+        );
+        assert_eq!(
+            bad("-Y 1 +X 8", &[2, 2, 0, 8, 0, 0, 0, 0]),
+            Err(Error::BadRle)
+        );
+        assert_eq!(
+            bad("-Y 1 +X 8", &[2, 2, 0, 8, 137, 0, 0, 0]),
+            Err(Error::BadRle)
+        );
+        assert_eq!(
+            bad("-Y 1 +X 8", &[2, 2, 0, 8, 9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            Err(Error::BadRle)
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let bad = |res: &str, px: &[u8]| decode_rgbe(&file(res, px)).map(|_| ());
+        assert_eq!(bad("-Y 2 +X 1", &[0; 7]), Err(Error::UnexpectedEof));
+        assert_eq!(bad("-Y 1 +X 1", &[0; 3]), Err(Error::UnexpectedEof));
+        assert_eq!(
+            bad("-Y 1 +X 8", &[2, 2, 0, 8, 136, 0, 136, 0]),
+            Err(Error::UnexpectedEof)
+        );
+        assert_eq!(
+            bad("-Y 1 +X 8", &[2, 2, 0, 8, 8, 1, 2, 3]),
+            Err(Error::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn huge_header_tiny_data() {
+        let bad = |res: &str, px: &[u8]| decode_rgbe(&file(res, px)).map(|_| ());
+        assert_eq!(
+            bad("-Y 20000 +X 20000", &[0; 64]),
+            Err(Error::UnexpectedEof)
+        );
+        // 一行很宽, 数据很少: 解码到哪里分配到哪里
+        assert_eq!(bad("-Y 1 +X 100000000", &[0; 8]), Err(Error::UnexpectedEof));
+        assert_eq!(
+            bad("-Y 4 +X 100000000", &[9; 16]),
+            Err(Error::UnexpectedEof)
+        );
+        // 旧 RLE 几个字节就能重复出很多像素, 只能靠像素数上限挡住
+        assert_eq!(bad("-Y 1 +X 400000000", &[9; 4]), Err(Error::UnexpectedEof));
+        assert_eq!(bad("-Y 1 +X 400000001", &[9; 4]), Err(Error::TooLarge));
+        assert_eq!(bad("-Y 20000 +X 20001", &[9; 4]), Err(Error::TooLarge));
+        assert_eq!(
+            bad("-Y 1 +X 1000000000", &[9, 9, 9, 9, 1, 1, 1, 255]),
+            Err(Error::TooLarge)
+        );
+        assert_eq!(
+            bad("-Y 1 +X 30000", &[2, 2, 0x75, 0x30, 0xFF, 0]),
+            Err(Error::UnexpectedEof)
+        );
+        // 像素数溢出
+        assert_eq!(
+            bad("-Y 4294967296 +X 4294967296", &[0; 4]),
+            Err(Error::TooLarge)
+        );
+        assert_eq!(
+            bad("-Y 1 +X 1000000000000000000", &[0; 4]),
+            Err(Error::TooLarge)
+        );
+    }
+}
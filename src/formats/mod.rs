@@ -0,0 +1,14 @@
+//! Readers and writers for image file formats.
+//!
+//! Decoders return a `DynamicImage` with the pixel type closest to the file's own layout,
+//! so nothing is lost; convert it with `to_rgba8()` and similar if you need one type.
+
+pub mod bmp;
+pub mod farbfeld;
+pub mod gif;
+pub mod hdr;
+pub mod netpbm;
+pub mod png;
+pub mod qoi;
+pub mod tga;
+pub mod y4m;
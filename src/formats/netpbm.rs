@@ -0,0 +1,737 @@
+//! Netpbm formats: PBM (`P1`, `P4`), PGM (`P2`, `P5`), PPM (`P3`, `P6`) and PAM (`P7`).
+//!
+//! Samples with a maxval up to 255 are scaled to 8 bits, larger ones to 16 bits.
+//! PBM is decoded as `GRAY8` with black = 0 and white = 255.
+//!
+//! ```rust
+//! use cr::formats::netpbm::{self, Kind};
+//! use cr::{DynamicImage, Image, RGB8};
+//!
+//! let image = DynamicImage::from(Image::filled(RGB8::new(1, 2, 3), 2, 1));
+//! let file = netpbm::encode(&image, Kind::Pixmap);
+//! assert_eq!(file, b"P6\n2 1\n255\n\x01\x02\x03\x01\x02\x03");
+//! assert_eq!(netpbm::decode(&file).unwrap(), image);
+//!
+//! let ascii = netpbm::decode(b"P2 # comment\n2 1 15\n0 15").unwrap();
+//! assert_eq!(ascii.to_gray8().buf(), [cr::alt::Gray(0), cr::alt::Gray(255)]);
+//! ```
+
+use crate::alt::{Gray, GrayAlpha};
+use crate::{ComponentOrder, ComponentSlice, DynamicImage, Image, IntoPixels, RGB, RGBA};
+use core::fmt;
+
+/// Netpbm variant, by its magic number
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Kind {
+    /// `P1`: black and white, ASCII
+    BitmapAscii,
+    /// `P2`: grayscale, ASCII
+    GraymapAscii,
+    /// `P3`: RGB, ASCII
+    PixmapAscii,
+    /// `P4`: black and white, 1 bit per pixel
+    Bitmap,
+    /// `P5`: grayscale, binary
+    Graymap,
+    /// `P6`: RGB, binary
+    Pixmap,
+    /// `P7`: PAM, binary, with any of the standard tuple types
+    Pam,
+}
+
+impl Kind {
+    /// The `P1`..`P7` digit
+    #[inline]
+    fn digit(self) -> u8 {
+        match self {
+            Kind::BitmapAscii => b'1',
+            Kind::GraymapAscii => b'2',
+            Kind::PixmapAscii => b'3',
+            Kind::Bitmap => b'4',
+            Kind::Graymap => b'5',
+            Kind::Pixmap => b'6',
+            Kind::Pam => b'7',
+        }
+    }
+
+    #[inline]
+    fn is_ascii(self) -> bool {
+        matches!(
+            self,
+            Kind::BitmapAscii | Kind::GraymapAscii | Kind::PixmapAscii
+        )
+    }
+}
+
+/// Why a Netpbm file couldn't be decoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// Doesn't start with `P1`..`P7`
+    BadMagic,
+    /// Malformed header, zero size, or fields that don't fit together
+    BadHeader,
+    /// Maxval isn't in `1..=65535`
+    BadMaxval,
+    /// PAM tuple type isn't one of the standard ones
+    BadTupleType,
+    /// Sample larger than maxval, or not a number in an ASCII file
+    BadSample,
+    /// The file ends before all samples
+    UnexpectedEof,
+    /// Width × height × depth doesn't fit in memory
+    TooLarge,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::BadMagic => "not a Netpbm file",
+            Error::BadHeader => "invalid Netpbm header",
+            Error::BadMaxval => "maxval must be between 1 and 65535",
+            Error::BadTupleType => "unsupported PAM tuple type",
+            Error::BadSample => "invalid sample",
+            Error::UnexpectedEof => "file is truncated",
+            Error::TooLarge => "image is too large",
+        })
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Channels of the decoded image
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Layout {
+    Gray,
+    GrayAlpha,
+    Rgb,
+    Rgba,
+}
+
+impl Layout {
+    #[inline]
+    fn depth(self) -> usize {
+        match self {
+            Layout::Gray => 1,
+            Layout::GrayAlpha => 2,
+            Layout::Rgb => 3,
+            Layout::Rgba => 4,
+        }
+    }
+}
+
+struct Header {
+    kind: Kind,
+    width: usize,
+    height: usize,
+    maxval: u32,
+    layout: Layout,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    #[inline]
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    /// Whitespace and `#` comments between header fields
+    fn skip_space(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == b'#' {
+                while !matches!(self.peek(), None | Some(b'\n' | b'\r')) {
+                    self.pos += 1;
+                }
+            } else if c.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Decimal number, after optional whitespace and comments
+    fn number(&mut self, err: Error) -> Result<u32, Error> {
+        self.skip_space();
+        let start = self.pos;
+        let mut n = 0u32;
+        while let Some(c @ b'0'..=b'9') = self.peek() {
+            n = n
+                .checked_mul(10)
+                .and_then(|n| n.checked_add((c - b'0') as u32))
+                .ok_or(err)?;
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(if self.peek().is_none() {
+                Error::UnexpectedEof
+            } else {
+                err
+            });
+        }
+        match self.peek() {
+            None => Ok(n),
+            Some(c) if c.is_ascii_whitespace() || c == b'#' => Ok(n),
+            Some(_) => Err(err),
+        }
+    }
+
+    /// Exactly one whitespace character, which ends the header of binary formats
+    fn single_space(&mut self) -> Result<(), Error> {
+        match self.peek() {
+            Some(c) if c.is_ascii_whitespace() => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(_) => Err(Error::BadHeader),
+            None => Err(Error::UnexpectedEof),
+        }
+    }
+
+    /// One PAM header line, without the line break
+    fn line(&mut self) -> Result<&'a [u8], Error> {
+        let rest = &self.data[self.pos..];
+        let len = rest
+            .iter()
+            .position(|&c| c == b'\n')
+            .ok_or(Error::UnexpectedEof)?;
+        self.pos += len + 1;
+        Ok(&rest[..len])
+    }
+
+    #[inline]
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+}
+
+fn read_header(r: &mut Reader<'_>) -> Result<Header, Error> {
+    let kind = match r.data {
+        [b'P', b'1', ..] => Kind::BitmapAscii,
+        [b'P', b'2', ..] => Kind::GraymapAscii,
+        [b'P', b'3', ..] => Kind::PixmapAscii,
+        [b'P', b'4', ..] => Kind::Bitmap,
+        [b'P', b'5', ..] => Kind::Graymap,
+        [b'P', b'6', ..] => Kind::Pixmap,
+        [b'P', b'7', ..] => Kind::Pam,
+        _ => return Err(Error::BadMagic),
+    };
+    r.pos = 2;
+    if kind == Kind::Pam {
+        return read_pam_header(r);
+    }
+
+    let width = r.number(Error::BadHeader)? as usize;
+    let height = r.number(Error::BadHeader)? as usize;
+    let (maxval, layout) = match kind {
+        Kind::BitmapAscii | Kind::Bitmap => (1, Layout::Gray),
+        Kind::GraymapAscii | Kind::Graymap => (r.number(Error::BadMaxval)?, Layout::Gray),
+        _ => (r.number(Error::BadMaxval)?, Layout::Rgb),
+    };
+    if !kind.is_ascii() {
+        r.single_space()?;
+    }
+    check_header(Header {
+        kind,
+        width,
+        height,
+        maxval,
+        layout,
+    })
+}
+
+fn read_pam_header(r: &mut Reader<'_>) -> Result<Header, Error> {
+    r.line()?;
+    let (mut width, mut height, mut depth, mut maxval) = (None, None, None, None);
+    let mut tuple_type = String::new();
+    loop {
+        let line = r.line()?;
+        let line = core::str::from_utf8(line)
+            .map_err(|_| Error::BadHeader)?
+            .trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let value = value.trim();
+        let number = || value.parse::<u32>().map_err(|_| Error::BadHeader);
+        match key {
+            "WIDTH" => width = Some(number()?),
+            "HEIGHT" => height = Some(number()?),
+            "DEPTH" => depth = Some(number()?),
+            "MAXVAL" => maxval = Some(value.parse::<u32>().map_err(|_| Error::BadMaxval)?),
+            "TUPLTYPE" => {
+                // 多个 TUPLTYPE 行用空格连接
+                if !tuple_type.is_empty() {
+                    tuple_type.push(' ');
+                }
+                tuple_type.push_str(value);
+            }
+            "ENDHDR" if value.is_empty() => break,
+            _ => return Err(Error::BadHeader),
+        }
+    }
+    let (Some(width), Some(height), Some(depth), Some(maxval)) = (width, height, depth, maxval)
+    else {
+        return Err(Error::BadHeader);
+    };
+
+    let (layout, bitmap) = match tuple_type.as_str() {
+        "BLACKANDWHITE" => (Layout::Gray, true),
+        "GRAYSCALE" => (Layout::Gray, false),
+        "RGB" => (Layout::Rgb, false),
+        "BLACKANDWHITE_ALPHA" => (Layout::GrayAlpha, true),
+        "GRAYSCALE_ALPHA" => (Layout::GrayAlpha, false),
+        "RGB_ALPHA" => (Layout::Rgba, false),
+        "" => match depth {
+            1 => (Layout::Gray, false),
+            2 => (Layout::GrayAlpha, false),
+            3 => (Layout::Rgb, false),
+            4 => (Layout::Rgba, false),
+            _ => return Err(Error::BadTupleType),
+        },
+        _ => return Err(Error::BadTupleType),
+    };
+    if layout.depth() != depth as usize || (bitmap && maxval != 1) {
+        return Err(Error::BadHeader);
+    }
+    check_header(Header {
+        kind: Kind::Pam,
+        width: width as usize,
+        height: height as usize,
+        maxval,
+        layout,
+    })
+}
+
+fn check_header(header: Header) -> Result<Header, Error> {
+    if header.width == 0 || header.height == 0 {
+        return Err(Error::BadHeader);
+    }
+    if !(1..=65535).contains(&header.maxval) {
+        return Err(Error::BadMaxval);
+    }
+    Ok(header)
+}
+
+/// Decode any Netpbm file. Only the first image of multi-image files is read.
+pub fn decode(data: &[u8]) -> Result<DynamicImage, Error> {
+    let mut r = Reader { data, pos: 0 };
+    let header = read_header(&mut r)?;
+    let count = header
+        .width
+        .checked_mul(header.height)
+        .and_then(|n| n.checked_mul(header.layout.depth()))
+        .ok_or(Error::TooLarge)?;
+
+    let samples = match header.kind {
+        Kind::BitmapAscii => read_ascii_bits(&mut r, count)?,
+        Kind::Bitmap => read_packed_bits(r.rest(), header.width, header.height)?,
+        Kind::GraymapAscii | Kind::PixmapAscii => read_ascii(&mut r, count, header.maxval)?,
+        _ => read_binary(r.rest(), count, header.maxval)?,
+    };
+    Ok(into_image(&header, samples))
+}
+
+/// PBM 里 1 是黑色; 反过来作为 maxval = 1 的灰度
+fn read_ascii_bits(r: &mut Reader<'_>, count: usize) -> Result<Vec<u16>, Error> {
+    if count > r.rest().len() {
+        return Err(Error::UnexpectedEof);
+    }
+    let mut samples = Vec::with_capacity(count);
+    while samples.len() < count {
+        r.skip_space();
+        match r.peek() {
+            Some(b'0') => samples.push(1),
+            Some(b'1') => samples.push(0),
+            Some(_) => return Err(Error::BadSample),
+            None => return Err(Error::UnexpectedEof),
+        }
+        r.pos += 1;
+    }
+    Ok(samples)
+}
+
+fn read_packed_bits(data: &[u8], width: usize, height: usize) -> Result<Vec<u16>, Error> {
+    let row_bytes = width.div_ceil(8);
+    let len = row_bytes.checked_mul(height).ok_or(Error::TooLarge)?;
+    let data = data.get(..len).ok_or(Error::UnexpectedEof)?;
+    Ok(data
+        .chunks_exact(row_bytes)
+        .flat_map(|row| (0..width).map(move |x| u16::from(row[x / 8] & (0x80 >> (x % 8)) == 0)))
+        .collect())
+}
+
+fn read_ascii(r: &mut Reader<'_>, count: usize, maxval: u32) -> Result<Vec<u16>, Error> {
+    if count > r.rest().len() {
+        return Err(Error::UnexpectedEof);
+    }
+    (0..count)
+        .map(|_| match r.number(Error::BadSample)? {
+            v if v <= maxval => Ok(v as u16),
+            _ => Err(Error::BadSample),
+        })
+        .collect()
+}
+
+fn read_binary(data: &[u8], count: usize, maxval: u32) -> Result<Vec<u16>, Error> {
+    let size = if maxval > 255 { 2 } else { 1 };
+    let len = count.checked_mul(size).ok_or(Error::TooLarge)?;
+    let data = data.get(..len).ok_or(Error::UnexpectedEof)?;
+    data.chunks_exact(size)
+        .map(|s| {
+            let v = s.iter().fold(0u32, |v, &b| (v << 8) | b as u32);
+            if v <= maxval {
+                Ok(v as u16)
+            } else {
+                Err(Error::BadSample)
+            }
+        })
+        .collect()
+}
+
+/// Scales samples to 8 or 16 bits and wraps them in the matching pixel type
+fn into_image(header: &Header, samples: Vec<u16>) -> DynamicImage {
+    let (w, h, maxval) = (header.width, header.height, header.maxval);
+    macro_rules! image {
+        ($t:ty, $max:expr, $($layout:ident => $variant:ident($px:ident)),+) => {{
+            let samples: Vec<$t> = if maxval == $max {
+                samples.into_iter().map(|v| v as $t).collect()
+            } else {
+                samples
+                    .into_iter()
+                    .map(|v| ((v as u32 * $max + maxval / 2) / maxval) as $t)
+                    .collect()
+            };
+            match header.layout {
+                $(Layout::$layout => DynamicImage::$variant(Image::new(
+                    samples.into_pixels::<$px<$t>>().expect("whole pixels"),
+                    w,
+                    h,
+                )),)+
+            }
+        }};
+    }
+    if maxval <= 255 {
+        image!(u8, 255, Gray => Gray8(Gray), GrayAlpha => GrayAlpha8(GrayAlpha), Rgb => Rgb8(RGB), Rgba => Rgba8(RGBA))
+    } else {
+        image!(u16, 65535, Gray => Gray16(Gray), GrayAlpha => GrayAlpha16(GrayAlpha), Rgb => Rgb16(RGB), Rgba => Rgba16(RGBA))
+    }
+}
+
+/// All components of all pixels, row by row
+fn samples<P, T>(image: &Image<P>) -> Vec<u16>
+where
+    [P]: ComponentSlice<T>,
+    T: Copy + Into<u16>,
+{
+    image
+        .rows()
+        .flat_map(|row| ComponentSlice::as_slice(row).iter().map(|&c| c.into()))
+        .collect()
+}
+
+/// Encode the image. It's converted to what the format can store:
+/// PBM thresholds gray at 50%, PGM and PPM drop alpha, and 16-bit images stay 16-bit
+/// (except in PBM). PAM keeps gray, alpha and depth as they are.
+pub fn encode(image: &DynamicImage, kind: Kind) -> Vec<u8> {
+    let format = image.format();
+    let wide = format.depth == 16;
+    let has_alpha = format.alpha != crate::AlphaKind::None;
+    let gray = matches!(
+        format.order,
+        ComponentOrder::Gray | ComponentOrder::GrayAlpha
+    );
+
+    let (layout, maxval, samples) = match kind {
+        Kind::BitmapAscii | Kind::Bitmap => {
+            let gray = image.to_gray8();
+            let bits = gray
+                .rows()
+                .flatten()
+                .map(|px| u16::from(px.0 < 128))
+                .collect();
+            (Layout::Gray, 1, bits)
+        }
+        Kind::GraymapAscii | Kind::Graymap => gray_samples(image, wide),
+        Kind::PixmapAscii | Kind::Pixmap => rgb_samples(image, wide),
+        Kind::Pam => match (gray, has_alpha) {
+            (true, false) => gray_samples(image, wide),
+            (false, false) => rgb_samples(image, wide),
+            (true, true) if wide => (
+                Layout::GrayAlpha,
+                65535,
+                samples(&image.to_image::<GrayAlpha<u16>>()),
+            ),
+            (true, true) => (
+                Layout::GrayAlpha,
+                255,
+                samples(&image.to_image::<GrayAlpha<u8>>()),
+            ),
+            (false, true) if wide => (Layout::Rgba, 65535, samples(&image.to_rgba16())),
+            (false, true) => (Layout::Rgba, 255, samples(&image.to_rgba8())),
+        },
+    };
+
+    let (width, height) = (image.width(), image.height());
+    let mut out = Vec::with_capacity(samples.len() * if wide { 2 } else { 1 } + 64);
+    out.extend_from_slice(&[b'P', kind.digit(), b'\n']);
+    if kind == Kind::Pam {
+        let tuple_type = match layout {
+            Layout::Gray => "GRAYSCALE",
+            Layout::GrayAlpha => "GRAYSCALE_ALPHA",
+            Layout::Rgb => "RGB",
+            Layout::Rgba => "RGB_ALPHA",
+        };
+        out.extend_from_slice(
+            format!(
+                "WIDTH {}\nHEIGHT {}\nDEPTH {}\nMAXVAL {}\nTUPLTYPE {}\nENDHDR\n",
+                width,
+                height,
+                layout.depth(),
+                maxval,
+                tuple_type
+            )
+            .as_bytes(),
+        );
+    } else if maxval == 1 {
+        out.extend_from_slice(format!("{} {}\n", width, height).as_bytes());
+    } else {
+        out.extend_from_slice(format!("{} {}\n{}\n", width, height, maxval).as_bytes());
+    }
+
+    match kind {
+        Kind::Bitmap => {
+            for row in samples.chunks_exact(width.max(1)) {
+                for byte in row.chunks(8) {
+                    out.push(
+                        byte.iter()
+                            .enumerate()
+                            .fold(0, |b, (i, &bit)| b | ((bit as u8) << (7 - i))),
+                    );
+                }
+            }
+        }
+        _ if kind.is_ascii() => write_ascii(&mut out, &samples),
+        _ if maxval > 255 => out.extend(samples.iter().flat_map(|s| s.to_be_bytes())),
+        _ => out.extend(samples.iter().map(|&s| s as u8)),
+    }
+    out
+}
+
+fn gray_samples(image: &DynamicImage, wide: bool) -> (Layout, u32, Vec<u16>) {
+    if wide {
+        (Layout::Gray, 65535, samples(&image.to_gray16()))
+    } else {
+        (Layout::Gray, 255, samples(&image.to_gray8()))
+    }
+}
+
+fn rgb_samples(image: &DynamicImage, wide: bool) -> (Layout, u32, Vec<u16>) {
+    if wide {
+        (Layout::Rgb, 65535, samples(&image.to_rgb16()))
+    } else {
+        (Layout::Rgb, 255, samples(&image.to_rgb8()))
+    }
+}
+
+/// Samples separated by spaces, with lines no longer than 70 characters as the spec asks
+fn write_ascii(out: &mut Vec<u8>, samples: &[u16]) {
+    let mut line_len = 0;
+    for s in samples {
+        let s = s.to_string();
+        if line_len > 0 && line_len + 1 + s.len() > 70 {
+            out.push(b'\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            out.push(b' ');
+            line_len += 1;
+        }
+        out.extend_from_slice(s.as_bytes());
+        line_len += s.len();
+    }
+    out.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alt::{GRAY16, GRAY8, GRAYA8};
+    use crate::{RGB16, RGB8, RGBA16, RGBA8};
+
+    const KINDS: [Kind; 7] = [
+        Kind::BitmapAscii,
+        Kind::GraymapAscii,
+        Kind::PixmapAscii,
+        Kind::Bitmap,
+        Kind::Graymap,
+        Kind::Pixmap,
+        Kind::Pam,
+    ];
+
+    #[test]
+    fn round_trips() {
+        let rgb: Vec<_> = (0..30u8).map(|i| RGB8::new(i * 8, 255 - i, i)).collect();
+        let rgb = DynamicImage::from(Image::new(rgb, 10, 3));
+        let rgb16 = DynamicImage::from(Image::new(
+            (0..6u16).map(|i| RGB16::new(i * 9000, 1, 65535)).collect(),
+            3,
+            2,
+        ));
+        let rgba = DynamicImage::from(Image::filled(RGBA8::new(1, 2, 3, 4), 2, 2));
+        let rgba16 = DynamicImage::from(Image::filled(RGBA16::new(1, 2, 3, 40000), 1, 1));
+        let graya = DynamicImage::from(Image::filled(GRAYA8::new(9, 200), 3, 1));
+        let gray16 = DynamicImage::from(Image::filled(GRAY16::new(300), 1, 2));
+
+        for kind in [Kind::PixmapAscii, Kind::Pixmap, Kind::Pam] {
+            assert_eq!(decode(&encode(&rgb, kind)).unwrap(), rgb);
+            assert_eq!(decode(&encode(&rgb16, kind)).unwrap(), rgb16);
+        }
+        for kind in [Kind::GraymapAscii, Kind::Graymap, Kind::Pam] {
+            assert_eq!(decode(&encode(&gray16, kind)).unwrap(), gray16);
+        }
+        for img in [&rgba, &rgba16, &graya] {
+            assert_eq!(decode(&encode(img, Kind::Pam)).unwrap(), *img);
+        }
+
+        // 1-bit: 阈值 50%
+        let bw: Vec<_> = (0..19u8)
+            .map(|i| GRAY8::new(if i % 3 == 0 { 0 } else { 255 }))
+            .collect();
+        let bw = DynamicImage::from(Image::new(bw, 19, 1));
+        for kind in [Kind::BitmapAscii, Kind::Bitmap] {
+            assert_eq!(decode(&encode(&bw, kind)).unwrap(), bw);
+        }
+        for kind in KINDS {
+            assert!(decode(&encode(&rgba, kind)).is_ok(), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn headers_and_scaling() {
+        let img = decode(b"P1\n# tiny\n3 2\n0 1 0\n110").unwrap();
+        assert_eq!(
+            img.as_image::<GRAY8>().unwrap().buf(),
+            [255, 0, 255, 0, 0, 255].map(GRAY8::new)
+        );
+        let img = decode(b"P4 9 1\n\x80\x80").unwrap();
+        assert_eq!(img.to_gray8().buf()[0], GRAY8::new(0));
+        assert_eq!(img.to_gray8().buf()[8], GRAY8::new(0));
+        assert_eq!(img.to_gray8().buf()[1], GRAY8::new(255));
+
+        let img = decode(b"P6 1 1 1000\n\x01\xF4\x03\xE8\x00\x00").unwrap();
+        assert_eq!(
+            img.as_image::<RGB16>().unwrap().buf(),
+            [RGB16::new(32768, 65535, 0)]
+        );
+        let img = decode(b"P5\n2 1\n3\n\x00\x02").unwrap();
+        assert_eq!(
+            img.as_image::<GRAY8>().unwrap().buf(),
+            [GRAY8::new(0), GRAY8::new(170)]
+        );
+
+        let pam = b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 2\nMAXVAL 1\nTUPLTYPE BLACKANDWHITE_ALPHA\nENDHDR\n\x01\x00";
+        assert_eq!(
+            decode(pam).unwrap().as_image::<GRAYA8>().unwrap().buf(),
+            [GRAYA8::new(255, 0)]
+        );
+    }
+
+    #[test]
+    fn bad_headers() {
+        assert_eq!(decode(b"P9 1 1 255\n\0"), Err(Error::BadMagic));
+        assert_eq!(decode(b"P"), Err(Error::BadMagic));
+        assert_eq!(decode(b"P5 0 1 255\n"), Err(Error::BadHeader));
+        assert_eq!(decode(b"P5 1x 1 255\n\0"), Err(Error::BadHeader));
+        // 二进制格式的头后面必须正好一个空白字符
+        assert_eq!(decode(b"P5 1 1 255x\0"), Err(Error::BadMaxval));
+        assert_eq!(decode(b"P5 1 1 255"), Err(Error::UnexpectedEof));
+        // 超过 u32 的数
+        assert_eq!(decode(b"P5 4294967296 1 255\n"), Err(Error::BadHeader));
+        assert_eq!(decode(b"P5 1 1"), Err(Error::UnexpectedEof));
+    }
+
+    #[test]
+    fn bad_maxval_and_samples() {
+        assert_eq!(decode(b"P5 1 1 70000\n\0\0"), Err(Error::BadMaxval));
+        assert_eq!(decode(b"P5 1 1 0\n\0"), Err(Error::BadMaxval));
+        // 样本不能超过 maxval
+        assert_eq!(decode(b"P5 2 1 100\n\x10\x65"), Err(Error::BadSample));
+        assert_eq!(decode(b"P5 1 1 300\n\x01\x2D"), Err(Error::BadSample));
+        assert_eq!(decode(b"P2 2 1 100\n1 x"), Err(Error::BadSample));
+        assert_eq!(decode(b"P2 1 1 100\n101"), Err(Error::BadSample));
+        assert_eq!(decode(b"P1 2 1\n0 2"), Err(Error::BadSample));
+    }
+
+    #[test]
+    fn truncated_data() {
+        assert_eq!(decode(b"P6 2 1 255\n\0\0\0"), Err(Error::UnexpectedEof));
+        assert_eq!(decode(b"P6 1 1 256\n\0\0\0\0\0"), Err(Error::UnexpectedEof));
+        assert_eq!(decode(b"P3 1 1 255\n1 2"), Err(Error::UnexpectedEof));
+        assert_eq!(decode(b"P1 3 1\n0 1"), Err(Error::UnexpectedEof));
+        assert_eq!(decode(b"P4 9 2\n\0\0\0"), Err(Error::UnexpectedEof));
+        assert_eq!(
+            decode(b"P7\nWIDTH 2\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nENDHDR\n\0\0\0\0"),
+            Err(Error::UnexpectedEof)
+        );
+        // PAM 头没有 ENDHDR
+        assert_eq!(
+            decode(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 255\n"),
+            Err(Error::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn pam_tuple_types() {
+        assert_eq!(
+            decode(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 255\nTUPLTYPE CMYK\nENDHDR\n\0"),
+            Err(Error::BadTupleType)
+        );
+        assert_eq!(
+            decode(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 5\nMAXVAL 255\nENDHDR\n\0\0\0\0\0"),
+            Err(Error::BadTupleType)
+        );
+        assert_eq!(
+            decode(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 3\nMAXVAL 255\nTUPLTYPE GRAYSCALE\nENDHDR\n\0"),
+            Err(Error::BadHeader)
+        );
+        assert_eq!(
+            decode(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 2\nTUPLTYPE BLACKANDWHITE\nENDHDR\n\0"),
+            Err(Error::BadHeader)
+        );
+        assert_eq!(
+            decode(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nENDHDR\n\0"),
+            Err(Error::BadHeader)
+        );
+        assert_eq!(
+            decode(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 255\nCOLOR red\nENDHDR\n\0"),
+            Err(Error::BadHeader)
+        );
+    }
+
+    #[test]
+    fn huge_header_tiny_data() {
+        // 数据不够时不能先按头里的尺寸分配内存
+        for kind in b"123456" {
+            let file = format!("P{} 1000000000 1000000000 255\n\0\0\0\0", *kind as char);
+            assert_eq!(
+                decode(file.as_bytes()),
+                Err(Error::UnexpectedEof),
+                "P{}",
+                *kind as char
+            );
+        }
+        assert_eq!(
+            decode(b"P7\nWIDTH 99999999\nHEIGHT 99999999\nDEPTH 4\nMAXVAL 65535\nENDHDR\n\0"),
+            Err(Error::UnexpectedEof)
+        );
+        // 样本数溢出 usize
+        assert_eq!(
+            decode(b"P7\nWIDTH 4294967295\nHEIGHT 4294967295\nDEPTH 4\nMAXVAL 255\nENDHDR\n"),
+            Err(Error::TooLarge)
+        );
+    }
+}
@@ -0,0 +1,856 @@
+use super::{crc32, paeth, passes, zlib, ColorType, Error, Info, RenderingIntent, SIGNATURE};
+use crate::alt::{Gray, GrayAlpha};
+use crate::{DynamicImage, Image, IntoPixels, RGB, RGBA};
+
+/// Chunks after the signature, with their CRC checked
+struct Chunks<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Chunks<'a> {
+    fn chunk(&mut self) -> Result<([u8; 4], &'a [u8]), Error> {
+        let rest = &self.data[self.pos..];
+        let head = rest.get(..8).ok_or(Error::UnexpectedEof)?;
+        let len = u32::from_be_bytes([head[0], head[1], head[2], head[3]]);
+        let kind = [head[4], head[5], head[6], head[7]];
+        if len > i32::MAX as u32 || !kind.iter().all(u8::is_ascii_alphabetic) {
+            return Err(Error::BadChunk);
+        }
+        let len = len as usize;
+        let body = rest.get(8..8 + len + 4).ok_or(Error::UnexpectedEof)?;
+        let (body, crc) = body.split_at(len);
+        if crc32(&[&kind, body]) != u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]) {
+            return Err(Error::BadCrc);
+        }
+        self.pos += 8 + len + 4;
+        Ok((kind, body))
+    }
+}
+
+/// Everything needed to decode the pixels
+struct Parsed<'a> {
+    info: Info,
+    palette: &'a [u8],
+    trns: Option<&'a [u8]>,
+    idat: Vec<u8>,
+}
+
+fn read_ihdr(data: &[u8]) -> Result<Info, Error> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err(Error::BadSignature);
+    }
+    let mut chunks = Chunks { data, pos: 8 };
+    let (kind, d) = chunks.chunk()?;
+    if &kind != b"IHDR" || d.len() != 13 {
+        return Err(Error::BadChunk);
+    }
+    let width = u32::from_be_bytes([d[0], d[1], d[2], d[3]]);
+    let height = u32::from_be_bytes([d[4], d[5], d[6], d[7]]);
+    let color_type = match d[9] {
+        0 => ColorType::Gray,
+        2 => ColorType::Rgb,
+        3 => ColorType::Indexed,
+        4 => ColorType::GrayAlpha,
+        6 => ColorType::Rgba,
+        _ => return Err(Error::BadHeader),
+    };
+    let valid_size = |n| n > 0 && n <= i32::MAX as u32;
+    if !valid_size(width)
+        || !valid_size(height)
+        || !color_type.allows_depth(d[8])
+        || d[10] != 0
+        || d[11] != 0
+        || d[12] > 1
+    {
+        return Err(Error::BadHeader);
+    }
+    Ok(Info {
+        width,
+        height,
+        bit_depth: d[8],
+        color_type,
+        interlaced: d[12] == 1,
+        gamma: None,
+        srgb: None,
+    })
+}
+
+/// Reads chunks up to `IEND`, or only up to the first `IDAT` if `header_only`
+fn parse(data: &[u8], header_only: bool) -> Result<Parsed<'_>, Error> {
+    let mut info = read_ihdr(data)?;
+    let mut chunks = Chunks {
+        data,
+        pos: 8 + 8 + 13 + 4,
+    };
+    let mut palette = None;
+    let mut trns = None;
+    let mut idat = Vec::new();
+    // 0: IDAT 之前, 1: IDAT 中, 2: IDAT 之后
+    let mut stage = 0;
+
+    loop {
+        let (kind, d) = chunks.chunk()?;
+        if &kind != b"IDAT" && stage == 1 {
+            stage = 2;
+        }
+        let before_idat = stage == 0;
+        match &kind {
+            b"IDAT" if stage < 2 => {
+                if header_only {
+                    break;
+                }
+                stage = 1;
+                idat.extend_from_slice(d);
+            }
+            b"PLTE" if before_idat && palette.is_none() && trns.is_none() => {
+                let entries = d.len() / 3;
+                if d.len() % 3 != 0 || entries == 0 || entries > 256 {
+                    return Err(Error::BadPalette);
+                }
+                match info.color_type {
+                    ColorType::Gray | ColorType::GrayAlpha => return Err(Error::BadChunk),
+                    ColorType::Indexed if entries > 1 << info.bit_depth => {
+                        return Err(Error::BadPalette)
+                    }
+                    _ => palette = Some(d),
+                }
+            }
+            b"tRNS" if before_idat && trns.is_none() => {
+                let valid = match info.color_type {
+                    ColorType::Gray => d.len() == 2,
+                    ColorType::Rgb => d.len() == 6,
+                    ColorType::Indexed => palette.is_some_and(|p| d.len() <= p.len() / 3),
+                    ColorType::GrayAlpha | ColorType::Rgba => false,
+                };
+                if !valid {
+                    return Err(Error::BadChunk);
+                }
+                trns = Some(d);
+            }
+            b"gAMA" if before_idat => {
+                let d: [u8; 4] = d.try_into().map_err(|_| Error::BadChunk)?;
+                info.gamma = Some(u32::from_be_bytes(d));
+            }
+            b"sRGB" if before_idat => {
+                info.srgb = Some(match d {
+                    [0] => RenderingIntent::Perceptual,
+                    [1] => RenderingIntent::RelativeColorimetric,
+                    [2] => RenderingIntent::Saturation,
+                    [3] => RenderingIntent::AbsoluteColorimetric,
+                    _ => return Err(Error::BadChunk),
+                });
+            }
+            b"IEND" if stage > 0 => break,
+            // 首字母小写的是辅助 chunk, 可以跳过
+            _ if kind[0].is_ascii_lowercase() => {}
+            _ => return Err(Error::BadChunk),
+        }
+    }
+
+    if info.color_type == ColorType::Indexed && palette.is_none() {
+        return Err(Error::BadPalette);
+    }
+    Ok(Parsed {
+        info,
+        palette: palette.unwrap_or_default(),
+        trns,
+        idat,
+    })
+}
+
+/// Read the header, `gAMA` and `sRGB` without decoding the image
+pub fn read_info(data: &[u8]) -> Result<Info, Error> {
+    parse(data, true).map(|png| png.info)
+}
+
+/// Decode a PNG file. See the [module docs](self) for the pixel type of each PNG type.
+pub fn decode(data: &[u8]) -> Result<(Info, DynamicImage), Error> {
+    let png = parse(data, false)?;
+    let info = png.info;
+    let (width, height) = (info.width as usize, info.height as usize);
+    let bits = info.color_type.channels() * info.bit_depth as usize;
+
+    let mut raw_len = 0usize;
+    for [.., w, h] in passes(width, height, info.interlaced) {
+        if w == 0 || h == 0 {
+            continue;
+        }
+        let stride = w.checked_mul(bits).ok_or(Error::TooLarge)?.div_ceil(8);
+        raw_len = (stride + 1)
+            .checked_mul(h)
+            .and_then(|n| n.checked_add(raw_len))
+            .ok_or(Error::TooLarge)?;
+    }
+    // deflate 的压缩比不超过 1032:1, 数据明显不够就不分配内存
+    if raw_len / 1032 > png.idat.len() {
+        return Err(Error::UnexpectedEof);
+    }
+    let mut raw = zlib::decompress(&png.idat, raw_len)?;
+
+    let out_channels = match (info.color_type, png.trns.is_some()) {
+        (ColorType::Gray, false) => 1,
+        (ColorType::Gray, true) | (ColorType::GrayAlpha, _) => 2,
+        (ColorType::Rgb | ColorType::Indexed, false) => 3,
+        _ => 4,
+    };
+    width
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(out_channels * 2))
+        .filter(|&n| n <= isize::MAX as usize)
+        .ok_or(Error::TooLarge)?;
+
+    macro_rules! image {
+        ($t:ty, $($n:literal => $variant:ident($px:ident)),+) => {{
+            let samples: Vec<$t> = expand(&png, &mut raw, out_channels, |v| v as $t)?;
+            match out_channels {
+                $($n => DynamicImage::$variant(Image::new(
+                    samples.into_pixels::<$px<$t>>().expect("whole pixels"),
+                    width,
+                    height,
+                )),)+
+                _ => unreachable!(),
+            }
+        }};
+    }
+    let image = if info.bit_depth == 16 {
+        image!(u16, 1 => Gray16(Gray), 2 => GrayAlpha16(GrayAlpha), 3 => Rgb16(RGB), 4 => Rgba16(RGBA))
+    } else {
+        image!(u8, 1 => Gray8(Gray), 2 => GrayAlpha8(GrayAlpha), 3 => Rgb8(RGB), 4 => Rgba8(RGBA))
+    };
+    Ok((info, image))
+}
+
+/// Undo a scanline's filter in place
+fn unfilter(filter: u8, row: &mut [u8], prev: &[u8], bpp: usize) -> Result<(), Error> {
+    match filter {
+        0 => {}
+        1 => {
+            for i in bpp..row.len() {
+                row[i] = row[i].wrapping_add(row[i - bpp]);
+            }
+        }
+        2 => {
+            for (x, &b) in row.iter_mut().zip(prev) {
+                *x = x.wrapping_add(b);
+            }
+        }
+        3 => {
+            for i in 0..row.len() {
+                let a = if i >= bpp { row[i - bpp] } else { 0 };
+                row[i] = row[i].wrapping_add(((a as u16 + prev[i] as u16) / 2) as u8);
+            }
+        }
+        4 => {
+            for i in 0..row.len() {
+                let (a, c) = if i >= bpp {
+                    (row[i - bpp], prev[i - bpp])
+                } else {
+                    (0, 0)
+                };
+                row[i] = row[i].wrapping_add(paeth(a, prev[i], c));
+            }
+        }
+        _ => return Err(Error::BadData),
+    }
+    Ok(())
+}
+
+/// Unfilters the passes and converts samples to the output channels
+fn expand<T: Copy + Default>(
+    png: &Parsed<'_>,
+    raw: &mut [u8],
+    out_channels: usize,
+    conv: impl Fn(u16) -> T,
+) -> Result<Vec<T>, Error> {
+    let info = &png.info;
+    let (width, height) = (info.width as usize, info.height as usize);
+    let depth = info.bit_depth as usize;
+    let channels = info.color_type.channels();
+    let bits = channels * depth;
+    let bpp = (bits / 8).max(1);
+    let max = if depth == 16 { 0xFFFF } else { 0xFF };
+    // 1/2/4 位灰度放大到 8 位: 255, 85, 17
+    let scale = if depth < 8 {
+        255 / ((1 << depth) - 1)
+    } else {
+        1
+    };
+    let key = png
+        .trns
+        .filter(|_| info.color_type != ColorType::Indexed)
+        .map(|t| {
+            let mut key = [0u16; 3];
+            for (k, c) in key.iter_mut().zip(t.chunks_exact(2)) {
+                *k = u16::from_be_bytes([c[0], c[1]]);
+            }
+            key
+        });
+
+    let sample = |row: &[u8], k: usize| -> u16 {
+        match depth {
+            16 => u16::from_be_bytes([row[2 * k], row[2 * k + 1]]),
+            8 => row[k] as u16,
+            _ => {
+                let bit = k * depth;
+                ((row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1)) as u16
+            }
+        }
+    };
+
+    let mut out = vec![T::default(); width * height * out_channels];
+    let mut pos = 0;
+    for [x0, y0, dx, dy, w, h] in passes(width, height, info.interlaced) {
+        if w == 0 || h == 0 {
+            continue;
+        }
+        let stride = (w * bits).div_ceil(8);
+        let zeros = vec![0; stride];
+        for j in 0..h {
+            let (before, rest) = raw.split_at_mut(pos + 1);
+            let prev = if j == 0 {
+                &zeros[..]
+            } else {
+                &before[pos - stride..pos]
+            };
+            let row = &mut rest[..stride];
+            unfilter(before[pos], row, prev, bpp)?;
+            let row = &*row;
+            pos += stride + 1;
+
+            let y = y0 + j * dy;
+            for i in 0..w {
+                let s = |c| sample(row, i * channels + c);
+                let mut px = [0u16; 4];
+                match info.color_type {
+                    ColorType::Indexed => {
+                        let index = s(0) as usize;
+                        let rgb = png
+                            .palette
+                            .get(index * 3..index * 3 + 3)
+                            .ok_or(Error::BadPalette)?;
+                        px = [rgb[0] as u16, rgb[1] as u16, rgb[2] as u16, 255];
+                        if let Some(trns) = png.trns {
+                            px[3] = trns.get(index).map_or(255, |&a| a as u16);
+                        }
+                    }
+                    ColorType::Gray => {
+                        let v = s(0);
+                        px[0] = v * scale;
+                        px[1] = if key == Some([v, 0, 0]) { 0 } else { max };
+                    }
+                    ColorType::Rgb => {
+                        px = [s(0), s(1), s(2), max];
+                        if key == Some([px[0], px[1], px[2]]) {
+                            px[3] = 0;
+                        }
+                    }
+                    _ => {
+                        for (c, p) in px.iter_mut().enumerate().take(channels) {
+                            *p = s(c);
+                        }
+                    }
+                }
+                let at = ((y * width) + x0 + i * dx) * out_channels;
+                for (o, &p) in out[at..at + out_channels].iter_mut().zip(&px) {
+                    *o = conv(p);
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alt::{GRAY16, GRAY8, GRAYA16, GRAYA8};
+    use crate::{RGB16, RGB8, RGBA8};
+
+    fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+        out.extend((data.len() as u32).to_be_bytes());
+        out.extend(kind);
+        out.extend(data);
+        out.extend(crc32(&[kind, data]).to_be_bytes());
+    }
+
+    /// zlib with stored blocks only
+    fn zlib_stored(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x78, 0x01];
+        let blocks: Vec<_> = data.chunks(65535).collect();
+        for (i, block) in blocks.iter().enumerate() {
+            out.push((i + 1 == blocks.len()) as u8);
+            out.extend((block.len() as u16).to_le_bytes());
+            out.extend((!(block.len() as u16)).to_le_bytes());
+            out.extend(*block);
+        }
+        if blocks.is_empty() {
+            out.extend([1, 0, 0, 0xFF, 0xFF]);
+        }
+        out.extend(zlib::adler32(data).to_be_bytes());
+        out
+    }
+
+    /// Filters rows of unfiltered scanlines, with filter type `y % 5` for row `y`
+    fn filter_rows(rows: &[Vec<u8>], bpp: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (y, row) in rows.iter().enumerate() {
+            let zeros = vec![0; row.len()];
+            let prev = if y == 0 { &zeros } else { &rows[y - 1] };
+            let f = (y % 5) as u8;
+            out.push(f);
+            for i in 0..row.len() {
+                let a = if i >= bpp { row[i - bpp] } else { 0 };
+                let c = if i >= bpp { prev[i - bpp] } else { 0 };
+                let predictor = match f {
+                    0 => 0,
+                    1 => a,
+                    2 => prev[i],
+                    3 => ((a as u16 + prev[i] as u16) / 2) as u8,
+                    _ => paeth(a, prev[i], c),
+                };
+                out.push(row[i].wrapping_sub(predictor));
+            }
+        }
+        out
+    }
+
+    /// `rows` are unfiltered scanlines of the whole image
+    fn png(
+        ihdr: [u8; 5],
+        size: (u32, u32),
+        extra: &[(&[u8; 4], &[u8])],
+        rows: &[Vec<u8>],
+    ) -> Vec<u8> {
+        let [depth, color, interlace, ..] = ihdr;
+        let channels = [1, 0, 3, 1, 2, 0, 4][color as usize];
+        let bpp = (channels * depth as usize / 8).max(1);
+        let bits = channels * depth as usize;
+        let (w, h) = (size.0 as usize, size.1 as usize);
+
+        let raw = if interlace == 1 {
+            // 从整幅图的扫描线中取出每个 pass 的像素
+            let mut raw = Vec::new();
+            for [x0, y0, dx, dy, pw, ph] in passes(w, h, true) {
+                if pw == 0 || ph == 0 {
+                    continue;
+                }
+                let pass: Vec<Vec<u8>> = (0..ph)
+                    .map(|j| {
+                        let row = &rows[y0 + j * dy];
+                        let mut out = vec![0u8; (pw * bits).div_ceil(8)];
+                        for i in 0..pw {
+                            let x = x0 + i * dx;
+                            for b in 0..bits {
+                                let (src, dst) = (x * bits + b, i * bits + b);
+                                let bit = (row[src / 8] >> (7 - src % 8)) & 1;
+                                out[dst / 8] |= bit << (7 - dst % 8);
+                            }
+                        }
+                        out
+                    })
+                    .collect();
+                raw.extend(filter_rows(&pass, bpp));
+            }
+            raw
+        } else {
+            filter_rows(rows, bpp)
+        };
+
+        let mut out = SIGNATURE.to_vec();
+        let mut ihdr_data = size.0.to_be_bytes().to_vec();
+        ihdr_data.extend(size.1.to_be_bytes());
+        ihdr_data.extend([depth, color, 0, 0, interlace]);
+        chunk(&mut out, b"IHDR", &ihdr_data);
+        for (kind, data) in extra {
+            chunk(&mut out, kind, data);
+        }
+        let z = zlib_stored(&raw);
+        // 拆成两个 IDAT
+        chunk(&mut out, b"IDAT", &z[..z.len() / 2]);
+        chunk(&mut out, b"IDAT", &z[z.len() / 2..]);
+        chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    fn rgb_rows(w: usize, h: usize) -> Vec<Vec<u8>> {
+        (0..h)
+            .map(|y| (0..w * 3).map(|i| (i * 37 + y * 101) as u8).collect())
+            .collect()
+    }
+
+    #[test]
+    fn filters_and_interlacing() {
+        let (w, h) = (7, 11);
+        let rows = rgb_rows(w, h);
+        let expected: Vec<RGB8> = rows
+            .iter()
+            .flat_map(|r| r.chunks_exact(3).map(|c| RGB8::new(c[0], c[1], c[2])))
+            .collect();
+        for interlace in [0, 1] {
+            let file = png([8, 2, interlace, 0, 0], (w as u32, h as u32), &[], &rows);
+            let (info, img) = decode(&file).unwrap();
+            assert_eq!(info.interlaced, interlace == 1);
+            assert_eq!(img.as_image::<RGB8>().unwrap().buf(), expected);
+        }
+
+        // 2 位灰度, 每个字节多个像素, 交错
+        let rows: Vec<Vec<u8>> = (0..9)
+            .map(|y| vec![0b00_01_10_11 ^ y as u8, 0b1110_0100])
+            .collect();
+        let plain = decode(&png([2, 0, 0, 0, 0], (6, 9), &[], &rows)).unwrap().1;
+        let adam7 = decode(&png([2, 0, 1, 0, 0], (6, 9), &[], &rows)).unwrap().1;
+        assert_eq!(plain, adam7);
+        assert_eq!(
+            plain.as_image::<GRAY8>().unwrap().buf()[..6],
+            [0, 85, 170, 255, 255, 170].map(Gray)
+        );
+    }
+
+    #[test]
+    fn color_types() {
+        // 1 位灰度
+        let img = decode(&png([1, 0, 0, 0, 0], (3, 1), &[], &[vec![0b1010_0000]]))
+            .unwrap()
+            .1;
+        assert_eq!(
+            img.as_image::<GRAY8>().unwrap().buf(),
+            [255, 0, 255].map(Gray)
+        );
+
+        // 4 位灰度 + tRNS
+        let img = decode(&png(
+            [4, 0, 0, 0, 0],
+            (2, 1),
+            &[(b"tRNS", &[0, 3])],
+            &[vec![0x3F]],
+        ))
+        .unwrap()
+        .1;
+        assert_eq!(
+            img.as_image::<GRAYA8>().unwrap().buf(),
+            [GrayAlpha(51, 0), GrayAlpha(255, 255)]
+        );
+
+        // 2 位调色板 + tRNS
+        let plte = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let img = decode(&png(
+            [2, 3, 0, 0, 0],
+            (3, 1),
+            &[(b"PLTE", &plte), (b"tRNS", &[128])],
+            &[vec![0b00_01_10_00]],
+        ))
+        .unwrap()
+        .1;
+        assert_eq!(
+            img.as_image::<RGBA8>().unwrap().buf(),
+            [
+                RGBA8::new(255, 0, 0, 128),
+                RGBA8::new(0, 255, 0, 255),
+                RGBA8::new(0, 0, 255, 255)
+            ]
+        );
+        let img = decode(&png(
+            [8, 3, 0, 0, 0],
+            (1, 1),
+            &[(b"PLTE", &plte)],
+            &[vec![2]],
+        ))
+        .unwrap()
+        .1;
+        assert_eq!(
+            img.as_image::<RGB8>().unwrap().buf(),
+            [RGB8::new(0, 0, 255)]
+        );
+
+        // 16 位: 大端转成本机字节序
+        let img = decode(&png(
+            [16, 2, 0, 0, 0],
+            (1, 1),
+            &[],
+            &[vec![1, 2, 3, 4, 5, 6]],
+        ))
+        .unwrap()
+        .1;
+        assert_eq!(
+            img.as_image::<RGB16>().unwrap().buf(),
+            [RGB16::new(0x102, 0x304, 0x506)]
+        );
+        let img = decode(&png(
+            [16, 0, 1, 0, 0],
+            (2, 1),
+            &[(b"tRNS", &[1, 0])],
+            &[vec![1, 0, 0xFF, 0xFF]],
+        ))
+        .unwrap()
+        .1;
+        assert_eq!(
+            img.as_image::<GRAYA16>().unwrap().buf(),
+            [GrayAlpha(256, 0), GrayAlpha(65535, 65535)]
+        );
+        let img = decode(&png([16, 0, 0, 0, 0], (1, 1), &[], &[vec![0xAB, 0xCD]]))
+            .unwrap()
+            .1;
+        assert_eq!(img.as_image::<GRAY16>().unwrap().buf(), [Gray(0xABCD)]);
+
+        let img = decode(&png([8, 4, 0, 0, 0], (1, 1), &[], &[vec![7, 9]]))
+            .unwrap()
+            .1;
+        assert_eq!(img.as_image::<GRAYA8>().unwrap().buf(), [GrayAlpha(7, 9)]);
+        let img = decode(&png([8, 6, 1, 0, 0], (1, 1), &[], &[vec![1, 2, 3, 4]]))
+            .unwrap()
+            .1;
+        assert_eq!(
+            img.as_image::<RGBA8>().unwrap().buf(),
+            [RGBA8::new(1, 2, 3, 4)]
+        );
+    }
+
+    #[test]
+    fn color_space_chunks() {
+        let rows = rgb_rows(2, 2);
+        let file = png(
+            [8, 2, 0, 0, 0],
+            (2, 2),
+            &[
+                (b"gAMA", &45455u32.to_be_bytes()),
+                (b"sRGB", &[0]),
+                (b"tEXt", b"a\0b"),
+            ],
+            &rows,
+        );
+        let info = read_info(&file).unwrap();
+        assert_eq!(info.gamma, Some(45455));
+        assert_eq!(info.gamma(), Some(0.45455));
+        assert_eq!(info.srgb, Some(RenderingIntent::Perceptual));
+        assert_eq!((info.width, info.height, info.bit_depth), (2, 2, 8));
+        assert_eq!(decode(&file).unwrap().0, info);
+
+        let plain = read_info(&png([8, 2, 0, 0, 0], (2, 2), &[], &rows)).unwrap();
+        assert_eq!((plain.gamma, plain.srgb), (None, None));
+    }
+
+    /// Signature, the given `IHDR` contents, other chunks, and `IEND`
+    fn raw_png(ihdr: &[u8], chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut out = SIGNATURE.to_vec();
+        chunk(&mut out, b"IHDR", ihdr);
+        for (kind, data) in chunks {
+            chunk(&mut out, kind, data);
+        }
+        chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    fn ihdr(width: u32, height: u32, rest: [u8; 5]) -> Vec<u8> {
+        let mut d = width.to_be_bytes().to_vec();
+        d.extend(height.to_be_bytes());
+        d.extend(rest);
+        d
+    }
+
+    #[test]
+    fn bad_headers() {
+        let rows = rgb_rows(2, 2);
+        let err = |ihdr| decode(&png(ihdr, (2, 2), &[], &rows)).map(|_| ());
+        assert_eq!(decode(b"GIF89a").map(|_| ()), Err(Error::BadSignature));
+        assert_eq!(err([16, 3, 0, 0, 0]), Err(Error::BadHeader));
+        assert_eq!(err([8, 2, 2, 0, 0]), Err(Error::BadHeader));
+        assert_eq!(err([4, 2, 0, 0, 0]), Err(Error::BadHeader));
+        assert_eq!(err([8, 5, 0, 0, 0]), Err(Error::BadHeader));
+
+        let idat = zlib_stored(&[0, 0, 0, 0]);
+        let bad = |ihdr: &[u8]| decode(&raw_png(ihdr, &[(b"IDAT", &idat)])).map(|_| ());
+        assert_eq!(bad(&ihdr(0, 1, [8, 0, 0, 0, 0])), Err(Error::BadHeader));
+        // 压缩方法和过滤方法只能是 0
+        assert_eq!(bad(&ihdr(1, 1, [8, 0, 1, 0, 0])), Err(Error::BadHeader));
+        assert_eq!(bad(&ihdr(1, 1, [8, 0, 0, 1, 0])), Err(Error::BadHeader));
+        assert_eq!(bad(&ihdr(1, 0, [8, 0, 0, 0, 0])), Err(Error::BadHeader));
+        assert_eq!(
+            bad(&ihdr(1 << 31, 1, [8, 0, 0, 0, 0])),
+            Err(Error::BadHeader)
+        );
+        assert_eq!(
+            bad(&ihdr(1, 1, [8, 0, 0, 0, 0])[..12]),
+            Err(Error::BadChunk)
+        );
+        // 第一个 chunk 必须是 IHDR
+        let mut first = SIGNATURE.to_vec();
+        chunk(&mut first, b"gAMA", &[0; 4]);
+        assert_eq!(decode(&first).map(|_| ()), Err(Error::BadChunk));
+    }
+
+    #[test]
+    fn crc_and_truncated_chunks() {
+        let rows = rgb_rows(2, 2);
+        let file = png([8, 2, 0, 0, 0], (2, 2), &[], &rows);
+        assert!(decode(&file).is_ok());
+        assert_eq!(
+            decode(&file[..file.len() - 1]).map(|_| ()),
+            Err(Error::UnexpectedEof)
+        );
+        // 在 IHDR 和 IDAT 中
+        assert_eq!(decode(&file[..20]).map(|_| ()), Err(Error::UnexpectedEof));
+        assert_eq!(decode(&file[..40]).map(|_| ()), Err(Error::UnexpectedEof));
+        let mut bad = file.clone();
+        bad[20] ^= 1;
+        assert_eq!(decode(&bad).map(|_| ()), Err(Error::BadCrc));
+        let mut bad = file.clone();
+        let last = bad.len() - 20;
+        bad[last] ^= 1;
+        assert_eq!(decode(&bad).map(|_| ()), Err(Error::BadCrc));
+        // chunk 长度超过 2³¹ - 1
+        let mut bad = file.clone();
+        bad[33] = 0x80;
+        assert_eq!(decode(&bad).map(|_| ()), Err(Error::BadChunk));
+    }
+
+    #[test]
+    fn chunk_order() {
+        let rows = rgb_rows(2, 2);
+        let err = |ihdr, extra: &[(&[u8; 4], &[u8])]| {
+            decode(&png(ihdr, (2, 2), extra, &rows)).map(|_| ())
+        };
+        let rgb = [8, 2, 0, 0, 0];
+        assert_eq!(err(rgb, &[(b"ABCD", &[])]), Err(Error::BadChunk));
+        // 首字母小写的辅助 chunk 跳过
+        assert_eq!(err(rgb, &[(b"abCD", &[1, 2])]), Ok(()));
+        assert_eq!(err(rgb, &[(b"gAMA", &[0; 3])]), Err(Error::BadChunk));
+        assert_eq!(err(rgb, &[(b"sRGB", &[4])]), Err(Error::BadChunk));
+        assert_eq!(
+            err([8, 6, 0, 0, 0], &[(b"tRNS", &[0, 0])]),
+            Err(Error::BadChunk)
+        );
+        assert_eq!(err(rgb, &[(b"tRNS", &[0, 0])]), Err(Error::BadChunk));
+        assert_eq!(
+            err([8, 0, 0, 0, 0], &[(b"PLTE", &[0, 0, 0])]),
+            Err(Error::BadChunk)
+        );
+
+        // 没有 IDAT, IDAT 不连续, IDAT 之后的 PLTE
+        let header = ihdr(1, 1, [8, 0, 0, 0, 0]);
+        let idat = zlib_stored(&[0, 7]);
+        assert_eq!(
+            decode(&raw_png(&header, &[])).map(|_| ()),
+            Err(Error::BadChunk)
+        );
+        assert_eq!(
+            decode(&raw_png(
+                &header,
+                &[(b"IDAT", &idat[..3]), (b"teXt", &[]), (b"IDAT", &idat[3..])]
+            ))
+            .map(|_| ()),
+            Err(Error::BadChunk)
+        );
+        assert_eq!(
+            decode(&raw_png(&header, &[(b"IDAT", &idat), (b"PLTE", &[0; 3])])).map(|_| ()),
+            Err(Error::BadChunk)
+        );
+    }
+
+    #[test]
+    fn palette_overflow() {
+        let err = |ihdr, extra: &[(&[u8; 4], &[u8])], rows: &[Vec<u8>]| {
+            decode(&png(ihdr, (2, 2), extra, rows)).map(|_| ())
+        };
+        let rows = [vec![0, 1], vec![1, 0]];
+        let indexed = [8, 3, 0, 0, 0];
+        let two = [0u8, 0, 0, 255, 255, 255];
+        assert_eq!(err(indexed, &[(b"PLTE", &two)], &rows), Ok(()));
+        assert_eq!(err(indexed, &[], &rows), Err(Error::BadPalette));
+        // 索引超出调色板
+        assert_eq!(
+            err(indexed, &[(b"PLTE", &two[..3])], &rows),
+            Err(Error::BadPalette)
+        );
+        // 长度不是 3 的倍数, 空的, 超过 256 项, 超过位深能表示的
+        assert_eq!(
+            err(indexed, &[(b"PLTE", &two[..4])], &rows),
+            Err(Error::BadPalette)
+        );
+        assert_eq!(
+            err(indexed, &[(b"PLTE", &[])], &rows),
+            Err(Error::BadPalette)
+        );
+        assert_eq!(
+            err(indexed, &[(b"PLTE", &[0; 257 * 3])], &rows),
+            Err(Error::BadPalette)
+        );
+        assert_eq!(
+            err(
+                [1, 3, 0, 0, 0],
+                &[(b"PLTE", &[0; 9])],
+                &[vec![0x40], vec![0]]
+            ),
+            Err(Error::BadPalette)
+        );
+        // tRNS 比调色板长, 在调色板前面, 调色板出现两次
+        assert_eq!(
+            err(indexed, &[(b"PLTE", &two), (b"tRNS", &[0; 3])], &rows),
+            Err(Error::BadChunk)
+        );
+        assert_eq!(
+            err(indexed, &[(b"tRNS", &[0]), (b"PLTE", &two)], &rows),
+            Err(Error::BadChunk)
+        );
+        assert_eq!(
+            err(indexed, &[(b"PLTE", &two), (b"PLTE", &two)], &rows),
+            Err(Error::BadChunk)
+        );
+    }
+
+    #[test]
+    fn bad_data() {
+        let rows = rgb_rows(2, 2);
+        assert_eq!(
+            decode(&png([8, 2, 0, 0, 0], (2, 2), &[], &rows[..1])).map(|_| ()),
+            Err(Error::BadData)
+        );
+        let header = [0, 0, 0, 2, 0, 0, 0, 2, 8, 2, 0, 0, 0];
+        // 未知的 filter 类型
+        let mut raw = filter_rows(&rows, 3);
+        raw[7] = 5;
+        let bad = raw_png(&header, &[(b"IDAT", &zlib_stored(&raw))]);
+        assert_eq!(decode(&bad).map(|_| ()), Err(Error::BadData));
+        // 解压出来的数据比图像多
+        let mut raw = filter_rows(&rows, 3);
+        raw.push(0);
+        let bad = raw_png(&header, &[(b"IDAT", &zlib_stored(&raw))]);
+        assert_eq!(decode(&bad).map(|_| ()), Err(Error::BadData));
+        // zlib 校验和不对
+        let mut z = zlib_stored(&filter_rows(&rows, 3));
+        *z.last_mut().unwrap() ^= 1;
+        let bad = raw_png(&header, &[(b"IDAT", &z)]);
+        assert_eq!(decode(&bad).map(|_| ()), Err(Error::BadZlib));
+    }
+
+    #[test]
+    fn huge_header_tiny_data() {
+        // 声明巨大尺寸, 数据只有几十字节: 不能先按尺寸分配内存
+        let idat = zlib_stored(&[0; 100]);
+        for (depth, color) in [(1, 0), (8, 2), (8, 3), (16, 4), (16, 6)] {
+            for interlace in [0, 1] {
+                let header = ihdr(0x7FFF_FFFF, 0x10000, [depth, color, 0, 0, interlace]);
+                let chunks: [(&[u8; 4], &[u8]); 2] = [(b"PLTE", &[0, 0, 0]), (b"IDAT", &idat)];
+                let file = raw_png(&header, &chunks[(color != 3) as usize..]);
+                assert_eq!(
+                    decode(&file).map(|_| ()),
+                    Err(Error::UnexpectedEof),
+                    "{depth} {color} {interlace}"
+                );
+            }
+        }
+        // 原始数据的长度溢出 usize
+        let header = ihdr(0x7FFF_FFFF, 0x7FFF_FFFF, [16, 6, 0, 0, 0]);
+        assert_eq!(
+            decode(&raw_png(&header, &[(b"IDAT", &idat)])).map(|_| ()),
+            Err(Error::TooLarge)
+        );
+        // 只读头部不受影响
+        assert_eq!(
+            read_info(&raw_png(&header, &[(b"IDAT", &idat)]))
+                .unwrap()
+                .width,
+            0x7FFF_FFFF
+        );
+    }
+}
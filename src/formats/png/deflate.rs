@@ -0,0 +1,509 @@
+//! Deflate (RFC 1951) compressor: LZ77 with hash chains, then each block is written
+//! stored, with the fixed code, or with its own Huffman code, whichever is smallest.
+
+use super::zlib::{CLEN_ORDER, DIST_BASE, DIST_EXTRA, LENGTH_BASE, LENGTH_EXTRA};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+const WINDOW: usize = 1 << 15;
+const HASH_BITS: u32 = 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const NONE: usize = usize::MAX;
+/// Tokens per block; each block gets its own Huffman code
+const BLOCK_TOKENS: usize = 1 << 14;
+/// Longest stored block
+const MAX_STORED: usize = 65535;
+
+/// Hash chain length and "good enough" match length for each level
+const LEVELS: [(usize, usize); 10] = [
+    (0, 0),
+    (4, 8),
+    (8, 16),
+    (16, 32),
+    (32, 64),
+    (64, 128),
+    (128, 128),
+    (256, MAX_MATCH),
+    (1024, MAX_MATCH),
+    (4096, MAX_MATCH),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(u8),
+    Match { len: u16, dist: u16 },
+}
+
+/// Writes bits least significant first
+struct Bits {
+    out: Vec<u8>,
+    buf: u64,
+    count: u32,
+}
+
+impl Bits {
+    #[inline]
+    fn put(&mut self, bits: u32, n: u32) {
+        self.buf |= (bits as u64) << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.buf as u8);
+            self.buf >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Pad with zeros to the next byte boundary
+    #[inline]
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.put(0, 8 - self.count);
+        }
+    }
+}
+
+/// Raw deflate data at `level` 0 (stored only) to 9 (smallest)
+pub(super) fn deflate(data: &[u8], level: u8) -> Vec<u8> {
+    let mut bits = Bits {
+        out: Vec::with_capacity(data.len() / 2 + 64),
+        buf: 0,
+        count: 0,
+    };
+    let (chain, nice) = LEVELS[level.min(9) as usize];
+    if chain == 0 || data.is_empty() {
+        write_stored(&mut bits, data, true);
+    } else {
+        let mut matcher = Matcher::new(data, chain, nice, level >= 4);
+        let mut tokens = Vec::with_capacity(BLOCK_TOKENS);
+        let mut start = 0;
+        while let Some(end) = matcher.fill(&mut tokens, BLOCK_TOKENS) {
+            write_block(&mut bits, &tokens, &data[start..end], end == data.len());
+            tokens.clear();
+            start = end;
+        }
+    }
+    bits.align();
+    bits.out
+}
+
+/// LZ77 over the whole input, with a hash of the next 3 bytes pointing into chains
+/// of earlier positions
+struct Matcher<'a> {
+    data: &'a [u8],
+    head: Vec<usize>,
+    prev: Vec<usize>,
+    chain: usize,
+    nice: usize,
+    lazy: bool,
+    /// Next position to add to the hash chains
+    inserted: usize,
+    pos: usize,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(data: &'a [u8], chain: usize, nice: usize, lazy: bool) -> Self {
+        Self {
+            data,
+            head: vec![NONE; 1 << HASH_BITS],
+            prev: vec![NONE; WINDOW],
+            chain,
+            nice,
+            lazy,
+            inserted: 0,
+            pos: 0,
+        }
+    }
+
+    #[inline]
+    fn hash(&self, i: usize) -> usize {
+        let d = &self.data[i..i + 3];
+        let v = (d[0] as u32) << 16 | (d[1] as u32) << 8 | d[2] as u32;
+        (v.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+    }
+
+    /// Add positions up to `end` (exclusive) to the chains
+    #[inline]
+    fn insert_until(&mut self, end: usize) {
+        let last = self.data.len().saturating_sub(MIN_MATCH - 1);
+        while self.inserted < end.min(last) {
+            let h = self.hash(self.inserted);
+            self.prev[self.inserted % WINDOW] = self.head[h];
+            self.head[h] = self.inserted;
+            self.inserted += 1;
+        }
+        self.inserted = self.inserted.max(end);
+    }
+
+    /// Longest earlier match for the bytes at `i`, as `(length, distance)`
+    fn find(&mut self, i: usize) -> (usize, usize) {
+        self.insert_until(i);
+        let data = self.data;
+        let max = (data.len() - i).min(MAX_MATCH);
+        if max < MIN_MATCH {
+            return (0, 0);
+        }
+        let (mut best, mut best_dist) = (0, 0);
+        let mut candidate = self.head[self.hash(i)];
+        for _ in 0..self.chain {
+            // 链上的位置只会越来越早, 超出窗口就停下
+            if candidate == NONE || candidate >= i || i - candidate > WINDOW {
+                break;
+            }
+            // 先比较当前最好长度处的字节, 快速排除
+            if data[candidate + best.min(max - 1)] == data[i + best.min(max - 1)] {
+                let len = data[candidate..candidate + max]
+                    .iter()
+                    .zip(&data[i..i + max])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best {
+                    best = len;
+                    best_dist = i - candidate;
+                    if len >= self.nice || len == max {
+                        break;
+                    }
+                }
+            }
+            let next = self.prev[candidate % WINDOW];
+            if next != NONE && next >= candidate {
+                break;
+            }
+            candidate = next;
+        }
+        if best >= MIN_MATCH {
+            (best, best_dist)
+        } else {
+            (0, 0)
+        }
+    }
+
+    /// Append up to `limit` tokens. Returns the input position they end at, or `None` when done.
+    fn fill(&mut self, tokens: &mut Vec<Token>, limit: usize) -> Option<usize> {
+        let data = self.data;
+        if self.pos >= data.len() {
+            return None;
+        }
+        while tokens.len() < limit && self.pos < data.len() {
+            let i = self.pos;
+            let (len, dist) = self.find(i);
+            if len == 0 {
+                tokens.push(Token::Literal(data[i]));
+                self.pos += 1;
+                continue;
+            }
+            // lazy matching: 下一个位置的匹配更长就先输出一个字面量
+            if self.lazy && len < self.nice && i + 1 < data.len() {
+                let (next_len, _) = self.find(i + 1);
+                if next_len > len {
+                    tokens.push(Token::Literal(data[i]));
+                    self.pos += 1;
+                    continue;
+                }
+            }
+            tokens.push(Token::Match {
+                len: len as u16,
+                dist: dist as u16,
+            });
+            self.pos += len;
+        }
+        Some(self.pos)
+    }
+}
+
+#[inline]
+fn length_symbol(len: usize) -> usize {
+    LENGTH_BASE.partition_point(|&b| b as usize <= len) - 1
+}
+
+#[inline]
+fn dist_symbol(dist: usize) -> usize {
+    DIST_BASE.partition_point(|&b| b as usize <= dist) - 1
+}
+
+/// Code lengths of an optimal prefix code, limited to `limit` bits.
+/// At least two symbols get a code, so the code is always complete.
+fn huffman_lengths(freqs: &[u32], limit: u8) -> Vec<u8> {
+    let mut freqs = freqs.to_vec();
+    for i in 0..freqs.len() {
+        if freqs.iter().filter(|&&f| f > 0).count() >= 2 {
+            break;
+        }
+        if freqs[i] == 0 {
+            freqs[i] = 1;
+        }
+    }
+    loop {
+        let lengths = build_lengths(&freqs);
+        if lengths.iter().all(|&l| l <= limit) {
+            return lengths;
+        }
+        // 太长就压平频率分布再试
+        for f in freqs.iter_mut().filter(|f| **f > 0) {
+            *f = (*f >> 1) + 1;
+        }
+    }
+}
+
+fn build_lengths(freqs: &[u32]) -> Vec<u8> {
+    let mut heap: BinaryHeap<_> = freqs
+        .iter()
+        .enumerate()
+        .filter(|(_, &f)| f > 0)
+        .map(|(i, &f)| Reverse((f as u64, i)))
+        .collect();
+    // 叶子是 0..n, 内部节点接在后面
+    let mut parent = vec![NONE; freqs.len()];
+    while heap.len() > 1 {
+        let Reverse((fa, a)) = heap.pop().expect("two nodes");
+        let Reverse((fb, b)) = heap.pop().expect("two nodes");
+        let node = parent.len();
+        parent.push(NONE);
+        parent[a] = node;
+        parent[b] = node;
+        heap.push(Reverse((fa + fb, node)));
+    }
+    let mut depth = vec![0u8; parent.len()];
+    // 父节点总在子节点之后, 倒序一遍就能算出深度
+    for i in (0..parent.len()).rev() {
+        if parent[i] != NONE {
+            depth[i] = depth[parent[i]] + 1;
+        }
+    }
+    (0..freqs.len())
+        .map(|i| if freqs[i] > 0 { depth[i] } else { 0 })
+        .collect()
+}
+
+/// Canonical codes for the lengths, bit-reversed for writing
+fn codes(lengths: &[u8]) -> Vec<u16> {
+    let mut count = [0u16; 16];
+    for &len in lengths {
+        count[len as usize] += 1;
+    }
+    count[0] = 0;
+    let mut next = [0u16; 16];
+    for len in 1..16 {
+        next[len] = (next[len - 1] + count[len - 1]) << 1;
+    }
+    lengths
+        .iter()
+        .map(|&len| {
+            if len == 0 {
+                return 0;
+            }
+            let code = next[len as usize];
+            next[len as usize] += 1;
+            code.reverse_bits() >> (16 - len)
+        })
+        .collect()
+}
+
+fn fixed_lengths() -> (Vec<u8>, Vec<u8>) {
+    let mut lit = vec![8u8; 288];
+    lit[144..256].fill(9);
+    lit[256..280].fill(7);
+    (lit, vec![5; 30])
+}
+
+/// Run-length encoded code lengths of a dynamic block header, as `(symbol, extra bits)`
+fn rle_lengths(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let v = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&l| l == v).count();
+        let mut left = run;
+        if v == 0 {
+            while left >= 11 {
+                let r = left.min(138);
+                out.push((18, (r - 11) as u8));
+                left -= r;
+            }
+            if left >= 3 {
+                out.push((17, (left - 3) as u8));
+                left = 0;
+            }
+        } else {
+            out.push((v, 0));
+            left -= 1;
+            while left >= 3 {
+                let r = left.min(6);
+                out.push((16, (r - 3) as u8));
+                left -= r;
+            }
+        }
+        out.extend(core::iter::repeat_n((v, 0), left));
+        i += run;
+    }
+    out
+}
+
+#[inline]
+fn clen_extra_bits(symbol: u8) -> u32 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+/// Bits to write the tokens with these codes, not counting the block header
+fn data_cost(tokens: &[Token], lit: &[u8], dist: &[u8]) -> usize {
+    let mut bits = lit[256] as usize;
+    for t in tokens {
+        bits += match *t {
+            Token::Literal(b) => lit[b as usize] as usize,
+            Token::Match { len, dist: d } => {
+                let (l, d) = (length_symbol(len as usize), dist_symbol(d as usize));
+                lit[257 + l] as usize
+                    + LENGTH_EXTRA[l] as usize
+                    + dist[d] as usize
+                    + DIST_EXTRA[d] as usize
+            }
+        };
+    }
+    bits
+}
+
+fn write_block(bits: &mut Bits, tokens: &[Token], raw: &[u8], last: bool) {
+    let mut lit_freq = [0u32; 286];
+    let mut dist_freq = [0u32; 30];
+    lit_freq[256] = 1;
+    for t in tokens {
+        match *t {
+            Token::Literal(b) => lit_freq[b as usize] += 1,
+            Token::Match { len, dist } => {
+                lit_freq[257 + length_symbol(len as usize)] += 1;
+                dist_freq[dist_symbol(dist as usize)] += 1;
+            }
+        }
+    }
+    let lit = huffman_lengths(&lit_freq, 15);
+    let dist = huffman_lengths(&dist_freq, 15);
+    let hlit = 257 + lit[257..].iter().rposition(|&l| l > 0).map_or(0, |p| p + 1);
+    let hdist = 1 + dist.iter().rposition(|&l| l > 0).unwrap_or(0);
+    let all: Vec<u8> = lit[..hlit].iter().chain(&dist[..hdist]).copied().collect();
+    let rle = rle_lengths(&all);
+    let mut clen_freq = [0u32; 19];
+    for &(s, _) in &rle {
+        clen_freq[s as usize] += 1;
+    }
+    let clen = huffman_lengths(&clen_freq, 7);
+    let hclen = 4.max(
+        19 - CLEN_ORDER
+            .iter()
+            .rev()
+            .take_while(|&&i| clen[i] == 0)
+            .count(),
+    );
+
+    let header_cost = 5
+        + 5
+        + 4
+        + 3 * hclen
+        + rle
+            .iter()
+            .map(|&(s, _)| clen[s as usize] as usize + clen_extra_bits(s) as usize)
+            .sum::<usize>();
+    let dynamic_cost = header_cost + data_cost(tokens, &lit, &dist);
+    let (fixed_lit, fixed_dist) = fixed_lengths();
+    let fixed_cost = data_cost(tokens, &fixed_lit, &fixed_dist);
+    let stored_cost = (raw.len() + raw.len().div_ceil(MAX_STORED).max(1) * 5) * 8;
+
+    if stored_cost <= dynamic_cost.min(fixed_cost) {
+        write_stored(bits, raw, last);
+        return;
+    }
+    bits.put(last as u32, 1);
+    if fixed_cost <= dynamic_cost {
+        bits.put(1, 2);
+        write_tokens(bits, tokens, &fixed_lit, &fixed_dist);
+    } else {
+        bits.put(2, 2);
+        bits.put((hlit - 257) as u32, 5);
+        bits.put((hdist - 1) as u32, 5);
+        bits.put((hclen - 4) as u32, 4);
+        for &i in &CLEN_ORDER[..hclen] {
+            bits.put(clen[i] as u32, 3);
+        }
+        let clen_codes = codes(&clen);
+        for &(s, extra) in &rle {
+            bits.put(clen_codes[s as usize] as u32, clen[s as usize] as u32);
+            bits.put(extra as u32, clen_extra_bits(s));
+        }
+        write_tokens(bits, tokens, &lit, &dist);
+    }
+}
+
+fn write_tokens(bits: &mut Bits, tokens: &[Token], lit: &[u8], dist: &[u8]) {
+    let (lit_codes, dist_codes) = (codes(lit), codes(dist));
+    for t in tokens {
+        match *t {
+            Token::Literal(b) => bits.put(lit_codes[b as usize] as u32, lit[b as usize] as u32),
+            Token::Match { len, dist: d } => {
+                let (len, d) = (len as usize, d as usize);
+                let l = length_symbol(len);
+                bits.put(lit_codes[257 + l] as u32, lit[257 + l] as u32);
+                bits.put(
+                    (len - LENGTH_BASE[l] as usize) as u32,
+                    LENGTH_EXTRA[l] as u32,
+                );
+                let s = dist_symbol(d);
+                bits.put(dist_codes[s] as u32, dist[s] as u32);
+                bits.put((d - DIST_BASE[s] as usize) as u32, DIST_EXTRA[s] as u32);
+            }
+        }
+    }
+    bits.put(lit_codes[256] as u32, lit[256] as u32);
+}
+
+fn write_stored(bits: &mut Bits, raw: &[u8], last: bool) {
+    let mut chunks = raw.chunks(MAX_STORED).peekable();
+    // 空数据也要写一个块
+    if chunks.peek().is_none() {
+        bits.put(last as u32, 3);
+        bits.align();
+        bits.out.extend_from_slice(&[0, 0, 0xFF, 0xFF]);
+        return;
+    }
+    while let Some(chunk) = chunks.next() {
+        let final_chunk = last && chunks.peek().is_none();
+        bits.put(final_chunk as u32, 3);
+        bits.align();
+        let len = chunk.len() as u16;
+        bits.out.extend_from_slice(&len.to_le_bytes());
+        bits.out.extend_from_slice(&(!len).to_le_bytes());
+        bits.out.extend_from_slice(chunk);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huffman() {
+        let lengths = huffman_lengths(&[10, 1, 1, 2, 0], 15);
+        assert_eq!(lengths, [1, 3, 3, 2, 0]);
+        // 单个符号也要得到完整的编码
+        assert_eq!(huffman_lengths(&[0, 0, 5], 15), [1, 0, 1]);
+        assert_eq!(huffman_lengths(&[0, 0, 0], 7), [1, 1, 0]);
+
+        // 斐波那契频率会产生很深的树, 需要限制长度
+        let mut fib = vec![1u32, 1];
+        while fib.len() < 30 {
+            fib.push(fib[fib.len() - 1] + fib[fib.len() - 2]);
+        }
+        let lengths = huffman_lengths(&fib, 15);
+        assert!(lengths.iter().all(|&l| (1..=15).contains(&l)));
+        let kraft: f64 = lengths.iter().map(|&l| 0.5f64.powi(l as i32)).sum();
+        assert!(kraft <= 1.0);
+
+        assert_eq!(codes(&[2, 1, 3, 3]), [0b01, 0b0, 0b011, 0b111]);
+        assert_eq!(rle_lengths(&[0; 150]), [(18, 127), (18, 1)]);
+        assert_eq!(rle_lengths(&[5; 8]), [(5, 0), (16, 3), (5, 0)]);
+        assert_eq!(rle_lengths(&[3, 3, 0, 0]), [(3, 0), (3, 0), (0, 0), (0, 0)]);
+    }
+}
@@ -0,0 +1,555 @@
+use super::{crc32, paeth, zlib, ColorType, Error, RenderingIntent, SIGNATURE};
+use crate::internal::dynamic::as_bytes;
+use crate::{convert, AlphaKind, ComponentOrder, DynamicImage, DynamicPixel, Endian};
+use crate::{Image, ImageRef, ImageVisitor, PixelFormat};
+use std::collections::{HashMap, HashSet};
+
+/// Filter applied to each row before compression
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Filter {
+    None = 0,
+    Sub = 1,
+    Up = 2,
+    Average = 3,
+    Paeth = 4,
+    /// Pick the best filter for each row. Indexed and 1/2/4-bit images use `None`.
+    Adaptive = 5,
+}
+
+/// Settings for [`encode`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Options {
+    /// Compression level, 0 (stored) to 9 (smallest)
+    pub level: u8,
+    pub filter: Filter,
+    /// Write an indexed image if it's 8-bit color with at most 256 distinct colors.
+    /// Images with an alpha channel always get a `tRNS` chunk, so they decode with alpha.
+    pub palette: bool,
+    /// Write an `sRGB` chunk with this intent
+    pub srgb: Option<RenderingIntent>,
+    /// Write a `gAMA` chunk: file gamma × 100000
+    pub gamma: Option<u32>,
+}
+
+impl Default for Options {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            level: 6,
+            filter: Filter::Adaptive,
+            palette: false,
+            srgb: None,
+            gamma: None,
+        }
+    }
+}
+
+/// Largest IDAT chunk written
+const IDAT_SIZE: usize = 1 << 20;
+
+/// Encode an image of any pixel type.
+///
+/// Gray and gray + alpha stay gray, everything else becomes RGB or RGBA, at 8 or 16 bits
+/// like the source. Premultiplied alpha is converted to straight alpha.
+#[inline]
+pub fn encode(image: &DynamicImage, options: &Options) -> Result<Vec<u8>, Error> {
+    image.visit(Visitor(options))
+}
+
+/// Encode a borrowed image, e.g. an `[RGBA8]` buffer. See [`encode`].
+pub fn encode_image<P: DynamicPixel>(
+    image: ImageRef<'_, P>,
+    options: &Options,
+) -> Result<Vec<u8>, Error> {
+    let (width, height) = (image.width(), image.height());
+    if width == 0 || height == 0 {
+        return Err(Error::BadHeader);
+    }
+    if width > i32::MAX as usize || height > i32::MAX as usize {
+        return Err(Error::TooLarge);
+    }
+
+    let src = P::FORMAT;
+    let color_type = match (src.order, src.alpha != AlphaKind::None) {
+        (ComponentOrder::Gray | ComponentOrder::GrayAlpha, false) => ColorType::Gray,
+        (ComponentOrder::Gray | ComponentOrder::GrayAlpha, true) => ColorType::GrayAlpha,
+        (_, false) => ColorType::Rgb,
+        (_, true) => ColorType::Rgba,
+    };
+    let depth = if src.depth > 8 { 16 } else { 8 };
+    let dst = match color_type {
+        ColorType::Gray => PixelFormat::GRAY8,
+        ColorType::GrayAlpha => PixelFormat::GRAYA8,
+        ColorType::Rgb => PixelFormat::RGB8,
+        _ => PixelFormat::RGBA8,
+    };
+    // PNG 的 16-bit 样本是 big-endian
+    let dst = PixelFormat { depth, ..dst }.with_endian(Endian::Big);
+    let row_len = width * dst.bytes_per_pixel();
+    let mut samples = vec![0; row_len * height];
+    for (row, out) in image.rows().zip(samples.chunks_exact_mut(row_len)) {
+        convert(as_bytes(row), src, out, dst, width, 1, (row_len, row_len))
+            .map_err(|_| Error::BadData)?;
+    }
+
+    let indexed = if options.palette && depth == 8 {
+        match color_type {
+            ColorType::Rgb | ColorType::Rgba => index(&samples, color_type.channels(), width),
+            _ => None,
+        }
+    } else {
+        None
+    };
+
+    let mut out = SIGNATURE.to_vec();
+    let (bit_depth, color_type) = match &indexed {
+        Some(p) => (p.depth, ColorType::Indexed),
+        None => (depth, color_type),
+    };
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[bit_depth, color_type as u8, 0, 0, 0]);
+    chunk(&mut out, b"IHDR", &ihdr);
+    if let Some(intent) = options.srgb {
+        chunk(&mut out, b"sRGB", &[intent as u8]);
+    }
+    if let Some(gamma) = options.gamma {
+        chunk(&mut out, b"gAMA", &gamma.to_be_bytes());
+    }
+
+    let (rows, row_len, bpp, filter) = match &indexed {
+        Some(p) => {
+            chunk(&mut out, b"PLTE", &p.plte);
+            if !p.trns.is_empty() {
+                chunk(&mut out, b"tRNS", &p.trns);
+            }
+            let filter = match options.filter {
+                Filter::Adaptive => Filter::None,
+                f => f,
+            };
+            (&p.rows[..], p.rows.len() / height, 1, filter)
+        }
+        None => (&samples[..], row_len, dst.bytes_per_pixel(), options.filter),
+    };
+
+    let mut filtered = Vec::with_capacity((row_len + 1) * height);
+    let mut scratch = vec![0; row_len];
+    let mut prev = vec![0; row_len];
+    for row in rows.chunks_exact(row_len) {
+        filter_row(filter, row, &prev, bpp, &mut scratch, &mut filtered);
+        prev.copy_from_slice(row);
+    }
+
+    let idat = zlib::compress(&filtered, options.level);
+    for part in idat.chunks(IDAT_SIZE) {
+        chunk(&mut out, b"IDAT", part);
+    }
+    chunk(&mut out, b"IEND", &[]);
+    Ok(out)
+}
+
+struct Visitor<'a>(&'a Options);
+
+impl ImageVisitor for Visitor<'_> {
+    type Output = Result<Vec<u8>, Error>;
+
+    #[inline]
+    fn visit<P: DynamicPixel>(self, image: &Image<P>) -> Self::Output {
+        encode_image(image.view(), self.0)
+    }
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc32(&[kind, data]).to_be_bytes());
+}
+
+/// Palette, its transparency, and packed rows of indices
+struct Indexed {
+    depth: u8,
+    plte: Vec<u8>,
+    trns: Vec<u8>,
+    rows: Vec<u8>,
+}
+
+/// Index the image if it has at most 256 colors
+fn index(samples: &[u8], channels: usize, width: usize) -> Option<Indexed> {
+    let mut seen = HashSet::new();
+    let mut order = Vec::new();
+    for px in samples.chunks_exact(channels) {
+        let rgba = [px[0], px[1], px[2], px.get(3).copied().unwrap_or(255)];
+        if seen.insert(rgba) {
+            if order.len() == 256 {
+                return None;
+            }
+            order.push(rgba);
+        }
+    }
+    // 透明的颜色放在前面, tRNS 可以短一些
+    order.sort_by_key(|c| c[3] == 255);
+    let colors: HashMap<_, _> = order
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (*c, i as u8))
+        .collect();
+
+    let depth = match order.len() {
+        0..=2 => 1,
+        3..=4 => 2,
+        5..=16 => 4,
+        _ => 8,
+    };
+    let per_byte = 8 / depth as usize;
+    let row_len = width.div_ceil(per_byte);
+    let mut rows = vec![0; row_len * (samples.len() / channels / width)];
+    for (row, out) in samples
+        .chunks_exact(width * channels)
+        .zip(rows.chunks_exact_mut(row_len))
+    {
+        for (x, px) in row.chunks_exact(channels).enumerate() {
+            let rgba = [px[0], px[1], px[2], px.get(3).copied().unwrap_or(255)];
+            let shift = 8 - depth as usize * (x % per_byte + 1);
+            out[x / per_byte] |= colors[&rgba] << shift;
+        }
+    }
+
+    let plte = order.iter().flat_map(|c| [c[0], c[1], c[2]]).collect();
+    let mut trns: Vec<u8> = order
+        .iter()
+        .map(|c| c[3])
+        .take_while(|&a| a < 255)
+        .collect();
+    // 没有 tRNS 的调色板图读回来是 RGB, 源图有 alpha 就至少写一项
+    if channels == 4 && trns.is_empty() {
+        trns.push(255);
+    }
+    Some(Indexed {
+        depth,
+        plte,
+        trns,
+        rows,
+    })
+}
+
+/// Append the filter type and the filtered row to `out`
+fn filter_row(
+    filter: Filter,
+    row: &[u8],
+    prev: &[u8],
+    bpp: usize,
+    scratch: &mut [u8],
+    out: &mut Vec<u8>,
+) {
+    if filter != Filter::Adaptive {
+        apply(filter, row, prev, bpp, scratch);
+        out.push(filter as u8);
+        out.extend_from_slice(scratch);
+        return;
+    }
+    // 按 libpng 的办法, 选有符号字节绝对值之和最小的过滤器
+    let mut best = (u64::MAX, Filter::None);
+    for f in [
+        Filter::None,
+        Filter::Sub,
+        Filter::Up,
+        Filter::Average,
+        Filter::Paeth,
+    ] {
+        apply(f, row, prev, bpp, scratch);
+        let cost = scratch
+            .iter()
+            .map(|&b| (b as i8).unsigned_abs() as u64)
+            .sum();
+        if cost < best.0 {
+            best = (cost, f);
+        }
+    }
+    apply(best.1, row, prev, bpp, scratch);
+    out.push(best.1 as u8);
+    out.extend_from_slice(scratch);
+}
+
+fn apply(filter: Filter, row: &[u8], prev: &[u8], bpp: usize, out: &mut [u8]) {
+    for i in 0..row.len() {
+        let a = if i >= bpp { row[i - bpp] } else { 0 };
+        let b = prev[i];
+        let c = if i >= bpp { prev[i - bpp] } else { 0 };
+        let predicted = match filter {
+            Filter::None | Filter::Adaptive => 0,
+            Filter::Sub => a,
+            Filter::Up => b,
+            Filter::Average => ((a as u16 + b as u16) / 2) as u8,
+            Filter::Paeth => paeth(a, b, c),
+        };
+        out[i] = row[i].wrapping_sub(predicted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{decode, read_info};
+    use super::*;
+    use crate::alt::*;
+    use crate::{RGB16, RGB8, RGBA16, RGBA8};
+
+    fn gradient(width: usize, height: usize) -> Image<RGBA8> {
+        let buf = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                RGBA8::new((x * 7) as u8, (y * 13) as u8, (x * y) as u8, !(x as u8))
+            })
+            .collect();
+        Image::new(buf, width, height)
+    }
+
+    fn map<Q>(image: &Image<RGBA8>, f: impl Fn(&RGBA8) -> Q) -> Image<Q> {
+        Image::new(
+            image.buf().iter().map(f).collect(),
+            image.width(),
+            image.height(),
+        )
+    }
+
+    fn round_trip<P: DynamicPixel + PartialEq + core::fmt::Debug>(image: &Image<P>) {
+        let png = encode(&P::into_dynamic(image.clone()), &Options::default()).unwrap();
+        let (_, decoded) = decode(&png).unwrap();
+        assert_eq!(decoded.width(), image.width());
+        assert_eq!(decoded.height(), image.height());
+        assert_eq!(decoded.to_image::<P>().buf(), image.buf());
+    }
+
+    #[test]
+    fn round_trips() {
+        let rgba = gradient(37, 11);
+        let png = encode_image(rgba.view(), &Options::default()).unwrap();
+        let (info, decoded) = decode(&png).unwrap();
+        assert_eq!((info.color_type, info.bit_depth), (ColorType::Rgba, 8));
+        assert_eq!(decoded.as_image::<RGBA8>().unwrap().buf(), rgba.buf());
+        // 同样的输入必须得到完全相同的文件
+        assert_eq!(encode_image(rgba.view(), &Options::default()).unwrap(), png);
+
+        round_trip(&rgba);
+        round_trip(&map(&rgba, |p| p.rgb()));
+        round_trip(&map(&rgba, |p| {
+            RGBA16::new(p.r as u16 * 257, p.g as u16, 65535 - p.b as u16, 1000)
+        }));
+        round_trip(&map(&rgba, |p| {
+            RGB16::new(p.r as u16 * 3, p.g as u16 * 250, 7)
+        }));
+        round_trip(&map(&rgba, |p| BGR8 {
+            b: p.b,
+            g: p.g,
+            r: p.r,
+        }));
+        round_trip(&map(&rgba, |p| BGRA8 {
+            b: p.b,
+            g: p.g,
+            r: p.r,
+            a: p.a,
+        }));
+        round_trip(&map(&rgba, |p| BGR16 {
+            b: p.b as u16 * 256,
+            g: 1,
+            r: p.r as u16,
+        }));
+        round_trip(&map(&rgba, |p| BGRA16 {
+            b: p.b as u16,
+            g: 2,
+            r: 3,
+            a: p.a as u16 * 250,
+        }));
+        round_trip(&map(&rgba, |p| Gray::new(p.r)));
+        round_trip(&map(&rgba, |p| Gray::new(p.r as u16 * 200 + p.g as u16)));
+        round_trip(&map(&rgba, |p| GrayAlpha::new(p.r, p.a)));
+        round_trip(&map(&rgba, |p| {
+            GrayAlpha::new(p.g as u16 * 255, p.a as u16)
+        }));
+        #[cfg(feature = "argb")]
+        {
+            round_trip(&map(&rgba, |p| ARGB8 {
+                a: p.a,
+                r: p.r,
+                g: p.g,
+                b: p.b,
+            }));
+            round_trip(&map(&rgba, |p| ABGR16 {
+                a: p.a as u16,
+                b: 9,
+                g: 8,
+                r: p.r as u16,
+            }));
+        }
+
+        let bgr = map(&rgba, |p| BGR8 {
+            b: p.b,
+            g: p.g,
+            r: p.r,
+        });
+        let (info, _) = decode(&encode_image(bgr.view(), &Options::default()).unwrap()).unwrap();
+        assert_eq!((info.color_type, info.bit_depth), (ColorType::Rgb, 8));
+        let gray = map(&rgba, |p| Gray::new(p.r as u16));
+        let (info, _) = decode(&encode_image(gray.view(), &Options::default()).unwrap()).unwrap();
+        assert_eq!((info.color_type, info.bit_depth), (ColorType::Gray, 16));
+    }
+
+    #[test]
+    fn filters_and_levels() {
+        let rgba = gradient(64, 40);
+        let mut sizes = Vec::new();
+        for filter in [
+            Filter::None,
+            Filter::Sub,
+            Filter::Up,
+            Filter::Average,
+            Filter::Paeth,
+            Filter::Adaptive,
+        ] {
+            for level in [0, 1, 6, 9] {
+                let options = Options {
+                    level,
+                    filter,
+                    ..Options::default()
+                };
+                let png = encode_image(rgba.view(), &options).unwrap();
+                let (_, decoded) = decode(&png).unwrap();
+                assert_eq!(decoded.as_image::<RGBA8>().unwrap().buf(), rgba.buf());
+                if level == 6 {
+                    sizes.push(png.len());
+                }
+            }
+        }
+        // 渐变图像用过滤器会比不用小很多
+        assert!(sizes[5] < sizes[0] / 2);
+
+        let big = gradient(700, 500);
+        let png = encode_image(
+            big.view(),
+            &Options {
+                level: 0,
+                ..Options::default()
+            },
+        )
+        .unwrap();
+        assert!(png.len() > 1 << 20);
+        assert_eq!(decode(&png).unwrap().1.to_rgba8().buf(), big.buf());
+    }
+
+    #[test]
+    fn palette() {
+        let colors = [
+            RGBA8::new(255, 0, 0, 255),
+            RGBA8::new(0, 0, 255, 0),
+            RGBA8::new(0, 255, 0, 255),
+            RGBA8::new(1, 2, 3, 128),
+            RGBA8::new(9, 9, 9, 255),
+        ];
+        let options = Options {
+            palette: true,
+            ..Options::default()
+        };
+        for count in [1, 2, 4, 5] {
+            let buf: Vec<_> = (0..13 * 5).map(|i| colors[i * 7 % count]).collect();
+            let image = ImageRef::new(&buf, 13, 5);
+            let png = encode_image(image, &options).unwrap();
+            let (info, decoded) = decode(&png).unwrap();
+            assert_eq!(info.color_type, ColorType::Indexed);
+            let depth = match count {
+                1..=2 => 1,
+                3..=4 => 2,
+                _ => 4,
+            };
+            assert_eq!(info.bit_depth, depth);
+            assert_eq!(decoded.to_rgba8().buf(), buf);
+        }
+
+        // 不透明的 RGBA 也要读回 RGBA
+        let opaque: Vec<_> = (0..12u8).map(|i| RGBA8::new(i % 3, 0, 9, 255)).collect();
+        let png = encode_image(ImageRef::new(&opaque, 4, 3), &options).unwrap();
+        let (info, decoded) = decode(&png).unwrap();
+        assert_eq!(info.color_type, ColorType::Indexed);
+        assert_eq!(decoded.as_image::<RGBA8>().unwrap().buf(), opaque);
+
+        // RGB 全部不透明就不写 tRNS
+        let rgb: Vec<_> = (0..300u32)
+            .map(|i| RGB8::new(i as u8 % 200, 7, 0))
+            .collect();
+        let png = encode_image(ImageRef::new(&rgb, 30, 10), &options).unwrap();
+        let (info, decoded) = decode(&png).unwrap();
+        assert_eq!((info.color_type, info.bit_depth), (ColorType::Indexed, 8));
+        assert_eq!(decoded.as_image::<RGB8>().unwrap().buf(), rgb);
+
+        // 超过 256 种颜色, 或者 16-bit, 就不用调色板
+        let many: Vec<_> = (0..300u32)
+            .map(|i| RGB8::new(i as u8, (i >> 8) as u8, 0))
+            .collect();
+        let png = encode_image(ImageRef::new(&many, 30, 10), &options).unwrap();
+        assert_eq!(read_info(&png).unwrap().color_type, ColorType::Rgb);
+        let wide: Vec<_> = (0..4).map(|_| RGB16::new(1, 2, 3)).collect();
+        let png = encode_image(ImageRef::new(&wide, 2, 2), &options).unwrap();
+        assert_eq!(read_info(&png).unwrap().color_type, ColorType::Rgb);
+    }
+
+    #[test]
+    fn encode_errors() {
+        let options = Options::default();
+        let empty: [RGB8; 0] = [];
+        assert_eq!(
+            encode_image(ImageRef::new(&empty, 0, 3), &options),
+            Err(Error::BadHeader)
+        );
+        assert_eq!(
+            encode_image(ImageRef::new(&empty, 3, 0), &options),
+            Err(Error::BadHeader)
+        );
+        let empty = DynamicImage::from(Image::<GRAY8>::new(Vec::new(), 0, 0));
+        assert_eq!(encode(&empty, &options), Err(Error::BadHeader));
+    }
+
+    #[test]
+    fn palette_limit() {
+        // 正好 256 种颜色还能用调色板, 257 种就不行
+        let options = Options {
+            palette: true,
+            ..Options::default()
+        };
+        let colors = |n: u32| -> Vec<RGBA8> {
+            (0..n)
+                .map(|i| RGBA8::new(i as u8, (i >> 8) as u8, 0, if i < 3 { 0 } else { 255 }))
+                .collect()
+        };
+        let full = colors(256);
+        let png = encode_image(ImageRef::new(&full, 16, 16), &options).unwrap();
+        let (info, decoded) = decode(&png).unwrap();
+        assert_eq!((info.color_type, info.bit_depth), (ColorType::Indexed, 8));
+        assert_eq!(decoded.as_image::<RGBA8>().unwrap().buf(), full);
+        let over = colors(257);
+        let png = encode_image(ImageRef::new(&over, 257, 1), &options).unwrap();
+        assert_eq!(read_info(&png).unwrap().color_type, ColorType::Rgba);
+    }
+
+    #[test]
+    fn color_space() {
+        let buf = [GRAY8::new(5); 6];
+        let options = Options {
+            srgb: Some(RenderingIntent::RelativeColorimetric),
+            gamma: Some(45455),
+            ..Options::default()
+        };
+        let png = encode_image(ImageRef::new(&buf, 3, 2), &options).unwrap();
+        let info = read_info(&png).unwrap();
+        assert_eq!(info.srgb, Some(RenderingIntent::RelativeColorimetric));
+        assert_eq!(info.gamma, Some(45455));
+        let info =
+            read_info(&encode_image(ImageRef::new(&buf, 3, 2), &Options::default()).unwrap())
+                .unwrap();
+        assert_eq!((info.srgb, info.gamma), (None, None));
+
+        assert_eq!(
+            encode_image(ImageRef::new(&buf, 0, 2), &options),
+            Err(Error::BadHeader)
+        );
+    }
+}
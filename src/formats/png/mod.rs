@@ -0,0 +1,247 @@
+//! PNG, with its own zlib implementation.
+//!
+//! Decoding supports every color type and bit depth, Adam7 interlacing, palettes and
+//! `tRNS` transparency. 16-bit samples are converted from big-endian to native `u16`,
+//! and 1/2/4-bit gray is scaled to 8 bits. `gAMA` and `sRGB` are reported in [`Info`].
+//!
+//! [`encode`] writes any pixel type: gray stays gray, other layouts become RGB or RGBA, with
+//! 8 or 16 bits like the source. See [`Options`] for the compression level, row filters,
+//! palette output for images with few colors, and the `sRGB`/`gAMA` chunks.
+//!
+//! | PNG | Output |
+//! |-----|--------|
+//! | Gray 1/2/4/8-bit | `GRAY8`, or `GRAYA8` with `tRNS` |
+//! | Gray 16-bit | `GRAY16`, or `GRAYA16` with `tRNS` |
+//! | RGB | `RGB8`/`RGB16`, or `RGBA8`/`RGBA16` with `tRNS` |
+//! | Indexed | `RGB8`, or `RGBA8` with `tRNS` |
+//! | Gray + alpha | `GRAYA8`/`GRAYA16` |
+//! | RGBA | `RGBA8`/`RGBA16` |
+//!
+//! ```rust
+//! use cr::formats::png::{self, RenderingIntent};
+//! use cr::RGB8;
+//!
+//! let file = b"\x89PNG\x0d\x0a\x1a\x0a\
+//!     \x00\x00\x00\x0dIHDR\x00\x00\x00\x01\x00\x00\x00\x01\x08\x02\x00\x00\x00\x90wS\xde\
+//!     \x00\x00\x00\x01sRGB\x00\xae\xce\x1c\xe9\
+//!     \x00\x00\x00\x0cIDATx\x9cc\xf8\xcf\xc0\x00\x00\x03\x01\x01\x00\xc9\xfe\x92\xef\
+//!     \x00\x00\x00\x00IEND\xaeB\x60\x82";
+//! let (info, image) = png::decode(file).unwrap();
+//! assert_eq!(info.srgb, Some(RenderingIntent::Perceptual));
+//! assert_eq!(image.as_image::<RGB8>().unwrap().buf(), [RGB8::new(255, 0, 0)]);
+//!
+//! let options = png::Options { palette: true, ..png::Options::default() };
+//! let again = png::encode(&image, &options).unwrap();
+//! assert_eq!(png::read_info(&again).unwrap().color_type, png::ColorType::Indexed);
+//! assert_eq!(png::decode(&again).unwrap().1, image);
+//! ```
+
+use core::fmt;
+
+mod decode;
+mod deflate;
+mod encode;
+mod zlib;
+
+pub use self::decode::*;
+pub use self::encode::*;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Why a PNG file couldn't be decoded or encoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// Doesn't start with the PNG signature
+    BadSignature,
+    /// Invalid `IHDR`, or a combination of color type and bit depth PNG doesn't allow.
+    /// When encoding: the image is empty.
+    BadHeader,
+    /// Chunk is malformed, in the wrong place, or an unknown critical chunk
+    BadChunk,
+    /// Chunk's CRC doesn't match its contents
+    BadCrc,
+    /// Missing palette, or a pixel refers to a color past its end
+    BadPalette,
+    /// Invalid zlib stream or checksum
+    BadZlib,
+    /// Unknown filter type, or decompressed data of the wrong size
+    BadData,
+    /// The file ends early
+    UnexpectedEof,
+    /// Width × height doesn't fit in memory
+    TooLarge,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::BadSignature => "not a PNG file",
+            Error::BadHeader => "invalid PNG header",
+            Error::BadChunk => "invalid PNG chunk",
+            Error::BadCrc => "PNG chunk CRC mismatch",
+            Error::BadPalette => "invalid palette or palette index",
+            Error::BadZlib => "invalid zlib data",
+            Error::BadData => "invalid image data",
+            Error::UnexpectedEof => "file is truncated",
+            Error::TooLarge => "image is too large",
+        })
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Color type from `IHDR`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ColorType {
+    Gray = 0,
+    Rgb = 2,
+    Indexed = 3,
+    GrayAlpha = 4,
+    Rgba = 6,
+}
+
+impl ColorType {
+    /// Samples per pixel
+    #[inline]
+    pub fn channels(self) -> usize {
+        match self {
+            ColorType::Gray | ColorType::Indexed => 1,
+            ColorType::GrayAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// Bit depths PNG allows for this color type
+    #[inline]
+    fn allows_depth(self, depth: u8) -> bool {
+        match self {
+            ColorType::Gray => matches!(depth, 1 | 2 | 4 | 8 | 16),
+            ColorType::Indexed => matches!(depth, 1 | 2 | 4 | 8),
+            _ => matches!(depth, 8 | 16),
+        }
+    }
+}
+
+/// Rendering intent from the `sRGB` chunk
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RenderingIntent {
+    Perceptual = 0,
+    RelativeColorimetric = 1,
+    Saturation = 2,
+    AbsoluteColorimetric = 3,
+}
+
+/// Header and color space information of a PNG file
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Info {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub interlaced: bool,
+    /// `gAMA`: file gamma × 100000, e.g. 45455 for 1/2.2
+    pub gamma: Option<u32>,
+    /// `sRGB`: the image is sRGB, with this intent
+    pub srgb: Option<RenderingIntent>,
+}
+
+impl Info {
+    /// File gamma as a number, e.g. 0.45455
+    #[inline]
+    pub fn gamma(&self) -> Option<f64> {
+        self.gamma.map(|g| g as f64 / 100_000.)
+    }
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+static CRC_TABLE: [u32; 256] = crc_table();
+
+/// CRC-32 of a chunk's type and data
+pub(crate) fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut c = !0u32;
+    for part in parts {
+        for &b in *part {
+            c = CRC_TABLE[((c ^ b as u32) & 0xFF) as usize] ^ (c >> 8);
+        }
+    }
+    !c
+}
+
+/// Paeth predictor from the left, above and upper left bytes
+#[inline]
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Adam7 passes: x, y offsets and steps
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// `(x0, y0, dx, dy, width, height)` of each pass, or the whole image if not interlaced
+fn passes(width: usize, height: usize, interlaced: bool) -> impl Iterator<Item = [usize; 6]> {
+    let passes: &[_] = if interlaced { &ADAM7 } else { &[(0, 0, 1, 1)] };
+    passes.iter().map(move |&(x0, y0, dx, dy)| {
+        let w = width.saturating_sub(x0).div_ceil(dx);
+        let h = height.saturating_sub(y0).div_ceil(dy);
+        [x0, y0, dx, dy, w, h]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc_and_passes() {
+        assert_eq!(crc32(&[b"IEND"]), 0xAE42_6082);
+        assert_eq!(crc32(&[b"IE", b"ND"]), 0xAE42_6082);
+        assert_eq!(crc32(&[b"123456789"]), 0xCBF4_3926);
+
+        let sizes: Vec<_> = passes(5, 3, true).map(|p| (p[4], p[5])).collect();
+        assert_eq!(
+            sizes,
+            [(1, 1), (1, 1), (2, 0), (1, 1), (3, 1), (2, 2), (5, 1)]
+        );
+        let total: usize = passes(33, 17, true).map(|p| p[4] * p[5]).sum();
+        assert_eq!(total, 33 * 17);
+    }
+}
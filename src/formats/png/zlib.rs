@@ -0,0 +1,413 @@
+//! zlib (RFC 1950) around deflate and inflate (RFC 1951), for PNG's `IDAT` data
+
+use super::Error;
+
+/// Adler-32 of the uncompressed data, at the end of a zlib stream
+pub(crate) fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 是 b 不会溢出 u32 的最大块长度
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// Decompress a zlib stream that must produce exactly `len` bytes
+pub(crate) fn decompress(data: &[u8], len: usize) -> Result<Vec<u8>, Error> {
+    let (cmf, flg) = match data {
+        [cmf, flg, ..] => (*cmf, *flg),
+        _ => return Err(Error::UnexpectedEof),
+    };
+    // 压缩方法 8 (deflate), 窗口最大 32K, 不支持预设字典
+    if cmf & 0x0F != 8
+        || cmf >> 4 > 7
+        || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31)
+        || flg & 0x20 != 0
+    {
+        return Err(Error::BadZlib);
+    }
+
+    let mut bits = Bits::new(&data[2..]);
+    let out = inflate(&mut bits, len)?;
+    if out.len() != len {
+        return Err(Error::BadData);
+    }
+    bits.align();
+    let checksum = (0..4).try_fold(0u32, |sum, _| Ok::<_, Error>((sum << 8) | bits.bits(8)?))?;
+    if checksum != adler32(&out) {
+        return Err(Error::BadZlib);
+    }
+    Ok(out)
+}
+
+/// Compress into a zlib stream, at `level` 0 (no compression) to 9 (smallest)
+pub(crate) fn compress(data: &[u8], level: u8) -> Vec<u8> {
+    let cmf = 0x78u8;
+    // FLEVEL 只是提示, 解码器不用它
+    let flevel = match level {
+        0..=1 => 0,
+        2..=5 => 1,
+        6 => 2,
+        _ => 3,
+    };
+    let mut flg = flevel << 6;
+    flg += (31 - (cmf as u16 * 256 + flg as u16) % 31) as u8 % 31;
+
+    let mut out = vec![cmf, flg];
+    out.extend(super::deflate::deflate(data, level));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// Reads bits least significant first, as deflate packs them
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u64,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            buf: 0,
+            count: 0,
+        }
+    }
+
+    /// Load up to `n` bits, fewer at the end of the data
+    #[inline]
+    fn fill(&mut self, n: u32) {
+        while self.count < n {
+            let Some(&b) = self.data.get(self.pos) else {
+                return;
+            };
+            self.pos += 1;
+            self.buf |= (b as u64) << self.count;
+            self.count += 8;
+        }
+    }
+
+    #[inline]
+    fn consume(&mut self, n: u32) {
+        self.buf >>= n;
+        self.count -= n;
+    }
+
+    #[inline]
+    fn bits(&mut self, n: u32) -> Result<u32, Error> {
+        self.fill(n);
+        if self.count < n {
+            return Err(Error::UnexpectedEof);
+        }
+        let v = (self.buf & ((1u64 << n) - 1)) as u32;
+        self.consume(n);
+        Ok(v)
+    }
+
+    /// Skip to the next byte boundary
+    #[inline]
+    fn align(&mut self) {
+        self.consume(self.count % 8);
+    }
+}
+
+/// Canonical Huffman code, decoded with a single table indexed by the next `bits` bits
+struct Huffman {
+    /// `symbol << 4 | length`, 0 for unused codes
+    table: Vec<u16>,
+    bits: u32,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, Error> {
+        let mut count = [0u16; 16];
+        for &len in lengths {
+            count[len as usize] += 1;
+        }
+        count[0] = 0;
+        // 检查编码没有超额分配
+        let mut left = 1i32;
+        for &n in &count[1..] {
+            left = (left << 1) - n as i32;
+            if left < 0 {
+                return Err(Error::BadZlib);
+            }
+        }
+
+        let bits = lengths.iter().copied().max().unwrap_or(0).max(1) as u32;
+        let mut next = [0u16; 16];
+        for len in 1..16 {
+            next[len] = (next[len - 1] + count[len - 1]) << 1;
+        }
+        let mut table = vec![0u16; 1 << bits];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len == 0 {
+                continue;
+            }
+            let code = next[len as usize];
+            next[len as usize] += 1;
+            let reversed = (code.reverse_bits() >> (16 - len)) as usize;
+            let entry = (symbol as u16) << 4 | len as u16;
+            for i in (reversed..table.len()).step_by(1 << len) {
+                table[i] = entry;
+            }
+        }
+        Ok(Self { table, bits })
+    }
+
+    #[inline]
+    fn decode(&self, bits: &mut Bits<'_>) -> Result<u16, Error> {
+        bits.fill(self.bits);
+        let entry = self.table[(bits.buf & ((1 << self.bits) - 1)) as usize];
+        let len = (entry & 15) as u32;
+        if len == 0 {
+            return Err(Error::BadZlib);
+        }
+        if len > bits.count {
+            return Err(Error::UnexpectedEof);
+        }
+        bits.consume(len);
+        Ok(entry >> 4)
+    }
+}
+
+pub(super) const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+pub(super) const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+pub(super) const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+pub(super) const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Order of the code length code lengths in a dynamic block header
+pub(super) const CLEN_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [8u8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    (
+        Huffman::new(&lengths).expect("valid fixed code"),
+        Huffman::new(&[5; 30]).expect("valid fixed code"),
+    )
+}
+
+fn dynamic_codes(bits: &mut Bits<'_>) -> Result<(Huffman, Huffman), Error> {
+    let hlit = bits.bits(5)? as usize + 257;
+    let hdist = bits.bits(5)? as usize + 1;
+    let hclen = bits.bits(4)? as usize + 4;
+    if hlit > 286 || hdist > 30 {
+        return Err(Error::BadZlib);
+    }
+    let mut clen = [0u8; 19];
+    for &i in &CLEN_ORDER[..hclen] {
+        clen[i] = bits.bits(3)? as u8;
+    }
+    let clen = Huffman::new(&clen)?;
+
+    let mut lengths = [0u8; 286 + 30];
+    let mut i = 0;
+    while i < hlit + hdist {
+        let (value, repeat) = match clen.decode(bits)? {
+            len @ 0..=15 => (len as u8, 1),
+            16 if i > 0 => (lengths[i - 1], 3 + bits.bits(2)? as usize),
+            17 => (0, 3 + bits.bits(3)? as usize),
+            18 => (0, 11 + bits.bits(7)? as usize),
+            _ => return Err(Error::BadZlib),
+        };
+        let run = lengths
+            .get_mut(i..i + repeat)
+            .filter(|_| i + repeat <= hlit + hdist);
+        run.ok_or(Error::BadZlib)?.fill(value);
+        i += repeat;
+    }
+    // 没有 end-of-block 码就无法结束这个块
+    if lengths[256] == 0 {
+        return Err(Error::BadZlib);
+    }
+    Ok((
+        Huffman::new(&lengths[..hlit])?,
+        Huffman::new(&lengths[hlit..hlit + hdist])?,
+    ))
+}
+
+/// Raw deflate data. Producing more than `limit` bytes is an error.
+fn inflate(bits: &mut Bits<'_>, limit: usize) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::with_capacity(limit);
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                bits.align();
+                let len = bits.bits(16)?;
+                if bits.bits(16)? != !len & 0xFFFF {
+                    return Err(Error::BadZlib);
+                }
+                if out.len() + len as usize > limit {
+                    return Err(Error::BadData);
+                }
+                for _ in 0..len {
+                    out.push(bits.bits(8)? as u8);
+                }
+            }
+            kind @ (1 | 2) => {
+                let (lit, dist) = if kind == 1 {
+                    fixed_codes()
+                } else {
+                    dynamic_codes(bits)?
+                };
+                loop {
+                    let symbol = lit.decode(bits)? as usize;
+                    if symbol < 256 {
+                        if out.len() == limit {
+                            return Err(Error::BadData);
+                        }
+                        out.push(symbol as u8);
+                        continue;
+                    }
+                    if symbol == 256 {
+                        break;
+                    }
+                    let i = symbol - 257;
+                    if i >= LENGTH_BASE.len() {
+                        return Err(Error::BadZlib);
+                    }
+                    let len = LENGTH_BASE[i] as usize + bits.bits(LENGTH_EXTRA[i] as u32)? as usize;
+                    let d = dist.decode(bits)? as usize;
+                    if d >= DIST_BASE.len() {
+                        return Err(Error::BadZlib);
+                    }
+                    let distance =
+                        DIST_BASE[d] as usize + bits.bits(DIST_EXTRA[d] as u32)? as usize;
+                    if distance > out.len() {
+                        return Err(Error::BadZlib);
+                    }
+                    if out.len() + len > limit {
+                        return Err(Error::BadData);
+                    }
+                    // 可能和自己重叠, 所以逐字节复制
+                    let start = out.len() - distance;
+                    for k in 0..len {
+                        out.push(out[start + k]);
+                    }
+                }
+            }
+            _ => return Err(Error::BadZlib),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    const TEXT: &[u8] = b"Hello, hello, hello! The quick brown fox jumps over the lazy dog. ";
+
+    #[test]
+    fn inflate_blocks() {
+        let text = TEXT.repeat(3);
+        let stored = hex("780101c60039ff48656c6c6f2c2068656c6c6f2c2068656c6c6f212054686520717569636b2062726f776e20666f78206a756d7073206f76657220746865206c617a7920646f672e2048656c6c6f2c2068656c6c6f2c2068656c6c6f212054686520717569636b2062726f776e20666f78206a756d7073206f76657220746865206c617a7920646f672e2048656c6c6f2c2068656c6c6f2c2068656c6c6f212054686520717569636b2062726f776e20666f78206a756d7073206f76657220746865206c617a7920646f672e20ef144555");
+        assert_eq!(decompress(&stored, text.len()).unwrap(), text);
+        let fixed = hex("7801f348cdc9c9d751c840a21415423252150a4b3393b315928af2cbf314d2f22b14b24a730b8a15f2cb528b144a80d2398955950a29f9e97a0a1e83c00400ef144555");
+        assert_eq!(decompress(&fixed, text.len()).unwrap(), text);
+
+        // 两个动态块, 中间有 sync flush 产生的空 stored 块
+        let expected: Vec<u8> = (0..3000u32)
+            .map(|i| (((i * 7) % 13) * ((i * 3) % 11) % 97 + 20) as u8)
+            .collect();
+        let dynamic = hex("78daeccecf0a82300007e097f8e59fa973a98c590b6518ee2092601ecc836074102f814187f0d4fbd34374ddf7041f52b2583ab5fd5b058e6957441be90ac44b8fdc5b9d46823d1a26315b558260d4a1788f287d903ea3c9b3930ab05b1eb0b93a9cbe401df8fea484f8406b78ce2078bce2ac42822b4de802252db7ad11b977647c732ee5916180642fbbcaf7746c613aa6633aa6633aff777e000000ffffedcecf0a82300007e097f8e59fe9e65219b316ca30f42092601ecc836074102f814187f0d4fbd34374ddf7041fe57375387d819afafea4a5fca02ce1398314d18ab30e08ae2c660bb4b2dcb646e8de918acdb914478e018abfec2adbb3b1454216ab4c6cff564160dae5e146ba1cd1d223f356a751e08f862bcc5615838e6520df230a1fa44f59fcec9406ec5650d3311dd3311dd3f9bff3032fa2222a");
+        assert_eq!(decompress(&dynamic, expected.len()).unwrap(), expected);
+
+        // 长度必须完全一致
+        assert_eq!(decompress(&fixed, text.len() - 1), Err(Error::BadData));
+        assert_eq!(decompress(&fixed, text.len() + 1), Err(Error::BadData));
+        let mut bad = fixed.clone();
+        *bad.last_mut().unwrap() ^= 1;
+        assert_eq!(decompress(&bad, text.len()), Err(Error::BadZlib));
+        assert_eq!(
+            decompress(&fixed[..fixed.len() - 10], text.len()),
+            Err(Error::UnexpectedEof)
+        );
+        assert_eq!(decompress(&[0x78, 0x02], 0), Err(Error::BadZlib));
+        // 保留的块类型 3
+        assert_eq!(decompress(&[0x78, 0x01, 0x07], 0), Err(Error::BadZlib));
+    }
+
+    #[test]
+    fn compress_levels() {
+        let mut noise = 0x1234_5678u32;
+        let inputs = [
+            Vec::new(),
+            vec![7],
+            TEXT.repeat(50),
+            vec![0; 200_000],
+            // 不可压缩的数据超过 64K, 需要多个 stored 块
+            (0..150_000)
+                .map(|_| {
+                    noise ^= noise << 13;
+                    noise ^= noise >> 17;
+                    noise ^= noise << 5;
+                    noise as u8
+                })
+                .collect(),
+            (0..100_000u64).map(|i| (i * i / 7 % 251) as u8).collect(),
+        ];
+        for data in &inputs {
+            let mut sizes = Vec::new();
+            for level in 0..=9 {
+                let z = compress(data, level);
+                assert!(z.len() <= data.len() + data.len() / 1000 + 11);
+                assert_eq!(decompress(&z, data.len()).unwrap(), *data, "level {level}");
+                sizes.push(z.len());
+            }
+            // 级别越高不应该越大
+            assert!(sizes[1..].windows(2).all(|w| w[1] <= w[0] + 8));
+        }
+        assert!(compress(&inputs[3], 9).len() < 300);
+        assert!(compress(&TEXT.repeat(50), 9).len() < 150);
+        assert_eq!(compress(b"", 6)[..2], [0x78, 0x9C]);
+        assert_eq!(compress(b"", 9)[..2], [0x78, 0xDA]);
+        assert_eq!(compress(b"", 0)[..2], [0x78, 0x01]);
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        let big = vec![0xFF; 100_000];
+        let (mut a, mut b) = (1u64, 0u64);
+        for &x in &big {
+            a = (a + x as u64) % 65521;
+            b = (b + a) % 65521;
+        }
+        assert_eq!(adler32(&big), (b << 16 | a) as u32);
+    }
+}
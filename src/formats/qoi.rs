@@ -0,0 +1,667 @@
+//! QOI, the "Quite OK Image" format: <https://qoiformat.org/qoi-specification.pdf>
+//!
+//! Works on `[RGB8]` and `[RGBA8]` slices. Decoded pixels are returned as bytes
+//! with the file's channel count, so use `AsPixels` to view them as pixels.
+//!
+//! ```rust
+//! use cr::formats::qoi::{self, Channels, ColorSpace};
+//! use cr::{AsPixels, RGBA8};
+//!
+//! let pixels = [RGBA8::new(255, 0, 0, 255), RGBA8::new(255, 0, 0, 128)];
+//! let file = qoi::encode(&pixels, 2, 1, ColorSpace::Srgb).unwrap();
+//!
+//! let (header, data) = qoi::decode(&file).unwrap();
+//! assert_eq!(header.channels, Channels::Rgba);
+//! let decoded: &[RGBA8] = data.as_pixels();
+//! assert_eq!(decoded, pixels);
+//! ```
+
+use crate::{RGB8, RGBA8};
+use core::fmt;
+use std::io::{self, Write};
+
+const MAGIC: &[u8; 4] = b"qoif";
+const HEADER_LEN: usize = 14;
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+/// The spec's limit, which keeps the decoded size well below 2GB
+pub const MAX_PIXELS: u64 = 400_000_000;
+
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const OP_RUN: u8 = 0xC0;
+const OP_RGB: u8 = 0xFE;
+const OP_RGBA: u8 = 0xFF;
+const MASK: u8 = 0xC0;
+
+/// Channel count stored in the header. It doesn't change how pixels are encoded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Channels {
+    Rgb = 3,
+    Rgba = 4,
+}
+
+/// Color space stored in the header. It's informative only.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// sRGB with linear alpha
+    Srgb = 0,
+    /// All channels linear
+    Linear = 1,
+}
+
+/// Why a QOI file couldn't be decoded, or pixels couldn't be encoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// Doesn't start with `qoif`
+    BadMagic,
+    /// Zero size, or unknown channels or color space
+    BadHeader,
+    /// More than [`MAX_PIXELS`]
+    TooLarge,
+    /// The file ends before all pixels
+    UnexpectedEof,
+    /// A run goes past the last pixel
+    Overrun,
+    /// The pixels aren't followed by the end marker
+    BadEndMarker,
+    /// Number of pixels given to the encoder doesn't match width × height
+    PixelCount,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::BadMagic => "not a QOI file",
+            Error::BadHeader => "invalid QOI header",
+            Error::TooLarge => "image is too large",
+            Error::UnexpectedEof => "file is truncated",
+            Error::Overrun => "run goes past the end of the image",
+            Error::BadEndMarker => "missing QOI end marker",
+            Error::PixelCount => "number of pixels doesn't match the image size",
+        })
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
+
+/// The 14-byte file header
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Header {
+    pub width: u32,
+    pub height: u32,
+    pub channels: Channels,
+    pub colorspace: ColorSpace,
+}
+
+impl Header {
+    /// Checks the size against [`MAX_PIXELS`]
+    pub fn new(
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colorspace: ColorSpace,
+    ) -> Result<Self, Error> {
+        let header = Self {
+            width,
+            height,
+            channels,
+            colorspace,
+        };
+        header.pixels()?;
+        Ok(header)
+    }
+
+    /// Read the header at the start of a file, without decoding it
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let h = data.get(..HEADER_LEN).ok_or(Error::UnexpectedEof)?;
+        if &h[..4] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        let channels = match h[12] {
+            3 => Channels::Rgb,
+            4 => Channels::Rgba,
+            _ => return Err(Error::BadHeader),
+        };
+        let colorspace = match h[13] {
+            0 => ColorSpace::Srgb,
+            1 => ColorSpace::Linear,
+            _ => return Err(Error::BadHeader),
+        };
+        Self::new(
+            u32::from_be_bytes([h[4], h[5], h[6], h[7]]),
+            u32::from_be_bytes([h[8], h[9], h[10], h[11]]),
+            channels,
+            colorspace,
+        )
+    }
+
+    /// Width × height
+    fn pixels(&self) -> Result<usize, Error> {
+        let n = self.width as u64 * self.height as u64;
+        if n == 0 {
+            Err(Error::BadHeader)
+        } else if n > MAX_PIXELS {
+            Err(Error::TooLarge)
+        } else {
+            Ok(n as usize)
+        }
+    }
+
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut h = [0; HEADER_LEN];
+        h[..4].copy_from_slice(MAGIC);
+        h[4..8].copy_from_slice(&self.width.to_be_bytes());
+        h[8..12].copy_from_slice(&self.height.to_be_bytes());
+        h[12] = self.channels as u8;
+        h[13] = self.colorspace as u8;
+        h
+    }
+}
+
+/// Pixel types QOI can store
+pub trait QoiPixel: Copy {
+    const CHANNELS: Channels;
+
+    fn to_rgba(self) -> RGBA8;
+    fn from_rgba(px: RGBA8) -> Self;
+}
+
+impl QoiPixel for RGB8 {
+    const CHANNELS: Channels = Channels::Rgb;
+
+    #[inline(always)]
+    fn to_rgba(self) -> RGBA8 {
+        RGBA8::new(self.r, self.g, self.b, 255)
+    }
+
+    #[inline(always)]
+    fn from_rgba(px: RGBA8) -> Self {
+        RGB8::new(px.r, px.g, px.b)
+    }
+}
+
+impl QoiPixel for RGBA8 {
+    const CHANNELS: Channels = Channels::Rgba;
+
+    #[inline(always)]
+    fn to_rgba(self) -> RGBA8 {
+        self
+    }
+
+    #[inline(always)]
+    fn from_rgba(px: RGBA8) -> Self {
+        px
+    }
+}
+
+#[inline(always)]
+fn hash(px: RGBA8) -> usize {
+    (px.r as usize * 3 + px.g as usize * 5 + px.b as usize * 7 + px.a as usize * 11) % 64
+}
+
+/// Encodes pixels as they arrive, in any number of [`write_pixels`](Encoder::write_pixels) calls
+pub struct Encoder<W: Write> {
+    out: W,
+    header: Header,
+    remaining: usize,
+    index: [RGBA8; 64],
+    prev: RGBA8,
+    run: u8,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> Encoder<W> {
+    /// Writes the header right away
+    pub fn new(mut out: W, header: Header) -> io::Result<Self> {
+        let remaining = header.pixels()?;
+        out.write_all(&header.to_bytes())?;
+        Ok(Self {
+            out,
+            header,
+            remaining,
+            index: [RGBA8::new(0, 0, 0, 0); 64],
+            prev: RGBA8::new(0, 0, 0, 255),
+            run: 0,
+            buf: Vec::new(),
+        })
+    }
+
+    /// Next pixels in row-major order. In an RGB file alpha is ignored.
+    pub fn write_pixels<P: QoiPixel>(&mut self, pixels: &[P]) -> io::Result<()> {
+        if pixels.len() > self.remaining {
+            return Err(Error::PixelCount.into());
+        }
+        self.remaining -= pixels.len();
+        self.buf.clear();
+        let opaque = self.header.channels == Channels::Rgb;
+        for px in pixels {
+            let mut px = px.to_rgba();
+            if opaque {
+                px.a = 255;
+            }
+            self.push(px);
+        }
+        self.out.write_all(&self.buf)
+    }
+
+    fn push(&mut self, px: RGBA8) {
+        if px == self.prev {
+            self.run += 1;
+            if self.run == 62 {
+                self.flush_run();
+            }
+            return;
+        }
+        self.flush_run();
+
+        let h = hash(px);
+        if self.index[h] == px {
+            self.buf.push(OP_INDEX | h as u8);
+        } else {
+            self.index[h] = px;
+            if px.a == self.prev.a {
+                let dr = px.r.wrapping_sub(self.prev.r) as i8;
+                let dg = px.g.wrapping_sub(self.prev.g) as i8;
+                let db = px.b.wrapping_sub(self.prev.b) as i8;
+                let (dr_dg, db_dg) = (dr.wrapping_sub(dg), db.wrapping_sub(dg));
+                if (-2..2).contains(&dr) && (-2..2).contains(&dg) && (-2..2).contains(&db) {
+                    self.buf.push(
+                        OP_DIFF | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8,
+                    );
+                } else if (-32..32).contains(&dg)
+                    && (-8..8).contains(&dr_dg)
+                    && (-8..8).contains(&db_dg)
+                {
+                    self.buf.push(OP_LUMA | (dg + 32) as u8);
+                    self.buf.push(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8);
+                } else {
+                    self.buf.extend_from_slice(&[OP_RGB, px.r, px.g, px.b]);
+                }
+            } else {
+                self.buf
+                    .extend_from_slice(&[OP_RGBA, px.r, px.g, px.b, px.a]);
+            }
+        }
+        self.prev = px;
+    }
+
+    #[inline]
+    fn flush_run(&mut self) {
+        if self.run > 0 {
+            self.buf.push(OP_RUN | (self.run - 1));
+            self.run = 0;
+        }
+    }
+
+    /// Writes the end marker. Fails if fewer pixels than width × height were written.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.remaining != 0 {
+            return Err(Error::PixelCount.into());
+        }
+        self.buf.clear();
+        self.flush_run();
+        self.buf.extend_from_slice(&END_MARKER);
+        self.out.write_all(&self.buf)?;
+        Ok(self.out)
+    }
+}
+
+/// Encode a whole image. The header's channel count comes from the pixel type.
+pub fn encode<P: QoiPixel>(
+    pixels: &[P],
+    width: u32,
+    height: u32,
+    colorspace: ColorSpace,
+) -> Result<Vec<u8>, Error> {
+    let header = Header::new(width, height, P::CHANNELS, colorspace)?;
+    if pixels.len() != header.pixels()? {
+        return Err(Error::PixelCount);
+    }
+    // 大小已经检查过, 写入 Vec 不会失败
+    let out = Vec::with_capacity(HEADER_LEN + pixels.len() + END_MARKER.len());
+    let mut encoder = Encoder::new(out, header).expect("valid header");
+    encoder.write_pixels(pixels).expect("pixel count checked");
+    Ok(encoder.finish().expect("pixel count checked"))
+}
+
+/// Decode a file. Components are returned with the file's channel count:
+/// 3 per pixel for [`Channels::Rgb`], 4 for [`Channels::Rgba`].
+pub fn decode(data: &[u8]) -> Result<(Header, Vec<u8>), Error> {
+    let header = Header::parse(data)?;
+    let out = match header.channels {
+        Channels::Rgb => decode_pixels(data, header, |px: RGBA8| [px.r, px.g, px.b])?.concat(),
+        Channels::Rgba => {
+            decode_pixels(data, header, |px: RGBA8| [px.r, px.g, px.b, px.a])?.concat()
+        }
+    };
+    Ok((header, out))
+}
+
+/// Decode a file to the given pixel type, regardless of the file's channel count
+pub fn decode_to<P: QoiPixel>(data: &[u8]) -> Result<(Header, Vec<P>), Error> {
+    let header = Header::parse(data)?;
+    Ok((header, decode_pixels(data, header, P::from_rgba)?))
+}
+
+fn decode_pixels<T>(
+    data: &[u8],
+    header: Header,
+    mut f: impl FnMut(RGBA8) -> T,
+) -> Result<Vec<T>, Error> {
+    let count = header.pixels()?;
+    let data = &data[HEADER_LEN..];
+    // 每个字节最多产生 62 个像素, 先检查再分配内存
+    if count.div_ceil(62) > data.len().saturating_sub(END_MARKER.len()) {
+        return Err(Error::UnexpectedEof);
+    }
+
+    let mut out = Vec::with_capacity(count);
+    let mut index = [RGBA8::new(0, 0, 0, 0); 64];
+    let mut px = RGBA8::new(0, 0, 0, 255);
+    let mut pos = 0;
+    let byte = |pos: &mut usize| {
+        let b = data.get(*pos).copied().ok_or(Error::UnexpectedEof);
+        *pos += 1;
+        b
+    };
+    while out.len() < count {
+        let op = byte(&mut pos)?;
+        match op {
+            OP_RGB => {
+                px.r = byte(&mut pos)?;
+                px.g = byte(&mut pos)?;
+                px.b = byte(&mut pos)?;
+            }
+            OP_RGBA => {
+                px.r = byte(&mut pos)?;
+                px.g = byte(&mut pos)?;
+                px.b = byte(&mut pos)?;
+                px.a = byte(&mut pos)?;
+            }
+            _ => match op & MASK {
+                OP_INDEX => px = index[op as usize],
+                OP_DIFF => {
+                    px.r = px.r.wrapping_add((op >> 4) & 3).wrapping_sub(2);
+                    px.g = px.g.wrapping_add((op >> 2) & 3).wrapping_sub(2);
+                    px.b = px.b.wrapping_add(op & 3).wrapping_sub(2);
+                }
+                OP_LUMA => {
+                    let next = byte(&mut pos)?;
+                    let dg = (op & 0x3F).wrapping_sub(32);
+                    px.r =
+                        px.r.wrapping_add(dg)
+                            .wrapping_add(next >> 4)
+                            .wrapping_sub(8);
+                    px.g = px.g.wrapping_add(dg);
+                    px.b =
+                        px.b.wrapping_add(dg)
+                            .wrapping_add(next & 0x0F)
+                            .wrapping_sub(8);
+                }
+                _ => {
+                    let run = (op & 0x3F) as usize + 1;
+                    if out.len() + run > count {
+                        return Err(Error::Overrun);
+                    }
+                    // 和规范一样, run 的像素也要放进 index: 开头的 (0,0,0,255) 还不在里面
+                    index[hash(px)] = px;
+                    for _ in 0..run {
+                        out.push(f(px));
+                    }
+                    continue;
+                }
+            },
+        }
+        index[hash(px)] = px;
+        out.push(f(px));
+    }
+
+    match data.get(pos..pos + END_MARKER.len()) {
+        Some(end) if end == END_MARKER => Ok(out),
+        Some(_) => Err(Error::BadEndMarker),
+        None => Err(Error::UnexpectedEof),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AsPixels;
+
+    /// Gradients, noise, flat areas and varying alpha
+    fn image(width: u32, height: u32, seed: u32) -> Vec<RGBA8> {
+        let mut state = seed;
+        let mut rand = move || {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        };
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| match (x / 7 + y / 5) % 4 {
+                0 => RGBA8::new(x as u8, y as u8, (x + y) as u8, 255),
+                1 => RGBA8::new(10, 20, 30, 255),
+                2 => RGBA8::new(rand(), rand(), rand(), rand() | 0x80),
+                _ => RGBA8::new((x * 3) as u8, 200, (y * 2) as u8, (x * 8) as u8),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trips() {
+        for (w, h, seed) in [(1, 1, 1), (64, 33, 2), (3, 200, 3), (257, 9, 4)] {
+            let rgba = image(w, h, seed);
+            let file = encode(&rgba, w, h, ColorSpace::Linear).unwrap();
+            let (header, data) = decode(&file).unwrap();
+            assert_eq!(
+                header,
+                Header::new(w, h, Channels::Rgba, ColorSpace::Linear).unwrap()
+            );
+            assert_eq!(AsPixels::<RGBA8>::as_pixels(&data[..]), rgba);
+
+            let rgb: Vec<RGB8> = rgba.iter().map(|px| px.rgb()).collect();
+            let file = encode(&rgb, w, h, ColorSpace::Srgb).unwrap();
+            let (header, data) = decode(&file).unwrap();
+            assert_eq!(header.channels, Channels::Rgb);
+            assert_eq!(header.colorspace, ColorSpace::Srgb);
+            assert_eq!(AsPixels::<RGB8>::as_pixels(&data[..]), rgb);
+            let (_, opaque) = decode_to::<RGBA8>(&file).unwrap();
+            assert!(opaque
+                .iter()
+                .zip(&rgb)
+                .all(|(a, b)| a.rgb() == *b && a.a == 255));
+        }
+
+        // 长的 run 需要拆成多个 62 像素的操作
+        let flat = vec![RGB8::new(0, 0, 0); 1000];
+        let file = encode(&flat, 100, 10, ColorSpace::Srgb).unwrap();
+        assert_eq!(file.len(), HEADER_LEN + 1000usize.div_ceil(62) + 8);
+        assert_eq!(decode_to::<RGB8>(&file).unwrap().1, flat);
+    }
+
+    #[test]
+    fn streaming() {
+        let pixels = image(40, 30, 7);
+        let whole = encode(&pixels, 40, 30, ColorSpace::Srgb).unwrap();
+
+        let header = Header::new(40, 30, Channels::Rgba, ColorSpace::Srgb).unwrap();
+        let mut encoder = Encoder::new(Vec::new(), header).unwrap();
+        for chunk in pixels.chunks(37) {
+            encoder.write_pixels(chunk).unwrap();
+        }
+        assert_eq!(encoder.finish().unwrap(), whole);
+
+        let mut encoder = Encoder::new(Vec::new(), header).unwrap();
+        encoder.write_pixels(&pixels[..10]).unwrap();
+        assert!(encoder.write_pixels(&pixels).is_err());
+        assert!(encoder.finish().is_err());
+    }
+
+    #[test]
+    fn ops() {
+        let px = |r, g, b, a| RGBA8::new(r, g, b, a);
+        // run, diff, luma, rgb, rgba, index
+        let pixels = [
+            px(0, 0, 0, 255),
+            px(1, 255, 0, 255),
+            px(17, 20, 14, 255),
+            px(100, 20, 14, 255),
+            px(100, 20, 14, 0),
+            px(1, 255, 0, 255),
+        ];
+        let file = encode(&pixels, 6, 1, ColorSpace::Srgb).unwrap();
+        assert_eq!(
+            &file[HEADER_LEN..],
+            [
+                0xC0,
+                0x40 | 3 << 4 | 1 << 2 | 2,
+                0x80 | (21 + 32),
+                ((-5i8 + 8) as u8) << 4 | (-7i8 + 8) as u8,
+                0xFE,
+                100,
+                20,
+                14,
+                0xFF,
+                100,
+                20,
+                14,
+                0,
+                hash(px(1, 255, 0, 255)) as u8,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                1
+            ]
+        );
+        assert_eq!(decode_to::<RGBA8>(&file).unwrap().1, pixels);
+    }
+
+    fn file(width: u32, height: u32, ops: &[u8]) -> Vec<u8> {
+        let mut file = b"qoif".to_vec();
+        file.extend(width.to_be_bytes());
+        file.extend(height.to_be_bytes());
+        file.extend([4, 0]);
+        file.extend_from_slice(ops);
+        file.extend(END_MARKER);
+        file
+    }
+
+    #[test]
+    fn run_updates_index() {
+        // 开头的 run 重复初始像素 (0,0,0,255), 之后可以用 OP_INDEX 引用它
+        let op_index = hash(RGBA8::new(0, 0, 0, 255)) as u8;
+        let (_, pixels) =
+            decode_to::<RGBA8>(&file(3, 1, &[0xC0, 0xFE, 1, 2, 3, op_index])).unwrap();
+        assert_eq!(
+            pixels,
+            [
+                RGBA8::new(0, 0, 0, 255),
+                RGBA8::new(1, 2, 3, 255),
+                RGBA8::new(0, 0, 0, 255),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_headers() {
+        assert!(decode(&file(1, 1, &[0xC0])).is_ok());
+        assert_eq!(decode(b"qoi"), Err(Error::UnexpectedEof));
+        assert_eq!(
+            decode(b"qoix\0\0\0\x01\0\0\0\x01\x03\0"),
+            Err(Error::BadMagic)
+        );
+        let mut bad = file(1, 1, &[0xC0]);
+        bad[12] = 5;
+        assert_eq!(decode(&bad), Err(Error::BadHeader));
+        let mut bad = file(1, 1, &[0xC0]);
+        bad[13] = 2;
+        assert_eq!(decode(&bad), Err(Error::BadHeader));
+        assert_eq!(decode(&file(0, 1, &[])), Err(Error::BadHeader));
+        assert_eq!(decode(&file(1, 0, &[])), Err(Error::BadHeader));
+    }
+
+    #[test]
+    fn size_limits() {
+        assert_eq!(decode(&file(1 << 16, 1 << 16, &[])), Err(Error::TooLarge));
+        assert_eq!(decode(&file(u32::MAX, u32::MAX, &[])), Err(Error::TooLarge));
+        let edge = MAX_PIXELS as u32;
+        assert_eq!(decode(&file(edge, 1, &[])), Err(Error::UnexpectedEof));
+        assert_eq!(decode(&file(edge + 1, 1, &[])), Err(Error::TooLarge));
+    }
+
+    #[test]
+    fn huge_header_tiny_data() {
+        // 一个字节最多 62 个像素: 多一个像素就是数据不够, 不会先分配内存
+        let ops = [0xFD; 4];
+        let (_, px) = decode(&file(62 * 4, 1, &ops)).unwrap();
+        assert_eq!(px.len(), 62 * 4 * 4);
+        assert_eq!(
+            decode(&file(62 * 4 + 1, 1, &ops)),
+            Err(Error::UnexpectedEof)
+        );
+        assert_eq!(
+            decode(&file(0x4000, 0x4000, &[0xFD; 64])),
+            Err(Error::UnexpectedEof)
+        );
+        assert_eq!(
+            decode_to::<RGBA8>(&file(20000, 20000, &[])),
+            Err(Error::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn runs_overrun() {
+        // 2×2 的图: RGB 加 4 个像素的 run
+        assert_eq!(
+            decode(&file(2, 2, &[0xFE, 1, 2, 3, 0xC3])),
+            Err(Error::Overrun)
+        );
+        assert_eq!(decode(&file(2, 2, &[0xC4])), Err(Error::Overrun));
+        assert!(decode(&file(2, 2, &[0xFE, 1, 2, 3, 0xC2])).is_ok());
+    }
+
+    #[test]
+    fn truncated_and_end_marker() {
+        let encoded = encode(&[RGB8::new(1, 2, 3); 4], 2, 2, ColorSpace::Srgb).unwrap();
+        assert!(decode(&encoded).is_ok());
+        assert_eq!(
+            decode(&encoded[..encoded.len() - 1]),
+            Err(Error::UnexpectedEof)
+        );
+        let mut bad = encoded.clone();
+        *bad.last_mut().unwrap() = 2;
+        assert_eq!(decode(&bad), Err(Error::BadEndMarker));
+        // 最后的 RGBA 和 LUMA 缺字节, 读进了结束标记, 结束标记就不完整了
+        assert_eq!(
+            decode(&file(2, 2, &[0xC2, 0xFF, 1, 2, 3])),
+            Err(Error::UnexpectedEof)
+        );
+        assert_eq!(
+            decode(&file(2, 2, &[0xC2, 0x80])),
+            Err(Error::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn encode_errors() {
+        assert_eq!(
+            encode(&[RGB8::new(1, 2, 3); 3], 2, 2, ColorSpace::Srgb),
+            Err(Error::PixelCount)
+        );
+        assert_eq!(
+            encode::<RGB8>(&[], 0, 2, ColorSpace::Srgb),
+            Err(Error::BadHeader)
+        );
+    }
+}
@@ -0,0 +1,1195 @@
+//! Truevision TGA, uncompressed and RLE.
+//!
+//! Decoding supports every image type of TGA 2.0 except the Huffman-coded ones, in any of
+//! the four origins. The image ID, footer and extension area are reported in [`Info`];
+//! the color correction table, postage stamp and scan line table are skipped.
+//!
+//! | TGA | Output |
+//! |-----|--------|
+//! | Gray 8-bit | `GRAY8` |
+//! | Gray 16-bit (gray + alpha) | `GRAYA8`, or `GRAY8` without alpha |
+//! | True-color 15/16-bit (ARGB1555) | `BGR8`, or `BGRA8` with the alpha bit |
+//! | True-color 24/32-bit | `BGR8`/`BGRA8` |
+//! | Color-mapped | like the color map entries |
+//!
+//! Alpha is only used when the header says it has alpha bits, and the extension area
+//! doesn't say they're undefined.
+//!
+//! Encoding writes gray, 24/32-bit true-color, 16-bit ARGB1555 or color-mapped images,
+//! uncompressed or RLE. See [`Options`].
+//!
+//! ```rust
+//! use cr::alt::BGRA8;
+//! use cr::formats::tga;
+//! use cr::{DynamicImage, Image};
+//!
+//! let image = DynamicImage::from(Image::filled(BGRA8 { b: 1, g: 2, r: 3, a: 4 }, 300, 2));
+//! let options = tga::Options { rle: true, ..tga::Options::default() };
+//! let file = tga::encode(&image, &options).unwrap();
+//! assert!(file.len() < 100);
+//! let (info, decoded) = tga::decode(&file).unwrap();
+//! assert_eq!((info.image_type, info.rle), (tga::ImageType::TrueColor, true));
+//! assert_eq!(decoded, image);
+//! ```
+
+use crate::alt::{GrayAlpha, BGR8, BGRA8, GRAY8, GRAYA8};
+use crate::{AlphaKind, ComponentOrder, DynamicImage, DynamicPixel, Image};
+use core::fmt;
+use std::collections::HashMap;
+
+const HEADER: usize = 18;
+const FOOTER: usize = 26;
+const SIGNATURE: &[u8; 18] = b"TRUEVISION-XFILE.\0";
+const EXTENSION: usize = 495;
+
+/// Why a TGA file couldn't be decoded or encoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// Malformed header or extension area, or zero size
+    BadHeader,
+    /// Huffman compression, or a bit depth that isn't supported
+    Unsupported,
+    /// Missing color map, or a pixel refers to a color outside of it
+    BadPalette,
+    /// RLE packet goes past the end of the image
+    BadRle,
+    /// The file ends before all pixels
+    UnexpectedEof,
+    /// Width or height is over 65535, or the image doesn't fit in memory
+    TooLarge,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::BadHeader => "invalid TGA header",
+            Error::Unsupported => "unsupported TGA image type or bit depth",
+            Error::BadPalette => "invalid color map or color map index",
+            Error::BadRle => "invalid RLE data",
+            Error::UnexpectedEof => "file is truncated",
+            Error::TooLarge => "image is too large",
+        })
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Image type from the header, without the RLE flag
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ImageType {
+    ColorMapped = 1,
+    TrueColor = 2,
+    Gray = 3,
+}
+
+/// Meaning of the alpha channel, from the extension area
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum AlphaType {
+    /// No alpha data
+    #[default]
+    None = 0,
+    /// Undefined data that can be ignored
+    UndefinedIgnore = 1,
+    /// Undefined data that should be kept
+    UndefinedRetain = 2,
+    /// Straight alpha
+    Straight = 3,
+    /// Premultiplied alpha. Pixels are decoded as they are.
+    Premultiplied = 4,
+}
+
+/// TGA 2.0 extension area. Text longer than its field is cut off when writing.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Extension {
+    /// Up to 40 bytes
+    pub author: String,
+    /// Up to 4 lines of 80 bytes
+    pub comments: String,
+    /// Month, day, year, hour, minute, second, or all 0
+    pub timestamp: [u16; 6],
+    /// Up to 40 bytes
+    pub job: String,
+    /// Hours, minutes, seconds
+    pub job_time: [u16; 3],
+    /// Up to 40 bytes
+    pub software: String,
+    /// Version × 100 and a letter, e.g. `(410, b'b')` for 4.10b
+    pub software_version: (u16, u8),
+    /// Background color
+    pub key_color: BGRA8,
+    /// Pixel width : height, or `(0, 0)` if unset
+    pub aspect_ratio: (u16, u16),
+    /// Gamma as a fraction, or `(0, 0)` if unset
+    pub gamma: (u16, u16),
+    pub alpha: AlphaType,
+}
+
+/// Header, image ID and extension area of a TGA file
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Info {
+    pub width: u16,
+    pub height: u16,
+    pub image_type: ImageType,
+    pub rle: bool,
+    /// Bits per pixel, or per index for color-mapped images
+    pub bits_per_pixel: u8,
+    /// Alpha bits per pixel, from the image descriptor
+    pub alpha_bits: u8,
+    /// First row in the file is the top one
+    pub top_down: bool,
+    /// First column in the file is the right one
+    pub right_to_left: bool,
+    pub id: Vec<u8>,
+    /// Only in TGA 2.0 files
+    pub extension: Option<Extension>,
+}
+
+impl Info {
+    /// Whether the extension area allows using the alpha data
+    #[inline]
+    fn alpha_allowed(&self) -> bool {
+        !matches!(
+            self.extension.as_ref().map(|e| e.alpha),
+            Some(AlphaType::None | AlphaType::UndefinedIgnore)
+        )
+    }
+}
+
+#[inline]
+fn u16_at(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+/// Text up to the first NUL
+fn text(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn read_extension(data: &[u8]) -> Result<Option<Extension>, Error> {
+    let Some(footer) = data.len().checked_sub(FOOTER).map(|pos| &data[pos..]) else {
+        return Ok(None);
+    };
+    if &footer[8..] != SIGNATURE {
+        return Ok(None);
+    }
+    let offset = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]) as usize;
+    if offset == 0 {
+        return Ok(None);
+    }
+    let ext = data
+        .get(offset..data.len() - FOOTER)
+        .and_then(|ext| ext.get(..EXTENSION))
+        .ok_or(Error::BadHeader)?;
+    if u16_at(ext, 0) as usize != EXTENSION || offset < HEADER {
+        return Err(Error::BadHeader);
+    }
+    let u16s = |pos: usize, out: &mut [u16]| {
+        for (i, v) in out.iter_mut().enumerate() {
+            *v = u16_at(ext, pos + i * 2);
+        }
+    };
+    let mut timestamp = [0; 6];
+    u16s(367, &mut timestamp);
+    let mut job_time = [0; 3];
+    u16s(420, &mut job_time);
+    let comments: Vec<_> = ext[43..367].chunks(81).map(text).collect();
+    let lines = comments
+        .iter()
+        .rposition(|l| !l.is_empty())
+        .map_or(0, |i| i + 1);
+    Ok(Some(Extension {
+        author: text(&ext[2..43]),
+        comments: comments[..lines].join("\n"),
+        timestamp,
+        job: text(&ext[379..420]),
+        job_time,
+        software: text(&ext[426..467]),
+        software_version: (u16_at(ext, 467), ext[469]),
+        key_color: BGRA8 {
+            b: ext[470],
+            g: ext[471],
+            r: ext[472],
+            a: ext[473],
+        },
+        aspect_ratio: (u16_at(ext, 474), u16_at(ext, 476)),
+        gamma: (u16_at(ext, 478), u16_at(ext, 480)),
+        alpha: match ext[494] {
+            0 => AlphaType::None,
+            1 => AlphaType::UndefinedIgnore,
+            2 => AlphaType::UndefinedRetain,
+            3 => AlphaType::Straight,
+            4 => AlphaType::Premultiplied,
+            _ => return Err(Error::BadHeader),
+        },
+    }))
+}
+
+/// The header and color map, and where the pixels start
+struct Parsed<'a> {
+    info: Info,
+    map_first: usize,
+    map_depth: u8,
+    map: &'a [u8],
+    pixels: &'a [u8],
+}
+
+fn parse(data: &[u8]) -> Result<Parsed<'_>, Error> {
+    let header = data.get(..HEADER).ok_or(Error::UnexpectedEof)?;
+    let (id_len, map_type, kind) = (header[0] as usize, header[1], header[2]);
+    let (map_first, map_len, map_depth) = (u16_at(header, 3), u16_at(header, 5), header[7]);
+    let (width, height) = (u16_at(header, 12), u16_at(header, 14));
+    let (depth, descriptor) = (header[16], header[17]);
+
+    let image_type = match kind & !8 {
+        1 => ImageType::ColorMapped,
+        2 => ImageType::TrueColor,
+        3 => ImageType::Gray,
+        // 32/33 是 Huffman 压缩
+        0 | 32 | 33 => return Err(Error::Unsupported),
+        _ => return Err(Error::BadHeader),
+    };
+    if width == 0 || height == 0 || map_type > 1 || descriptor & 0xC0 != 0 {
+        return Err(Error::BadHeader);
+    }
+    let supported = match image_type {
+        ImageType::ColorMapped => matches!(depth, 8 | 16) && map_type == 1,
+        ImageType::TrueColor => matches!(depth, 15 | 16 | 24 | 32),
+        ImageType::Gray => matches!(depth, 8 | 16),
+    };
+    if !supported || (map_type == 1 && !matches!(map_depth, 15 | 16 | 24 | 32)) {
+        return Err(if image_type == ImageType::ColorMapped && map_type == 0 {
+            Error::BadPalette
+        } else {
+            Error::Unsupported
+        });
+    }
+
+    let id = data
+        .get(HEADER..HEADER + id_len)
+        .ok_or(Error::UnexpectedEof)?;
+    let map_start = HEADER + id_len;
+    let map_size = if map_type == 1 {
+        map_len as usize * (map_depth as usize).div_ceil(8)
+    } else {
+        0
+    };
+    let map = data
+        .get(map_start..map_start + map_size)
+        .ok_or(Error::UnexpectedEof)?;
+
+    Ok(Parsed {
+        info: Info {
+            width,
+            height,
+            image_type,
+            rle: kind & 8 != 0,
+            bits_per_pixel: depth,
+            alpha_bits: descriptor & 0x0F,
+            top_down: descriptor & 0x20 != 0,
+            right_to_left: descriptor & 0x10 != 0,
+            id: id.to_vec(),
+            extension: read_extension(data)?,
+        },
+        map_first: map_first as usize,
+        map_depth,
+        map,
+        pixels: &data[map_start + map_size..],
+    })
+}
+
+/// Read the header, image ID and extension area, without decoding the pixels
+pub fn read_info(data: &[u8]) -> Result<Info, Error> {
+    parse(data).map(|p| p.info)
+}
+
+/// Decode a `.tga` file
+pub fn decode(data: &[u8]) -> Result<(Info, DynamicImage), Error> {
+    let Parsed {
+        info,
+        map_first,
+        map_depth,
+        map,
+        pixels,
+    } = parse(data)?;
+    let count = info.width as usize * info.height as usize;
+    let size = (info.bits_per_pixel as usize).div_ceil(8);
+    let len = count.checked_mul(size).ok_or(Error::TooLarge)?;
+    let raw = if info.rle {
+        decode_rle(pixels, len, size)?
+    } else {
+        pixels.get(..len).ok_or(Error::UnexpectedEof)?.to_vec()
+    };
+
+    let alpha = info.alpha_bits > 0 && info.alpha_allowed();
+    let image = match (info.image_type, size) {
+        (ImageType::Gray, 1) => orient(&info, raw.into_iter().map(GRAY8::new).collect()),
+        (ImageType::Gray, _) if alpha => orient(
+            &info,
+            raw.chunks_exact(2)
+                .map(|c| GrayAlpha::new(c[0], c[1]))
+                .collect::<Vec<GRAYA8>>(),
+        ),
+        (ImageType::Gray, _) => orient(
+            &info,
+            raw.chunks_exact(2).map(|c| GRAY8::new(c[0])).collect(),
+        ),
+        (ImageType::TrueColor, _) => {
+            let bgra = colors(&raw, info.bits_per_pixel);
+            finish(&info, bgra, alpha && info.bits_per_pixel != 24)
+        }
+        (ImageType::ColorMapped, _) => {
+            let palette = colors(map, map_depth);
+            let lookup = |i: usize| {
+                i.checked_sub(map_first)
+                    .and_then(|i| palette.get(i))
+                    .copied()
+                    .ok_or(Error::BadPalette)
+            };
+            let bgra = if size == 1 {
+                raw.iter().map(|&i| lookup(i as usize)).collect()
+            } else {
+                raw.chunks_exact(2)
+                    .map(|c| lookup(u16_at(c, 0) as usize))
+                    .collect::<Result<_, _>>()
+            }?;
+            // 颜色表的 alpha 看表项的位数
+            let alpha = match map_depth {
+                32 => info.alpha_allowed(),
+                16 => alpha,
+                _ => false,
+            };
+            finish(&info, bgra, alpha)
+        }
+    };
+    Ok((info, image))
+}
+
+/// Unpack 15/16-bit ARGB1555, 24-bit BGR or 32-bit BGRA
+fn colors(raw: &[u8], depth: u8) -> Vec<BGRA8> {
+    match depth {
+        15 | 16 => raw
+            .chunks_exact(2)
+            .map(|c| {
+                let px = u16_at(c, 0);
+                // 5 位扩展到 8 位
+                let c5 = |shift: u16| {
+                    let v = (px >> shift & 0x1F) as u8;
+                    v << 3 | v >> 2
+                };
+                BGRA8 {
+                    b: c5(0),
+                    g: c5(5),
+                    r: c5(10),
+                    a: if px & 0x8000 != 0 { 255 } else { 0 },
+                }
+            })
+            .collect(),
+        24 => raw
+            .chunks_exact(3)
+            .map(|c| BGRA8 {
+                b: c[0],
+                g: c[1],
+                r: c[2],
+                a: 255,
+            })
+            .collect(),
+        _ => raw
+            .chunks_exact(4)
+            .map(|c| BGRA8 {
+                b: c[0],
+                g: c[1],
+                r: c[2],
+                a: c[3],
+            })
+            .collect(),
+    }
+}
+
+fn finish(info: &Info, bgra: Vec<BGRA8>, alpha: bool) -> DynamicImage {
+    if alpha {
+        orient(info, bgra)
+    } else {
+        let bgr = bgra
+            .into_iter()
+            .map(|px| BGR8 {
+                b: px.b,
+                g: px.g,
+                r: px.r,
+            })
+            .collect();
+        orient(info, bgr)
+    }
+}
+
+/// Put the rows and columns from file order into top-down, left-to-right order
+fn orient<P: DynamicPixel>(info: &Info, mut pixels: Vec<P>) -> DynamicImage {
+    let (width, height) = (info.width as usize, info.height as usize);
+    if info.right_to_left {
+        for row in pixels.chunks_exact_mut(width) {
+            row.reverse();
+        }
+    }
+    if !info.top_down {
+        for y in 0..height / 2 {
+            let (top, bottom) = pixels.split_at_mut((height - 1 - y) * width);
+            top[y * width..][..width].swap_with_slice(&mut bottom[..width]);
+        }
+    }
+    P::into_dynamic(Image::new(pixels, width, height))
+}
+
+/// Expand RLE packets into `len` bytes of `size`-byte pixels. Packets may cross rows.
+fn decode_rle(data: &[u8], len: usize, size: usize) -> Result<Vec<u8>, Error> {
+    // 一个包最多展开成 128 个像素, 数据明显不够就不分配内存
+    if len / 128 > data.len() {
+        return Err(Error::UnexpectedEof);
+    }
+    let mut out = Vec::with_capacity(len);
+    let mut pos = 0;
+    while out.len() < len {
+        let head = *data.get(pos).ok_or(Error::UnexpectedEof)?;
+        let count = (head & 0x7F) as usize + 1;
+        if out.len() + count * size > len {
+            return Err(Error::BadRle);
+        }
+        if head & 0x80 != 0 {
+            let px = data
+                .get(pos + 1..pos + 1 + size)
+                .ok_or(Error::UnexpectedEof)?;
+            for _ in 0..count {
+                out.extend_from_slice(px);
+            }
+            pos += 1 + size;
+        } else {
+            let n = count * size;
+            let px = data.get(pos + 1..pos + 1 + n).ok_or(Error::UnexpectedEof)?;
+            out.extend_from_slice(px);
+            pos += 1 + n;
+        }
+    }
+    Ok(out)
+}
+
+/// Settings for [`encode`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Options {
+    /// Compress with RLE
+    pub rle: bool,
+    /// Store rows top to bottom instead of TGA's usual bottom to top
+    pub top_down: bool,
+    /// Image ID, up to 255 bytes
+    pub id: Vec<u8>,
+    /// Write an extension area. Its `alpha` is written as given.
+    pub extension: Option<Extension>,
+    /// Write a color-mapped image with 8-bit indices if there are at most 256 distinct colors.
+    /// Gray images stay gray.
+    pub color_map: bool,
+    /// Write colors, or color map entries, as 16-bit ARGB1555: 5 bits per channel, and
+    /// alpha 128 or more is opaque. Gray images stay gray.
+    pub argb1555: bool,
+}
+
+/// Encode an image as a TGA 2.0 file.
+///
+/// Gray becomes 8-bit gray, gray with alpha 16-bit gray, and everything else `BGR8` or
+/// `BGRA8`, or ARGB1555 and/or color-mapped as set in [`Options`].
+/// 16-bit images are reduced to 8 bits.
+pub fn encode(image: &DynamicImage, options: &Options) -> Result<Vec<u8>, Error> {
+    let (width, height) = (image.width(), image.height());
+    if width == 0 || height == 0 || options.id.len() > 255 {
+        return Err(Error::BadHeader);
+    }
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(Error::TooLarge);
+    }
+    let format = image.format();
+    let has_alpha = format.alpha != AlphaKind::None;
+    let gray = matches!(
+        format.order,
+        ComponentOrder::Gray | ComponentOrder::GrayAlpha
+    );
+
+    let (mut kind, mut depth, mut samples): (u8, u8, Vec<u8>) = match (gray, has_alpha) {
+        (true, false) => (
+            3,
+            8,
+            image.to_gray8().rows().flatten().map(|px| px.0).collect(),
+        ),
+        (true, true) => (
+            3,
+            16,
+            image
+                .to_image::<GRAYA8>()
+                .rows()
+                .flatten()
+                .flat_map(|px| [px.0, px.1])
+                .collect(),
+        ),
+        (false, false) => (
+            2,
+            24,
+            image
+                .to_image::<BGR8>()
+                .rows()
+                .flatten()
+                .flat_map(|px| [px.b, px.g, px.r])
+                .collect(),
+        ),
+        (false, true) => (
+            2,
+            32,
+            image
+                .to_bgra8()
+                .rows()
+                .flatten()
+                .flat_map(|px| [px.b, px.g, px.r, px.a])
+                .collect(),
+        ),
+    };
+    let mut alpha_bits = if has_alpha { 8 } else { 0 };
+    if !gray && options.argb1555 {
+        samples = to_argb1555(&samples, depth as usize / 8);
+        depth = 16;
+        alpha_bits = has_alpha as u8;
+    }
+    // 颜色表的位数是原来的像素位数, 像素换成 8 位索引
+    let mut map = (0, Vec::new());
+    if !gray && options.color_map {
+        if let Some((entries, indices)) = index(&samples, depth as usize / 8) {
+            map = (depth, entries);
+            samples = indices;
+            (kind, depth) = (1, 8);
+        }
+    }
+    let (map_depth, map) = map;
+    let map_len = map.len() / (map_depth as usize / 8).max(1);
+    let size = depth as usize / 8;
+    let row_len = width * size;
+
+    let mut out = Vec::with_capacity(HEADER + map.len() + samples.len() + EXTENSION + FOOTER);
+    out.extend_from_slice(&[
+        options.id.len() as u8,
+        (map_depth != 0) as u8,
+        kind | if options.rle { 8 } else { 0 },
+    ]);
+    out.extend_from_slice(&[0, 0]);
+    out.extend_from_slice(&(map_len as u16).to_le_bytes());
+    out.push(map_depth);
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&(width as u16).to_le_bytes());
+    out.extend_from_slice(&(height as u16).to_le_bytes());
+    out.extend_from_slice(&[depth, alpha_bits | if options.top_down { 0x20 } else { 0 }]);
+    out.extend_from_slice(&options.id);
+    out.extend_from_slice(&map);
+
+    for y in 0..height {
+        let y = if options.top_down { y } else { height - 1 - y };
+        let row = &samples[y * row_len..][..row_len];
+        if options.rle {
+            encode_rle(row, size, &mut out);
+        } else {
+            out.extend_from_slice(row);
+        }
+    }
+
+    let mut ext_offset = 0u32;
+    if let Some(ext) = &options.extension {
+        ext_offset = out.len() as u32;
+        write_extension(ext, &mut out);
+    }
+    out.extend_from_slice(&ext_offset.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(SIGNATURE);
+    Ok(out)
+}
+
+/// Reduce 24-bit BGR or 32-bit BGRA to ARGB1555
+fn to_argb1555(samples: &[u8], size: usize) -> Vec<u8> {
+    samples
+        .chunks_exact(size)
+        .flat_map(|px| {
+            let c5 = |v: u8| (v >> 3) as u16;
+            let a = px.get(3).is_some_and(|&a| a >= 128) as u16;
+            (a << 15 | c5(px[2]) << 10 | c5(px[1]) << 5 | c5(px[0])).to_le_bytes()
+        })
+        .collect()
+}
+
+/// Color map entries in order of first use, and an index per pixel.
+/// `None` if there are more than 256 colors.
+fn index(samples: &[u8], size: usize) -> Option<(Vec<u8>, Vec<u8>)> {
+    let mut colors = HashMap::new();
+    let mut map = Vec::new();
+    let indices = samples
+        .chunks_exact(size)
+        .map(|px| {
+            let next = colors.len();
+            let i = *colors.entry(px).or_insert(next);
+            if i == next {
+                map.extend_from_slice(px);
+            }
+            u8::try_from(i).ok()
+        })
+        .collect::<Option<_>>()?;
+    Some((map, indices))
+}
+
+/// Pack one row; packets don't cross rows, as TGA 2.0 recommends
+fn encode_rle(row: &[u8], size: usize, out: &mut Vec<u8>) {
+    let pixels: Vec<&[u8]> = row.chunks_exact(size).collect();
+    let mut i = 0;
+    while i < pixels.len() {
+        let run = pixels[i..]
+            .iter()
+            .take(128)
+            .take_while(|&&px| px == pixels[i])
+            .count();
+        if run >= 2 {
+            out.push(0x80 | (run - 1) as u8);
+            out.extend_from_slice(pixels[i]);
+            i += run;
+            continue;
+        }
+        // 原样输出, 直到下一段重复的像素
+        let start = i;
+        while i < pixels.len()
+            && i - start < 128
+            && !(i + 1 < pixels.len() && pixels[i] == pixels[i + 1])
+        {
+            i += 1;
+        }
+        out.push((i - start - 1) as u8);
+        for px in &pixels[start..i] {
+            out.extend_from_slice(px);
+        }
+    }
+}
+
+/// Text in a NUL-terminated field of `len` bytes, cut off at a character boundary
+fn put_text(out: &mut Vec<u8>, text: &str, len: usize) {
+    let mut end = text.len().min(len - 1);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    out.extend_from_slice(&text.as_bytes()[..end]);
+    out.extend(core::iter::repeat_n(0, len - end));
+}
+
+fn write_extension(ext: &Extension, out: &mut Vec<u8>) {
+    let start = out.len();
+    out.extend_from_slice(&(EXTENSION as u16).to_le_bytes());
+    put_text(out, &ext.author, 41);
+    let mut lines = ext.comments.lines();
+    for _ in 0..4 {
+        put_text(out, lines.next().unwrap_or(""), 81);
+    }
+    for v in ext.timestamp {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    put_text(out, &ext.job, 41);
+    for v in ext.job_time {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    put_text(out, &ext.software, 41);
+    out.extend_from_slice(&ext.software_version.0.to_le_bytes());
+    out.push(ext.software_version.1);
+    let key = ext.key_color;
+    out.extend_from_slice(&[key.b, key.g, key.r, key.a]);
+    for v in [
+        ext.aspect_ratio.0,
+        ext.aspect_ratio.1,
+        ext.gamma.0,
+        ext.gamma.1,
+    ] {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    // 色彩校正表, 缩略图和扫描行表的偏移量都不写
+    out.extend_from_slice(&[0; 12]);
+    out.push(ext.alpha as u8);
+    debug_assert_eq!(out.len() - start, EXTENSION);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RGB16, RGBA8};
+
+    fn header(kind: u8, map: (u16, u16, u8), size: (u16, u16), depth: u8, desc: u8) -> Vec<u8> {
+        let mut h = vec![0, (map.2 != 0) as u8, kind];
+        h.extend_from_slice(&map.0.to_le_bytes());
+        h.extend_from_slice(&map.1.to_le_bytes());
+        h.extend_from_slice(&[map.2, 0, 0, 0, 0]);
+        h.extend_from_slice(&size.0.to_le_bytes());
+        h.extend_from_slice(&size.1.to_le_bytes());
+        h.extend_from_slice(&[depth, desc]);
+        h
+    }
+
+    fn bgr(image: &DynamicImage) -> Vec<[u8; 3]> {
+        image
+            .to_image::<BGR8>()
+            .buf()
+            .iter()
+            .map(|p| [p.b, p.g, p.r])
+            .collect()
+    }
+
+    #[test]
+    fn round_trips() {
+        let bgra: Vec<_> = (0..35u8)
+            .map(|i| BGRA8 {
+                b: i,
+                g: i / 3 * 3,
+                r: 7,
+                a: i % 2 * 255,
+            })
+            .collect();
+        let bgra = Image::new(bgra, 7, 5);
+        let images = [
+            DynamicImage::from(bgra.clone()),
+            DynamicImage::from(Image::new(
+                bgra.buf()
+                    .iter()
+                    .map(|p| BGR8 {
+                        b: p.b,
+                        g: p.g,
+                        r: p.r,
+                    })
+                    .collect(),
+                7,
+                5,
+            )),
+            DynamicImage::from(Image::new(
+                bgra.buf().iter().map(|p| GRAY8::new(p.g)).collect(),
+                7,
+                5,
+            )),
+            DynamicImage::from(Image::new(
+                bgra.buf()
+                    .iter()
+                    .map(|p| GrayAlpha::new(p.g, p.a))
+                    .collect::<Vec<GRAYA8>>(),
+                7,
+                5,
+            )),
+        ];
+        for image in &images {
+            for (rle, top_down) in [(false, false), (true, false), (false, true), (true, true)] {
+                let options = Options {
+                    rle,
+                    top_down,
+                    id: b"id".to_vec(),
+                    ..Options::default()
+                };
+                let file = encode(image, &options).unwrap();
+                let (info, decoded) = decode(&file).unwrap();
+                assert_eq!(
+                    (info.rle, info.top_down, &info.id[..]),
+                    (rle, top_down, &b"id"[..])
+                );
+                assert_eq!(info.extension, None);
+                assert_eq!(&decoded, image);
+            }
+        }
+
+        // 16-bit 降到 8-bit
+        let wide = DynamicImage::from(Image::filled(RGB16::new(0xFFFF, 0x8080, 0), 2, 2));
+        let (info, decoded) = decode(&encode(&wide, &Options::default()).unwrap()).unwrap();
+        assert_eq!(info.bits_per_pixel, 24);
+        assert_eq!(bgr(&decoded), [[0, 0x80, 0xFF]; 4]);
+    }
+
+    #[test]
+    fn rle_packets() {
+        let mut row = vec![BGR8 { b: 1, g: 2, r: 3 }; 200];
+        row[199].b = 9;
+        row[198].b = 8;
+        let image = DynamicImage::from(Image::new(row, 200, 1));
+        let file = encode(
+            &image,
+            &Options {
+                rle: true,
+                ..Options::default()
+            },
+        )
+        .unwrap();
+        // 128 + 70 个相同的像素, 然后 2 个原样输出的像素
+        assert_eq!(
+            file[HEADER..file.len() - FOOTER],
+            [0xFF, 1, 2, 3, 0x80 | 69, 1, 2, 3, 1, 8, 2, 3, 9, 2, 3]
+        );
+        assert_eq!(decode(&file).unwrap().1, image);
+
+        // 解码时包可以跨行
+        let mut file = header(10, (0, 0, 0), (3, 2), 24, 0x20);
+        file.extend_from_slice(&[0x83, 1, 1, 1, 0x01, 2, 2, 2, 3, 3, 3]);
+        let (_, image) = decode(&file).unwrap();
+        assert_eq!(
+            bgr(&image),
+            [[1; 3], [1; 3], [1; 3], [1; 3], [2; 3], [3; 3]]
+        );
+    }
+
+    #[test]
+    fn color_maps_and_origins() {
+        // 24-bit 颜色表从索引 2 开始, 从下往上, 从右往左
+        let mut file = header(1, (2, 3, 24), (2, 2), 8, 0x10);
+        file.extend_from_slice(&[10, 10, 10, 20, 20, 20, 30, 30, 30]);
+        file.extend_from_slice(&[2, 3, 4, 2]);
+        let (info, image) = decode(&file).unwrap();
+        assert_eq!(
+            (info.image_type, info.right_to_left),
+            (ImageType::ColorMapped, true)
+        );
+        assert!(image.as_image::<BGR8>().is_some());
+        assert_eq!(bgr(&image), [[10; 3], [30; 3], [20; 3], [10; 3]]);
+
+        // RLE, 16-bit 索引和 32-bit 颜色表
+        let mut file = header(9, (0, 2, 32), (3, 1), 16, 0x28);
+        file.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        file.extend_from_slice(&[0x82, 1, 0]);
+        let (_, image) = decode(&file).unwrap();
+        let expected = BGRA8 {
+            b: 5,
+            g: 6,
+            r: 7,
+            a: 8,
+        };
+        assert_eq!(image.as_image::<BGRA8>().unwrap().buf(), [expected; 3]);
+
+        file[HEADER + 8 + 1] = 2;
+        assert_eq!(decode(&file), Err(Error::BadPalette));
+        let mut file = header(1, (0, 0, 0), (1, 1), 8, 0);
+        file.push(0);
+        assert_eq!(decode(&file), Err(Error::BadPalette));
+    }
+
+    #[test]
+    fn writes_color_maps_and_argb1555() {
+        // 5 位的值扩展到 8 位后可以原样读回
+        let c5 = |v: u8| v << 3 | v >> 2;
+        let bgra: Vec<_> = (0..12u8)
+            .map(|i| BGRA8 {
+                b: c5(i % 3),
+                g: c5(31),
+                r: c5(i % 2 * 16),
+                a: if i % 4 == 0 { 0 } else { 255 },
+            })
+            .collect();
+        let bgra = DynamicImage::from(Image::new(bgra, 4, 3));
+        let bgr = DynamicImage::from(bgra.to_image::<BGR8>());
+        for rle in [false, true] {
+            for (color_map, argb1555) in [(true, false), (false, true), (true, true)] {
+                let options = Options {
+                    rle,
+                    color_map,
+                    argb1555,
+                    ..Options::default()
+                };
+                for image in [&bgra, &bgr] {
+                    let file = encode(image, &options).unwrap();
+                    let (info, decoded) = decode(&file).unwrap();
+                    let (image_type, bits) = if color_map {
+                        (ImageType::ColorMapped, 8)
+                    } else {
+                        (ImageType::TrueColor, 16)
+                    };
+                    assert_eq!((info.image_type, info.bits_per_pixel), (image_type, bits));
+                    assert_eq!(&decoded, image, "{options:?}");
+                }
+            }
+        }
+
+        // 1 位 alpha, 5 位颜色
+        let px = BGRA8 {
+            b: 0xFF,
+            g: 0x80,
+            r: 0x07,
+            a: 200,
+        };
+        let image = DynamicImage::from(Image::filled(px, 2, 1));
+        let options = Options {
+            argb1555: true,
+            ..Options::default()
+        };
+        let file = encode(&image, &options).unwrap();
+        assert_eq!(file[16..18], [16, 1]);
+        assert_eq!(file[HEADER..HEADER + 2], [0x1F, 0x82]);
+
+        // 颜色太多就不用颜色表; 灰度不用颜色表
+        let many = DynamicImage::from(Image::new(
+            (0..300u32)
+                .map(|i| BGR8 {
+                    b: i as u8,
+                    g: (i >> 8) as u8,
+                    r: 0,
+                })
+                .collect(),
+            300,
+            1,
+        ));
+        let options = Options {
+            color_map: true,
+            ..Options::default()
+        };
+        let (info, decoded) = decode(&encode(&many, &options).unwrap()).unwrap();
+        assert_eq!(info.image_type, ImageType::TrueColor);
+        assert_eq!(decoded, many);
+        let gray = DynamicImage::from(Image::filled(GRAY8::new(3), 2, 2));
+        let info = read_info(&encode(&gray, &options).unwrap()).unwrap();
+        assert_eq!(info.image_type, ImageType::Gray);
+    }
+
+    #[test]
+    fn argb1555_and_gray() {
+        // 0x801F: alpha 位和纯蓝, 0x7C00: 纯红
+        let mut file = header(2, (0, 0, 0), (2, 1), 16, 0x21);
+        file.extend_from_slice(&[0x1F, 0x80, 0x00, 0x7C]);
+        let (_, image) = decode(&file).unwrap();
+        assert_eq!(
+            image.as_image::<BGRA8>().unwrap().buf(),
+            [
+                BGRA8 {
+                    b: 255,
+                    g: 0,
+                    r: 0,
+                    a: 255
+                },
+                BGRA8 {
+                    b: 0,
+                    g: 0,
+                    r: 255,
+                    a: 0
+                },
+            ]
+        );
+        // 没有 alpha 位就是 BGR8
+        file[17] = 0x20;
+        assert_eq!(bgr(&decode(&file).unwrap().1), [[255, 0, 0], [0, 0, 255]]);
+        file[16] = 15;
+        assert_eq!(bgr(&decode(&file).unwrap().1), [[255, 0, 0], [0, 0, 255]]);
+
+        let mut file = header(3, (0, 0, 0), (1, 2), 16, 0x08);
+        file.extend_from_slice(&[1, 2, 3, 4]);
+        let (_, image) = decode(&file).unwrap();
+        assert_eq!(
+            image.as_image::<GRAYA8>().unwrap().buf(),
+            [GrayAlpha::new(3, 4), GrayAlpha::new(1, 2)]
+        );
+    }
+
+    #[test]
+    fn extension_area() {
+        let ext = Extension {
+            author: "Somebody".into(),
+            comments: "first line\nsecond line".into(),
+            timestamp: [10, 18, 2026, 12, 30, 5],
+            job: "Level 3".into(),
+            job_time: [1, 2, 3],
+            software: "Tool".into(),
+            software_version: (410, b'b'),
+            key_color: BGRA8 {
+                b: 1,
+                g: 2,
+                r: 3,
+                a: 4,
+            },
+            aspect_ratio: (1, 1),
+            gamma: (22, 10),
+            alpha: AlphaType::Straight,
+        };
+        let image = DynamicImage::from(Image::filled(RGBA8::new(1, 2, 3, 4), 2, 2));
+        let options = Options {
+            extension: Some(ext.clone()),
+            ..Options::default()
+        };
+        let file = encode(&image, &options).unwrap();
+        assert_eq!(file.len(), HEADER + 16 + EXTENSION + FOOTER);
+        let (info, decoded) = decode(&file).unwrap();
+        assert_eq!(info.extension.as_ref(), Some(&ext));
+        assert_eq!(decoded.to_rgba8().buf(), image.to_rgba8().buf());
+
+        // 扩展区说没有 alpha, 就不用 alpha 位
+        let options = Options {
+            extension: Some(Extension {
+                alpha: AlphaType::UndefinedIgnore,
+                author: "a".repeat(50),
+                ..Extension::default()
+            }),
+            ..Options::default()
+        };
+        let (info, decoded) = decode(&encode(&image, &options).unwrap()).unwrap();
+        assert_eq!(info.extension.unwrap().author, "a".repeat(40));
+        assert!(decoded.as_image::<BGR8>().is_some());
+    }
+
+    #[test]
+    fn bad_headers() {
+        let image = DynamicImage::from(Image::filled(BGR8 { b: 1, g: 2, r: 3 }, 4, 4));
+        let file = encode(&image, &Options::default()).unwrap();
+        assert!(decode(&file).is_ok());
+        assert_eq!(decode(&file[..10]), Err(Error::UnexpectedEof));
+        let bad = |pos: usize, value: u8| {
+            let mut bad = file.clone();
+            bad[pos] = value;
+            decode(&bad)
+        };
+        assert_eq!(bad(2, 4), Err(Error::BadHeader));
+        assert_eq!(bad(1, 2), Err(Error::BadHeader));
+        assert_eq!(bad(12, 0), Err(Error::BadHeader));
+        assert_eq!(bad(17, 0xC0), Err(Error::BadHeader));
+        // 图像 ID 超出文件
+        let mut file = header(2, (0, 0, 0), (1, 1), 24, 0);
+        file[0] = 200;
+        file.extend_from_slice(&[0; 3]);
+        assert_eq!(decode(&file), Err(Error::UnexpectedEof));
+    }
+
+    #[test]
+    fn unsupported_types() {
+        let image = DynamicImage::from(Image::filled(BGR8 { b: 1, g: 2, r: 3 }, 4, 4));
+        let file = encode(&image, &Options::default()).unwrap();
+        let bad = |pos: usize, value: u8| {
+            let mut bad = file.clone();
+            bad[pos] = value;
+            decode(&bad)
+        };
+        // 没有图像数据, Huffman 压缩, 不支持的位数
+        assert_eq!(bad(2, 0), Err(Error::Unsupported));
+        assert_eq!(bad(2, 32), Err(Error::Unsupported));
+        assert_eq!(bad(2, 33), Err(Error::Unsupported));
+        assert_eq!(bad(16, 12), Err(Error::Unsupported));
+        assert_eq!(bad(16, 8), Err(Error::Unsupported));
+        let mut gray = header(3, (0, 0, 0), (1, 1), 24, 0);
+        gray.extend_from_slice(&[0; 3]);
+        assert_eq!(decode(&gray), Err(Error::Unsupported));
+        // 颜色表项的位数
+        let mut file = header(1, (0, 1, 8), (1, 1), 8, 0);
+        file.extend_from_slice(&[0, 0]);
+        assert_eq!(decode(&file), Err(Error::Unsupported));
+    }
+
+    #[test]
+    fn color_map_errors() {
+        // 颜色映射的图没有颜色表
+        let mut file = header(1, (0, 0, 0), (1, 1), 8, 0);
+        file.push(0);
+        assert_eq!(decode(&file), Err(Error::BadPalette));
+        // 索引小于第一个表项, 或者超出表
+        let mut file = header(1, (5, 2, 24), (2, 1), 8, 0);
+        file.extend_from_slice(&[1, 1, 1, 2, 2, 2]);
+        file.extend_from_slice(&[5, 6]);
+        assert!(decode(&file).is_ok());
+        let last = file.len() - 1;
+        file[last] = 4;
+        assert_eq!(decode(&file), Err(Error::BadPalette));
+        file[last] = 7;
+        assert_eq!(decode(&file), Err(Error::BadPalette));
+        // 16 位索引超出表
+        let mut file = header(1, (0, 1, 24), (1, 1), 16, 0);
+        file.extend_from_slice(&[1, 1, 1, 0, 1]);
+        assert_eq!(decode(&file), Err(Error::BadPalette));
+        // 颜色表被截断
+        let mut file = header(1, (0, 300, 32), (1, 1), 8, 0);
+        file.extend_from_slice(&[0; 100]);
+        assert_eq!(decode(&file), Err(Error::UnexpectedEof));
+    }
+
+    #[test]
+    fn rle_bounds() {
+        // 重复包和原样包都不能超出图像
+        let mut file = header(10, (0, 0, 0), (2, 1), 24, 0);
+        file.extend_from_slice(&[0x82, 1, 1, 1]);
+        assert_eq!(decode(&file), Err(Error::BadRle));
+        let mut file = header(10, (0, 0, 0), (2, 1), 24, 0);
+        file.extend_from_slice(&[0x02, 1, 1, 1, 2, 2, 2, 3, 3, 3]);
+        assert_eq!(decode(&file), Err(Error::BadRle));
+        // 包头之后的像素被截断
+        let mut file = header(10, (0, 0, 0), (2, 1), 24, 0);
+        file.extend_from_slice(&[0x81, 1, 1]);
+        assert_eq!(decode(&file), Err(Error::UnexpectedEof));
+        let mut file = header(10, (0, 0, 0), (2, 1), 24, 0);
+        file.extend_from_slice(&[0x01, 1, 1, 1, 2]);
+        assert_eq!(decode(&file), Err(Error::UnexpectedEof));
+        let mut file = header(10, (0, 0, 0), (2, 1), 24, 0);
+        file.extend_from_slice(&[0x80, 1, 1, 1]);
+        assert_eq!(decode(&file), Err(Error::UnexpectedEof));
+    }
+
+    #[test]
+    fn extension_area_errors() {
+        let image = DynamicImage::from(Image::filled(BGR8 { b: 1, g: 2, r: 3 }, 2, 2));
+        let options = Options {
+            extension: Some(Extension::default()),
+            ..Options::default()
+        };
+        let file = encode(&image, &options).unwrap();
+        assert!(decode(&file).is_ok());
+        let footer = file.len() - FOOTER;
+        let ext = footer - EXTENSION;
+        // 偏移量指到文件外面, 或者头部里面
+        let mut bad = file.clone();
+        bad[footer] = 0xFF;
+        assert_eq!(decode(&bad), Err(Error::BadHeader));
+        let mut bad = file.clone();
+        bad[footer..footer + 4].copy_from_slice(&4u32.to_le_bytes());
+        assert_eq!(decode(&bad), Err(Error::BadHeader));
+        // 长度字段不对, alpha 类型不认识
+        let mut bad = file.clone();
+        bad[ext] = 0;
+        assert_eq!(decode(&bad), Err(Error::BadHeader));
+        let mut bad = file.clone();
+        bad[footer - 1] = 5;
+        assert_eq!(decode(&bad), Err(Error::BadHeader));
+        // 没有签名的尾部就不是 TGA 2.0, 不读扩展区
+        let mut bad = file.clone();
+        bad[footer] = 0xFF;
+        *bad.last_mut().unwrap() = 1;
+        assert_eq!(decode(&bad).unwrap().0.extension, None);
+    }
+
+    #[test]
+    fn truncated_pixels() {
+        let image = DynamicImage::from(Image::filled(BGR8 { b: 1, g: 2, r: 3 }, 4, 4));
+        let file = encode(&image, &Options::default()).unwrap();
+        assert_eq!(decode(&file[..HEADER + 20]), Err(Error::UnexpectedEof));
+        assert_eq!(decode(&file[..HEADER + 47]), Err(Error::UnexpectedEof));
+        let mut file = header(3, (0, 0, 0), (2, 2), 16, 0);
+        file.extend_from_slice(&[0; 7]);
+        assert_eq!(decode(&file), Err(Error::UnexpectedEof));
+    }
+
+    #[test]
+    fn huge_header_tiny_data() {
+        // 巨大的图像, 数据太少时不分配内存
+        for (kind, depth) in [(2, 32), (2, 16), (3, 8), (10, 32), (11, 16)] {
+            let mut file = header(kind, (0, 0, 0), (65535, 65535), depth, 0);
+            file.extend_from_slice(&[0xFF, 0, 0, 0, 0]);
+            assert_eq!(decode(&file), Err(Error::UnexpectedEof), "{kind} {depth}");
+        }
+        let mut file = header(9, (0, 1, 24), (65535, 65535), 8, 0);
+        file.extend_from_slice(&[0, 0, 0, 0xFF, 0]);
+        assert_eq!(decode(&file), Err(Error::UnexpectedEof));
+        // 每个包最多 128 个像素: 正好够的数据可以解码
+        let mut file = header(10, (0, 0, 0), (256, 1), 32, 0);
+        file.extend_from_slice(&[0xFF, 1, 2, 3, 4, 0xFF, 1, 2, 3, 4]);
+        assert!(decode(&file).is_ok());
+    }
+
+    #[test]
+    fn encode_errors() {
+        let empty = DynamicImage::from(Image::<BGR8>::new(Vec::new(), 0, 0));
+        assert_eq!(encode(&empty, &Options::default()), Err(Error::BadHeader));
+        let wide = DynamicImage::from(Image::filled(GRAY8::new(0), 70_000, 1));
+        assert_eq!(encode(&wide, &Options::default()), Err(Error::TooLarge));
+        let tall = DynamicImage::from(Image::filled(GRAY8::new(0), 1, 70_000));
+        assert_eq!(encode(&tall, &Options::default()), Err(Error::TooLarge));
+        let options = Options {
+            id: vec![0; 256],
+            ..Options::default()
+        };
+        let image = DynamicImage::from(Image::filled(GRAY8::new(0), 1, 1));
+        assert_eq!(encode(&image, &options), Err(Error::BadHeader));
+    }
+}
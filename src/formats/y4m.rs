@@ -0,0 +1,861 @@
+//! YUV4MPEG2 (`.y4m`): raw planar YUV video with a one-line text header.
+//!
+//! [`Decoder`] iterates over the frames of a file in memory without copying them; each
+//! [`Frame`] gives access to its Y, U and V planes, and converts to `RGB8` or `RGB16`.
+//! [`Encoder`] writes planes frame by frame.
+//!
+//! 8-bit samples are one byte. Deeper ones (`420p10`, `mono10` and so on, up to 16 bits)
+//! are two bytes, little-endian. Chroma siting only matters for the header: conversion
+//! to RGB uses the nearest chroma sample.
+//!
+//! ```rust
+//! use cr::formats::y4m::{self, Colorspace, Header, Matrix};
+//! use cr::RGB8;
+//!
+//! let header = Header::new(2, 2, (30, 1), Colorspace::C420Jpeg);
+//! let mut encoder = y4m::Encoder::new(Vec::new(), &header).unwrap();
+//! encoder.write_frame(&[235; 4], &[128], &[128]).unwrap();
+//! let file = encoder.finish().unwrap();
+//! assert!(file.starts_with(b"YUV4MPEG2 W2 H2 F30:1 Ip A0:0 C420jpeg\nFRAME\n"));
+//!
+//! let mut decoder = y4m::Decoder::new(&file).unwrap();
+//! assert_eq!(decoder.header(), header);
+//! let frame = decoder.next().unwrap().unwrap();
+//! assert_eq!(frame.y(), [235; 4]);
+//! assert_eq!(frame.to_rgb8(Matrix::Bt601).buf(), [RGB8::new(255, 255, 255); 4]);
+//! assert!(decoder.next().is_none());
+//! ```
+
+use crate::{Image, RGB16, RGB8};
+use core::fmt;
+use std::io::{self, Write};
+
+const MAGIC: &[u8] = b"YUV4MPEG2 ";
+const FRAME: &[u8] = b"FRAME";
+
+/// Why a Y4M file couldn't be decoded, or a frame couldn't be encoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Error {
+    /// Doesn't start with `YUV4MPEG2`
+    BadMagic,
+    /// Malformed or missing header fields, or zero size
+    BadHeader,
+    /// Colour space or bit depth this module doesn't handle, e.g. `411` or `444alpha`
+    Unsupported,
+    /// A frame doesn't start with `FRAME`.
+    /// When encoding: a plane with the wrong number of bytes.
+    BadFrame,
+    /// The file ends in the middle of the header or a frame
+    UnexpectedEof,
+    /// A frame doesn't fit in memory
+    TooLarge,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::BadMagic => "not a YUV4MPEG2 file",
+            Error::BadHeader => "invalid Y4M header",
+            Error::Unsupported => "unsupported Y4M colour space",
+            Error::BadFrame => "invalid Y4M frame",
+            Error::UnexpectedEof => "file is truncated",
+            Error::TooLarge => "frame is too large",
+        })
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
+
+/// Chroma subsampling and siting, the `C` header field
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum Colorspace {
+    /// 4:2:0, chroma centered between luma samples. The default when `C` is missing.
+    #[default]
+    C420Jpeg,
+    /// 4:2:0, chroma between luma rows but on even columns
+    C420Mpeg2,
+    /// 4:2:0, chroma alternating lines (PAL DV)
+    C420Paldv,
+    /// 4:2:2, half-width chroma
+    C422,
+    /// 4:4:4, no subsampling
+    C444,
+    /// Luma only, no U and V planes
+    Mono,
+}
+
+impl Colorspace {
+    /// How many times smaller chroma planes are, as powers of two, across and down
+    #[inline]
+    fn chroma_shift(self) -> (u32, u32) {
+        match self {
+            Colorspace::C420Jpeg | Colorspace::C420Mpeg2 | Colorspace::C420Paldv => (1, 1),
+            Colorspace::C422 => (1, 0),
+            Colorspace::C444 | Colorspace::Mono => (0, 0),
+        }
+    }
+
+    /// Parse the `C` field's value, which for over 8 bits has the depth instead of the siting
+    fn parse(value: &[u8]) -> Result<(Self, u8), Error> {
+        let cs = match value {
+            b"420jpeg" | b"420" => return Ok((Colorspace::C420Jpeg, 8)),
+            b"420mpeg2" => return Ok((Colorspace::C420Mpeg2, 8)),
+            b"420paldv" => return Ok((Colorspace::C420Paldv, 8)),
+            b"422" => return Ok((Colorspace::C422, 8)),
+            b"444" => return Ok((Colorspace::C444, 8)),
+            b"mono" => return Ok((Colorspace::Mono, 8)),
+            [b'4', b'2', b'0', b'p', ..] => Colorspace::C420Jpeg,
+            [b'4', b'2', b'2', b'p', ..] => Colorspace::C422,
+            [b'4', b'4', b'4', b'p', ..] => Colorspace::C444,
+            [b'm', b'o', b'n', b'o', ..] => Colorspace::Mono,
+            _ => return Err(Error::Unsupported),
+        };
+        match &value[4..] {
+            b"9" => Ok((cs, 9)),
+            [b'1', d @ b'0'..=b'6'] => Ok((cs, 10 + d - b'0')),
+            _ => Err(Error::Unsupported),
+        }
+    }
+
+    /// The `C` field's value
+    fn name(self, bit_depth: u8) -> String {
+        if bit_depth > 8 {
+            let prefix = match self {
+                Colorspace::C420Jpeg | Colorspace::C420Mpeg2 | Colorspace::C420Paldv => "420p",
+                Colorspace::C422 => "422p",
+                Colorspace::C444 => "444p",
+                Colorspace::Mono => "mono",
+            };
+            return format!("{prefix}{bit_depth}");
+        }
+        match self {
+            Colorspace::C420Jpeg => "420jpeg",
+            Colorspace::C420Mpeg2 => "420mpeg2",
+            Colorspace::C420Paldv => "420paldv",
+            Colorspace::C422 => "422",
+            Colorspace::C444 => "444",
+            Colorspace::Mono => "mono",
+        }
+        .into()
+    }
+}
+
+/// Field order, the `I` header field. It's informative only.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum Interlace {
+    /// `p`, the default
+    #[default]
+    Progressive,
+    /// `t`
+    TopFirst,
+    /// `b`
+    BottomFirst,
+    /// `m`, given per frame
+    Mixed,
+}
+
+/// The stream header
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Header {
+    pub width: usize,
+    pub height: usize,
+    /// Frames per second as a fraction, `0:0` if unknown
+    pub frame_rate: (u32, u32),
+    pub interlace: Interlace,
+    /// Pixel aspect ratio as a fraction, `0:0` if unknown
+    pub pixel_aspect: (u32, u32),
+    pub colorspace: Colorspace,
+    /// 8, or 9 to 16 for two bytes per sample
+    pub bit_depth: u8,
+    /// From `XCOLORRANGE=FULL`. Otherwise samples use the limited "TV" range.
+    pub full_range: bool,
+}
+
+impl Header {
+    /// Progressive 8-bit limited-range video with unknown pixel aspect
+    pub fn new(
+        width: usize,
+        height: usize,
+        frame_rate: (u32, u32),
+        colorspace: Colorspace,
+    ) -> Self {
+        Self {
+            width,
+            height,
+            frame_rate,
+            interlace: Interlace::Progressive,
+            pixel_aspect: (0, 0),
+            colorspace,
+            bit_depth: 8,
+            full_range: false,
+        }
+    }
+
+    /// Read the header line at the start of a file
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        Self::parse_len(data).map(|(h, _)| h)
+    }
+
+    /// The header, and the length of its line including the line break
+    fn parse_len(data: &[u8]) -> Result<(Self, usize), Error> {
+        if !data.starts_with(MAGIC) {
+            return Err(if MAGIC.starts_with(data) {
+                Error::UnexpectedEof
+            } else {
+                Error::BadMagic
+            });
+        }
+        let len = data
+            .iter()
+            .position(|&c| c == b'\n')
+            .ok_or(Error::UnexpectedEof)?;
+        let mut header = Self::new(0, 0, (0, 0), Colorspace::C420Jpeg);
+        for field in data[MAGIC.len()..len].split(|&c| c == b' ') {
+            let Some((&tag, value)) = field.split_first() else {
+                continue;
+            };
+            match tag {
+                b'W' => header.width = number(value)? as usize,
+                b'H' => header.height = number(value)? as usize,
+                b'F' => header.frame_rate = ratio(value)?,
+                b'A' => header.pixel_aspect = ratio(value)?,
+                b'I' => {
+                    header.interlace = match value {
+                        b"p" | b"?" => Interlace::Progressive,
+                        b"t" => Interlace::TopFirst,
+                        b"b" => Interlace::BottomFirst,
+                        b"m" => Interlace::Mixed,
+                        _ => return Err(Error::BadHeader),
+                    }
+                }
+                b'C' => (header.colorspace, header.bit_depth) = Colorspace::parse(value)?,
+                // 其它扩展参数都忽略
+                b'X' => match value {
+                    b"COLORRANGE=FULL" => header.full_range = true,
+                    b"COLORRANGE=LIMITED" => header.full_range = false,
+                    _ => {}
+                },
+                _ => return Err(Error::BadHeader),
+            }
+        }
+        if header.width == 0 || header.height == 0 {
+            return Err(Error::BadHeader);
+        }
+        header.frame_len()?;
+        Ok((header, len + 1))
+    }
+
+    /// Width and height of the U and V planes, zero for mono
+    pub fn chroma_size(&self) -> (usize, usize) {
+        if self.colorspace == Colorspace::Mono {
+            return (0, 0);
+        }
+        let (sx, sy) = self.colorspace.chroma_shift();
+        (self.width.div_ceil(1 << sx), self.height.div_ceil(1 << sy))
+    }
+
+    /// Bytes per sample
+    #[inline]
+    fn sample_len(&self) -> usize {
+        if self.bit_depth > 8 {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Bytes in the Y plane, and in each of the U and V planes
+    fn plane_lens(&self) -> Result<(usize, usize), Error> {
+        let (cw, ch) = self.chroma_size();
+        let len = |w: usize, h: usize| w.checked_mul(h)?.checked_mul(self.sample_len());
+        len(self.width, self.height)
+            .zip(len(cw, ch))
+            .ok_or(Error::TooLarge)
+    }
+
+    /// Bytes of pixel data in a frame, not counting the `FRAME` line
+    pub fn frame_len(&self) -> Result<usize, Error> {
+        let (y, c) = self.plane_lens()?;
+        c.checked_mul(2)
+            .and_then(|c| y.checked_add(c))
+            .ok_or(Error::TooLarge)
+    }
+
+    fn line(&self) -> Result<Vec<u8>, Error> {
+        if self.width == 0 || self.height == 0 {
+            return Err(Error::BadHeader);
+        }
+        if !(8..=16).contains(&self.bit_depth) {
+            return Err(Error::Unsupported);
+        }
+        self.frame_len()?;
+        let interlace = match self.interlace {
+            Interlace::Progressive => 'p',
+            Interlace::TopFirst => 't',
+            Interlace::BottomFirst => 'b',
+            Interlace::Mixed => 'm',
+        };
+        let mut line = format!(
+            "YUV4MPEG2 W{} H{} F{}:{} I{interlace} A{}:{} C{}",
+            self.width,
+            self.height,
+            self.frame_rate.0,
+            self.frame_rate.1,
+            self.pixel_aspect.0,
+            self.pixel_aspect.1,
+            self.colorspace.name(self.bit_depth),
+        );
+        if self.full_range {
+            line.push_str(" XCOLORRANGE=FULL");
+        }
+        line.push('\n');
+        Ok(line.into_bytes())
+    }
+}
+
+fn number(value: &[u8]) -> Result<u32, Error> {
+    if value.is_empty() || !value.iter().all(u8::is_ascii_digit) {
+        return Err(Error::BadHeader);
+    }
+    core::str::from_utf8(value)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or(Error::BadHeader)
+}
+
+/// `n:d`
+fn ratio(value: &[u8]) -> Result<(u32, u32), Error> {
+    let colon = value
+        .iter()
+        .position(|&c| c == b':')
+        .ok_or(Error::BadHeader)?;
+    Ok((number(&value[..colon])?, number(&value[colon + 1..])?))
+}
+
+/// YUV to RGB coefficients
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum Matrix {
+    /// SD video
+    #[default]
+    Bt601,
+    /// HD video
+    Bt709,
+}
+
+impl Matrix {
+    /// Red and blue luma weights
+    #[inline]
+    fn kr_kb(self) -> (f32, f32) {
+        match self {
+            Matrix::Bt601 => (0.299, 0.114),
+            Matrix::Bt709 => (0.2126, 0.0722),
+        }
+    }
+}
+
+/// One frame's planes, borrowed from the file
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Frame<'a> {
+    header: Header,
+    y: &'a [u8],
+    u: &'a [u8],
+    v: &'a [u8],
+}
+
+impl<'a> Frame<'a> {
+    /// Luma, width × height samples
+    #[inline]
+    pub fn y(&self) -> &'a [u8] {
+        self.y
+    }
+
+    /// Blue difference chroma, [`Header::chroma_size`] samples. Empty for mono.
+    #[inline]
+    pub fn u(&self) -> &'a [u8] {
+        self.u
+    }
+
+    /// Red difference chroma, [`Header::chroma_size`] samples. Empty for mono.
+    #[inline]
+    pub fn v(&self) -> &'a [u8] {
+        self.v
+    }
+
+    /// Y, U and V samples as numbers, for any bit depth
+    pub fn planes_u16(&self) -> [Vec<u16>; 3] {
+        [self.y, self.u, self.v].map(|plane| {
+            if self.header.bit_depth > 8 {
+                plane
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect()
+            } else {
+                plane.iter().map(|&c| c as u16).collect()
+            }
+        })
+    }
+
+    /// Converts to RGB in `0..=1`, and hands each pixel to `put` in row-major order
+    fn convert(&self, matrix: Matrix, mut put: impl FnMut([f32; 3])) {
+        let h = &self.header;
+        let [y_plane, u_plane, v_plane] = self.planes_u16();
+        let shift = h.bit_depth as u32 - 8;
+        let max = ((1u32 << h.bit_depth) - 1) as f32;
+        let mid = (128u32 << shift) as f32;
+        let (y_off, y_range, c_range) = if h.full_range {
+            (0., max, max)
+        } else {
+            (
+                (16u32 << shift) as f32,
+                (219u32 << shift) as f32,
+                (224u32 << shift) as f32,
+            )
+        };
+        let (kr, kb) = matrix.kr_kb();
+        let kg = 1. - kr - kb;
+        let (sx, sy) = h.colorspace.chroma_shift();
+        let (cw, _) = h.chroma_size();
+        for y in 0..h.height {
+            for x in 0..h.width {
+                let luma = (y_plane[y * h.width + x] as f32 - y_off) / y_range;
+                let (cb, cr) = if h.colorspace == Colorspace::Mono {
+                    (0., 0.)
+                } else {
+                    let i = (y >> sy) * cw + (x >> sx);
+                    (
+                        (u_plane[i] as f32 - mid) / c_range,
+                        (v_plane[i] as f32 - mid) / c_range,
+                    )
+                };
+                let r = luma + 2. * (1. - kr) * cr;
+                let b = luma + 2. * (1. - kb) * cb;
+                let g = (luma - kr * r - kb * b) / kg;
+                put([r, g, b]);
+            }
+        }
+    }
+
+    /// RGB with the given matrix, rounded and clamped
+    pub fn to_rgb8(&self, matrix: Matrix) -> Image<RGB8> {
+        let mut buf = Vec::with_capacity(self.header.width * self.header.height);
+        let c = |v: f32| (v.clamp(0., 1.) * 255. + 0.5) as u8;
+        self.convert(matrix, |[r, g, b]| buf.push(RGB8::new(c(r), c(g), c(b))));
+        Image::new(buf, self.header.width, self.header.height)
+    }
+
+    /// RGB with the given matrix, rounded and clamped. Keeps the precision of deep video.
+    pub fn to_rgb16(&self, matrix: Matrix) -> Image<RGB16> {
+        let mut buf = Vec::with_capacity(self.header.width * self.header.height);
+        let c = |v: f32| (v.clamp(0., 1.) * 65535. + 0.5) as u16;
+        self.convert(matrix, |[r, g, b]| buf.push(RGB16::new(c(r), c(g), c(b))));
+        Image::new(buf, self.header.width, self.header.height)
+    }
+}
+
+/// Reads a Y4M file frame by frame. After an error the iterator ends.
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    header: Header,
+    done: bool,
+}
+
+impl<'a> Decoder<'a> {
+    /// Reads the header
+    pub fn new(data: &'a [u8]) -> Result<Self, Error> {
+        let (header, pos) = Header::parse_len(data)?;
+        Ok(Self {
+            data,
+            pos,
+            header,
+            done: false,
+        })
+    }
+
+    #[inline]
+    pub fn header(&self) -> Header {
+        self.header
+    }
+
+    fn read_frame(&mut self) -> Result<Option<Frame<'a>>, Error> {
+        let rest = &self.data[self.pos..];
+        if rest.is_empty() {
+            return Ok(None);
+        }
+        if !rest.starts_with(FRAME) {
+            return Err(if FRAME.starts_with(rest) {
+                Error::UnexpectedEof
+            } else {
+                Error::BadFrame
+            });
+        }
+        // 帧参数不用, 跳到行尾
+        let line = rest
+            .iter()
+            .position(|&c| c == b'\n')
+            .ok_or(Error::UnexpectedEof)?;
+        if !matches!(rest[FRAME.len()], b' ' | b'\n') {
+            return Err(Error::BadFrame);
+        }
+        let (y_len, c_len) = self.header.plane_lens()?;
+        let planes = rest[line + 1..]
+            .get(..y_len + 2 * c_len)
+            .ok_or(Error::UnexpectedEof)?;
+        self.pos += line + 1 + planes.len();
+        let (y, uv) = planes.split_at(y_len);
+        let (u, v) = uv.split_at(c_len);
+        Ok(Some(Frame {
+            header: self.header,
+            y,
+            u,
+            v,
+        }))
+    }
+}
+
+impl<'a> Iterator for Decoder<'a> {
+    type Item = Result<Frame<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_frame() {
+            Ok(Some(frame)) => Some(Ok(frame)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Writes frames one by one
+pub struct Encoder<W: Write> {
+    out: W,
+    y_len: usize,
+    c_len: usize,
+}
+
+impl<W: Write> Encoder<W> {
+    /// Writes the header right away
+    pub fn new(mut out: W, header: &Header) -> io::Result<Self> {
+        let line = header.line()?;
+        let (y_len, c_len) = header.plane_lens()?;
+        out.write_all(&line)?;
+        Ok(Self { out, y_len, c_len })
+    }
+
+    /// Next frame's planes, in the file's layout: one or two bytes per sample.
+    /// `u` and `v` are empty for mono.
+    pub fn write_frame(&mut self, y: &[u8], u: &[u8], v: &[u8]) -> io::Result<()> {
+        if y.len() != self.y_len || u.len() != self.c_len || v.len() != self.c_len {
+            return Err(Error::BadFrame.into());
+        }
+        self.out.write_all(b"FRAME\n")?;
+        self.out.write_all(y)?;
+        self.out.write_all(u)?;
+        self.out.write_all(v)
+    }
+
+    /// Returns the writer. Y4M has no trailer.
+    pub fn finish(self) -> io::Result<W> {
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers() {
+        let h = Header::parse(
+            b"YUV4MPEG2 W352 H288 F30000:1001 It A128:117 C420mpeg2 XYSCSS=420MPEG2 XCOLORRANGE=FULL\n",
+        )
+        .unwrap();
+        assert_eq!(
+            h,
+            Header {
+                width: 352,
+                height: 288,
+                frame_rate: (30000, 1001),
+                interlace: Interlace::TopFirst,
+                pixel_aspect: (128, 117),
+                colorspace: Colorspace::C420Mpeg2,
+                bit_depth: 8,
+                full_range: true,
+            }
+        );
+        assert_eq!(h.chroma_size(), (176, 144));
+        assert_eq!(h.frame_len(), Ok(352 * 288 * 3 / 2));
+
+        // 缺省的色彩空间是 420jpeg; 奇数尺寸向上取整
+        let h = Header::parse(b"YUV4MPEG2 W5 H3\n").unwrap();
+        assert_eq!(
+            (h.colorspace, h.bit_depth, h.frame_rate),
+            (Colorspace::C420Jpeg, 8, (0, 0))
+        );
+        assert_eq!(h.chroma_size(), (3, 2));
+
+        for (c, cs, depth, len) in [
+            ("420", Colorspace::C420Jpeg, 8, 6),
+            ("420paldv", Colorspace::C420Paldv, 8, 6),
+            ("422", Colorspace::C422, 8, 8),
+            ("444", Colorspace::C444, 8, 12),
+            ("mono", Colorspace::Mono, 8, 4),
+            ("420p10", Colorspace::C420Jpeg, 10, 12),
+            ("422p9", Colorspace::C422, 9, 16),
+            ("444p16", Colorspace::C444, 16, 24),
+            ("mono12", Colorspace::Mono, 12, 8),
+        ] {
+            let h = Header::parse(format!("YUV4MPEG2 W2 H2 C{c}\n").as_bytes()).unwrap();
+            assert_eq!(
+                (h.colorspace, h.bit_depth, h.frame_len()),
+                (cs, depth, Ok(len)),
+                "{c}"
+            );
+            // 写出再读回来
+            let mut line = h.line().unwrap();
+            line.extend_from_slice(b"FRAME\n");
+            assert_eq!(Header::parse(&line), Ok(h));
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut header = Header::new(3, 2, (25, 1), Colorspace::C422);
+        header.bit_depth = 10;
+        let frames: Vec<[Vec<u8>; 3]> = (0..3u16)
+            .map(|n| {
+                let plane = |len: u16, k: u16| -> Vec<u8> {
+                    (0..len)
+                        .flat_map(|i| (i * k + n * 100).to_le_bytes())
+                        .collect()
+                };
+                [plane(6, 150), plane(4, 200), plane(4, 250)]
+            })
+            .collect();
+        let mut encoder = Encoder::new(Vec::new(), &header).unwrap();
+        for [y, u, v] in &frames {
+            encoder.write_frame(y, u, v).unwrap();
+        }
+        let file = encoder.finish().unwrap();
+
+        let decoder = Decoder::new(&file).unwrap();
+        assert_eq!(decoder.header(), header);
+        let decoded: Vec<_> = decoder.map(Result::unwrap).collect();
+        assert_eq!(decoded.len(), frames.len());
+        for (frame, [y, u, v]) in decoded.iter().zip(&frames) {
+            assert_eq!((frame.y(), frame.u(), frame.v()), (&y[..], &u[..], &v[..]));
+        }
+        assert_eq!(decoded[1].planes_u16()[0], [100, 250, 400, 550, 700, 850]);
+
+        // 帧参数被跳过
+        let file = b"YUV4MPEG2 W1 H1 Cmono\nFRAME Ixyz\n\x07FRAME\n\x08";
+        let planes: Vec<_> = Decoder::new(file)
+            .unwrap()
+            .map(|f| f.unwrap().y()[0])
+            .collect();
+        assert_eq!(planes, [7, 8]);
+    }
+
+    #[test]
+    fn converts() {
+        fn frame<'a>(header: Header, y: &'a [u8], u: &'a [u8], v: &'a [u8]) -> Frame<'a> {
+            Frame { header, y, u, v }
+        }
+        // 有限范围: 16 是黑, 235 是白; 色度在 2×2 块里共用
+        let h = Header::new(2, 2, (0, 0), Colorspace::C420Jpeg);
+        let f = frame(h, &[16, 235, 126, 126], &[128], &[128]);
+        let gray = RGB8::new(128, 128, 128);
+        assert_eq!(
+            f.to_rgb8(Matrix::Bt601).buf(),
+            [RGB8::new(0, 0, 0), RGB8::new(255, 255, 255), gray, gray]
+        );
+        assert_eq!(
+            f.to_rgb16(Matrix::Bt709).buf()[1],
+            RGB16::new(65535, 65535, 65535)
+        );
+
+        // BT.601 和 BT.709 的纯红, 取整后的 YUV 会差一点
+        let h = Header::new(1, 1, (0, 0), Colorspace::C444);
+        for (yuv, matrix) in [
+            ([81, 90, 240], Matrix::Bt601),
+            ([63, 102, 240], Matrix::Bt709),
+        ] {
+            let [y, u, v] = yuv.map(|c| [c]);
+            let px = frame(h, &y, &u, &v).to_rgb8(matrix).buf()[0];
+            assert!(px.r >= 254 && px.g <= 1 && px.b <= 1, "{matrix:?} {px:?}");
+        }
+
+        // 全范围, 10 位, 单色
+        let mut h = Header::new(2, 1, (0, 0), Colorspace::Mono);
+        h.bit_depth = 10;
+        h.full_range = true;
+        let f = frame(h, &[0xFF, 0x03, 0x00, 0x02], &[], &[]);
+        assert_eq!(
+            f.to_rgb16(Matrix::Bt601).buf(),
+            [
+                RGB16::new(65535, 65535, 65535),
+                RGB16::new(32800, 32800, 32800)
+            ]
+        );
+        assert_eq!(f.to_rgb8(Matrix::Bt601).buf()[1], RGB8::new(128, 128, 128));
+
+        // 4:2:2 每两列共用色度
+        let h = Header::new(2, 2, (0, 0), Colorspace::C422);
+        let rgb = frame(h, &[126; 4], &[128, 128], &[128, 240]).to_rgb8(Matrix::Bt601);
+        assert_eq!(rgb.buf()[0], rgb.buf()[1]);
+        assert!(rgb.buf()[2].r > 200);
+    }
+
+    #[test]
+    fn bad_headers() {
+        assert_eq!(Header::parse(b"YUV4MPEG"), Err(Error::UnexpectedEof));
+        assert_eq!(Header::parse(b"YUV4MPEG2 W2 H1"), Err(Error::UnexpectedEof));
+        assert_eq!(Header::parse(b"YUV4MPEG1 W2 H1\n"), Err(Error::BadMagic));
+        assert_eq!(Header::parse(b"YUV4MPEG2 W2\n"), Err(Error::BadHeader));
+        assert_eq!(Header::parse(b"YUV4MPEG2 W0 H1\n"), Err(Error::BadHeader));
+        assert_eq!(
+            Header::parse(b"YUV4MPEG2 W2 H1 F30\n"),
+            Err(Error::BadHeader)
+        );
+        assert_eq!(Header::parse(b"YUV4MPEG2 W2 H-1\n"), Err(Error::BadHeader));
+        // 超出 u32 的数字
+        assert_eq!(
+            Header::parse(b"YUV4MPEG2 W4294967296 H1\n"),
+            Err(Error::BadHeader)
+        );
+        assert_eq!(
+            Header::parse(b"YUV4MPEG2 W2 H1 Iq\n"),
+            Err(Error::BadHeader)
+        );
+        assert_eq!(
+            Header::parse(b"YUV4MPEG2 W2 H1 Z1\n"),
+            Err(Error::BadHeader)
+        );
+    }
+
+    #[test]
+    fn unsupported_colorspaces() {
+        assert_eq!(
+            Header::parse(b"YUV4MPEG2 W2 H1 C411\n"),
+            Err(Error::Unsupported)
+        );
+        assert_eq!(
+            Header::parse(b"YUV4MPEG2 W2 H1 C444alpha\n"),
+            Err(Error::Unsupported)
+        );
+        // 位深只支持 9 到 16
+        assert_eq!(
+            Header::parse(b"YUV4MPEG2 W2 H1 C420p8\n"),
+            Err(Error::Unsupported)
+        );
+        assert_eq!(
+            Header::parse(b"YUV4MPEG2 W2 H1 C420p17\n"),
+            Err(Error::Unsupported)
+        );
+    }
+
+    #[test]
+    fn bad_frame_markers() {
+        let next = |data: &[u8]| Decoder::new(data).unwrap().next().unwrap().map(|_| ());
+        assert_eq!(
+            next(b"YUV4MPEG2 W2 H1 C444\nFRAMEX\n123456"),
+            Err(Error::BadFrame)
+        );
+        assert_eq!(
+            next(b"YUV4MPEG2 W2 H1 C444\nframe\n123456"),
+            Err(Error::BadFrame)
+        );
+        // FRAME 行没有换行
+        assert_eq!(
+            next(b"YUV4MPEG2 W2 H1 C444\nFRAME Ixyz"),
+            Err(Error::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn truncated_frames() {
+        let file = b"YUV4MPEG2 W2 H1 C444\nFRAME\n123456";
+        assert_eq!(Decoder::new(file).unwrap().count(), 1);
+        let next = |data: &[u8]| Decoder::new(data).unwrap().next().unwrap().map(|_| ());
+        assert_eq!(next(&file[..file.len() - 1]), Err(Error::UnexpectedEof));
+        assert_eq!(next(&file[..24]), Err(Error::UnexpectedEof));
+        // 第二帧不完整
+        let mut two = file.to_vec();
+        two.extend_from_slice(b"FRAME\n12");
+        let mut decoder = Decoder::new(&two).unwrap();
+        assert!(decoder.next().unwrap().is_ok());
+        assert_eq!(
+            decoder.next().unwrap().map(|_| ()),
+            Err(Error::UnexpectedEof)
+        );
+
+        // 出错之后迭代器就停下
+        let mut decoder = Decoder::new(&file[..30]).unwrap();
+        assert!(decoder.next().unwrap().is_err());
+        assert!(decoder.next().is_none());
+    }
+
+    #[test]
+    fn huge_header_tiny_data() {
+        // 头里的尺寸很大, 数据只有几个字节, 不能先按帧大小申请内存
+        let file = b"YUV4MPEG2 W65535 H65535 C444p16\nFRAME\n123456";
+        let mut decoder = Decoder::new(file).unwrap();
+        assert_eq!(
+            decoder.next().unwrap().map(|_| ()),
+            Err(Error::UnexpectedEof)
+        );
+        assert!(decoder.next().is_none());
+
+        // 帧大小溢出
+        assert_eq!(
+            Header::parse(b"YUV4MPEG2 W4294967295 H4294967295 C420p16\n"),
+            Err(Error::TooLarge)
+        );
+        assert_eq!(
+            Header::parse(b"YUV4MPEG2 W4294967295 H4294967295 C444\n"),
+            Err(Error::TooLarge)
+        );
+        assert_eq!(
+            Header::parse(b"YUV4MPEG2 W4294967295 H4294967295 Cmono\n"),
+            Ok(Header {
+                width: 4294967295,
+                height: 4294967295,
+                colorspace: Colorspace::Mono,
+                ..Header::new(1, 1, (0, 0), Colorspace::Mono)
+            })
+        );
+    }
+
+    #[test]
+    fn encode_errors() {
+        let mut header = Header::new(2, 1, (1, 1), Colorspace::C444);
+        let mut encoder = Encoder::new(Vec::new(), &header).unwrap();
+        // 平面长度不对
+        assert!(encoder.write_frame(&[0; 2], &[0; 2], &[0; 1]).is_err());
+        assert!(encoder.write_frame(&[0; 3], &[0; 2], &[0; 2]).is_err());
+        header.bit_depth = 17;
+        assert!(Encoder::new(Vec::new(), &header).is_err());
+        header.bit_depth = 7;
+        assert!(Encoder::new(Vec::new(), &header).is_err());
+        header.bit_depth = 8;
+        header.width = 0;
+        assert!(Encoder::new(Vec::new(), &header).is_err());
+        header.width = usize::MAX;
+        header.height = 2;
+        assert!(Encoder::new(Vec::new(), &header).is_err());
+    }
+}
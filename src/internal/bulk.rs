@@ -0,0 +1,361 @@
+use crate::alt::{BGR, BGRA};
+use crate::{RGB, RGBA};
+use core::mem;
+use core::slice;
+
+#[cfg(feature = "argb")]
+use crate::alt::{ABGR, ARGB};
+
+/// Converts whole slices of 8-bit and 16-bit pixels between layouts, e.g. `[BGRA8]` to `[RGBA8]`.
+///
+/// Covers channel swaps (RGBA↔BGRA, RGB↔BGR, ARGB↔RGBA, ABGR↔BGRA), adding opaque alpha
+/// (RGB→RGBA) and dropping alpha (RGBA→RGB). On x86_64 it uses SSSE3 or AVX2 when the CPU
+/// supports them (detected at run time), otherwise converts pixel by pixel.
+///
+/// ```rust
+/// use cr::alt::BGRA8;
+/// use cr::{ConvertSlice, RGBA8};
+///
+/// let screen = vec![BGRA8 { b: 3, g: 2, r: 1, a: 255 }; 100];
+/// let mut rgba = vec![RGBA8::default(); 100];
+/// screen.convert_into(&mut rgba);
+///
+/// assert_eq!(rgba[99], RGBA8::new(1, 2, 3, 255));
+/// ```
+pub trait ConvertSlice<Dst> {
+    /// Convert every pixel into `dst`.
+    ///
+    /// Panics if `dst` has a different length.
+    fn convert_into(&self, dst: &mut [Dst]);
+
+    /// Convert every pixel into a new `Vec`
+    fn convert_to_vec(&self) -> Vec<Dst>;
+}
+
+/// Marks a destination channel that isn't copied, but filled with the maximum value
+const OPAQUE: u8 = 0xFF;
+
+/// Byte-level description of a conversion between two layouts with the same component type
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    src_channels: usize,
+    dst_channels: usize,
+    /// For each destination channel, index of the source channel (or `OPAQUE`)
+    order: [u8; 4],
+    component_size: usize,
+}
+
+impl Layout {
+    /// Number of whole pixels that fit in 16 bytes of both source and destination
+    #[inline]
+    fn pixels_per_step(&self) -> usize {
+        let src = 16 / (self.src_channels * self.component_size);
+        let dst = 16 / (self.dst_channels * self.component_size);
+        src.min(dst)
+    }
+
+    /// `pshufb` mask for one 16-byte step, and bytes that have to be OR-ed in for opaque alpha
+    fn masks(&self) -> ([u8; 16], [u8; 16]) {
+        let (mut shuffle, mut fill) = ([0x80u8; 16], [0u8; 16]);
+        let dst_px = self.dst_channels * self.component_size;
+        for j in 0..self.pixels_per_step() * dst_px {
+            let (px, ch, byte) = (
+                j / dst_px,
+                (j % dst_px) / self.component_size,
+                j % self.component_size,
+            );
+            match self.order[ch] {
+                OPAQUE => fill[j] = 0xFF,
+                src_ch => {
+                    shuffle[j] = (px * self.src_channels * self.component_size
+                        + src_ch as usize * self.component_size
+                        + byte) as u8
+                }
+            }
+        }
+        (shuffle, fill)
+    }
+}
+
+/// Which implementation to use. Tests force each one to compare them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Path {
+    Scalar,
+    #[cfg(target_arch = "x86_64")]
+    Ssse3,
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+}
+
+impl Path {
+    #[inline]
+    fn detect() -> Self {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return Path::Avx2;
+            }
+            if is_x86_feature_detected!("ssse3") {
+                return Path::Ssse3;
+            }
+        }
+        Path::Scalar
+    }
+}
+
+/// Converts as many pixels as the SIMD path can, then finishes the rest with `f`
+#[inline]
+fn convert<S: Copy, D>(src: &[S], dst: &mut [D], layout: &Layout, path: Path, f: impl Fn(S) -> D) {
+    assert_eq!(
+        src.len(),
+        dst.len(),
+        "source and destination must have the same length"
+    );
+    // Safety: pixel types are repr(C) structs of u8/u16, without padding
+    let (src_bytes, dst_bytes) = unsafe {
+        (
+            slice::from_raw_parts(src.as_ptr() as *const u8, mem::size_of_val(src)),
+            slice::from_raw_parts_mut(dst.as_mut_ptr() as *mut u8, mem::size_of_val(dst)),
+        )
+    };
+    let done = match path {
+        Path::Scalar => 0,
+        #[cfg(target_arch = "x86_64")]
+        Path::Ssse3 => unsafe { x86::ssse3(src_bytes, dst_bytes, src.len(), layout) },
+        #[cfg(target_arch = "x86_64")]
+        Path::Avx2 => unsafe { x86::avx2(src_bytes, dst_bytes, src.len(), layout) },
+    };
+    for (d, s) in dst[done..].iter_mut().zip(&src[done..]) {
+        *d = f(*s);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::Layout;
+    use core::arch::x86_64::*;
+
+    /// Returns number of pixels converted. Each step reads and writes 16 bytes,
+    /// but only advances by whole pixels; bytes past them are overwritten by the next step.
+    #[target_feature(enable = "ssse3")]
+    pub(super) unsafe fn ssse3(src: &[u8], dst: &mut [u8], len: usize, layout: &Layout) -> usize {
+        let (shuffle, fill) = layout.masks();
+        let shuffle = _mm_loadu_si128(shuffle.as_ptr() as *const __m128i);
+        let fill = _mm_loadu_si128(fill.as_ptr() as *const __m128i);
+        let px = layout.pixels_per_step();
+        let src_step = px * layout.src_channels * layout.component_size;
+        let dst_step = px * layout.dst_channels * layout.component_size;
+
+        let (mut done, mut s, mut d) = (0, 0, 0);
+        while done + px <= len && s + 16 <= src.len() && d + 16 <= dst.len() {
+            let v = _mm_loadu_si128(src.as_ptr().add(s) as *const __m128i);
+            let v = _mm_or_si128(_mm_shuffle_epi8(v, shuffle), fill);
+            _mm_storeu_si128(dst.as_mut_ptr().add(d) as *mut __m128i, v);
+            done += px;
+            s += src_step;
+            d += dst_step;
+        }
+        done
+    }
+
+    /// Same as `ssse3`, but two steps at a time, one in each 128-bit lane
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn avx2(src: &[u8], dst: &mut [u8], len: usize, layout: &Layout) -> usize {
+        let (shuffle, fill) = layout.masks();
+        let shuffle = _mm256_broadcastsi128_si256(_mm_loadu_si128(shuffle.as_ptr() as *const _));
+        let fill = _mm256_broadcastsi128_si256(_mm_loadu_si128(fill.as_ptr() as *const _));
+        let px = layout.pixels_per_step();
+        let src_step = px * layout.src_channels * layout.component_size;
+        let dst_step = px * layout.dst_channels * layout.component_size;
+
+        let (mut done, mut s, mut d) = (0, 0, 0);
+        while done + 2 * px <= len
+            && s + src_step + 16 <= src.len()
+            && d + dst_step + 16 <= dst.len()
+        {
+            let src_ptr = src.as_ptr().add(s);
+            let v = if src_step == 16 {
+                _mm256_loadu_si256(src_ptr as *const __m256i)
+            } else {
+                let lo = _mm_loadu_si128(src_ptr as *const __m128i);
+                let hi = _mm_loadu_si128(src_ptr.add(src_step) as *const __m128i);
+                _mm256_inserti128_si256(_mm256_castsi128_si256(lo), hi, 1)
+            };
+            let v = _mm256_or_si256(_mm256_shuffle_epi8(v, shuffle), fill);
+            let dst_ptr = dst.as_mut_ptr().add(d);
+            if dst_step == 16 {
+                _mm256_storeu_si256(dst_ptr as *mut __m256i, v);
+            } else {
+                // 先写低 lane, 高 lane 会覆盖它末尾多余的 bytes
+                _mm_storeu_si128(dst_ptr as *mut __m128i, _mm256_castsi256_si128(v));
+                _mm_storeu_si128(
+                    dst_ptr.add(dst_step) as *mut __m128i,
+                    _mm256_extracti128_si256(v, 1),
+                );
+            }
+            done += 2 * px;
+            s += 2 * src_step;
+            d += 2 * dst_step;
+        }
+        // 剩下不到两步的部分交给 SSSE3 (AVX2 implies SSSE3)
+        done + ssse3(&src[s..], &mut dst[d..], len - done, layout)
+    }
+}
+
+macro_rules! convert_slice_impl {
+    ($src:ident => $dst:ident, $src_ch:expr => $dst_ch:expr, $order:expr, $f:expr) => {
+        convert_slice_impl!(@one u8, $src => $dst, $src_ch => $dst_ch, $order, $f);
+        convert_slice_impl!(@one u16, $src => $dst, $src_ch => $dst_ch, $order, $f);
+    };
+    (@one $t:ty, $src:ident => $dst:ident, $src_ch:expr => $dst_ch:expr, $order:expr, $f:expr) => {
+        impl ConvertSlice<$dst<$t>> for [$src<$t>] {
+            #[inline]
+            fn convert_into(&self, dst: &mut [$dst<$t>]) {
+                const LAYOUT: Layout = Layout {
+                    src_channels: $src_ch,
+                    dst_channels: $dst_ch,
+                    order: $order,
+                    component_size: mem::size_of::<$t>(),
+                };
+                convert(self, dst, &LAYOUT, Path::detect(), $f);
+            }
+
+            #[inline]
+            fn convert_to_vec(&self) -> Vec<$dst<$t>> {
+                let mut dst = vec![$dst::default(); self.len()];
+                self.convert_into(&mut dst);
+                dst
+            }
+        }
+    };
+}
+
+const X: u8 = OPAQUE;
+
+convert_slice_impl!(RGBA => BGRA, 4 => 4, [2, 1, 0, 3], |px| px.into());
+convert_slice_impl!(BGRA => RGBA, 4 => 4, [2, 1, 0, 3], |px| px.into());
+convert_slice_impl!(RGB => BGR, 3 => 3, [2, 1, 0, X], |px| px.into());
+convert_slice_impl!(BGR => RGB, 3 => 3, [2, 1, 0, X], |px| px.into());
+
+#[cfg(feature = "argb")]
+convert_slice_impl!(ARGB => RGBA, 4 => 4, [1, 2, 3, 0], |px| px.into());
+#[cfg(feature = "argb")]
+convert_slice_impl!(RGBA => ARGB, 4 => 4, [3, 0, 1, 2], |px| px.into());
+#[cfg(feature = "argb")]
+convert_slice_impl!(ABGR => BGRA, 4 => 4, [1, 2, 3, 0], |px| px.into());
+#[cfg(feature = "argb")]
+convert_slice_impl!(BGRA => ABGR, 4 => 4, [3, 0, 1, 2], |px| px.into());
+
+convert_slice_impl!(RGB => RGBA, 3 => 4, [0, 1, 2, X], |px| px.into());
+convert_slice_impl!(BGR => BGRA, 3 => 4, [0, 1, 2, X], |px| px.into());
+convert_slice_impl!(RGB => BGRA, 3 => 4, [2, 1, 0, X], |px| px.into());
+convert_slice_impl!(BGR => RGBA, 3 => 4, [2, 1, 0, X], |px| px.into());
+
+convert_slice_impl!(RGBA => RGB, 4 => 3, [0, 1, 2, X], |px| px.rgb());
+convert_slice_impl!(BGRA => BGR, 4 => 3, [0, 1, 2, X], |px| px.bgr());
+convert_slice_impl!(RGBA => BGR, 4 => 3, [2, 1, 0, X], |px| px.bgr());
+convert_slice_impl!(BGRA => RGB, 4 => 3, [2, 1, 0, X], |px| px.bgr().into());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alt::{BGR8, BGRA16, BGRA8};
+    use crate::{RGB16, RGB8, RGBA16, RGBA8};
+
+    fn paths() -> Vec<Path> {
+        #[allow(unused_mut)]
+        let mut paths = vec![Path::Scalar];
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("ssse3") {
+                paths.push(Path::Ssse3);
+            }
+            if is_x86_feature_detected!("avx2") {
+                paths.push(Path::Avx2);
+            }
+        }
+        paths
+    }
+
+    /// Every path must give the same result as plain `From` conversions, for every length
+    fn check<S, D>(
+        src: &[S],
+        order: [u8; 4],
+        src_ch: usize,
+        dst_ch: usize,
+        f: impl Fn(S) -> D + Copy,
+    ) where
+        S: Copy,
+        D: Copy + Default + PartialEq + core::fmt::Debug,
+    {
+        let layout = Layout {
+            src_channels: src_ch,
+            dst_channels: dst_ch,
+            order,
+            component_size: mem::size_of::<S>() / src_ch,
+        };
+        for len in 0..src.len() {
+            let expected: Vec<D> = src[..len].iter().copied().map(f).collect();
+            for path in paths() {
+                let mut dst = vec![D::default(); len];
+                convert(&src[..len], &mut dst, &layout, path, f);
+                assert_eq!(dst, expected, "{:?} len={}", path, len);
+            }
+        }
+    }
+
+    #[test]
+    fn all_paths_agree() {
+        let bytes: Vec<u8> = (0..=255).cycle().skip(7).take(200 * 4).collect();
+        let words: Vec<u16> = (0..200 * 4)
+            .map(|i| (i as u16).wrapping_mul(0x9E37))
+            .collect();
+        use crate::FromSlice;
+
+        let (rgba8, rgb8) = (&bytes.as_rgba()[..70], &bytes.as_rgb()[..70]);
+        let (rgba16, rgb16) = (&words.as_rgba()[..40], &words.as_rgb()[..40]);
+
+        check(rgba8, [2, 1, 0, 3], 4, 4, BGRA8::from);
+        check(rgb8, [2, 1, 0, X], 3, 3, BGR8::from);
+        check(rgb8, [0, 1, 2, X], 3, 4, RGBA8::from);
+        check(rgb8, [2, 1, 0, X], 3, 4, BGRA8::from);
+        check(rgba8, [0, 1, 2, X], 4, 3, |px: RGBA8| px.rgb());
+        check(rgba8, [2, 1, 0, X], 4, 3, |px: RGBA8| px.bgr());
+
+        check(rgba16, [2, 1, 0, 3], 4, 4, BGRA16::from);
+        check(rgb16, [0, 1, 2, X], 3, 4, RGBA16::from);
+        check(rgba16, [0, 1, 2, X], 4, 3, |px: RGBA16| px.rgb());
+        check(rgb16, [2, 1, 0, X], 3, 3, crate::alt::BGR16::from);
+    }
+
+    #[test]
+    #[cfg(feature = "argb")]
+    fn argb_paths_agree() {
+        use crate::alt::{ABGR8, ARGB8};
+        use crate::FromSlice;
+
+        let bytes: Vec<u8> = (0..=255).collect();
+        check(&bytes.as_argb()[..50], [1, 2, 3, 0], 4, 4, RGBA8::from);
+        check(&bytes.as_rgba()[..50], [3, 0, 1, 2], 4, 4, ARGB8::from);
+        check(&bytes.as_abgr()[..50], [1, 2, 3, 0], 4, 4, BGRA8::from);
+        check(&bytes.as_bgra()[..50], [3, 0, 1, 2], 4, 4, ABGR8::from);
+    }
+
+    #[test]
+    fn convert_slices() {
+        let bgra = [BGRA8 {
+            b: 1,
+            g: 2,
+            r: 3,
+            a: 4,
+        }; 33];
+        let rgba: Vec<RGBA8> = bgra.convert_to_vec();
+        assert!(rgba.iter().all(|px| *px == RGBA8::new(3, 2, 1, 4)));
+
+        let rgb: Vec<RGB8> = rgba.convert_to_vec();
+        assert!(rgb.iter().all(|px| *px == RGB8::new(3, 2, 1)));
+
+        let opaque: Vec<RGBA16> = [RGB16::new(1, 2, 3); 9].convert_to_vec();
+        assert_eq!(opaque[8], RGBA16::new(1, 2, 3, 0xFFFF));
+    }
+}
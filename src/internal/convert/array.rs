@@ -55,6 +55,7 @@ impl<T: Copy> From<[T; 4]> for ARGB<T> {
 }
 
 #[cfg(feature = "argb")]
+#[allow(clippy::from_over_into)]
 impl<T> Into<[T; 4]> for ARGB<T> {
     #[inline(always)]
     fn into(self) -> [T; 4] {
@@ -109,8 +110,8 @@ fn convert_array() {
     assert_eq!(Into::<[u8; 3]>::into(RGB8::new(1, 2, 3)), [1, 2, 3]);
     assert_eq!(RGBA8::from([1, 2, 3, 4]), RGBA8::new(1, 2, 3, 4));
     assert_eq!(Into::<[u8; 4]>::into(RGBA8::new(1, 2, 3, 4)), [1, 2, 3, 4]);
-    assert_eq!(BGR8::from([3, 2, 1]), BGR8::new(1, 2, 3));
-    assert_eq!(Into::<[u8; 3]>::into(BGR8::new(1, 2, 3)), [3, 2, 1]);
+    assert_eq!(BGR8::from([3, 2, 1]), BGR8::new(3, 2, 1));
+    assert_eq!(Into::<[u8; 3]>::into(BGR8::new(3, 2, 1)), [3, 2, 1]);
     assert_eq!(BGRA8::from([3, 2, 1, 4]), BGRA8::new(1, 2, 3, 4));
     assert_eq!(Into::<[u8; 4]>::into(BGRA8::new(1, 2, 3, 4)), [3, 2, 1, 4]);
 }
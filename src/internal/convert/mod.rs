@@ -8,6 +8,9 @@ use core::slice;
 
 mod array;
 mod tuple;
+mod vec;
+
+pub use self::vec::*;
 
 /// Casts a slice of bytes into a slice of pixels, e.g. `[u8]` to `[RGB8]`.
 ///
@@ -33,8 +36,22 @@ pub trait AsPixels<PixelType> {
     fn as_pixels_mut(&mut self) -> &mut [PixelType];
 }
 
+pub(crate) mod sealed {
+    /// Pixel types the crate can reinterpret as their components and back.
+    ///
+    /// # Safety
+    ///
+    /// `Self` must be a `repr(C)` struct made only of `T` fields, so that it has
+    /// the alignment of `T`, no padding, and every sequence of `T`s is a valid `Self`.
+    /// Unlike `AsPixels`, nothing outside this crate can implement it.
+    pub unsafe trait PixelOf<T> {}
+}
+
 macro_rules! as_pixels_impl {
     ($typ:ident, $elems:expr) => {
+        // Safety: the pixel is a repr(C) struct of `$elems` fields of `T`
+        unsafe impl<T> sealed::PixelOf<T> for $typ<T> {}
+
         impl<T> AsPixels<$typ<T>> for [T] {
             fn as_pixels(&self) -> &[$typ<T>] {
                 unsafe { slice::from_raw_parts(self.as_ptr() as *const _, self.len() / $elems) }
@@ -213,6 +230,83 @@ unsafe fn from_items_to_struct_mut<F, T>(from: &mut [F]) -> &mut [T] {
     slice::from_raw_parts_mut(from.as_mut_ptr() as *mut T, len)
 }
 
+/// Reorder channels of pixels stored in a slice of components, without copying the slice.
+///
+/// Works for any two layouts with the same number of components that can be converted with `From`,
+/// e.g. `BGRA` → `RGBA`, `RGB` → `BGR` or `ARGB` → `RGBA`.
+///
+/// ```rust
+/// use cr::alt::BGRA;
+/// use cr::{ReorderInPlace, RGBA};
+///
+/// let mut bytes = vec![3u8, 2, 1, 255, 6, 5, 4, 255];
+/// let rgba = bytes.reorder_in_place::<BGRA<u8>, RGBA<u8>>();
+/// assert_eq!(rgba[1], RGBA::new(4, 5, 6, 255));
+///
+/// assert_eq!(bytes, [1, 2, 3, 255, 4, 5, 6, 255]);
+/// ```
+///
+/// The pixels are written over each other, so a type that only implements `AsPixels`
+/// can't be used, because it might have a different alignment or padding:
+///
+/// ```rust,compile_fail
+/// use cr::{AsPixels, ReorderInPlace, RGBA8};
+///
+/// #[derive(Copy, Clone)]
+/// struct Wide(u32);
+///
+/// impl From<RGBA8> for Wide {
+///     fn from(px: RGBA8) -> Self { Wide(u32::from_ne_bytes([px.r, px.g, px.b, px.a])) }
+/// }
+///
+/// impl AsPixels<Wide> for [u8] {
+///     fn as_pixels(&self) -> &[Wide] { &[] }
+///     fn as_pixels_mut(&mut self) -> &mut [Wide] { &mut [] }
+/// }
+///
+/// let mut bytes = [0u8; 8];
+/// bytes.reorder_in_place::<RGBA8, Wide>();
+/// ```
+pub trait ReorderInPlace<T> {
+    /// Reinterpret the slice as `Src` pixels, convert each one into `Dst` in place,
+    /// and return the slice reinterpreted as `Dst` pixels.
+    ///
+    /// Leftover elements are left untouched if the slice isn't evenly divisible into pixels.
+    ///
+    /// Only the crate's own pixel types can be used.
+    ///
+    /// Panics if `Src` and `Dst` have different sizes.
+    fn reorder_in_place<Src, Dst>(&mut self) -> &mut [Dst]
+    where
+        Self: AsPixels<Src> + AsPixels<Dst>,
+        Src: sealed::PixelOf<T> + Copy + Into<Dst>,
+        Dst: sealed::PixelOf<T>;
+}
+
+impl<T: Copy> ReorderInPlace<T> for [T] {
+    #[inline]
+    fn reorder_in_place<Src, Dst>(&mut self) -> &mut [Dst]
+    where
+        Self: AsPixels<Src> + AsPixels<Dst>,
+        Src: sealed::PixelOf<T> + Copy + Into<Dst>,
+        Dst: sealed::PixelOf<T>,
+    {
+        assert_eq!(
+            mem::size_of::<Src>(),
+            mem::size_of::<Dst>(),
+            "pixel layouts must have the same number of components"
+        );
+        assert_eq!(mem::align_of::<Src>(), mem::align_of::<Dst>());
+        for px in AsPixels::<Src>::as_pixels_mut(self) {
+            let reordered: Dst = (*px).into();
+            // Safety: `PixelOf` makes both repr(C) structs of `T` without padding,
+            // and they have the same size and alignment
+            unsafe { (px as *mut Src as *mut Dst).write(reordered) };
+        }
+        AsPixels::<Dst>::as_pixels_mut(self)
+    }
+}
+
 macro_rules! rgb_impl_from {
     ($typename:ident, $from:ty, $to:ty) => {
         impl From<$typename<$from>> for $typename<$to> {
@@ -429,6 +523,27 @@ fn argb_converts() {
     assert_eq!(ABGR::from(bgra), abgr);
 }
 
+#[test]
+fn reorders_in_place() {
+    let mut bytes = [1u8, 2, 3, 4, 5, 6, 7];
+    assert_eq!(
+        bytes.reorder_in_place::<RGB<u8>, BGR<u8>>(),
+        [BGR { b: 3, g: 2, r: 1 }, BGR { b: 6, g: 5, r: 4 }]
+    );
+    assert_eq!(bytes, [3, 2, 1, 6, 5, 4, 7]);
+
+    let mut words = [1u16, 2, 3, 0xFFFF];
+    words.reorder_in_place::<BGRA<u16>, RGBA<u16>>();
+    assert_eq!(words, [3, 2, 1, 0xFFFF]);
+
+    #[cfg(feature = "argb")]
+    {
+        let mut argb = [255u8, 1, 2, 3];
+        argb.reorder_in_place::<ARGB<u8>, RGBA<u8>>();
+        assert_eq!(argb, [1, 2, 3, 255]);
+    }
+}
+
 #[test]
 fn converts() {
     assert_eq!([1, 2].as_gray(), [Gray::new(1), Gray::new(2)]);
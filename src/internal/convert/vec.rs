@@ -0,0 +1,208 @@
+use super::sealed::PixelOf;
+use core::fmt;
+use core::mem;
+
+/// Turns a `Vec` of components (e.g. bytes from a decoder) into a `Vec` of pixels,
+/// reusing the same allocation.
+///
+/// ```rust
+/// use cr::{IntoComponents, IntoPixels, RGB8};
+///
+/// let bytes = vec![1u8, 2, 3, 4, 5, 6];
+/// let pixels: Vec<RGB8> = bytes.into_pixels().unwrap();
+/// assert_eq!(pixels, [RGB8::new(1, 2, 3), RGB8::new(4, 5, 6)]);
+///
+/// let bytes: Vec<u8> = pixels.into_components();
+/// assert_eq!(bytes, [1, 2, 3, 4, 5, 6]);
+/// ```
+///
+/// Only the crate's own pixel types can be used, because any bit pattern of the components
+/// must be a valid pixel. Implementing `AsPixels` for another type isn't enough:
+///
+/// ```rust,compile_fail
+/// use cr::{AsPixels, IntoPixels};
+///
+/// struct Ref(&'static u8);
+///
+/// impl AsPixels<Ref> for [usize] {
+///     fn as_pixels(&self) -> &[Ref] { &[] }
+///     fn as_pixels_mut(&mut self) -> &mut [Ref] { &mut [] }
+/// }
+///
+/// let _ = vec![0usize].into_pixels::<Ref>();
+/// ```
+pub trait IntoPixels<T> {
+    /// Reinterpret the vec as pixels without copying.
+    ///
+    /// Fails if the length isn't a multiple of the number of components per pixel,
+    /// or if the capacity isn't either (after trying `shrink_to_fit`).
+    /// The error gives the original vec back.
+    fn into_pixels<P>(self) -> Result<Vec<P>, IntoPixelsError<T>>
+    where
+        P: PixelOf<T>;
+}
+
+/// Turns a `Vec` of pixels into a `Vec` of their components, reusing the same allocation.
+///
+/// See `IntoPixels`.
+pub trait IntoComponents<T> {
+    /// Reinterpret the vec as components without copying. This can't fail.
+    fn into_components(self) -> Vec<T>;
+}
+
+/// Why `IntoPixels::into_pixels` failed
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum IntoPixelsErrorKind {
+    /// Length isn't a multiple of the number of components per pixel
+    Length,
+    /// Capacity isn't a multiple of the number of components per pixel,
+    /// so the allocation couldn't be freed correctly as a `Vec` of pixels
+    Capacity,
+}
+
+/// Error from `IntoPixels::into_pixels`, with the original vec
+#[derive(Clone, PartialEq, Eq)]
+pub struct IntoPixelsError<T> {
+    vec: Vec<T>,
+    components: usize,
+    kind: IntoPixelsErrorKind,
+}
+
+impl<T> IntoPixelsError<T> {
+    /// What went wrong
+    #[inline(always)]
+    pub fn kind(&self) -> IntoPixelsErrorKind {
+        self.kind
+    }
+
+    /// Give back the vec that couldn't be converted
+    #[inline(always)]
+    pub fn into_vec(self) -> Vec<T> {
+        self.vec
+    }
+}
+
+impl<T> fmt::Debug for IntoPixelsError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IntoPixelsError")
+            .field("len", &self.vec.len())
+            .field("capacity", &self.vec.capacity())
+            .field("components", &self.components)
+            .field("kind", &self.kind)
+            .finish()
+    }
+}
+
+impl<T> fmt::Display for IntoPixelsError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            IntoPixelsErrorKind::Length => write!(
+                f,
+                "length {} is not a multiple of {} components per pixel",
+                self.vec.len(),
+                self.components
+            ),
+            IntoPixelsErrorKind::Capacity => write!(
+                f,
+                "capacity {} is not a multiple of {} components per pixel",
+                self.vec.capacity(),
+                self.components
+            ),
+        }
+    }
+}
+
+impl<T> std::error::Error for IntoPixelsError<T> {}
+
+/// Number of `T` in `P`
+#[inline(always)]
+fn components_per_pixel<T, P>() -> usize {
+    // `PixelOf` 保证了这些, 再检查一遍也没有成本: 都是常量, 会被优化掉
+    assert_ne!(0, mem::size_of::<T>());
+    assert_ne!(0, mem::size_of::<P>());
+    assert_eq!(mem::align_of::<T>(), mem::align_of::<P>());
+    assert_eq!(0, mem::size_of::<P>() % mem::size_of::<T>());
+    mem::size_of::<P>() / mem::size_of::<T>()
+}
+
+impl<T> IntoPixels<T> for Vec<T> {
+    fn into_pixels<P>(mut self) -> Result<Vec<P>, IntoPixelsError<T>>
+    where
+        P: PixelOf<T>,
+    {
+        let components = components_per_pixel::<T, P>();
+        let error = |vec, kind| IntoPixelsError {
+            vec,
+            components,
+            kind,
+        };
+
+        if !self.len().is_multiple_of(components) {
+            return Err(error(self, IntoPixelsErrorKind::Length));
+        }
+        if !self.capacity().is_multiple_of(components) {
+            self.shrink_to_fit();
+            if !self.capacity().is_multiple_of(components) {
+                return Err(error(self, IntoPixelsErrorKind::Capacity));
+            }
+        }
+
+        let mut vec = mem::ManuallyDrop::new(self);
+        // Safety: `P` is a repr(C) struct of `T` only, so it has the same alignment,
+        // every group of components is a valid pixel, and the byte length and capacity stay the same
+        Ok(unsafe {
+            Vec::from_raw_parts(
+                vec.as_mut_ptr() as *mut P,
+                vec.len() / components,
+                vec.capacity() / components,
+            )
+        })
+    }
+}
+
+impl<T, P> IntoComponents<T> for Vec<P>
+where
+    P: PixelOf<T>,
+{
+    fn into_components(self) -> Vec<T> {
+        let components = components_per_pixel::<T, P>();
+        let mut vec = mem::ManuallyDrop::new(self);
+        // Safety: `P` is a repr(C) struct of `T` only, without padding,
+        // and the byte length and capacity stay the same
+        unsafe {
+            Vec::from_raw_parts(
+                vec.as_mut_ptr() as *mut T,
+                vec.len() * components,
+                vec.capacity() * components,
+            )
+        }
+    }
+}
+
+#[test]
+fn vec_casts() {
+    use crate::alt::GrayAlpha;
+    use crate::{RGB16, RGBA8};
+
+    let bytes: Vec<u8> = (0..8).collect();
+    let ptr = bytes.as_ptr();
+    let pixels = bytes.into_pixels::<RGBA8>().unwrap();
+    assert_eq!(pixels.as_ptr() as *const u8, ptr);
+    assert_eq!(pixels[1], RGBA8::new(4, 5, 6, 7));
+
+    let bytes: Vec<u8> = pixels.into_components();
+    assert_eq!((bytes.as_ptr(), bytes.len()), (ptr, 8));
+
+    let err = vec![1u16; 4].into_pixels::<RGB16>().unwrap_err();
+    assert_eq!(err.kind(), IntoPixelsErrorKind::Length);
+    assert_eq!(
+        err.to_string(),
+        "length 4 is not a multiple of 3 components per pixel"
+    );
+    assert_eq!(err.into_vec(), [1; 4]);
+
+    let mut odd = Vec::with_capacity(7);
+    odd.extend([1u8, 2, 3, 4]);
+    let gray = odd.into_pixels::<GrayAlpha<u8>>().unwrap();
+    assert_eq!(gray, [GrayAlpha(1, 2), GrayAlpha(3, 4)]);
+}
@@ -0,0 +1,391 @@
+use super::format::{convert, PixelFormat};
+use super::img::Image;
+use crate::alt::*;
+use crate::{RGB16, RGB8, RGBA16, RGBA8};
+use core::marker::PhantomData;
+use core::mem;
+use core::slice;
+
+mod sealed {
+    /// Only the pixel types listed in `DynamicImage` can be viewed as raw bytes
+    pub trait Sealed {}
+}
+
+/// Pixel types that have a `DynamicImage` variant: 8-bit and 16-bit (native endian)
+/// versions of every layout except `GRB`.
+///
+/// The trait is sealed: the byte views used for conversions rely on every implementation
+/// being a `repr(C)` struct of `u8` or `u16` without padding.
+///
+/// ```rust,compile_fail
+/// #[derive(Copy, Clone, Default)]
+/// struct Padded(u8, u32);
+///
+/// impl cr::DynamicPixel for Padded {
+///     const FORMAT: cr::PixelFormat = cr::PixelFormat::RGBA8;
+///     fn into_dynamic(image: cr::Image<Self>) -> cr::DynamicImage {
+///         let pixels = image.buf().iter().map(|p| cr::RGBA8::new(p.0, 0, 0, p.1 as u8)).collect();
+///         cr::DynamicImage::from(cr::Image::new(pixels, image.width(), image.height()))
+///     }
+///     fn from_dynamic(d: cr::DynamicImage) -> Result<cr::Image<Self>, cr::DynamicImage> { Err(d) }
+///     fn from_dynamic_ref(d: &cr::DynamicImage) -> Option<&cr::Image<Self>> { None }
+/// }
+/// ```
+pub trait DynamicPixel: sealed::Sealed + Copy + Default + 'static {
+    /// Run-time description of the layout
+    const FORMAT: PixelFormat;
+
+    /// Wrap the image in its `DynamicImage` variant
+    fn into_dynamic(image: Image<Self>) -> DynamicImage;
+
+    /// The image, if `dynamic` is this pixel type's variant
+    fn from_dynamic(dynamic: DynamicImage) -> Result<Image<Self>, DynamicImage>;
+
+    /// The image, if `dynamic` is this pixel type's variant
+    fn from_dynamic_ref(dynamic: &DynamicImage) -> Option<&Image<Self>>;
+}
+
+/// Does something with a `DynamicImage`, whatever its pixel type is. See `DynamicImage::visit`.
+pub trait ImageVisitor {
+    /// Result of the visit
+    type Output;
+
+    /// Called with the image inside the `DynamicImage`
+    fn visit<P: DynamicPixel>(self, image: &Image<P>) -> Self::Output;
+}
+
+/// Like `ImageVisitor`, but can modify the image. See `DynamicImage::visit_mut`.
+pub trait ImageVisitorMut {
+    /// Result of the visit
+    type Output;
+
+    /// Called with the image inside the `DynamicImage`
+    fn visit_mut<P: DynamicPixel>(self, image: &mut Image<P>) -> Self::Output;
+}
+
+const fn format16(fmt: PixelFormat) -> PixelFormat {
+    PixelFormat { depth: 16, ..fmt }
+}
+
+macro_rules! dynamic_image {
+    ($($(#[$meta:meta])* $variant:ident($px:ty) => $fmt:expr,)+) => {
+        /// Image whose pixel type is only known at run time, e.g. at a plugin boundary.
+        ///
+        /// Match on it directly, use `visit` to run generic code on any variant,
+        /// or convert it with `to_rgba8()` and similar.
+        ///
+        /// ```rust
+        /// use cr::alt::Gray;
+        /// use cr::{DynamicImage, Image, ImageVisitor, DynamicPixel, RGBA8};
+        ///
+        /// let img: DynamicImage = Image::filled(Gray(0xFFFFu16), 2, 2).into();
+        /// assert_eq!(img.to_rgba8().buf()[0], RGBA8::new(255, 255, 255, 255));
+        ///
+        /// struct Bytes;
+        /// impl ImageVisitor for Bytes {
+        ///     type Output = usize;
+        ///     fn visit<P: DynamicPixel>(self, image: &Image<P>) -> usize {
+        ///         image.width() * image.height() * std::mem::size_of::<P>()
+        ///     }
+        /// }
+        /// assert_eq!(img.visit(Bytes), 8);
+        /// ```
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        #[non_exhaustive]
+        pub enum DynamicImage {
+            $(
+                $(#[$meta])*
+                $variant(Image<$px>),
+            )+
+        }
+
+        $(
+            $(#[$meta])*
+            impl sealed::Sealed for $px {}
+
+            $(#[$meta])*
+            impl DynamicPixel for $px {
+                const FORMAT: PixelFormat = $fmt;
+
+                #[inline]
+                fn into_dynamic(image: Image<Self>) -> DynamicImage {
+                    DynamicImage::$variant(image)
+                }
+
+                #[inline]
+                fn from_dynamic(dynamic: DynamicImage) -> Result<Image<Self>, DynamicImage> {
+                    match dynamic {
+                        DynamicImage::$variant(image) => Ok(image),
+                        other => Err(other),
+                    }
+                }
+
+                #[inline]
+                fn from_dynamic_ref(dynamic: &DynamicImage) -> Option<&Image<Self>> {
+                    match dynamic {
+                        DynamicImage::$variant(image) => Some(image),
+                        _ => None,
+                    }
+                }
+            }
+
+            $(#[$meta])*
+            impl From<Image<$px>> for DynamicImage {
+                #[inline]
+                fn from(image: Image<$px>) -> Self {
+                    DynamicImage::$variant(image)
+                }
+            }
+        )+
+
+        impl DynamicImage {
+            /// Run the visitor with the image, whatever its pixel type is
+            pub fn visit<V: ImageVisitor>(&self, visitor: V) -> V::Output {
+                match self {
+                    $(
+                        $(#[$meta])*
+                        DynamicImage::$variant(image) => visitor.visit(image),
+                    )+
+                }
+            }
+
+            /// Run the visitor with the mutable image, whatever its pixel type is
+            pub fn visit_mut<V: ImageVisitorMut>(&mut self, visitor: V) -> V::Output {
+                match self {
+                    $(
+                        $(#[$meta])*
+                        DynamicImage::$variant(image) => visitor.visit_mut(image),
+                    )+
+                }
+            }
+        }
+    };
+}
+
+dynamic_image! {
+    Rgb8(RGB8) => PixelFormat::RGB8,
+    Rgb16(RGB16) => PixelFormat::RGB16,
+    Rgba8(RGBA8) => PixelFormat::RGBA8,
+    Rgba16(RGBA16) => PixelFormat::RGBA16,
+    Bgr8(BGR8) => PixelFormat::BGR8,
+    Bgr16(BGR16) => format16(PixelFormat::BGR8),
+    Bgra8(BGRA8) => PixelFormat::BGRA8,
+    Bgra16(BGRA16) => format16(PixelFormat::BGRA8),
+    #[cfg(feature = "argb")]
+    Argb8(ARGB8) => PixelFormat::ARGB8,
+    #[cfg(feature = "argb")]
+    Argb16(ARGB16) => format16(PixelFormat::ARGB8),
+    #[cfg(feature = "argb")]
+    Abgr8(ABGR8) => PixelFormat::ABGR8,
+    #[cfg(feature = "argb")]
+    Abgr16(ABGR16) => format16(PixelFormat::ABGR8),
+    Gray8(GRAY8) => PixelFormat::GRAY8,
+    Gray16(GRAY16) => PixelFormat::GRAY16,
+    GrayAlpha8(GRAYA8) => PixelFormat::GRAYA8,
+    GrayAlpha16(GRAYA16) => format16(PixelFormat::GRAYA8),
+}
+
+struct Dimensions;
+impl ImageVisitor for Dimensions {
+    type Output = (usize, usize, PixelFormat);
+
+    #[inline]
+    fn visit<P: DynamicPixel>(self, image: &Image<P>) -> Self::Output {
+        (image.width(), image.height(), P::FORMAT)
+    }
+}
+
+struct ToImage<Q>(PhantomData<Q>);
+impl<Q: DynamicPixel> ImageVisitor for ToImage<Q> {
+    type Output = Image<Q>;
+
+    #[inline]
+    fn visit<P: DynamicPixel>(self, image: &Image<P>) -> Image<Q> {
+        convert_image(image)
+    }
+}
+
+/// Bytes of a buffer of pixels
+#[inline]
+pub(crate) fn as_bytes<P: DynamicPixel>(buf: &[P]) -> &[u8] {
+    // Safety: `DynamicPixel` is sealed, and only implemented for repr(C) pixels of `u8` or `u16`, without padding
+    unsafe { slice::from_raw_parts(buf.as_ptr() as *const u8, mem::size_of_val(buf)) }
+}
+
+#[inline]
+fn as_bytes_mut<P: DynamicPixel>(buf: &mut [P]) -> &mut [u8] {
+    // Safety: as above, and every byte pattern is a valid pixel
+    unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, mem::size_of_val(buf)) }
+}
+
+/// Converts between any two pixel types with the `convert` blitter
+fn convert_image<P: DynamicPixel, Q: DynamicPixel>(image: &Image<P>) -> Image<Q> {
+    let (width, height) = (image.width(), image.height());
+    let mut out = Image::filled(Q::default(), width, height);
+    let strides = (
+        image.stride() * mem::size_of::<P>(),
+        width * mem::size_of::<Q>(),
+    );
+    convert(
+        as_bytes(image.buf()),
+        P::FORMAT,
+        as_bytes_mut(out.buf_mut()),
+        Q::FORMAT,
+        width,
+        height,
+        strides,
+    )
+    .expect("image buffers always fit their dimensions");
+    out
+}
+
+impl DynamicImage {
+    /// Width in pixels
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.visit(Dimensions).0
+    }
+
+    /// Height in pixels
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.visit(Dimensions).1
+    }
+
+    /// Run-time description of the pixel type
+    #[inline]
+    pub fn format(&self) -> PixelFormat {
+        self.visit(Dimensions).2
+    }
+
+    /// The image, if it has pixels of type `P`
+    #[inline]
+    pub fn as_image<P: DynamicPixel>(&self) -> Option<&Image<P>> {
+        P::from_dynamic_ref(self)
+    }
+
+    /// The image, if it has pixels of type `P`, otherwise `self` unchanged
+    #[inline]
+    pub fn into_image<P: DynamicPixel>(self) -> Result<Image<P>, Self> {
+        P::from_dynamic(self)
+    }
+
+    /// Copy of the image converted to pixels of type `P`. See `convert` for how formats are mapped.
+    #[inline]
+    pub fn to_image<P: DynamicPixel>(&self) -> Image<P> {
+        self.visit(ToImage(PhantomData))
+    }
+
+    /// Like `to_image`, but doesn't copy if the image already has pixels of type `P`
+    #[inline]
+    pub fn into_converted<P: DynamicPixel>(self) -> Image<P> {
+        self.into_image().unwrap_or_else(|other| other.to_image())
+    }
+
+    /// Copy converted to `RGB8`
+    #[inline]
+    pub fn to_rgb8(&self) -> Image<RGB8> {
+        self.to_image()
+    }
+
+    /// Copy converted to `RGBA8`
+    #[inline]
+    pub fn to_rgba8(&self) -> Image<RGBA8> {
+        self.to_image()
+    }
+
+    /// Copy converted to `RGB16`
+    #[inline]
+    pub fn to_rgb16(&self) -> Image<RGB16> {
+        self.to_image()
+    }
+
+    /// Copy converted to `RGBA16`
+    #[inline]
+    pub fn to_rgba16(&self) -> Image<RGBA16> {
+        self.to_image()
+    }
+
+    /// Copy converted to `BGRA8`
+    #[inline]
+    pub fn to_bgra8(&self) -> Image<BGRA8> {
+        self.to_image()
+    }
+
+    /// Copy converted to `GRAY8`, using `Luma::Rec601`
+    #[inline]
+    pub fn to_gray8(&self) -> Image<GRAY8> {
+        self.to_image()
+    }
+
+    /// Copy converted to `GRAY16`, using `Luma::Rec601`
+    #[inline]
+    pub fn to_gray16(&self) -> Image<GRAY16> {
+        self.to_image()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variants_and_conversions() {
+        let bgra = Image::new_stride(
+            vec![
+                BGRA8 {
+                    b: 3,
+                    g: 2,
+                    r: 1,
+                    a: 4,
+                };
+                6
+            ],
+            2,
+            2,
+            3,
+        );
+        let img = DynamicImage::from(bgra.clone());
+        assert_eq!((img.width(), img.height()), (2, 2));
+        assert_eq!(img.format(), PixelFormat::BGRA8);
+        assert!(matches!(img, DynamicImage::Bgra8(_)));
+        assert_eq!(img.as_image::<BGRA8>(), Some(&bgra));
+        assert_eq!(img.as_image::<RGBA8>(), None);
+
+        let rgba = img.to_rgba8();
+        assert_eq!(rgba.stride(), 2);
+        assert_eq!(rgba.buf(), [RGBA8::new(1, 2, 3, 4); 4]);
+        assert_eq!(img.to_rgba16().buf()[3], RGBA16::new(257, 514, 771, 1028));
+        assert_eq!(img.to_gray8().buf()[0], Gray(2));
+
+        let img = img.into_image::<RGB8>().unwrap_err();
+        assert_eq!(img.clone().into_converted::<BGRA8>(), bgra);
+        assert_eq!(img.into_converted::<RGB8>().buf()[1], RGB8::new(1, 2, 3));
+    }
+
+    #[test]
+    fn visitors() {
+        struct Invert;
+        impl ImageVisitorMut for Invert {
+            type Output = ();
+            fn visit_mut<P: DynamicPixel>(self, image: &mut Image<P>) {
+                for byte in as_bytes_mut(image.buf_mut()) {
+                    *byte = !*byte;
+                }
+            }
+        }
+
+        let mut img = DynamicImage::GrayAlpha16(Image::filled(GrayAlpha(0u16, 0xFF00), 1, 1));
+        img.visit_mut(Invert);
+        assert_eq!(
+            img.to_rgba16().buf()[0],
+            RGBA16::new(0xFFFF, 0xFFFF, 0xFFFF, 0x00FF)
+        );
+        assert_eq!(
+            DynamicImage::Gray8(Image::filled(Gray(7), 0, 3))
+                .to_rgb8()
+                .buf(),
+            []
+        );
+    }
+}
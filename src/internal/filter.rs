@@ -0,0 +1,403 @@
+use super::matrix::{ColorMatrix, MatrixPixel};
+use core::fmt;
+use core::str::FromStr;
+
+/// One CSS filter function, as defined by the Filter Effects Module Level 1.
+///
+/// Amounts are numbers (`1.0` = 100%). Colour is processed in sRGB, like browsers do
+/// for filter functions, and is not premultiplied.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CssFilter {
+    /// `brightness()`: multiplies colour components
+    Brightness(f32),
+    /// `contrast()`: scales colour components around `0.5`
+    Contrast(f32),
+    /// `grayscale()`, `0..=1`
+    Grayscale(f32),
+    /// `hue-rotate()`, in degrees
+    HueRotate(f32),
+    /// `invert()`, `0..=1`
+    Invert(f32),
+    /// `opacity()`, `0..=1`
+    Opacity(f32),
+    /// `saturate()`
+    Saturate(f32),
+    /// `sepia()`, `0..=1`
+    Sepia(f32),
+}
+
+impl CssFilter {
+    /// The equivalent `feColorMatrix`/`feComponentTransfer` as a matrix
+    pub fn to_matrix(&self) -> ColorMatrix {
+        match *self {
+            CssFilter::Brightness(a) => ColorMatrix::brightness(a),
+            CssFilter::Contrast(a) => ColorMatrix::contrast(a),
+            CssFilter::Grayscale(a) => ColorMatrix::grayscale(a),
+            CssFilter::HueRotate(deg) => ColorMatrix::hue_rotate(deg),
+            CssFilter::Invert(a) => ColorMatrix::invert(a),
+            CssFilter::Opacity(a) => ColorMatrix::opacity(a),
+            CssFilter::Saturate(a) => ColorMatrix::saturate(a),
+            CssFilter::Sepia(a) => ColorMatrix::sepia(a),
+        }
+    }
+}
+
+/// A list of CSS filter functions, applied in order.
+///
+/// Every function's result is clamped to `0..=1` before the next one runs, as the spec requires,
+/// so the list can't be collapsed into a single `ColorMatrix`.
+///
+/// ```rust
+/// use cr::{CssFilters, RGBA8};
+///
+/// let filters: CssFilters = "brightness(1.2) contrast(80%) sepia(0.3)".parse().unwrap();
+/// let mut pixels = [RGBA8::new(200, 100, 50, 255)];
+/// filters.apply(&mut pixels);
+///
+/// assert!("blur(2px)".parse::<CssFilters>().is_err());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CssFilters {
+    filters: Vec<CssFilter>,
+}
+
+impl CssFilters {
+    /// Empty list, same as `none`
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a function at the end
+    #[inline]
+    pub fn push(&mut self, filter: CssFilter) {
+        self.filters.push(filter);
+    }
+
+    /// The functions, in order
+    #[inline]
+    pub fn filters(&self) -> &[CssFilter] {
+        &self.filters
+    }
+
+    /// Parse a CSS `filter` property value, e.g. `"brightness(1.2) contrast(80%)"` or `"none"`
+    #[inline]
+    pub fn parse(s: &str) -> Result<Self, CssFilterError> {
+        s.parse()
+    }
+
+    /// Filter every pixel in place
+    pub fn apply<P: MatrixPixel>(&self, pixels: &mut [P]) {
+        if self.filters.is_empty() {
+            return;
+        }
+        let matrices: Vec<_> = self.filters.iter().map(CssFilter::to_matrix).collect();
+        for px in pixels {
+            let mut c = px.to_rgba_f64();
+            for m in &matrices {
+                c = m.transform(c).map(|v| v.clamp(0., 1.));
+            }
+            *px = px.with_rgba_f64(c);
+        }
+    }
+}
+
+impl From<Vec<CssFilter>> for CssFilters {
+    #[inline]
+    fn from(filters: Vec<CssFilter>) -> Self {
+        Self { filters }
+    }
+}
+
+/// Why a filter string couldn't be parsed
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CssFilterErrorKind {
+    /// Not one of the colour filter functions (`blur()`, `drop-shadow()` and `url()` aren't supported)
+    UnknownFunction,
+    /// Argument isn't a valid number, percentage or angle, or is negative
+    InvalidArgument,
+    /// Missing parenthesis or other malformed input
+    Syntax,
+}
+
+/// Error from parsing `CssFilters`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CssFilterError {
+    kind: CssFilterErrorKind,
+    pos: usize,
+}
+
+impl CssFilterError {
+    /// What went wrong
+    #[inline(always)]
+    pub fn kind(&self) -> CssFilterErrorKind {
+        self.kind
+    }
+
+    /// Byte offset in the string where the problem starts
+    #[inline(always)]
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+impl fmt::Display for CssFilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self.kind {
+            CssFilterErrorKind::UnknownFunction => "unknown filter function",
+            CssFilterErrorKind::InvalidArgument => "invalid filter argument",
+            CssFilterErrorKind::Syntax => "malformed filter",
+        };
+        write!(f, "{} at byte {}", msg, self.pos)
+    }
+}
+
+impl std::error::Error for CssFilterError {}
+
+impl FromStr for CssFilters {
+    type Err = CssFilterError;
+
+    fn from_str(s: &str) -> Result<Self, CssFilterError> {
+        let err = |kind, pos| CssFilterError { kind, pos };
+        let trimmed = s.trim_matches(is_css_space);
+        if trimmed.eq_ignore_ascii_case("none") {
+            return Ok(Self::new());
+        }
+        if trimmed.is_empty() {
+            return Err(err(CssFilterErrorKind::Syntax, 0));
+        }
+
+        let mut filters = Vec::new();
+        let mut pos = 0;
+        loop {
+            pos += s[pos..].len() - s[pos..].trim_start_matches(is_css_space).len();
+            if pos == s.len() {
+                break;
+            }
+            // 函数之间必须有空格
+            if !filters.is_empty() && !s[..pos].ends_with(is_css_space) {
+                return Err(err(CssFilterErrorKind::Syntax, pos));
+            }
+
+            let name_len = s[pos..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+                .unwrap_or(s.len() - pos);
+            let name = &s[pos..pos + name_len];
+            let open = pos + name_len;
+            if name.is_empty() || !s[open..].starts_with('(') {
+                return Err(err(CssFilterErrorKind::Syntax, open));
+            }
+            let close = match s[open..].find(')') {
+                Some(i) => open + i,
+                None => return Err(err(CssFilterErrorKind::Syntax, s.len())),
+            };
+            let arg = s[open + 1..close].trim_matches(is_css_space);
+            let arg_pos = open + 1;
+
+            let filter = parse_function(name, arg).map_err(|kind| {
+                err(
+                    kind,
+                    if kind == CssFilterErrorKind::UnknownFunction {
+                        pos
+                    } else {
+                        arg_pos
+                    },
+                )
+            })?;
+            filters.push(filter);
+            pos = close + 1;
+        }
+        Ok(Self { filters })
+    }
+}
+
+#[inline]
+fn is_css_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0C')
+}
+
+fn parse_function(name: &str, arg: &str) -> Result<CssFilter, CssFilterErrorKind> {
+    let name = name.to_ascii_lowercase();
+    if name == "hue-rotate" {
+        return Ok(CssFilter::HueRotate(if arg.is_empty() {
+            0.
+        } else {
+            parse_angle(arg)?
+        }));
+    }
+
+    let filter: fn(f32) -> CssFilter = match name.as_str() {
+        "brightness" => CssFilter::Brightness,
+        "contrast" => CssFilter::Contrast,
+        "grayscale" => CssFilter::Grayscale,
+        "invert" => CssFilter::Invert,
+        "opacity" => CssFilter::Opacity,
+        "saturate" => CssFilter::Saturate,
+        "sepia" => CssFilter::Sepia,
+        _ => return Err(CssFilterErrorKind::UnknownFunction),
+    };
+    let mut amount = if arg.is_empty() {
+        1.
+    } else {
+        parse_amount(arg)?
+    };
+    // 规范: 超过 100% 的值被 clamp 到 1
+    if matches!(name.as_str(), "grayscale" | "invert" | "opacity" | "sepia") {
+        amount = amount.min(1.);
+    }
+    Ok(filter(amount))
+}
+
+/// `<number> | <percentage>`, not negative
+fn parse_amount(arg: &str) -> Result<f32, CssFilterErrorKind> {
+    let (value, unit) = split_number(arg)?;
+    let value = match unit {
+        "" => value,
+        "%" => value / 100.,
+        _ => return Err(CssFilterErrorKind::InvalidArgument),
+    };
+    if value < 0. {
+        return Err(CssFilterErrorKind::InvalidArgument);
+    }
+    Ok(value)
+}
+
+/// `<angle> | <zero>`, in degrees
+fn parse_angle(arg: &str) -> Result<f32, CssFilterErrorKind> {
+    let (value, unit) = split_number(arg)?;
+    let unit = unit.to_ascii_lowercase();
+    Ok(match unit.as_str() {
+        "deg" => value,
+        "grad" => value * 0.9,
+        "rad" => value.to_degrees(),
+        "turn" => value * 360.,
+        "" if value == 0. => 0.,
+        _ => return Err(CssFilterErrorKind::InvalidArgument),
+    })
+}
+
+/// Splits a CSS `<number>` from the unit that follows it
+fn split_number(arg: &str) -> Result<(f32, &str), CssFilterErrorKind> {
+    let b = arg.as_bytes();
+    let digits = |mut i: usize| {
+        while i < b.len() && b[i].is_ascii_digit() {
+            i += 1;
+        }
+        i
+    };
+
+    let mut end = if matches!(b.first(), Some(b'+' | b'-')) {
+        1
+    } else {
+        0
+    };
+    let int_end = digits(end);
+    let mut has_digits = int_end > end;
+    end = int_end;
+    if b.get(end) == Some(&b'.') && b.get(end + 1).is_some_and(u8::is_ascii_digit) {
+        end = digits(end + 1);
+        has_digits = true;
+    }
+    if !has_digits {
+        return Err(CssFilterErrorKind::InvalidArgument);
+    }
+    if matches!(b.get(end), Some(b'e' | b'E')) {
+        let sign = usize::from(matches!(b.get(end + 1), Some(b'+' | b'-')));
+        if b.get(end + 1 + sign).is_some_and(u8::is_ascii_digit) {
+            end = digits(end + 1 + sign);
+        }
+    }
+
+    let value: f32 = arg[..end]
+        .parse()
+        .map_err(|_| CssFilterErrorKind::InvalidArgument)?;
+    if !value.is_finite() {
+        return Err(CssFilterErrorKind::InvalidArgument);
+    }
+    Ok((value, &arg[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RGBA8;
+
+    fn apply(filters: &str, px: RGBA8) -> RGBA8 {
+        let mut pixels = [px];
+        CssFilters::parse(filters).unwrap().apply(&mut pixels);
+        pixels[0]
+    }
+
+    #[test]
+    fn parsing() {
+        let f = CssFilters::parse(" brightness(1.2) contrast(80%)\tsepia(.3) HUE-ROTATE(0.5turn) ")
+            .unwrap();
+        assert_eq!(
+            f.filters(),
+            [
+                CssFilter::Brightness(1.2),
+                CssFilter::Contrast(0.8),
+                CssFilter::Sepia(0.3),
+                CssFilter::HueRotate(180.),
+            ]
+        );
+        assert_eq!(
+            CssFilters::parse("invert() opacity(250%) hue-rotate() saturate(2e1)")
+                .unwrap()
+                .filters(),
+            [
+                CssFilter::Invert(1.),
+                CssFilter::Opacity(1.),
+                CssFilter::HueRotate(0.),
+                CssFilter::Saturate(20.),
+            ]
+        );
+        assert_eq!(CssFilters::parse("none").unwrap(), CssFilters::new());
+        assert_eq!(
+            CssFilters::parse("hue-rotate(100grad)").unwrap().filters(),
+            [CssFilter::HueRotate(90.)]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let kind = |s: &str| CssFilters::parse(s).unwrap_err().kind();
+        assert_eq!(kind("blur(2px)"), CssFilterErrorKind::UnknownFunction);
+        assert_eq!(kind("brightness(-1)"), CssFilterErrorKind::InvalidArgument);
+        assert_eq!(kind("brightness(1px)"), CssFilterErrorKind::InvalidArgument);
+        assert_eq!(kind("brightness(1.)"), CssFilterErrorKind::InvalidArgument);
+        assert_eq!(kind("hue-rotate(90)"), CssFilterErrorKind::InvalidArgument);
+        assert_eq!(kind("sepia(1, 2)"), CssFilterErrorKind::InvalidArgument);
+        assert_eq!(kind("sepia(1"), CssFilterErrorKind::Syntax);
+        assert_eq!(kind("sepia(1)invert(1)"), CssFilterErrorKind::Syntax);
+        assert_eq!(kind("sepia"), CssFilterErrorKind::Syntax);
+        assert_eq!(kind(""), CssFilterErrorKind::Syntax);
+
+        let e = CssFilters::parse("invert(1) drop-shadow(1px 1px red)").unwrap_err();
+        assert_eq!(e.position(), 10);
+        assert_eq!(e.to_string(), "unknown filter function at byte 10");
+    }
+
+    #[test]
+    fn spec_results() {
+        let px = RGBA8::new(100, 200, 0, 255);
+        assert_eq!(apply("brightness(2)", px), RGBA8::new(200, 255, 0, 255));
+        // 0.5 * c + 0.25
+        assert_eq!(apply("contrast(0.5)", px), RGBA8::new(114, 164, 64, 255));
+        assert_eq!(apply("invert(1)", px), RGBA8::new(155, 55, 255, 255));
+        assert_eq!(apply("opacity(50%)", px), RGBA8::new(100, 200, 0, 128));
+        assert_eq!(apply("none", px), px);
+        assert_eq!(
+            apply("grayscale(1)", RGBA8::new(255, 0, 0, 9)),
+            RGBA8::new(54, 54, 54, 9)
+        );
+
+        // 每一步之后都 clamp, 不能合并成一个矩阵
+        assert_eq!(
+            apply("brightness(2) contrast(0.5)", RGBA8::new(200, 0, 0, 255)).r,
+            191
+        );
+        let merged = ColorMatrix::brightness(2.).then(&ColorMatrix::contrast(0.5));
+        assert_eq!(merged.apply(RGBA8::new(200, 0, 0, 255)).r, 255);
+    }
+}
@@ -0,0 +1,961 @@
+use super::bulk::ConvertSlice;
+use super::convert::AsPixels;
+use super::gray::ToGray;
+use crate::alt::{BGR, BGRA};
+use crate::{RGB, RGBA};
+use core::fmt;
+
+#[cfg(feature = "argb")]
+use crate::alt::{ABGR, ARGB};
+
+/// Order of the components of a `PixelFormat`.
+///
+/// For `Packing::Aligned` it's the order in memory. For `Packing::Packed` it's the order
+/// in the pixel word, from the most significant bits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ComponentOrder {
+    Gray,
+    GrayAlpha,
+    Rgb,
+    Bgr,
+    Rgba,
+    Bgra,
+    Argb,
+    Abgr,
+}
+
+/// How components are stored
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Packing {
+    /// Every component is its own integer of `depth` bits (8 or 16)
+    Aligned,
+    /// The whole pixel is one integer of `depth` bits (8, 16 or 32),
+    /// with the given number of bits per component, in `order`
+    Packed([u8; 4]),
+}
+
+/// Byte order of 16-bit components and of packed pixel words
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    /// Endianness of the machine
+    #[cfg(target_endian = "little")]
+    pub const NATIVE: Self = Endian::Little;
+    /// Endianness of the machine
+    #[cfg(target_endian = "big")]
+    pub const NATIVE: Self = Endian::Big;
+}
+
+/// Meaning of the alpha component
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AlphaKind {
+    /// The format has no alpha component
+    None,
+    /// Colour components are independent of alpha
+    Straight,
+    /// Colour components are already multiplied by alpha
+    Premultiplied,
+    /// There's a component in the alpha position, but it's padding (`X` in `XRGB8888`).
+    /// It's read as opaque, and written as all ones.
+    Ignored,
+}
+
+/// Pixel layout described at run time, e.g. from V4L2, DRM or Wayland.
+///
+/// The compile-time types like `RGBA8` or `BGR8` map to `Packing::Aligned` formats.
+///
+/// ```rust
+/// use cr::{convert, PixelFormat};
+///
+/// // a Wayland/DRM XRGB8888 buffer is B, G, R, X in memory
+/// let xrgb = PixelFormat::from_drm_fourcc(u32::from_le_bytes(*b"XR24")).unwrap();
+/// let src = [30u8, 20, 10, 0, 3, 2, 1, 0];
+/// let mut dst = [0u8; 6];
+/// convert(&src, xrgb, &mut dst, PixelFormat::RGB8, 2, 1, (8, 6)).unwrap();
+/// assert_eq!(dst, [10, 20, 30, 1, 2, 3]);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PixelFormat {
+    /// Order of components
+    pub order: ComponentOrder,
+    /// Bits per component for `Packing::Aligned`, bits per pixel for `Packing::Packed`
+    pub depth: u8,
+    /// How components are stored
+    pub packing: Packing,
+    /// Byte order of 16-bit components and packed words
+    pub endian: Endian,
+    /// Meaning of the alpha component
+    pub alpha: AlphaKind,
+}
+
+/// Builds a FourCC code from its four characters, e.g. `fourcc(*b"XR24")`
+#[inline]
+pub const fn fourcc(code: [u8; 4]) -> u32 {
+    u32::from_le_bytes(code)
+}
+
+impl PixelFormat {
+    pub const GRAY8: Self = Self::aligned(ComponentOrder::Gray, 8, AlphaKind::None);
+    pub const GRAYA8: Self = Self::aligned(ComponentOrder::GrayAlpha, 8, AlphaKind::Straight);
+    pub const RGB8: Self = Self::aligned(ComponentOrder::Rgb, 8, AlphaKind::None);
+    pub const BGR8: Self = Self::aligned(ComponentOrder::Bgr, 8, AlphaKind::None);
+    pub const RGBA8: Self = Self::aligned(ComponentOrder::Rgba, 8, AlphaKind::Straight);
+    pub const BGRA8: Self = Self::aligned(ComponentOrder::Bgra, 8, AlphaKind::Straight);
+    pub const ARGB8: Self = Self::aligned(ComponentOrder::Argb, 8, AlphaKind::Straight);
+    pub const ABGR8: Self = Self::aligned(ComponentOrder::Abgr, 8, AlphaKind::Straight);
+    /// 16-bit components in native endian, like `[u16]`
+    pub const GRAY16: Self = Self::aligned(ComponentOrder::Gray, 16, AlphaKind::None);
+    /// 16-bit components in native endian, like `[u16]`
+    pub const RGB16: Self = Self::aligned(ComponentOrder::Rgb, 16, AlphaKind::None);
+    /// 16-bit components in native endian, like `[u16]`
+    pub const RGBA16: Self = Self::aligned(ComponentOrder::Rgba, 16, AlphaKind::Straight);
+    /// 5-6-5 bits in a little-endian `u16`
+    pub const RGB565: Self = Self::packed(
+        ComponentOrder::Rgb,
+        [5, 6, 5, 0],
+        Endian::Little,
+        AlphaKind::None,
+    );
+
+    /// Format with one integer per component, in native endian
+    #[inline]
+    pub const fn aligned(order: ComponentOrder, depth: u8, alpha: AlphaKind) -> Self {
+        Self {
+            order,
+            depth,
+            packing: Packing::Aligned,
+            endian: Endian::NATIVE,
+            alpha,
+        }
+    }
+
+    /// Format with all components in one integer. `bits` are per component, in `order`.
+    ///
+    /// Bit widths that add up to more than 255 give a format that isn't valid.
+    #[inline]
+    pub const fn packed(
+        order: ComponentOrder,
+        bits: [u8; 4],
+        endian: Endian,
+        alpha: AlphaKind,
+    ) -> Self {
+        let depth = match bits[0].checked_add(bits[1]) {
+            Some(d) => match d.checked_add(bits[2]) {
+                Some(d) => d.checked_add(bits[3]),
+                None => None,
+            },
+            None => None,
+        };
+        Self {
+            order,
+            // 0 is never a valid depth, so `is_valid` rejects it
+            depth: match depth {
+                Some(d) => d,
+                None => 0,
+            },
+            packing: Packing::Packed(bits),
+            endian,
+            alpha,
+        }
+    }
+
+    /// Same format with different endianness
+    #[inline]
+    pub const fn with_endian(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    /// Same format with a different alpha kind
+    #[inline]
+    pub const fn with_alpha(mut self, alpha: AlphaKind) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    /// Number of components, including alpha or padding
+    #[inline]
+    pub const fn channels(&self) -> usize {
+        self.order.roles().len()
+    }
+
+    /// Size of one pixel in bytes
+    #[inline]
+    pub const fn bytes_per_pixel(&self) -> usize {
+        match self.packing {
+            Packing::Aligned => self.channels() * (self.depth as usize / 8),
+            Packing::Packed(_) => self.depth as usize / 8,
+        }
+    }
+
+    /// Checks that depth, bit widths and alpha kind fit together
+    pub fn is_valid(&self) -> bool {
+        let has_alpha = self.order.roles().contains(&Role::A);
+        if has_alpha == (self.alpha == AlphaKind::None) {
+            return false;
+        }
+        match self.packing {
+            Packing::Aligned => matches!(self.depth, 8 | 16),
+            Packing::Packed(bits) => {
+                let (used, unused) = bits.split_at(self.channels());
+                matches!(self.depth, 8 | 16 | 32)
+                    && used.iter().all(|&b| (1..=16).contains(&b))
+                    && unused.iter().all(|&b| b == 0)
+                    && used.iter().map(|&b| b as u32).sum::<u32>() == self.depth as u32
+            }
+        }
+    }
+
+    /// Format of a DRM (`drm_fourcc.h`) FourCC code. Wayland `wl_shm` uses the same codes,
+    /// except `0` and `1`; see `from_wl_shm`.
+    ///
+    /// DRM doesn't say whether alpha is premultiplied; this returns `AlphaKind::Straight`.
+    pub fn from_drm_fourcc(code: u32) -> Option<Self> {
+        DRM_FORMATS
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|&(_, fmt)| fmt)
+    }
+
+    /// DRM FourCC code of the format, if there's one. Premultiplied alpha is treated like straight.
+    pub fn to_drm_fourcc(&self) -> Option<u32> {
+        let this = self.straight().canonical();
+        DRM_FORMATS
+            .iter()
+            .find(|(_, fmt)| *fmt == this)
+            .map(|&(c, _)| c)
+    }
+
+    /// Format of a Wayland `wl_shm` format code. Wayland buffers have premultiplied alpha.
+    pub fn from_wl_shm(format: u32) -> Option<Self> {
+        let code = match format {
+            0 => fourcc(*b"AR24"),
+            1 => fourcc(*b"XR24"),
+            code => code,
+        };
+        let fmt = Self::from_drm_fourcc(code)?;
+        Some(match fmt.alpha {
+            AlphaKind::Straight => fmt.with_alpha(AlphaKind::Premultiplied),
+            _ => fmt,
+        })
+    }
+
+    /// Format of a V4L2 (`videodev2.h`) pixel format code. Only RGB and gray formats are known.
+    pub fn from_v4l2_fourcc(code: u32) -> Option<Self> {
+        V4L2_FORMATS
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|&(_, fmt)| fmt)
+    }
+
+    /// V4L2 pixel format code of the format, if there's one
+    pub fn to_v4l2_fourcc(&self) -> Option<u32> {
+        let this = self.straight().canonical();
+        V4L2_FORMATS
+            .iter()
+            .find(|(_, fmt)| *fmt == this)
+            .map(|&(c, _)| c)
+    }
+
+    #[inline]
+    fn straight(&self) -> Self {
+        match self.alpha {
+            AlphaKind::Premultiplied => self.with_alpha(AlphaKind::Straight),
+            _ => *self,
+        }
+    }
+
+    /// 8-bit formats are the same in either endianness
+    #[inline]
+    fn canonical(&self) -> Self {
+        match (self.packing, self.depth) {
+            (Packing::Aligned, 8) | (Packing::Packed(_), 8) => self.with_endian(Endian::Little),
+            _ => *self,
+        }
+    }
+}
+
+/// What a component means
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Role {
+    Y,
+    R,
+    G,
+    B,
+    A,
+}
+
+impl ComponentOrder {
+    #[inline]
+    const fn roles(self) -> &'static [Role] {
+        use Role::*;
+        match self {
+            ComponentOrder::Gray => &[Y],
+            ComponentOrder::GrayAlpha => &[Y, A],
+            ComponentOrder::Rgb => &[R, G, B],
+            ComponentOrder::Bgr => &[B, G, R],
+            ComponentOrder::Rgba => &[R, G, B, A],
+            ComponentOrder::Bgra => &[B, G, R, A],
+            ComponentOrder::Argb => &[A, R, G, B],
+            ComponentOrder::Abgr => &[A, B, G, R],
+        }
+    }
+}
+
+const fn drm_aligned(order: ComponentOrder, depth: u8, alpha: AlphaKind) -> PixelFormat {
+    PixelFormat::aligned(order, depth, alpha).with_endian(Endian::Little)
+}
+
+const fn drm_packed(order: ComponentOrder, bits: [u8; 4], alpha: AlphaKind) -> PixelFormat {
+    PixelFormat::packed(order, bits, Endian::Little, alpha)
+}
+
+// DRM 格式都是 little-endian 的整数, 分量从高位写起; 8-bit 分量的格式在内存里是倒过来的
+const DRM_FORMATS: &[(u32, PixelFormat)] = {
+    use AlphaKind::*;
+    use ComponentOrder::*;
+    &[
+        (fourcc(*b"AR24"), drm_aligned(Bgra, 8, Straight)),
+        (fourcc(*b"XR24"), drm_aligned(Bgra, 8, Ignored)),
+        (fourcc(*b"AB24"), drm_aligned(Rgba, 8, Straight)),
+        (fourcc(*b"XB24"), drm_aligned(Rgba, 8, Ignored)),
+        (fourcc(*b"RA24"), drm_aligned(Abgr, 8, Straight)),
+        (fourcc(*b"RX24"), drm_aligned(Abgr, 8, Ignored)),
+        (fourcc(*b"BA24"), drm_aligned(Argb, 8, Straight)),
+        (fourcc(*b"BX24"), drm_aligned(Argb, 8, Ignored)),
+        (fourcc(*b"RG24"), drm_aligned(Bgr, 8, None)),
+        (fourcc(*b"BG24"), drm_aligned(Rgb, 8, None)),
+        (fourcc(*b"AB48"), drm_aligned(Rgba, 16, Straight)),
+        (fourcc(*b"XB48"), drm_aligned(Rgba, 16, Ignored)),
+        (fourcc(*b"AR48"), drm_aligned(Bgra, 16, Straight)),
+        (fourcc(*b"XR48"), drm_aligned(Bgra, 16, Ignored)),
+        (fourcc(*b"RG16"), drm_packed(Rgb, [5, 6, 5, 0], None)),
+        (fourcc(*b"BG16"), drm_packed(Bgr, [5, 6, 5, 0], None)),
+        (fourcc(*b"AR15"), drm_packed(Argb, [1, 5, 5, 5], Straight)),
+        (fourcc(*b"XR15"), drm_packed(Argb, [1, 5, 5, 5], Ignored)),
+        (fourcc(*b"AR12"), drm_packed(Argb, [4, 4, 4, 4], Straight)),
+        (fourcc(*b"XR12"), drm_packed(Argb, [4, 4, 4, 4], Ignored)),
+        (
+            fourcc(*b"AR30"),
+            drm_packed(Argb, [2, 10, 10, 10], Straight),
+        ),
+        (fourcc(*b"XR30"), drm_packed(Argb, [2, 10, 10, 10], Ignored)),
+        (
+            fourcc(*b"AB30"),
+            drm_packed(Abgr, [2, 10, 10, 10], Straight),
+        ),
+        (fourcc(*b"XB30"), drm_packed(Abgr, [2, 10, 10, 10], Ignored)),
+    ]
+};
+
+const V4L2_FORMATS: &[(u32, PixelFormat)] = {
+    use AlphaKind::*;
+    use ComponentOrder::*;
+    &[
+        (fourcc(*b"RGB3"), drm_aligned(Rgb, 8, None)),
+        (fourcc(*b"BGR3"), drm_aligned(Bgr, 8, None)),
+        (fourcc(*b"GREY"), drm_aligned(Gray, 8, None)),
+        (fourcc(*b"Y16 "), drm_aligned(Gray, 16, None)),
+        (fourcc(*b"AR24"), drm_aligned(Bgra, 8, Straight)),
+        (fourcc(*b"XR24"), drm_aligned(Bgra, 8, Ignored)),
+        (fourcc(*b"AB24"), drm_aligned(Rgba, 8, Straight)),
+        (fourcc(*b"XB24"), drm_aligned(Rgba, 8, Ignored)),
+        (fourcc(*b"BA24"), drm_aligned(Argb, 8, Straight)),
+        (fourcc(*b"BX24"), drm_aligned(Argb, 8, Ignored)),
+        (fourcc(*b"RA24"), drm_aligned(Abgr, 8, Straight)),
+        (fourcc(*b"RX24"), drm_aligned(Abgr, 8, Ignored)),
+        (fourcc(*b"RGBP"), drm_packed(Rgb, [5, 6, 5, 0], None)),
+        (
+            fourcc(*b"RGBR"),
+            PixelFormat::packed(Rgb, [5, 6, 5, 0], Endian::Big, None),
+        ),
+        (fourcc(*b"AR15"), drm_packed(Argb, [1, 5, 5, 5], Straight)),
+        (fourcc(*b"XR15"), drm_packed(Argb, [1, 5, 5, 5], Ignored)),
+        (fourcc(*b"AR12"), drm_packed(Argb, [4, 4, 4, 4], Straight)),
+        (fourcc(*b"XR12"), drm_packed(Argb, [4, 4, 4, 4], Ignored)),
+    ]
+};
+
+/// Why `convert` failed
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ConvertError {
+    /// One of the formats isn't valid, see `PixelFormat::is_valid`
+    InvalidFormat,
+    /// A stride is smaller than a row of pixels, or a row is larger than memory
+    StrideTooSmall,
+    /// A buffer is smaller than `height` rows, or the rows are larger than memory
+    BufferTooSmall,
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConvertError::InvalidFormat => "invalid pixel format",
+            ConvertError::StrideTooSmall => "stride is smaller than a row of pixels",
+            ConvertError::BufferTooSmall => "buffer is too small for the image",
+        })
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+/// Converts an image between two run-time pixel formats.
+///
+/// Strides are `(src, dst)` in bytes. Conversions between 8-bit layouts that have a
+/// `ConvertSlice` implementation (e.g. BGRA to RGBA, RGB to BGRA) use it directly; everything
+/// else goes through 16-bit RGBA with straight alpha. Colour is turned into gray with
+/// `Luma::Rec601`, missing alpha is opaque, and premultiplied alpha is converted as needed.
+#[allow(clippy::too_many_arguments)]
+pub fn convert(
+    src: &[u8],
+    src_fmt: PixelFormat,
+    dst: &mut [u8],
+    dst_fmt: PixelFormat,
+    width: usize,
+    height: usize,
+    strides: (usize, usize),
+) -> Result<(), ConvertError> {
+    if !src_fmt.is_valid() || !dst_fmt.is_valid() {
+        return Err(ConvertError::InvalidFormat);
+    }
+    let (Some(src_row), Some(dst_row)) = (
+        width.checked_mul(src_fmt.bytes_per_pixel()),
+        width.checked_mul(dst_fmt.bytes_per_pixel()),
+    ) else {
+        return Err(ConvertError::StrideTooSmall);
+    };
+    let (src_stride, dst_stride) = strides;
+    if src_stride < src_row || dst_stride < dst_row {
+        return Err(ConvertError::StrideTooSmall);
+    }
+    if height == 0 || width == 0 {
+        return Ok(());
+    }
+    let needed = |stride: usize, row: usize| {
+        (height - 1)
+            .checked_mul(stride)
+            .and_then(|n| n.checked_add(row))
+    };
+    if needed(src_stride, src_row).is_none_or(|n| src.len() < n)
+        || needed(dst_stride, dst_row).is_none_or(|n| dst.len() < n)
+    {
+        return Err(ConvertError::BufferTooSmall);
+    }
+
+    let rows = (0..height).map(|y| {
+        (
+            &src[y * src_stride..][..src_row],
+            y * dst_stride..y * dst_stride + dst_row,
+        )
+    });
+
+    let (src_fmt, dst_fmt) = (src_fmt.canonical(), dst_fmt.canonical());
+    if let Some(fill_alpha) = copy_path(&src_fmt, &dst_fmt) {
+        for (s, d) in rows {
+            let d = &mut dst[d];
+            d.copy_from_slice(s);
+            if let Some(offset) = fill_alpha {
+                let size = dst_fmt.depth as usize / 8;
+                for px in d.chunks_exact_mut(dst_fmt.bytes_per_pixel()) {
+                    px[offset..][..size].fill(0xFF);
+                }
+            }
+        }
+        return Ok(());
+    }
+    if let Some(typed) = typed_path(&src_fmt, &dst_fmt) {
+        for (s, d) in rows {
+            typed(s, &mut dst[d]);
+        }
+        return Ok(());
+    }
+
+    let (reader, writer) = (Reader::new(&src_fmt), Writer::new(&dst_fmt));
+    for (s, d) in rows {
+        let dst = &mut dst[d];
+        for (s, d) in s
+            .chunks_exact(src_fmt.bytes_per_pixel())
+            .zip(dst.chunks_exact_mut(dst_fmt.bytes_per_pixel()))
+        {
+            writer.write(reader.read(s), d);
+        }
+    }
+    Ok(())
+}
+
+/// Copying bytes is enough when the layout is the same. `Some(Some(offset))` if the alpha
+/// bytes at `offset` then need to be set to opaque, because one side's alpha is `Ignored`.
+fn copy_path(src: &PixelFormat, dst: &PixelFormat) -> Option<Option<usize>> {
+    if src.with_alpha(dst.alpha) != *dst {
+        return None;
+    }
+    match (src.alpha, dst.alpha) {
+        (s, d) if s == d && s != AlphaKind::Ignored => Some(None),
+        // 忽略的 alpha 读出来是不透明, 写出去是全 1; 预乘的颜色在不透明时不变, 但别的 alpha 需要还原
+        (AlphaKind::Straight | AlphaKind::Ignored, AlphaKind::Ignored)
+        | (AlphaKind::Ignored, AlphaKind::Straight | AlphaKind::Premultiplied)
+            if dst.packing == Packing::Aligned =>
+        {
+            let a = dst.order.roles().iter().position(|&r| r == Role::A)?;
+            Some(Some(a * (dst.depth as usize / 8)))
+        }
+        _ => None,
+    }
+}
+
+/// Alpha can be copied as-is, dropped, or filled with opaque when the source has none.
+/// `Ignored` alpha has to be read as opaque and written as all ones, which the typed
+/// conversions don't do.
+#[inline]
+fn alpha_copyable(src: AlphaKind, dst: AlphaKind) -> bool {
+    match (src, dst) {
+        // 预乘的颜色需要先还原
+        (AlphaKind::Premultiplied, AlphaKind::Premultiplied) => true,
+        (AlphaKind::Premultiplied, _) => false,
+        (_, AlphaKind::None) => true,
+        (AlphaKind::None, _) => true,
+        (AlphaKind::Ignored, _) | (_, AlphaKind::Ignored) => false,
+        (s, d) => s == d,
+    }
+}
+
+type RowFn = fn(&[u8], &mut [u8]);
+
+#[inline]
+fn typed_row<S, D>(src: &[u8], dst: &mut [u8])
+where
+    [u8]: AsPixels<S> + AsPixels<D>,
+    [S]: ConvertSlice<D>,
+{
+    let src: &[S] = src.as_pixels();
+    src.convert_into(dst.as_pixels_mut());
+}
+
+/// Row converter from `ConvertSlice`, for 8-bit aligned formats
+fn typed_path(src: &PixelFormat, dst: &PixelFormat) -> Option<RowFn> {
+    if src.packing != Packing::Aligned
+        || dst.packing != Packing::Aligned
+        || src.depth != 8
+        || dst.depth != 8
+        || !alpha_copyable(src.alpha, dst.alpha)
+    {
+        return None;
+    }
+
+    use ComponentOrder::*;
+    macro_rules! pairs {
+        ($($s:ident => $d:ident: $st:ident => $dt:ident),+ $(,)?) => {
+            match (src.order, dst.order) {
+                $(($s, $d) => Some(typed_row::<$st<u8>, $dt<u8>> as RowFn),)+
+                #[cfg(feature = "argb")]
+                (Argb, Rgba) => Some(typed_row::<ARGB<u8>, RGBA<u8>> as RowFn),
+                #[cfg(feature = "argb")]
+                (Rgba, Argb) => Some(typed_row::<RGBA<u8>, ARGB<u8>> as RowFn),
+                #[cfg(feature = "argb")]
+                (Abgr, Bgra) => Some(typed_row::<ABGR<u8>, BGRA<u8>> as RowFn),
+                #[cfg(feature = "argb")]
+                (Bgra, Abgr) => Some(typed_row::<BGRA<u8>, ABGR<u8>> as RowFn),
+                _ => None,
+            }
+        };
+    }
+    pairs! {
+        Rgba => Bgra: RGBA => BGRA,
+        Bgra => Rgba: BGRA => RGBA,
+        Rgb => Bgr: RGB => BGR,
+        Bgr => Rgb: BGR => RGB,
+        Rgb => Rgba: RGB => RGBA,
+        Bgr => Bgra: BGR => BGRA,
+        Rgb => Bgra: RGB => BGRA,
+        Bgr => Rgba: BGR => RGBA,
+        Rgba => Rgb: RGBA => RGB,
+        Bgra => Bgr: BGRA => BGR,
+        Rgba => Bgr: RGBA => BGR,
+        Bgra => Rgb: BGRA => RGB,
+    }
+}
+
+/// Where each component is, and how to scale it to 16 bits
+#[derive(Clone, Copy)]
+struct Component {
+    role: Role,
+    /// Byte offset for aligned formats, bit shift for packed ones
+    pos: u32,
+    max: u32,
+}
+
+fn components(fmt: &PixelFormat) -> ([Component; 4], usize) {
+    let roles = fmt.order.roles();
+    let mut out = [Component {
+        role: Role::Y,
+        pos: 0,
+        max: 0,
+    }; 4];
+    let mut shift = fmt.depth as u32;
+    for (i, &role) in roles.iter().enumerate() {
+        out[i] = match fmt.packing {
+            Packing::Aligned => Component {
+                role,
+                pos: i as u32 * (fmt.depth as u32 / 8),
+                max: (1u32 << fmt.depth) - 1,
+            },
+            Packing::Packed(bits) => {
+                shift -= bits[i] as u32;
+                Component {
+                    role,
+                    pos: shift,
+                    max: (1u32 << bits[i]) - 1,
+                }
+            }
+        };
+    }
+    (out, roles.len())
+}
+
+#[inline]
+fn read_int(bytes: &[u8], endian: Endian) -> u32 {
+    match endian {
+        Endian::Little => bytes.iter().rev().fold(0, |v, &b| (v << 8) | b as u32),
+        Endian::Big => bytes.iter().fold(0, |v, &b| (v << 8) | b as u32),
+    }
+}
+
+#[inline]
+fn write_int(bytes: &mut [u8], endian: Endian, mut v: u32) {
+    let len = bytes.len();
+    for i in 0..len {
+        let byte = match endian {
+            Endian::Little => i,
+            Endian::Big => len - 1 - i,
+        };
+        bytes[byte] = v as u8;
+        v >>= 8;
+    }
+}
+
+/// Reads any format as `RGBA<u16>` with straight alpha
+struct Reader {
+    fmt: PixelFormat,
+    components: [Component; 4],
+    len: usize,
+}
+
+impl Reader {
+    fn new(fmt: &PixelFormat) -> Self {
+        let (components, len) = components(fmt);
+        Self {
+            fmt: *fmt,
+            components,
+            len,
+        }
+    }
+
+    #[inline]
+    fn read(&self, px: &[u8]) -> RGBA<u16> {
+        let word = match self.fmt.packing {
+            Packing::Packed(_) => read_int(px, self.fmt.endian),
+            Packing::Aligned => 0,
+        };
+        let mut out = RGBA::new(0, 0, 0, 0xFFFF);
+        for c in &self.components[..self.len] {
+            let v = match self.fmt.packing {
+                Packing::Packed(_) => (word >> c.pos) & c.max,
+                Packing::Aligned => {
+                    let size = self.fmt.depth as usize / 8;
+                    read_int(&px[c.pos as usize..][..size], self.fmt.endian)
+                }
+            };
+            // 四舍五入到 16-bit
+            let v = ((v * 0xFFFF + c.max / 2) / c.max) as u16;
+            match c.role {
+                Role::Y => {
+                    out.r = v;
+                    out.g = v;
+                    out.b = v;
+                }
+                Role::R => out.r = v,
+                Role::G => out.g = v,
+                Role::B => out.b = v,
+                Role::A => {
+                    if self.fmt.alpha != AlphaKind::Ignored {
+                        out.a = v;
+                    }
+                }
+            }
+        }
+        if self.fmt.alpha == AlphaKind::Premultiplied {
+            let a = out.a as u32;
+            out = if a == 0 {
+                RGBA::new(0, 0, 0, 0)
+            } else {
+                out.map_rgb(|c| ((c as u32 * 0xFFFF + a / 2) / a).min(0xFFFF) as u16)
+            };
+        }
+        out
+    }
+}
+
+/// Writes `RGBA<u16>` with straight alpha in any format
+struct Writer {
+    fmt: PixelFormat,
+    components: [Component; 4],
+    len: usize,
+}
+
+impl Writer {
+    fn new(fmt: &PixelFormat) -> Self {
+        let (components, len) = components(fmt);
+        Self {
+            fmt: *fmt,
+            components,
+            len,
+        }
+    }
+
+    #[inline]
+    fn write(&self, mut px: RGBA<u16>, out: &mut [u8]) {
+        if self.fmt.alpha == AlphaKind::Premultiplied {
+            let a = px.a as u32;
+            px = px.map_rgb(|c| ((c as u32 * a + 0x7FFF) / 0xFFFF) as u16);
+        }
+        let mut word = 0;
+        for c in &self.components[..self.len] {
+            let v = match c.role {
+                Role::Y => px.rgb().to_gray().0,
+                Role::R => px.r,
+                Role::G => px.g,
+                Role::B => px.b,
+                Role::A if self.fmt.alpha == AlphaKind::Ignored => 0xFFFF,
+                Role::A => px.a,
+            };
+            let v = (v as u32 * c.max + 0x7FFF) / 0xFFFF;
+            match self.fmt.packing {
+                Packing::Packed(_) => word |= v << c.pos,
+                Packing::Aligned => {
+                    let size = self.fmt.depth as usize / 8;
+                    write_int(&mut out[c.pos as usize..][..size], self.fmt.endian, v);
+                }
+            }
+        }
+        if let Packing::Packed(_) = self.fmt.packing {
+            write_int(out, self.fmt.endian, word);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert_one(src: &[u8], src_fmt: PixelFormat, dst_fmt: PixelFormat) -> Vec<u8> {
+        let mut dst = vec![0; dst_fmt.bytes_per_pixel()];
+        let strides = (src.len(), dst.len());
+        convert(src, src_fmt, &mut dst, dst_fmt, 1, 1, strides).unwrap();
+        dst
+    }
+
+    #[test]
+    fn fourcc_mapping() {
+        let xrgb = PixelFormat::from_drm_fourcc(fourcc(*b"XR24")).unwrap();
+        assert_eq!(xrgb.bytes_per_pixel(), 4);
+        assert_eq!(xrgb.to_drm_fourcc(), Some(fourcc(*b"XR24")));
+        assert_eq!(PixelFormat::RGB8.to_v4l2_fourcc(), Some(fourcc(*b"RGB3")));
+        assert_eq!(PixelFormat::RGB8.to_drm_fourcc(), Some(fourcc(*b"BG24")));
+        assert_eq!(
+            PixelFormat::from_wl_shm(0),
+            Some(PixelFormat::BGRA8.with_alpha(AlphaKind::Premultiplied))
+        );
+        assert_eq!(
+            PixelFormat::from_wl_shm(0).unwrap().to_drm_fourcc(),
+            Some(fourcc(*b"AR24"))
+        );
+        assert_eq!(PixelFormat::from_drm_fourcc(fourcc(*b"NV12")), None);
+
+        for &(code, fmt) in DRM_FORMATS.iter().chain(V4L2_FORMATS) {
+            assert!(fmt.is_valid(), "{:?}", code.to_le_bytes());
+        }
+        assert!(!PixelFormat::aligned(ComponentOrder::Rgba, 8, AlphaKind::None).is_valid());
+        assert!(!PixelFormat::packed(
+            ComponentOrder::Rgb,
+            [5, 5, 5, 0],
+            Endian::Little,
+            AlphaKind::None
+        )
+        .is_valid());
+    }
+
+    #[test]
+    fn packed_and_endian() {
+        let rgb565 = PixelFormat::RGB565;
+        // 0xF800 = pure red
+        assert_eq!(
+            convert_one(&[0x00, 0xF8], rgb565, PixelFormat::RGB8),
+            [255, 0, 0]
+        );
+        assert_eq!(
+            convert_one(&[0, 255, 0], PixelFormat::RGB8, rgb565),
+            [0xE0, 0x07]
+        );
+        assert_eq!(
+            convert_one(
+                &[0, 0, 128],
+                PixelFormat::RGB8,
+                rgb565.with_endian(Endian::Big)
+            ),
+            [0x00, 0x10]
+        );
+
+        let ar30 = PixelFormat::from_drm_fourcc(fourcc(*b"AR30")).unwrap();
+        let px = convert_one(&[255, 128, 0, 85], PixelFormat::RGBA8, ar30);
+        assert_eq!(
+            convert_one(&px, ar30, PixelFormat::RGBA8),
+            [255, 128, 0, 85]
+        );
+
+        let be16 = PixelFormat::RGB16.with_endian(Endian::Big);
+        assert_eq!(
+            convert_one(&[0x12, 0x34, 0, 0, 0xFF, 0xFF], be16, PixelFormat::RGB8),
+            [0x12, 0, 255]
+        );
+    }
+
+    #[test]
+    fn alpha_and_gray() {
+        let premul = PixelFormat::RGBA8.with_alpha(AlphaKind::Premultiplied);
+        assert_eq!(
+            convert_one(&[100, 50, 0, 128], premul, PixelFormat::RGBA8),
+            [199, 100, 0, 128]
+        );
+        assert_eq!(
+            convert_one(&[200, 100, 0, 128], PixelFormat::RGBA8, premul),
+            [100, 50, 0, 128]
+        );
+        assert_eq!(
+            convert_one(&[255, 0, 0], PixelFormat::RGB8, PixelFormat::GRAYA8),
+            [76, 255]
+        );
+        assert_eq!(
+            convert_one(
+                &[9, 8, 7, 6],
+                PixelFormat::BGRA8.with_alpha(AlphaKind::Ignored),
+                PixelFormat::RGBA8
+            ),
+            [7, 8, 9, 255]
+        );
+        assert_eq!(
+            convert_one(&[0x12, 0x34], PixelFormat::GRAY16, PixelFormat::GRAY16),
+            [0x12, 0x34]
+        );
+    }
+
+    #[test]
+    fn typed_and_generic_agree() {
+        let formats = [
+            PixelFormat::RGB8,
+            PixelFormat::BGR8,
+            PixelFormat::RGBA8,
+            PixelFormat::BGRA8,
+            PixelFormat::ARGB8,
+            PixelFormat::ABGR8,
+            // XBGR8888 等, 只复制字节会把填充当成 alpha
+            PixelFormat::RGBA8.with_alpha(AlphaKind::Ignored),
+            PixelFormat::BGRA8.with_alpha(AlphaKind::Ignored),
+            PixelFormat::ARGB8.with_alpha(AlphaKind::Ignored),
+            PixelFormat::ABGR8.with_alpha(AlphaKind::Ignored),
+            PixelFormat::RGBA16.with_alpha(AlphaKind::Ignored),
+            PixelFormat::RGBA16,
+        ];
+        let (w, h) = (5, 3);
+        let src: Vec<u8> = (0..64 * h).map(|i| (i * 7 + 3) as u8).collect();
+        for &sf in &formats {
+            for &df in &formats {
+                let mut typed = vec![0u8; 48 * h];
+                convert(&src, sf, &mut typed, df, w, h, (64, 48)).unwrap();
+
+                let mut generic = vec![0u8; 48 * h];
+                let (reader, writer) = (Reader::new(&sf), Writer::new(&df));
+                for y in 0..h {
+                    for x in 0..w {
+                        let s = &src[y * 64 + x * sf.bytes_per_pixel()..][..sf.bytes_per_pixel()];
+                        let d = &mut generic[y * 48 + x * df.bytes_per_pixel()..]
+                            [..df.bytes_per_pixel()];
+                        writer.write(reader.read(s), d);
+                    }
+                }
+                assert_eq!(typed, generic, "{sf:?} -> {df:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let mut dst = [0u8; 12];
+        let src = [0u8; 16];
+        let c = |src: &[u8], dst: &mut [u8], strides| {
+            convert(
+                src,
+                PixelFormat::RGBA8,
+                dst,
+                PixelFormat::RGB8,
+                2,
+                2,
+                strides,
+            )
+        };
+        assert_eq!(c(&src, &mut dst, (8, 6)), Ok(()));
+        assert_eq!(c(&src, &mut dst, (7, 6)), Err(ConvertError::StrideTooSmall));
+        assert_eq!(
+            c(&src[..15], &mut dst, (8, 6)),
+            Err(ConvertError::BufferTooSmall)
+        );
+        assert_eq!(c(&src, &mut dst, (8, 7)), Err(ConvertError::BufferTooSmall));
+        assert_eq!(
+            convert(
+                &src,
+                PixelFormat::RGBA8.with_alpha(AlphaKind::None),
+                &mut dst,
+                PixelFormat::RGB8,
+                1,
+                1,
+                (4, 3)
+            ),
+            Err(ConvertError::InvalidFormat)
+        );
+
+        // 溢出的尺寸要报错, 不能回绕
+        let wide = PixelFormat::packed(
+            ComponentOrder::Rgba,
+            [200, 200, 8, 0],
+            Endian::Little,
+            AlphaKind::Straight,
+        );
+        assert!(!wide.is_valid());
+        let c = |src: &[u8], dst: &mut [u8], width, height, strides| {
+            convert(
+                src,
+                PixelFormat::RGBA8,
+                dst,
+                PixelFormat::RGB8,
+                width,
+                height,
+                strides,
+            )
+        };
+        assert_eq!(
+            c(&src, &mut dst, usize::MAX / 2, 1, (usize::MAX, usize::MAX)),
+            Err(ConvertError::StrideTooSmall)
+        );
+        assert_eq!(
+            c(&src, &mut dst, 1, usize::MAX, (8, 6)),
+            Err(ConvertError::BufferTooSmall)
+        );
+        assert_eq!(
+            c(&src, &mut dst, 1, 2, (usize::MAX, 6)),
+            Err(ConvertError::BufferTooSmall)
+        );
+    }
+}
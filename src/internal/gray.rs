@@ -0,0 +1,325 @@
+use crate::alt::*;
+use crate::{RGB, RGBA};
+
+/// Formula used to turn a colour into a shade of gray
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Luma {
+    /// `0.299 R + 0.587 G + 0.114 B` (BT.601, used by JPEG and most video)
+    #[default]
+    Rec601,
+    /// `0.2126 R + 0.7152 G + 0.0722 B` (BT.709, sRGB primaries)
+    Rec709,
+    /// `0.2627 R + 0.6780 G + 0.0593 B` (BT.2020)
+    Rec2020,
+    /// `(R + G + B) / 3`
+    Average,
+    /// Relative luminance (CIE Y) of sRGB-encoded components.
+    ///
+    /// Components are decoded to linear light, weighted with BT.709 coefficients, and the result
+    /// is encoded back with the sRGB curve, so the gray looks as bright as the colour.
+    Luminance,
+}
+
+impl Luma {
+    /// Exact weights as `(r, g, b, divisor)`, or `None` for `Luminance`
+    #[inline]
+    fn weights(self) -> Option<(u64, u64, u64, u64)> {
+        match self {
+            Luma::Rec601 => Some((299, 587, 114, 1000)),
+            Luma::Rec709 => Some((2126, 7152, 722, 10000)),
+            Luma::Rec2020 => Some((2627, 6780, 593, 10000)),
+            Luma::Average => Some((1, 1, 1, 3)),
+            Luma::Luminance => None,
+        }
+    }
+}
+
+/// sRGB transfer function, for components in `0..=1`
+#[inline]
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[inline]
+fn linear_to_srgb(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Relative luminance of sRGB-encoded `0..=1` components, encoded back as sRGB
+#[inline]
+fn luminance(r: f64, g: f64, b: f64) -> f64 {
+    let y = 0.2126 * srgb_to_linear(r) + 0.7152 * srgb_to_linear(g) + 0.0722 * srgb_to_linear(b);
+    linear_to_srgb(y)
+}
+
+/// Component types that can be converted to gray: `u8`, `u16` (rounded exactly), `f32` and `f64`
+pub trait GrayComponent: Copy {
+    /// Gray value of the colour
+    fn luma(r: Self, g: Self, b: Self, luma: Luma) -> Self;
+}
+
+macro_rules! gray_int_impl {
+    ($t:ty) => {
+        impl GrayComponent for $t {
+            #[inline]
+            fn luma(r: Self, g: Self, b: Self, luma: Luma) -> Self {
+                match luma.weights() {
+                    // 整数运算, 四舍五入; 权重之和等于除数, 所以结果不会超过 MAX
+                    Some((wr, wg, wb, d)) => {
+                        ((wr * r as u64 + wg * g as u64 + wb * b as u64 + d / 2) / d) as $t
+                    }
+                    None => {
+                        let max = <$t>::MAX as f64;
+                        let y = luminance(r as f64 / max, g as f64 / max, b as f64 / max);
+                        (y * max).round() as $t
+                    }
+                }
+            }
+        }
+    };
+}
+
+macro_rules! gray_float_impl {
+    ($t:ty) => {
+        impl GrayComponent for $t {
+            #[inline]
+            fn luma(r: Self, g: Self, b: Self, luma: Luma) -> Self {
+                match luma.weights() {
+                    Some((wr, wg, wb, d)) => {
+                        let d = d as $t;
+                        (wr as $t / d) * r + (wg as $t / d) * g + (wb as $t / d) * b
+                    }
+                    None => luminance(r as f64, g as f64, b as f64) as $t,
+                }
+            }
+        }
+    };
+}
+
+gray_int_impl! {u8}
+gray_int_impl! {u16}
+gray_float_impl! {f32}
+gray_float_impl! {f64}
+
+/// Converts colour pixels to `Gray`, or `GrayAlpha` if they have alpha.
+///
+/// ```rust
+/// use cr::{Luma, ToGray, RGB8, RGBA8};
+/// use cr::alt::{Gray, GrayAlpha};
+///
+/// assert_eq!(RGB8::new(255, 0, 0).to_gray(), Gray(76));
+/// assert_eq!(RGB8::new(255, 0, 0).to_gray_with(Luma::Rec709), Gray(54));
+/// assert_eq!(RGBA8::new(10, 20, 30, 40).to_gray_with(Luma::Average), GrayAlpha(20, 40));
+/// ```
+pub trait ToGray {
+    /// `Gray` or `GrayAlpha`
+    type Gray;
+
+    /// Convert using the default `Luma::Rec601` weights
+    #[inline]
+    fn to_gray(&self) -> Self::Gray {
+        self.to_gray_with(Luma::default())
+    }
+
+    /// Convert using the given formula
+    fn to_gray_with(&self, luma: Luma) -> Self::Gray;
+}
+
+macro_rules! to_gray_impl {
+    ($typ:ident) => {
+        impl<T: GrayComponent> ToGray for $typ<T> {
+            type Gray = Gray<T>;
+
+            #[inline]
+            fn to_gray_with(&self, luma: Luma) -> Gray<T> {
+                Gray(T::luma(self.r, self.g, self.b, luma))
+            }
+        }
+    };
+}
+
+macro_rules! to_gray_alpha_impl {
+    ($typ:ident) => {
+        impl<T: GrayComponent, A: Copy> ToGray for $typ<T, A> {
+            type Gray = GrayAlpha<T, A>;
+
+            #[inline]
+            fn to_gray_with(&self, luma: Luma) -> GrayAlpha<T, A> {
+                GrayAlpha(T::luma(self.r, self.g, self.b, luma), self.a)
+            }
+        }
+    };
+}
+
+to_gray_impl! {RGB}
+to_gray_impl! {BGR}
+
+#[cfg(feature = "grb")]
+to_gray_impl! {GRB}
+
+to_gray_alpha_impl! {RGBA}
+to_gray_alpha_impl! {BGRA}
+
+#[cfg(feature = "argb")]
+to_gray_alpha_impl! {ARGB}
+
+#[cfg(feature = "argb")]
+to_gray_alpha_impl! {ABGR}
+
+/// Converts slices of colour pixels to gray. See `ToGray`.
+pub trait ToGraySlice<G> {
+    /// Write gray version of every pixel into `dst`.
+    ///
+    /// Panics if `dst` has a different length.
+    fn to_gray_into(&self, dst: &mut [G], luma: Luma);
+
+    /// Gray version of every pixel
+    fn to_gray_vec(&self, luma: Luma) -> Vec<G>;
+}
+
+impl<P: ToGray> ToGraySlice<P::Gray> for [P] {
+    #[inline]
+    #[track_caller]
+    fn to_gray_into(&self, dst: &mut [P::Gray], luma: Luma) {
+        assert_eq!(
+            self.len(),
+            dst.len(),
+            "source and destination must have the same length"
+        );
+        for (d, px) in dst.iter_mut().zip(self) {
+            *d = px.to_gray_with(luma);
+        }
+    }
+
+    #[inline]
+    fn to_gray_vec(&self, luma: Luma) -> Vec<P::Gray> {
+        self.iter().map(|px| px.to_gray_with(luma)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RGB16, RGB8, RGBA8};
+
+    const ALL: [Luma; 5] = [
+        Luma::Rec601,
+        Luma::Rec709,
+        Luma::Rec2020,
+        Luma::Average,
+        Luma::Luminance,
+    ];
+
+    #[test]
+    fn extremes() {
+        for luma in ALL {
+            assert_eq!(RGB8::new(255, 255, 255).to_gray_with(luma), Gray(255));
+            assert_eq!(RGB8::new(0, 0, 0).to_gray_with(luma), Gray(0));
+            assert_eq!(RGB8::new(77, 77, 77).to_gray_with(luma), Gray(77));
+            assert_eq!(
+                RGB16::new(65535, 65535, 65535).to_gray_with(luma),
+                Gray(65535)
+            );
+            let white = RGB::new(1f32, 1., 1.).to_gray_with(luma).0;
+            assert!((white - 1.).abs() < 1e-6, "{:?} {}", luma, white);
+        }
+    }
+
+    #[test]
+    fn integer_rounding() {
+        // 0.299 * 255 = 76.245, 0.587 * 255 = 149.685, 0.114 * 255 = 29.07
+        assert_eq!(RGB8::new(255, 0, 0).to_gray(), Gray(76));
+        assert_eq!(RGB8::new(0, 255, 0).to_gray(), Gray(150));
+        assert_eq!(RGB8::new(0, 0, 255).to_gray(), Gray(29));
+        // 0.0722 * 255 = 18.411, 0.2627 * 255 = 66.9885
+        assert_eq!(RGB8::new(0, 0, 255).to_gray_with(Luma::Rec709), Gray(18));
+        assert_eq!(RGB8::new(255, 0, 0).to_gray_with(Luma::Rec2020), Gray(67));
+        // (1 + 2 + 2) / 3 = 1.67
+        assert_eq!(RGB8::new(1, 2, 2).to_gray_with(Luma::Average), Gray(2));
+
+        // integer path must agree with float math rounded to nearest
+        for v in (0..=255u8).step_by(5) {
+            let px = RGB8::new(v, 255 - v, v / 2);
+            let f = RGB::new(v as f64, (255 - v) as f64, (v / 2) as f64);
+            for luma in ALL {
+                let expected = f.to_gray_with(luma).0;
+                let expected = if luma == Luma::Luminance {
+                    (luminance(f.r / 255., f.g / 255., f.b / 255.) * 255.).round()
+                } else {
+                    expected.round()
+                };
+                assert_eq!(
+                    px.to_gray_with(luma).0 as f64,
+                    expected,
+                    "{:?} {:?}",
+                    luma,
+                    px
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn luminance_of_primaries() {
+        // Y of pure green is 0.7152, which is 0.8615 in sRGB
+        let g = RGB::new(0f64, 1., 0.).to_gray_with(Luma::Luminance).0;
+        assert!((g - 0.8615).abs() < 1e-3, "{}", g);
+        assert_eq!(
+            RGB8::new(0, 255, 0).to_gray_with(Luma::Luminance),
+            Gray(220)
+        );
+    }
+
+    #[test]
+    fn layouts_and_slices() {
+        let bgr = BGR {
+            b: 0u8,
+            g: 0,
+            r: 255,
+        };
+        assert_eq!(bgr.to_gray(), Gray(76));
+        let bgra = BGRA {
+            b: 0u8,
+            g: 0,
+            r: 255,
+            a: 9u8,
+        };
+        assert_eq!(bgra.to_gray(), GrayAlpha(76, 9));
+
+        let pixels = [RGBA8::new(255, 0, 0, 1), RGBA8::new(0, 0, 0, 2)];
+        assert_eq!(
+            pixels.to_gray_vec(Luma::Rec601),
+            [GrayAlpha(76, 1), GrayAlpha(0, 2)]
+        );
+        let mut dst = [GrayAlpha::default(); 2];
+        pixels.to_gray_into(&mut dst, Luma::Average);
+        assert_eq!(dst, [GrayAlpha(85, 1), GrayAlpha(0, 2)]);
+    }
+
+    #[test]
+    #[cfg(feature = "argb")]
+    fn alpha_first() {
+        let px = ARGB8 {
+            a: 3,
+            r: 255,
+            g: 0,
+            b: 0,
+        };
+        assert_eq!(px.to_gray(), GrayAlpha(76, 3));
+        let px = ABGR8 {
+            a: 3,
+            b: 255,
+            g: 0,
+            r: 0,
+        };
+        assert_eq!(px.to_gray(), GrayAlpha(29, 3));
+    }
+}
@@ -0,0 +1,996 @@
+use super::convert::AsPixels;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Index, IndexMut};
+use core::ptr::NonNull;
+
+/// Owned 2D image, a `Vec` of pixels with a width, height and stride.
+///
+/// `stride` is the number of pixels (not bytes) between the start of two consecutive rows.
+/// It can be larger than `width`, in which case the extra pixels at the end of each row are
+/// padding and are never visited by row iterators.
+///
+/// ```rust
+/// use cr::{Image, RGB8};
+///
+/// let mut img = Image::new(vec![RGB8::new(0, 0, 0); 6], 3, 2);
+/// img[(2, 1)] = RGB8::new(255, 0, 0);
+///
+/// assert_eq!(img.rows().nth(1).unwrap()[2], RGB8::new(255, 0, 0));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Image<P> {
+    buf: Vec<P>,
+    width: usize,
+    height: usize,
+    stride: usize,
+}
+
+/// Borrowed, read-only view of a 2D image. See `Image`.
+///
+/// The view only ever reads the `width` pixels of each row, so the stride padding may belong
+/// to another view (e.g. after `split_at_col`).
+pub struct ImageRef<'a, P> {
+    ptr: NonNull<P>,
+    width: usize,
+    height: usize,
+    stride: usize,
+    _marker: PhantomData<&'a [P]>,
+}
+
+/// Borrowed, mutable view of a 2D image. See `Image`.
+///
+/// Views returned by `split_at_row`, `split_at_col` and `tiles_mut` never overlap,
+/// so they can be sent to different threads.
+pub struct ImageMut<'a, P> {
+    ptr: NonNull<P>,
+    width: usize,
+    height: usize,
+    stride: usize,
+    _marker: PhantomData<&'a mut [P]>,
+}
+
+// 和 `&[P]`/`&mut [P]` 一样的规则: 每个 view 独占(或共享)自己的 rows, 不会访问 stride padding
+unsafe impl<P: Sync> Send for ImageRef<'_, P> {}
+unsafe impl<P: Sync> Sync for ImageRef<'_, P> {}
+unsafe impl<P: Send> Send for ImageMut<'_, P> {}
+unsafe impl<P: Sync> Sync for ImageMut<'_, P> {}
+
+/// 至少需要多少个 pixels: 最后一行不需要包含 stride 的 padding
+#[inline]
+fn required_len(width: usize, height: usize, stride: usize) -> usize {
+    if height == 0 {
+        0
+    } else {
+        stride * (height - 1) + width
+    }
+}
+
+#[inline]
+#[track_caller]
+fn check_dimensions(len: usize, width: usize, height: usize, stride: usize) {
+    assert!(
+        stride >= width,
+        "stride {} is smaller than width {}",
+        stride,
+        width
+    );
+    let required = required_len(width, height, stride);
+    assert!(
+        len >= required,
+        "buffer of {} pixels is too small for {}x{} image with stride {} (needs {})",
+        len,
+        width,
+        height,
+        stride,
+        required
+    );
+}
+
+/// Checks the rectangle is inside the image, and returns its offset from the first pixel
+#[inline]
+#[track_caller]
+fn crop_offset(
+    (width, height, stride): (usize, usize, usize),
+    x: usize,
+    y: usize,
+    w: usize,
+    h: usize,
+) -> usize {
+    assert!(
+        x <= width && w <= width - x && y <= height && h <= height - y,
+        "{}x{} at ({}, {}) is out of bounds of {}x{} image",
+        w,
+        h,
+        x,
+        y,
+        width,
+        height
+    );
+    // 空的区域不能移动指针, 否则可能会越过 buffer 的末尾
+    if w == 0 || h == 0 {
+        0
+    } else {
+        y * stride + x
+    }
+}
+
+impl<P> Image<P> {
+    /// Wrap a buffer of `width * height` pixels, with rows packed tightly.
+    ///
+    /// Panics if the buffer is too small.
+    #[inline]
+    #[track_caller]
+    pub fn new(buf: Vec<P>, width: usize, height: usize) -> Self {
+        Self::new_stride(buf, width, height, width)
+    }
+
+    /// Wrap a buffer where rows start every `stride` pixels.
+    ///
+    /// Panics if `stride < width` or the buffer is too small.
+    #[inline]
+    #[track_caller]
+    pub fn new_stride(buf: Vec<P>, width: usize, height: usize, stride: usize) -> Self {
+        check_dimensions(buf.len(), width, height, stride);
+        Self {
+            buf,
+            width,
+            height,
+            stride,
+        }
+    }
+
+    /// The underlying buffer, including stride padding
+    #[inline(always)]
+    pub fn buf(&self) -> &[P] {
+        &self.buf
+    }
+
+    /// The underlying buffer, including stride padding
+    #[inline(always)]
+    pub fn buf_mut(&mut self) -> &mut [P] {
+        &mut self.buf
+    }
+
+    /// Give back the underlying buffer
+    #[inline(always)]
+    pub fn into_buf(self) -> Vec<P> {
+        self.buf
+    }
+
+    /// Borrow as a read-only view
+    #[inline]
+    pub fn view(&self) -> ImageRef<'_, P> {
+        ImageRef::new_stride(&self.buf, self.width, self.height, self.stride)
+    }
+
+    /// Borrow as a mutable view
+    #[inline]
+    pub fn view_mut(&mut self) -> ImageMut<'_, P> {
+        ImageMut::new_stride(&mut self.buf, self.width, self.height, self.stride)
+    }
+
+    #[inline(always)]
+    fn as_ptr(&self) -> *const P {
+        self.buf.as_ptr()
+    }
+
+    #[inline(always)]
+    fn as_mut_ptr(&mut self) -> *mut P {
+        self.buf.as_mut_ptr()
+    }
+}
+
+impl<P: Clone> Image<P> {
+    /// New `width` x `height` image with every pixel set to `pixel`
+    ///
+    /// Panics if `width * height` overflows.
+    #[inline]
+    #[track_caller]
+    pub fn filled(pixel: P, width: usize, height: usize) -> Self {
+        let len = width
+            .checked_mul(height)
+            .expect("image size overflows usize");
+        Self::new(vec![pixel; len], width, height)
+    }
+}
+
+impl<'a, P> ImageRef<'a, P> {
+    /// Wrap a slice of `width * height` pixels, with rows packed tightly.
+    ///
+    /// Panics if the slice is too small.
+    #[inline]
+    #[track_caller]
+    pub fn new(buf: &'a [P], width: usize, height: usize) -> Self {
+        Self::new_stride(buf, width, height, width)
+    }
+
+    /// Wrap a slice where rows start every `stride` pixels.
+    ///
+    /// Panics if `stride < width` or the slice is too small.
+    #[inline]
+    #[track_caller]
+    pub fn new_stride(buf: &'a [P], width: usize, height: usize, stride: usize) -> Self {
+        check_dimensions(buf.len(), width, height, stride);
+        // Safety: the slice is borrowed for 'a and is large enough
+        unsafe { Self::from_raw(NonNull::from(buf).cast(), width, height, stride) }
+    }
+
+    /// Reinterpret a slice of components (e.g. bytes) as an image, see `AsPixels`/`FromSlice`.
+    ///
+    /// `stride` is in pixels, not components.
+    ///
+    /// ```rust
+    /// use cr::{ImageRef, RGB8};
+    ///
+    /// let bytes = [1u8, 2, 3, 4, 5, 6];
+    /// let img: ImageRef<'_, RGB8> = ImageRef::from_components(&bytes[..], 1, 2, 1);
+    ///
+    /// assert_eq!(img[(0, 1)], RGB8::new(4, 5, 6));
+    /// ```
+    #[inline]
+    #[track_caller]
+    pub fn from_components<T>(buf: &'a [T], width: usize, height: usize, stride: usize) -> Self
+    where
+        [T]: AsPixels<P>,
+    {
+        Self::new_stride(buf.as_pixels(), width, height, stride)
+    }
+
+    /// Safety: every row must be readable for `'a` and not mutated by anyone else
+    #[inline(always)]
+    unsafe fn from_raw(ptr: NonNull<P>, width: usize, height: usize, stride: usize) -> Self {
+        Self {
+            ptr,
+            width,
+            height,
+            stride,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Split into rows `0..y` and `y..height`, both sharing memory with this view.
+    ///
+    /// Panics if `y > height`.
+    #[inline]
+    #[track_caller]
+    pub fn split_at_row(self, y: usize) -> (Self, Self) {
+        let (top, bottom) = split_row_dims(self.dims(), y);
+        // Safety: both halves are inside of this view
+        unsafe {
+            (
+                Self::from_raw(self.ptr, top.0, top.1, self.stride),
+                Self::from_raw(self.offset_ptr(bottom.2), bottom.0, bottom.1, self.stride),
+            )
+        }
+    }
+
+    /// Split into columns `0..x` and `x..width`, both sharing memory with this view.
+    ///
+    /// Panics if `x > width`.
+    #[inline]
+    #[track_caller]
+    pub fn split_at_col(self, x: usize) -> (Self, Self) {
+        let (left, right) = split_col_dims(self.dims(), x);
+        // Safety: both halves are inside of this view
+        unsafe {
+            (
+                Self::from_raw(self.ptr, left.0, left.1, self.stride),
+                Self::from_raw(self.offset_ptr(right.2), right.0, right.1, self.stride),
+            )
+        }
+    }
+
+    #[inline(always)]
+    fn as_ptr(&self) -> *const P {
+        self.ptr.as_ptr()
+    }
+}
+
+impl<'a, P> ImageMut<'a, P> {
+    /// Wrap a slice of `width * height` pixels, with rows packed tightly.
+    ///
+    /// Panics if the slice is too small.
+    #[inline]
+    #[track_caller]
+    pub fn new(buf: &'a mut [P], width: usize, height: usize) -> Self {
+        Self::new_stride(buf, width, height, width)
+    }
+
+    /// Wrap a slice where rows start every `stride` pixels.
+    ///
+    /// Panics if `stride < width` or the slice is too small.
+    #[inline]
+    #[track_caller]
+    pub fn new_stride(buf: &'a mut [P], width: usize, height: usize, stride: usize) -> Self {
+        check_dimensions(buf.len(), width, height, stride);
+        // Safety: the slice is exclusively borrowed for 'a and is large enough
+        unsafe { Self::from_raw(NonNull::from(buf).cast(), width, height, stride) }
+    }
+
+    /// Reinterpret a mutable slice of components (e.g. bytes) as an image, see `AsPixels`/`FromSlice`.
+    ///
+    /// `stride` is in pixels, not components.
+    #[inline]
+    #[track_caller]
+    pub fn from_components<T>(buf: &'a mut [T], width: usize, height: usize, stride: usize) -> Self
+    where
+        [T]: AsPixels<P>,
+    {
+        Self::new_stride(buf.as_pixels_mut(), width, height, stride)
+    }
+
+    /// Safety: every row must be writable for `'a` and not accessed through any other view
+    #[inline(always)]
+    unsafe fn from_raw(ptr: NonNull<P>, width: usize, height: usize, stride: usize) -> Self {
+        Self {
+            ptr,
+            width,
+            height,
+            stride,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Reborrow as a read-only view
+    #[inline]
+    pub fn view(&self) -> ImageRef<'_, P> {
+        // Safety: `&self` prevents writes while the view exists
+        unsafe { ImageRef::from_raw(self.ptr, self.width, self.height, self.stride) }
+    }
+
+    /// Reborrow as a shorter-lived mutable view
+    #[inline]
+    pub fn view_mut(&mut self) -> ImageMut<'_, P> {
+        // Safety: `&mut self` is exclusive while the view exists
+        unsafe { ImageMut::from_raw(self.ptr, self.width, self.height, self.stride) }
+    }
+
+    /// Split into rows `0..y` and `y..height`, which can be modified independently.
+    ///
+    /// Panics if `y > height`.
+    #[inline]
+    #[track_caller]
+    pub fn split_at_row(self, y: usize) -> (Self, Self) {
+        let (top, bottom) = split_row_dims(self.dims(), y);
+        // Safety: the halves don't overlap and are inside of this view
+        unsafe {
+            (
+                Self::from_raw(self.ptr, top.0, top.1, self.stride),
+                Self::from_raw(self.offset_ptr(bottom.2), bottom.0, bottom.1, self.stride),
+            )
+        }
+    }
+
+    /// Split into columns `0..x` and `x..width`, which can be modified independently.
+    ///
+    /// Panics if `x > width`.
+    #[inline]
+    #[track_caller]
+    pub fn split_at_col(self, x: usize) -> (Self, Self) {
+        let (left, right) = split_col_dims(self.dims(), x);
+        // Safety: the halves don't overlap and are inside of this view
+        unsafe {
+            (
+                Self::from_raw(self.ptr, left.0, left.1, self.stride),
+                Self::from_raw(self.offset_ptr(right.2), right.0, right.1, self.stride),
+            )
+        }
+    }
+
+    #[inline(always)]
+    fn as_ptr(&self) -> *const P {
+        self.ptr.as_ptr()
+    }
+
+    #[inline(always)]
+    fn as_mut_ptr(&mut self) -> *mut P {
+        self.ptr.as_ptr()
+    }
+}
+
+/// `(width, height, offset)` of the two halves
+type SplitDims = ((usize, usize, usize), (usize, usize, usize));
+
+#[inline]
+#[track_caller]
+fn split_row_dims((width, height, stride): (usize, usize, usize), y: usize) -> SplitDims {
+    assert!(y <= height, "row {} out of bounds (height {})", y, height);
+    let offset = crop_offset((width, height, stride), 0, y, width, height - y);
+    ((width, y, 0), (width, height - y, offset))
+}
+
+#[inline]
+#[track_caller]
+fn split_col_dims((width, height, stride): (usize, usize, usize), x: usize) -> SplitDims {
+    assert!(x <= width, "column {} out of bounds (width {})", x, width);
+    let offset = crop_offset((width, height, stride), x, 0, width - x, height);
+    ((x, height, 0), (width - x, height, offset))
+}
+
+impl<P> Clone for ImageRef<'_, P> {
+    #[inline(always)]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<P> Copy for ImageRef<'_, P> {}
+
+impl<'a, P> From<ImageMut<'a, P>> for ImageRef<'a, P> {
+    #[inline]
+    fn from(other: ImageMut<'a, P>) -> Self {
+        // Safety: the mutable view is consumed, so nothing can write to it anymore
+        unsafe { Self::from_raw(other.ptr, other.width, other.height, other.stride) }
+    }
+}
+
+impl<P> fmt::Debug for ImageRef<'_, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImageRef")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("stride", &self.stride)
+            .finish()
+    }
+}
+
+impl<P> fmt::Debug for ImageMut<'_, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImageMut")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("stride", &self.stride)
+            .finish()
+    }
+}
+
+/// Methods shared by `Image`, `ImageRef` and `ImageMut`
+macro_rules! impl_img_read {
+    ($img:ty) => {
+        impl<P> $img {
+            /// Width in pixels
+            #[inline(always)]
+            pub fn width(&self) -> usize {
+                self.width
+            }
+
+            /// Height in pixels
+            #[inline(always)]
+            pub fn height(&self) -> usize {
+                self.height
+            }
+
+            /// Number of pixels between the start of consecutive rows
+            #[inline(always)]
+            pub fn stride(&self) -> usize {
+                self.stride
+            }
+
+            /// Pixel at `(x, y)`, or `None` if it's out of bounds
+            #[inline]
+            pub fn get(&self, x: usize, y: usize) -> Option<&P> {
+                if x < self.width && y < self.height {
+                    // Safety: bounds checked above
+                    Some(unsafe { &*self.as_ptr().add(y * self.stride + x) })
+                } else {
+                    None
+                }
+            }
+
+            /// Row `y`, exactly `width` pixels long, or `None` if it's out of bounds
+            #[inline]
+            pub fn row(&self, y: usize) -> Option<&[P]> {
+                if y < self.height {
+                    // Safety: bounds checked above
+                    Some(unsafe {
+                        core::slice::from_raw_parts(self.as_ptr().add(y * self.stride), self.width)
+                    })
+                } else {
+                    None
+                }
+            }
+
+            /// Iterate over rows, each exactly `width` pixels long (stride padding is skipped)
+            #[inline]
+            pub fn rows(&self) -> Rows<'_, P> {
+                Rows {
+                    ptr: self.as_ptr(),
+                    width: self.width,
+                    stride: self.stride,
+                    remaining: self.height,
+                    _marker: PhantomData,
+                }
+            }
+
+            /// A read-only `w` x `h` view of the area starting at `(x, y)`, sharing memory with this image.
+            ///
+            /// Panics if the area doesn't fit in the image.
+            #[inline]
+            #[track_caller]
+            pub fn crop_ref(&self, x: usize, y: usize, w: usize, h: usize) -> ImageRef<'_, P> {
+                let offset = crop_offset(self.dims(), x, y, w, h);
+                // Safety: the area is inside of the image, and borrowed from `&self`
+                unsafe { ImageRef::from_raw(self.offset_ptr(offset), w, h, self.stride) }
+            }
+
+            /// Iterate over non-overlapping `tile_width` x `tile_height` views, row by row.
+            ///
+            /// Tiles at the right and bottom edges are smaller if the image isn't evenly divisible.
+            #[inline]
+            #[track_caller]
+            pub fn tiles(&self, tile_width: usize, tile_height: usize) -> Tiles<'_, P> {
+                Tiles {
+                    ptr: self.offset_ptr(0),
+                    cursor: TileCursor::new(self.dims(), tile_width, tile_height),
+                    _marker: PhantomData,
+                }
+            }
+
+            #[inline(always)]
+            fn dims(&self) -> (usize, usize, usize) {
+                (self.width, self.height, self.stride)
+            }
+
+            /// Pointer to a pixel known to be within (or one past) the image
+            #[inline(always)]
+            fn offset_ptr(&self, offset: usize) -> NonNull<P> {
+                // Safety: callers only use offsets of pixels inside of the image
+                unsafe { NonNull::new_unchecked(self.as_ptr().add(offset) as *mut P) }
+            }
+        }
+
+        /// `img[(x, y)]`
+        impl<P> Index<(usize, usize)> for $img {
+            type Output = P;
+
+            #[inline]
+            #[track_caller]
+            fn index(&self, (x, y): (usize, usize)) -> &P {
+                assert!(
+                    x < self.width,
+                    "x {} out of bounds (width {})",
+                    x,
+                    self.width
+                );
+                assert!(
+                    y < self.height,
+                    "y {} out of bounds (height {})",
+                    y,
+                    self.height
+                );
+                // Safety: bounds checked above
+                unsafe { &*self.as_ptr().add(y * self.stride + x) }
+            }
+        }
+    };
+}
+
+/// Methods shared by `Image` and `ImageMut`
+macro_rules! impl_img_write {
+    ($img:ty) => {
+        impl<P> $img {
+            /// Mutable pixel at `(x, y)`, or `None` if it's out of bounds
+            #[inline]
+            pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut P> {
+                if x < self.width && y < self.height {
+                    // Safety: bounds checked above
+                    Some(unsafe { &mut *self.as_mut_ptr().add(y * self.stride + x) })
+                } else {
+                    None
+                }
+            }
+
+            /// Mutable row `y`, exactly `width` pixels long, or `None` if it's out of bounds
+            #[inline]
+            pub fn row_mut(&mut self, y: usize) -> Option<&mut [P]> {
+                if y < self.height {
+                    // Safety: bounds checked above
+                    Some(unsafe {
+                        core::slice::from_raw_parts_mut(
+                            self.as_mut_ptr().add(y * self.stride),
+                            self.width,
+                        )
+                    })
+                } else {
+                    None
+                }
+            }
+
+            /// Iterate over mutable rows, each exactly `width` pixels long (stride padding is skipped)
+            #[inline]
+            pub fn rows_mut(&mut self) -> RowsMut<'_, P> {
+                RowsMut {
+                    ptr: self.as_mut_ptr(),
+                    width: self.width,
+                    stride: self.stride,
+                    remaining: self.height,
+                    _marker: PhantomData,
+                }
+            }
+
+            /// A mutable `w` x `h` view of the area starting at `(x, y)`, sharing memory with this image.
+            ///
+            /// Panics if the area doesn't fit in the image.
+            #[inline]
+            #[track_caller]
+            pub fn crop_mut(&mut self, x: usize, y: usize, w: usize, h: usize) -> ImageMut<'_, P> {
+                let offset = crop_offset(self.dims(), x, y, w, h);
+                // Safety: the area is inside of the image, and borrowed from `&mut self`
+                unsafe {
+                    let ptr = NonNull::new_unchecked(self.as_mut_ptr().add(offset));
+                    ImageMut::from_raw(ptr, w, h, self.stride)
+                }
+            }
+
+            /// Iterate over non-overlapping mutable `tile_width` x `tile_height` views, row by row.
+            ///
+            /// Tiles at the right and bottom edges are smaller if the image isn't evenly divisible.
+            /// The tiles are `Send`, so they can be processed on different threads.
+            #[inline]
+            #[track_caller]
+            pub fn tiles_mut(&mut self, tile_width: usize, tile_height: usize) -> TilesMut<'_, P> {
+                TilesMut {
+                    // Safety: the pointer comes from `&mut self`
+                    ptr: unsafe { NonNull::new_unchecked(self.as_mut_ptr()) },
+                    cursor: TileCursor::new(self.dims(), tile_width, tile_height),
+                    _marker: PhantomData,
+                }
+            }
+        }
+
+        /// `img[(x, y)] = px`
+        impl<P> IndexMut<(usize, usize)> for $img {
+            #[inline]
+            #[track_caller]
+            fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut P {
+                assert!(
+                    x < self.width,
+                    "x {} out of bounds (width {})",
+                    x,
+                    self.width
+                );
+                assert!(
+                    y < self.height,
+                    "y {} out of bounds (height {})",
+                    y,
+                    self.height
+                );
+                // Safety: bounds checked above
+                unsafe { &mut *self.as_mut_ptr().add(y * self.stride + x) }
+            }
+        }
+    };
+}
+
+impl_img_read! {Image<P>}
+impl_img_read! {ImageRef<'_, P>}
+impl_img_read! {ImageMut<'_, P>}
+
+impl_img_write! {Image<P>}
+impl_img_write! {ImageMut<'_, P>}
+
+/// Iterator over rows of an image. See `Image::rows()`
+pub struct Rows<'a, P> {
+    ptr: *const P,
+    width: usize,
+    stride: usize,
+    remaining: usize,
+    _marker: PhantomData<&'a [P]>,
+}
+
+unsafe impl<P: Sync> Send for Rows<'_, P> {}
+unsafe impl<P: Sync> Sync for Rows<'_, P> {}
+
+impl<'a, P> Iterator for Rows<'a, P> {
+    type Item = &'a [P];
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a [P]> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        // Safety: the row is inside of the image; the pointer only moves if there's another row
+        unsafe {
+            let row = core::slice::from_raw_parts(self.ptr, self.width);
+            if self.remaining > 0 {
+                self.ptr = self.ptr.add(self.stride);
+            }
+            Some(row)
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<P> ExactSizeIterator for Rows<'_, P> {}
+
+/// Iterator over mutable rows of an image. See `Image::rows_mut()`
+pub struct RowsMut<'a, P> {
+    ptr: *mut P,
+    width: usize,
+    stride: usize,
+    remaining: usize,
+    _marker: PhantomData<&'a mut [P]>,
+}
+
+unsafe impl<P: Send> Send for RowsMut<'_, P> {}
+unsafe impl<P: Sync> Sync for RowsMut<'_, P> {}
+
+impl<'a, P> Iterator for RowsMut<'a, P> {
+    type Item = &'a mut [P];
+
+    #[inline]
+    fn next(&mut self) -> Option<&'a mut [P]> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        // Safety: rows don't overlap, and each one is handed out only once
+        unsafe {
+            let row = core::slice::from_raw_parts_mut(self.ptr, self.width);
+            if self.remaining > 0 {
+                self.ptr = self.ptr.add(self.stride);
+            }
+            Some(row)
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<P> ExactSizeIterator for RowsMut<'_, P> {}
+
+/// Position of the next tile, shared by `Tiles` and `TilesMut`
+#[derive(Debug, Clone)]
+struct TileCursor {
+    width: usize,
+    height: usize,
+    stride: usize,
+    tile_width: usize,
+    tile_height: usize,
+    x: usize,
+    y: usize,
+}
+
+impl TileCursor {
+    #[inline]
+    #[track_caller]
+    fn new(
+        (width, height, stride): (usize, usize, usize),
+        tile_width: usize,
+        tile_height: usize,
+    ) -> Self {
+        assert!(tile_width > 0 && tile_height > 0, "tiles can't be empty");
+        Self {
+            width,
+            height,
+            stride,
+            tile_width,
+            tile_height,
+            x: 0,
+            // 空的图片没有 tiles
+            y: if width == 0 { height } else { 0 },
+        }
+    }
+
+    /// `(offset, width, height)` of the next tile
+    #[inline]
+    fn next(&mut self) -> Option<(usize, usize, usize)> {
+        if self.y >= self.height {
+            return None;
+        }
+        let w = self.tile_width.min(self.width - self.x);
+        let h = self.tile_height.min(self.height - self.y);
+        let offset = self.y * self.stride + self.x;
+
+        self.x += w;
+        if self.x >= self.width {
+            self.x = 0;
+            self.y += h;
+        }
+        Some((offset, w, h))
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        if self.y >= self.height {
+            return 0;
+        }
+        let cols = self.width.div_ceil(self.tile_width);
+        let rows = (self.height - self.y).div_ceil(self.tile_height);
+        rows * cols - self.x / self.tile_width
+    }
+}
+
+/// Iterator over read-only tiles of an image. See `Image::tiles()`
+pub struct Tiles<'a, P> {
+    ptr: NonNull<P>,
+    cursor: TileCursor,
+    _marker: PhantomData<&'a [P]>,
+}
+
+unsafe impl<P: Sync> Send for Tiles<'_, P> {}
+unsafe impl<P: Sync> Sync for Tiles<'_, P> {}
+
+impl<'a, P> Iterator for Tiles<'a, P> {
+    type Item = ImageRef<'a, P>;
+
+    #[inline]
+    fn next(&mut self) -> Option<ImageRef<'a, P>> {
+        let (offset, w, h) = self.cursor.next()?;
+        // Safety: the tile is inside of the image
+        unsafe {
+            let ptr = NonNull::new_unchecked(self.ptr.as_ptr().add(offset));
+            Some(ImageRef::from_raw(ptr, w, h, self.cursor.stride))
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.cursor.len();
+        (len, Some(len))
+    }
+}
+
+impl<P> ExactSizeIterator for Tiles<'_, P> {}
+
+/// Iterator over mutable tiles of an image. See `Image::tiles_mut()`
+pub struct TilesMut<'a, P> {
+    ptr: NonNull<P>,
+    cursor: TileCursor,
+    _marker: PhantomData<&'a mut [P]>,
+}
+
+unsafe impl<P: Send> Send for TilesMut<'_, P> {}
+unsafe impl<P: Sync> Sync for TilesMut<'_, P> {}
+
+impl<'a, P> Iterator for TilesMut<'a, P> {
+    type Item = ImageMut<'a, P>;
+
+    #[inline]
+    fn next(&mut self) -> Option<ImageMut<'a, P>> {
+        let (offset, w, h) = self.cursor.next()?;
+        // Safety: tiles don't overlap, and each one is handed out only once
+        unsafe {
+            let ptr = NonNull::new_unchecked(self.ptr.as_ptr().add(offset));
+            Some(ImageMut::from_raw(ptr, w, h, self.cursor.stride))
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.cursor.len();
+        (len, Some(len))
+    }
+}
+
+impl<P> ExactSizeIterator for TilesMut<'_, P> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FromSlice, RGB8, RGBA8};
+
+    #[test]
+    fn stride() {
+        // 2x3 image with 1 pixel of padding per row, and no padding after the last row
+        let buf: Vec<u8> = (0..8).collect();
+        let mut img = Image::new_stride(buf, 2, 3, 3);
+
+        assert_eq!(img[(1, 2)], 7);
+        assert_eq!(img.get(2, 0), None);
+        assert_eq!(img.get(0, 3), None);
+        assert_eq!(
+            img.rows().collect::<Vec<_>>(),
+            [&[0u8, 1][..], &[3, 4], &[6, 7]]
+        );
+
+        for row in img.rows_mut() {
+            row[0] = 100;
+        }
+        *img.get_mut(1, 0).unwrap() = 200;
+        assert_eq!(img.buf(), [100, 200, 2, 100, 4, 5, 100, 7]);
+    }
+
+    #[test]
+    fn views() {
+        let mut bytes = [0u8; 4 * 4];
+        {
+            let mut img = ImageMut::<RGBA8>::from_components(&mut bytes[..], 2, 2, 2);
+            img[(1, 1)] = RGBA8::new(1, 2, 3, 4);
+            assert_eq!(img.view().rows().len(), 2);
+        }
+        let img = ImageRef::new(bytes.as_rgba(), 2, 2);
+        assert_eq!(img[(1, 1)], RGBA8::new(1, 2, 3, 4));
+        assert_eq!(img.rows().flatten().filter(|px| px.a == 0).count(), 3);
+
+        let rgb = ImageRef::new(bytes.as_rgb(), 1, 5);
+        assert_eq!(rgb[(0, 4)], RGB8::new(1, 2, 3));
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_small() {
+        let _ = Image::new_stride(vec![0u8; 7], 2, 3, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "image size overflows usize")]
+    fn filled_overflow() {
+        // release 里乘法会回绕, 得到一个很小的 buffer
+        let _ = Image::filled(0u8, usize::MAX / 2 + 1, 2);
+    }
+
+    #[test]
+    fn empty() {
+        let img = Image::<u8>::new(vec![], 0, 5);
+        assert_eq!(img.rows().filter(|r| r.is_empty()).count(), 5);
+        assert_eq!(img.tiles(4, 4).count(), 0);
+        let img = Image::<u8>::new(vec![], 5, 0);
+        assert_eq!(img.rows().count(), 0);
+        assert_eq!(img.crop_ref(5, 0, 0, 0).rows().count(), 0);
+    }
+
+    #[test]
+    fn crop() {
+        let mut img = Image::new((0..20u8).collect(), 5, 4);
+        let sub = img.crop_ref(1, 1, 3, 2);
+        assert_eq!((sub.width(), sub.stride()), (3, 5));
+        assert_eq!(
+            sub.rows().collect::<Vec<_>>(),
+            [&[6u8, 7, 8][..], &[11, 12, 13]]
+        );
+        assert_eq!(sub.crop_ref(2, 1, 1, 1)[(0, 0)], 13);
+
+        let mut sub = img.crop_mut(3, 2, 2, 2);
+        sub.rows_mut().flatten().for_each(|px| *px = 0);
+        assert_eq!(img.row(2).unwrap(), [10, 11, 12, 0, 0]);
+        assert_eq!(img.row(3).unwrap(), [15, 16, 17, 0, 0]);
+    }
+
+    #[test]
+    fn split() {
+        let mut img = Image::filled(0u8, 4, 3);
+        let (top, bottom) = img.view_mut().split_at_row(1);
+        assert_eq!((top.height(), bottom.height()), (1, 2));
+        let (mut left, mut right) = bottom.split_at_col(3);
+        left.rows_mut().flatten().for_each(|px| *px = 1);
+        right.rows_mut().flatten().for_each(|px| *px = 2);
+        assert_eq!(img.buf(), [0, 0, 0, 0, 1, 1, 1, 2, 1, 1, 1, 2]);
+
+        let (a, b) = img.view().split_at_col(4);
+        assert_eq!((a.width(), b.width(), b.rows().len()), (4, 0, 3));
+    }
+
+    #[test]
+    fn parallel_tiles() {
+        let mut img = Image::new_stride(vec![RGB8::default(); 130 * 100], 129, 100, 130);
+        let tiles = img.tiles_mut(64, 64);
+        assert_eq!(tiles.len(), 6);
+
+        std::thread::scope(|s| {
+            for (i, mut tile) in tiles.enumerate() {
+                s.spawn(move || {
+                    for row in tile.rows_mut() {
+                        row.fill(RGB8::new(i as u8 + 1, 0, 0));
+                    }
+                });
+            }
+        });
+
+        assert_eq!(img[(0, 0)].r, 1);
+        assert_eq!(img[(64, 0)].r, 2);
+        assert_eq!(img[(128, 63)].r, 3);
+        assert_eq!(img[(128, 64)].r, 6);
+        assert_eq!(img.buf()[129], RGB8::default());
+        assert!(img.rows().flatten().all(|px| px.r != 0));
+    }
+}
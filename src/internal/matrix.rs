@@ -0,0 +1,418 @@
+use super::pixel::MathComponent;
+use crate::alt::*;
+use crate::{RGB, RGBA};
+use core::ops::Mul;
+
+/// Colour transform as a 4×5 matrix, like SVG `feColorMatrix` and Android `ColorMatrix`.
+///
+/// Rows compute `R`, `G`, `B`, `A`; columns are the input `R`, `G`, `B`, `A` and a constant offset.
+/// Components are normalized to `0..=1` first (integers are divided by their `MAX`),
+/// so the offsets are in that range too. Integer results are rounded and saturated;
+/// float results aren't clamped.
+///
+/// Pixels without alpha are transformed as if their alpha was `1`, and the alpha row is ignored.
+///
+/// ```rust
+/// use cr::{ColorMatrix, RGB8, RGBA8};
+///
+/// let adjust = ColorMatrix::saturate(0.5).then(&ColorMatrix::hue_rotate(90.));
+/// let px = adjust.apply(RGB8::new(200, 100, 50));
+///
+/// let mut pixels = [RGBA8::new(255, 0, 0, 255), RGBA8::new(0, 0, 0, 128)];
+/// ColorMatrix::invert(1.).apply_slice(&mut pixels);
+/// assert_eq!(pixels, [RGBA8::new(0, 255, 255, 255), RGBA8::new(255, 255, 255, 128)]);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ColorMatrix {
+    m: [[f32; 5]; 4],
+}
+
+/// Rec.709 luma weights used by SVG and CSS filters
+const LR: f32 = 0.213;
+const LG: f32 = 0.715;
+const LB: f32 = 0.072;
+
+impl ColorMatrix {
+    /// Leaves every colour unchanged
+    pub const IDENTITY: Self = Self {
+        m: [
+            [1., 0., 0., 0., 0.],
+            [0., 1., 0., 0., 0.],
+            [0., 0., 1., 0., 0.],
+            [0., 0., 0., 1., 0.],
+        ],
+    };
+
+    /// Full matrix, row by row
+    #[inline]
+    pub const fn from_4x5(m: [[f32; 5]; 4]) -> Self {
+        Self { m }
+    }
+
+    /// RGB-only matrix, without offsets. Alpha is left unchanged.
+    #[inline]
+    pub const fn from_3x3(m: [[f32; 3]; 3]) -> Self {
+        Self {
+            m: [
+                [m[0][0], m[0][1], m[0][2], 0., 0.],
+                [m[1][0], m[1][1], m[1][2], 0., 0.],
+                [m[2][0], m[2][1], m[2][2], 0., 0.],
+                [0., 0., 0., 1., 0.],
+            ],
+        }
+    }
+
+    /// The matrix, row by row
+    #[inline]
+    pub const fn to_4x5(&self) -> [[f32; 5]; 4] {
+        self.m
+    }
+
+    /// `0` is grayscale, `1` is unchanged, above `1` oversaturates
+    pub fn saturate(s: f32) -> Self {
+        Self::from_3x3([
+            [LR + (1. - LR) * s, LG - LG * s, LB - LB * s],
+            [LR - LR * s, LG + (1. - LG) * s, LB - LB * s],
+            [LR - LR * s, LG - LG * s, LB + (1. - LB) * s],
+        ])
+    }
+
+    /// Rotates hue by the angle in degrees, keeping luminance
+    pub fn hue_rotate(degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Self::from_3x3([
+            [
+                LR + cos * (1. - LR) - sin * LR,
+                LG - cos * LG - sin * LG,
+                LB - cos * LB + sin * (1. - LB),
+            ],
+            [
+                LR - cos * LR + sin * 0.143,
+                LG + cos * (1. - LG) + sin * 0.140,
+                LB - cos * LB - sin * 0.283,
+            ],
+            [
+                LR - cos * LR - sin * (1. - LR),
+                LG - cos * LG + sin * LG,
+                LB + cos * (1. - LB) + sin * LB,
+            ],
+        ])
+    }
+
+    /// `0` is unchanged, `1` is full sepia. The amount is clamped to `0..=1`.
+    pub fn sepia(amount: f32) -> Self {
+        let a = 1. - amount.clamp(0., 1.);
+        Self::from_3x3([
+            [0.393 + 0.607 * a, 0.769 - 0.769 * a, 0.189 - 0.189 * a],
+            [0.349 - 0.349 * a, 0.686 + 0.314 * a, 0.168 - 0.168 * a],
+            [0.272 - 0.272 * a, 0.534 - 0.534 * a, 0.131 + 0.869 * a],
+        ])
+    }
+
+    /// `0` is unchanged, `1` is fully gray. The amount is clamped to `0..=1`.
+    pub fn grayscale(amount: f32) -> Self {
+        let a = 1. - amount.clamp(0., 1.);
+        Self::from_3x3([
+            [
+                0.2126 + 0.7874 * a,
+                0.7152 - 0.7152 * a,
+                0.0722 - 0.0722 * a,
+            ],
+            [
+                0.2126 - 0.2126 * a,
+                0.7152 + 0.2848 * a,
+                0.0722 - 0.0722 * a,
+            ],
+            [
+                0.2126 - 0.2126 * a,
+                0.7152 - 0.7152 * a,
+                0.0722 + 0.9278 * a,
+            ],
+        ])
+    }
+
+    /// Multiplies colour components by `amount`
+    pub fn brightness(amount: f32) -> Self {
+        Self::from_3x3([[amount, 0., 0.], [0., amount, 0.], [0., 0., amount]])
+    }
+
+    /// Scales colour components around the middle gray: `0` is all gray, `1` is unchanged
+    pub fn contrast(amount: f32) -> Self {
+        let offset = 0.5 - 0.5 * amount;
+        Self::from_4x5([
+            [amount, 0., 0., 0., offset],
+            [0., amount, 0., 0., offset],
+            [0., 0., amount, 0., offset],
+            [0., 0., 0., 1., 0.],
+        ])
+    }
+
+    /// Multiplies alpha by `amount`, clamped to `0..=1`
+    pub fn opacity(amount: f32) -> Self {
+        let a = amount.clamp(0., 1.);
+        Self::from_4x5([
+            [1., 0., 0., 0., 0.],
+            [0., 1., 0., 0., 0.],
+            [0., 0., 1., 0., 0.],
+            [0., 0., 0., a, 0.],
+        ])
+    }
+
+    /// `0` is unchanged, `1` is the negative. The amount is clamped to `0..=1`.
+    pub fn invert(amount: f32) -> Self {
+        let a = amount.clamp(0., 1.);
+        let d = 1. - 2. * a;
+        Self::from_4x5([
+            [d, 0., 0., 0., a],
+            [0., d, 0., 0., a],
+            [0., 0., d, 0., a],
+            [0., 0., 0., 1., 0.],
+        ])
+    }
+
+    /// Matrix that applies `self` first, and then `next`
+    #[must_use]
+    pub fn then(&self, next: &Self) -> Self {
+        let (a, b) = (&next.m, &self.m);
+        let mut m = [[0f32; 5]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, out) in row.iter_mut().enumerate() {
+                *out = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+            }
+            row[4] += a[i][4];
+        }
+        Self { m }
+    }
+
+    /// Transform one pixel
+    #[inline]
+    pub fn apply<P: MatrixPixel>(&self, px: P) -> P {
+        px.with_rgba_f64(self.transform(px.to_rgba_f64()))
+    }
+
+    /// Transform normalized `R, G, B, A`
+    #[inline]
+    pub(crate) fn transform(&self, c: [f64; 4]) -> [f64; 4] {
+        core::array::from_fn(|i| {
+            let row = &self.m[i];
+            row[0] as f64 * c[0]
+                + row[1] as f64 * c[1]
+                + row[2] as f64 * c[2]
+                + row[3] as f64 * c[3]
+                + row[4] as f64
+        })
+    }
+
+    /// Transform every pixel in place
+    pub fn apply_slice<P: MatrixPixel>(&self, pixels: &mut [P]) {
+        for px in pixels {
+            *px = self.apply(*px);
+        }
+    }
+
+    /// Write transformed pixels into `dst`.
+    ///
+    /// Panics if `dst` has a different length.
+    #[track_caller]
+    pub fn apply_into<P: MatrixPixel>(&self, src: &[P], dst: &mut [P]) {
+        assert_eq!(
+            src.len(),
+            dst.len(),
+            "source and destination must have the same length"
+        );
+        for (d, &px) in dst.iter_mut().zip(src) {
+            *d = self.apply(px);
+        }
+    }
+}
+
+impl Default for ColorMatrix {
+    #[inline]
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// `a * b` applies `b` first, like multiplying matrices
+impl Mul for ColorMatrix {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, rhs: Self) -> Self {
+        rhs.then(&self)
+    }
+}
+
+/// Pixels that `ColorMatrix` can transform
+pub trait MatrixPixel: Copy {
+    /// `R, G, B, A` normalized to `0..=1`. Alpha is `1` if the pixel has none.
+    fn to_rgba_f64(self) -> [f64; 4];
+
+    /// Same pixel with new normalized components. Alpha is ignored if the pixel has none.
+    fn with_rgba_f64(self, c: [f64; 4]) -> Self;
+}
+
+macro_rules! matrix_pixel_impl {
+    ($typ:ident) => {
+        impl<T: MathComponent> MatrixPixel for $typ<T> {
+            #[inline]
+            fn to_rgba_f64(self) -> [f64; 4] {
+                let max = T::OPAQUE.to_f64();
+                [
+                    self.r.to_f64() / max,
+                    self.g.to_f64() / max,
+                    self.b.to_f64() / max,
+                    1.,
+                ]
+            }
+
+            #[inline]
+            fn with_rgba_f64(self, c: [f64; 4]) -> Self {
+                let max = T::OPAQUE.to_f64();
+                $typ {
+                    r: T::from_f64(c[0] * max),
+                    g: T::from_f64(c[1] * max),
+                    b: T::from_f64(c[2] * max),
+                }
+            }
+        }
+    };
+}
+
+macro_rules! matrix_pixel_alpha_impl {
+    ($typ:ident) => {
+        impl<T: MathComponent, A: MathComponent> MatrixPixel for $typ<T, A> {
+            #[inline]
+            fn to_rgba_f64(self) -> [f64; 4] {
+                let max = T::OPAQUE.to_f64();
+                [
+                    self.r.to_f64() / max,
+                    self.g.to_f64() / max,
+                    self.b.to_f64() / max,
+                    self.a.to_f64() / A::OPAQUE.to_f64(),
+                ]
+            }
+
+            #[inline]
+            fn with_rgba_f64(self, c: [f64; 4]) -> Self {
+                let max = T::OPAQUE.to_f64();
+                $typ {
+                    r: T::from_f64(c[0] * max),
+                    g: T::from_f64(c[1] * max),
+                    b: T::from_f64(c[2] * max),
+                    a: A::from_f64(c[3] * A::OPAQUE.to_f64()),
+                }
+            }
+        }
+    };
+}
+
+matrix_pixel_impl! {RGB}
+matrix_pixel_impl! {BGR}
+
+#[cfg(feature = "grb")]
+matrix_pixel_impl! {GRB}
+
+matrix_pixel_alpha_impl! {RGBA}
+matrix_pixel_alpha_impl! {BGRA}
+
+#[cfg(feature = "argb")]
+matrix_pixel_alpha_impl! {ARGB}
+
+#[cfg(feature = "argb")]
+matrix_pixel_alpha_impl! {ABGR}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RGB8, RGBA8};
+
+    fn assert_close(a: ColorMatrix, b: ColorMatrix) {
+        assert_close_eps(a, b, 1e-5);
+    }
+
+    fn assert_close_eps(a: ColorMatrix, b: ColorMatrix, eps: f32) {
+        for (ra, rb) in a.m.iter().zip(&b.m) {
+            for (x, y) in ra.iter().zip(rb) {
+                assert!((x - y).abs() < eps, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn builders() {
+        assert_close(ColorMatrix::saturate(1.), ColorMatrix::IDENTITY);
+        assert_close(ColorMatrix::hue_rotate(0.), ColorMatrix::IDENTITY);
+        assert_close(ColorMatrix::hue_rotate(360.), ColorMatrix::IDENTITY);
+        assert_close(ColorMatrix::sepia(0.), ColorMatrix::IDENTITY);
+        assert_close(ColorMatrix::grayscale(0.), ColorMatrix::IDENTITY);
+        assert_close(ColorMatrix::invert(0.), ColorMatrix::IDENTITY);
+        assert_close(
+            ColorMatrix::invert(1.).then(&ColorMatrix::invert(1.)),
+            ColorMatrix::IDENTITY,
+        );
+        // SVG 规范里的系数只有三位小数, 所以旋转只是近似可加
+        assert_close_eps(
+            ColorMatrix::hue_rotate(100.).then(&ColorMatrix::hue_rotate(20.)),
+            ColorMatrix::hue_rotate(120.),
+            1e-3,
+        );
+
+        let gray = ColorMatrix::saturate(0.).apply(RGB8::new(255, 0, 0));
+        assert_eq!((gray.r, gray.g, gray.b), (54, 54, 54));
+        assert_eq!(
+            ColorMatrix::grayscale(1.).apply(RGB::new(1f32, 1., 1.)),
+            RGB::new(1., 1., 1.)
+        );
+        assert_eq!(
+            ColorMatrix::sepia(1.).apply(RGB8::new(255, 255, 255)),
+            RGB8::new(255, 255, 239)
+        );
+    }
+
+    #[test]
+    fn composition_matches_sequential() {
+        let a = ColorMatrix::sepia(0.7);
+        let b = ColorMatrix::invert(0.3);
+        let c = ColorMatrix::from_4x5([
+            [1., 0., 0., 0.5, 0.],
+            [0., 1., 0., 0., 0.1],
+            [0., 0., 1., 0., 0.],
+            [0.1, 0., 0., 0.5, 0.],
+        ]);
+        let px = RGBA::new(0.2f64, 0.4, 0.6, 0.8);
+        let step = c.apply(b.apply(a.apply(px)));
+        let once = a.then(&b).then(&c).apply(px);
+        assert_close(c * b * a, a.then(&b).then(&c));
+        for (x, y) in [
+            (step.r, once.r),
+            (step.g, once.g),
+            (step.b, once.b),
+            (step.a, once.a),
+        ] {
+            assert!((x - y).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn layouts_and_slices() {
+        let m = ColorMatrix::from_4x5([
+            [0., 0., 1., 0., 0.],
+            [0., 1., 0., 0., 0.],
+            [1., 0., 0., 0., 0.],
+            [0., 0., 0., 0.5, 0.],
+        ]);
+        let src = [RGBA8::new(1, 2, 3, 255), RGBA8::new(10, 20, 30, 100)];
+        let mut dst = [RGBA8::default(); 2];
+        m.apply_into(&src, &mut dst);
+        assert_eq!(dst, [RGBA8::new(3, 2, 1, 128), RGBA8::new(30, 20, 10, 50)]);
+
+        let mut bgr = [BGR {
+            b: 3u16,
+            g: 2,
+            r: 1,
+        }];
+        m.apply_slice(&mut bgr);
+        assert_eq!(bgr, [BGR { b: 1, g: 2, r: 3 }]);
+    }
+}
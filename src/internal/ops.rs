@@ -1,7 +1,11 @@
 use super::pixel::*;
 use crate::alt::Gray;
 use crate::alt::GrayAlpha;
+use crate::alt::BGR;
+use crate::alt::BGRA;
 
+#[cfg(feature = "argb")]
+use crate::alt::ABGR;
 #[cfg(feature = "argb")]
 use crate::alt::ARGB;
 
@@ -11,182 +15,298 @@ use crate::alt::GRB;
 use crate::RGB;
 use crate::RGBA;
 
-use core::iter::Sum;
+use core::iter::{Product, Sum};
 use core::ops::*;
 
 macro_rules! impl_struct_ops_opaque {
     ($ty:ident => $($field:tt)+) => {
-        /// `px + px`
-        impl<T: Add> Add for $ty<T> {
-            type Output = $ty<<T as Add>::Output>;
+        impl_struct_ops!($ty<T> => $($field)+);
+    };
+}
+
+macro_rules! impl_struct_ops_alpha {
+    ($ty:ident => $($field:tt)+) => {
+        impl_struct_ops!($ty<T, A> => $($field)+);
+    };
+}
+
+/// Every pixel-by-pixel operator, for a pixel type with one (`T`) or two (`T, A`) component types
+macro_rules! impl_struct_ops {
+    ($ty:ident<$($g:ident),+> => $($field:tt)+) => {
+        impl_struct_op!(Add add AddAssign add_assign "`px + px`"; $ty<$($g),+> => $($field)+);
+        impl_struct_op!(Sub sub SubAssign sub_assign "`px - px`"; $ty<$($g),+> => $($field)+);
+        impl_struct_op!(Mul mul MulAssign mul_assign "`px * px`"; $ty<$($g),+> => $($field)+);
+        impl_struct_op!(Div div DivAssign div_assign "`px / px`"; $ty<$($g),+> => $($field)+);
+        impl_int_ops!($ty<$($g),+> => $($field)+);
+
+        /// `-px`
+        impl<$($g: Neg),+> Neg for $ty<$($g),+> {
+            type Output = $ty<$(<$g as Neg>::Output),+>;
 
             #[inline(always)]
-            fn add(self, other: $ty<T>) -> Self::Output {
+            fn neg(self) -> Self::Output {
                 $ty {
                     $(
-                        $field: self.$field + other.$field,
+                        $field: -self.$field,
                     )+
                 }
             }
         }
 
-        /// `px + px`
-        impl<T> AddAssign for $ty<T>
-        where
-            T: Add<Output = T> + Copy
-        {
-            #[inline(always)]
-            fn add_assign(&mut self, other: $ty<T>) {
-                *self = Self {
-                    $(
-                        $field: self.$field + other.$field,
-                    )+
-                };
-            }
-        }
-
-        /// `px * px`
-        impl<T: Mul> Mul for $ty<T> {
-            type Output = $ty<<T as Mul>::Output>;
+        /// `-&px`
+        impl<$($g: Neg + Copy),+> Neg for &$ty<$($g),+> {
+            type Output = $ty<$(<$g as Neg>::Output),+>;
 
             #[inline(always)]
-            fn mul(self, other: $ty<T>) -> Self::Output {
-                $ty {
-                    $(
-                        $field: self.$field * other.$field,
-                    )+
-                }
+            fn neg(self) -> Self::Output {
+                -*self
             }
         }
 
-        /// `px * px`
-        impl<T> MulAssign for $ty<T>
+        impl<$($g),+> Sum<$ty<$($g),+>> for $ty<$($g),+>
         where
-            T: Mul<Output = T> + Copy
+            $($g: Default + Add<Output = $g>),+
         {
             #[inline(always)]
-            fn mul_assign(&mut self, other: $ty<T>) {
-                *self = Self {
-                    $(
-                        $field: self.$field * other.$field,
-                    )+
-                };
+            fn sum<I: Iterator<Item=Self>>(iter: I) -> Self {
+                iter.fold($ty::default(), Add::add)
             }
         }
 
-        /// `px - px`
-        impl<T: Sub> Sub for $ty<T> {
-            type Output = $ty<<T as Sub>::Output>;
-
+        impl<'a, $($g),+> Sum<&'a $ty<$($g),+>> for $ty<$($g),+>
+        where
+            $($g: Default + Add<Output = $g> + Copy),+
+        {
             #[inline(always)]
-            fn sub(self, other: $ty<T>) -> Self::Output {
-                $ty {
-                    $(
-                        $field: self.$field - other.$field,
-                    )+
-                }
+            fn sum<I: Iterator<Item=&'a Self>>(iter: I) -> Self {
+                iter.copied().sum()
             }
         }
 
-        /// `px - px`
-        impl<T> SubAssign for $ty<T>
+        /// Starts from a pixel with all components set to `1`
+        impl<$($g),+> Product<$ty<$($g),+>> for $ty<$($g),+>
         where
-            T: Sub<Output = T> + Copy
+            $($g: From<u8> + Mul<Output = $g>),+
         {
             #[inline(always)]
-            fn sub_assign(&mut self, other: $ty<T>) {
-                *self = Self {
+            fn product<I: Iterator<Item=Self>>(iter: I) -> Self {
+                let one = $ty {
                     $(
-                        $field: self.$field - other.$field,
+                        $field: From::from(1u8),
                     )+
                 };
+                iter.fold(one, Mul::mul)
             }
         }
 
-        impl<T> Sum<$ty<T>> for $ty<T>
+        impl<'a, $($g),+> Product<&'a $ty<$($g),+>> for $ty<$($g),+>
         where
-            T: Default + Add<Output=T>
+            $($g: From<u8> + Mul<Output = $g> + Copy),+
         {
             #[inline(always)]
-            fn sum<I: Iterator<Item=Self>>(iter: I) -> Self {
-                iter.fold($ty::default(), Add::add)
+            fn product<I: Iterator<Item=&'a Self>>(iter: I) -> Self {
+                iter.copied().product()
             }
         }
     };
 }
 
-macro_rules! impl_struct_ops_alpha {
-    ($ty:ident => $($field:tt)+) => {
-        /// `px + px`
-        impl<T: Add, A: Add> Add for $ty<T, A> {
-            type Output = $ty<<T as Add>::Output, <A as Add>::Output>;
+/// One binary operator, its `&px` variant and its assigning variant
+macro_rules! impl_struct_op {
+    ($Op:ident $op:ident $OpAssign:ident $op_assign:ident $doc:literal; $ty:ident<$($g:ident),+> => $($field:tt)+) => {
+        #[doc = $doc]
+        impl<$($g: $Op),+> $Op for $ty<$($g),+> {
+            type Output = $ty<$(<$g as $Op>::Output),+>;
 
             #[inline(always)]
-            fn add(self, other: $ty<T, A>) -> Self::Output {
+            fn $op(self, other: $ty<$($g),+>) -> Self::Output {
                 $ty {
                     $(
-                        $field: self.$field + other.$field,
+                        $field: self.$field.$op(other.$field),
                     )+
                 }
             }
         }
 
-        /// `px + px`
-        impl<T, A> AddAssign for $ty<T, A>
+        #[doc = $doc]
+        impl<'a, $($g: $Op + Copy),+> $Op<&'a $ty<$($g),+>> for &'a $ty<$($g),+> {
+            type Output = $ty<$(<$g as $Op>::Output),+>;
+
+            #[inline(always)]
+            fn $op(self, other: &'a $ty<$($g),+>) -> Self::Output {
+                (*self).$op(*other)
+            }
+        }
+
+        #[doc = $doc]
+        impl<$($g),+> $OpAssign for $ty<$($g),+>
         where
-            T: Add<Output = T> + Copy,
-            A: Add<Output = A> + Copy
+            $($g: $Op<Output = $g> + Copy),+
         {
             #[inline(always)]
-            fn add_assign(&mut self, other: $ty<T, A>) {
+            fn $op_assign(&mut self, other: $ty<$($g),+>) {
                 *self = Self {
                     $(
-                        $field: self.$field + other.$field,
+                        $field: self.$field.$op(other.$field),
                     )+
                 };
             }
         }
+    };
+}
 
-        /// `px - px`
-        impl<T: Sub, A: Sub> Sub for $ty<T, A> {
-            type Output = $ty<<T as Sub>::Output, <A as Sub>::Output>;
+/// Integer component types, for overflow-aware arithmetic on pixels.
+///
+/// Implemented for all primitive integer types.
+pub trait IntegerComponent: Copy {
+    /// See `u8::saturating_add`
+    fn saturating_add(self, other: Self) -> Self;
+    /// See `u8::saturating_sub`
+    fn saturating_sub(self, other: Self) -> Self;
+    /// See `u8::saturating_mul`
+    fn saturating_mul(self, other: Self) -> Self;
+    /// See `u8::wrapping_add`
+    fn wrapping_add(self, other: Self) -> Self;
+    /// See `u8::wrapping_sub`
+    fn wrapping_sub(self, other: Self) -> Self;
+    /// See `u8::wrapping_mul`
+    fn wrapping_mul(self, other: Self) -> Self;
+    /// See `u8::checked_add`
+    fn checked_add(self, other: Self) -> Option<Self>;
+    /// See `u8::checked_sub`
+    fn checked_sub(self, other: Self) -> Option<Self>;
+    /// See `u8::checked_mul`
+    fn checked_mul(self, other: Self) -> Option<Self>;
+}
 
+macro_rules! integer_component_impl {
+    ($($t:ty)+) => {$(
+        impl IntegerComponent for $t {
             #[inline(always)]
-            fn sub(self, other: $ty<T, A>) -> Self::Output {
-                $ty {
-                    $(
-                        $field: self.$field - other.$field,
-                    )+
-                }
+            fn saturating_add(self, other: Self) -> Self { <$t>::saturating_add(self, other) }
+            #[inline(always)]
+            fn saturating_sub(self, other: Self) -> Self { <$t>::saturating_sub(self, other) }
+            #[inline(always)]
+            fn saturating_mul(self, other: Self) -> Self { <$t>::saturating_mul(self, other) }
+            #[inline(always)]
+            fn wrapping_add(self, other: Self) -> Self { <$t>::wrapping_add(self, other) }
+            #[inline(always)]
+            fn wrapping_sub(self, other: Self) -> Self { <$t>::wrapping_sub(self, other) }
+            #[inline(always)]
+            fn wrapping_mul(self, other: Self) -> Self { <$t>::wrapping_mul(self, other) }
+            #[inline(always)]
+            fn checked_add(self, other: Self) -> Option<Self> { <$t>::checked_add(self, other) }
+            #[inline(always)]
+            fn checked_sub(self, other: Self) -> Option<Self> { <$t>::checked_sub(self, other) }
+            #[inline(always)]
+            fn checked_mul(self, other: Self) -> Option<Self> { <$t>::checked_mul(self, other) }
+        }
+    )+};
+}
+
+integer_component_impl! {u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize}
+
+/// Pixel wrapper whose `+`, `-` and `*` saturate instead of overflowing,
+/// like `core::num::Saturating` does for integers.
+///
+/// Operators with a scalar apply it to every component, including alpha.
+///
+/// ```rust
+/// use cr::{Saturating, RGB8};
+///
+/// let px = Saturating(RGB8::new(100, 200, 250));
+/// assert_eq!((px + 10).0, RGB8::new(110, 210, 255));
+/// assert_eq!((px - Saturating(RGB8::new(150, 0, 0))).0, RGB8::new(0, 200, 250));
+/// ```
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(transparent)]
+pub struct Saturating<P>(pub P);
+
+/// One saturating binary operator for `Saturating<px>`, with a pixel and with a scalar
+macro_rules! impl_saturating_op {
+    ($Op:ident $op:ident $OpAssign:ident $op_assign:ident $method:ident; $ty:ident<$($g:ident),+> => $($field:tt)+) => {
+        impl<$($g: IntegerComponent),+> $Op for Saturating<$ty<$($g),+>> {
+            type Output = Self;
+
+            #[inline(always)]
+            fn $op(self, other: Self) -> Self {
+                Saturating(self.0.$method(other.0))
             }
         }
 
-        /// `px - px`
-        impl<T, A> SubAssign for $ty<T, A>
-        where
-            T: Sub<Output = T> + Copy,
-            A: Sub<Output = A> + Copy
-        {
+        impl<$($g: IntegerComponent),+> $OpAssign for Saturating<$ty<$($g),+>> {
             #[inline(always)]
-            fn sub_assign(&mut self, other: $ty<T, A>) {
-                *self = Self {
-                    $(
-                        $field: self.$field - other.$field,
-                    )+
-                };
+            fn $op_assign(&mut self, other: Self) {
+                self.0 = self.0.$method(other.0);
             }
         }
 
-        impl<T, A> Sum<$ty<T, A>> for $ty<T, A>
-        where
-            T: Default + Add<Output=T>,
-            A: Default + Add<Output=A>
-        {
+        impl<T: IntegerComponent> $Op<T> for Saturating<$ty<T>> {
+            type Output = Self;
+
             #[inline(always)]
-            fn sum<I: Iterator<Item=Self>>(iter: I) -> Self {
-                iter.fold($ty::default(), Add::add)
+            fn $op(self, r: T) -> Self {
+                Saturating(self.0.$method($ty { $($field: r),+ }))
             }
         }
+
+        impl<T: IntegerComponent> $OpAssign<T> for Saturating<$ty<T>> {
+            #[inline(always)]
+            fn $op_assign(&mut self, r: T) {
+                self.0 = self.0.$method($ty { $($field: r),+ });
+            }
+        }
+    };
+}
+
+/// Saturating, wrapping and checked methods, and `Saturating` operators
+macro_rules! impl_int_ops {
+    ($ty:ident<$($g:ident),+> => $($field:tt)+) => {
+        impl<$($g: IntegerComponent),+> $ty<$($g),+> {
+            int_method!(saturating_add "`px + px`, clamped to the range of the components"; $($field)+);
+            int_method!(saturating_sub "`px - px`, clamped to the range of the components"; $($field)+);
+            int_method!(saturating_mul "`px * px`, clamped to the range of the components"; $($field)+);
+            int_method!(wrapping_add "`px + px`, wrapping around on overflow"; $($field)+);
+            int_method!(wrapping_sub "`px - px`, wrapping around on overflow"; $($field)+);
+            int_method!(wrapping_mul "`px * px`, wrapping around on overflow"; $($field)+);
+            checked_int_method!(checked_add "`px + px`, or `None` if any component overflows"; $($field)+);
+            checked_int_method!(checked_sub "`px - px`, or `None` if any component overflows"; $($field)+);
+            checked_int_method!(checked_mul "`px * px`, or `None` if any component overflows"; $($field)+);
+        }
+
+        impl_saturating_op!(Add add AddAssign add_assign saturating_add; $ty<$($g),+> => $($field)+);
+        impl_saturating_op!(Sub sub SubAssign sub_assign saturating_sub; $ty<$($g),+> => $($field)+);
+        impl_saturating_op!(Mul mul MulAssign mul_assign saturating_mul; $ty<$($g),+> => $($field)+);
+    };
+}
+
+macro_rules! int_method {
+    ($method:ident $doc:literal; $($field:tt)+) => {
+        #[doc = $doc]
+        #[inline(always)]
+        #[must_use]
+        pub fn $method(self, other: Self) -> Self {
+            Self {
+                $(
+                    $field: IntegerComponent::$method(self.$field, other.$field),
+                )+
+            }
+        }
+    };
+}
+
+macro_rules! checked_int_method {
+    ($method:ident $doc:literal; $($field:tt)+) => {
+        #[doc = $doc]
+        #[inline(always)]
+        #[must_use]
+        pub fn $method(self, other: Self) -> Option<Self> {
+            Some(Self {
+                $(
+                    $field: IntegerComponent::$method(self.$field, other.$field)?,
+                )+
+            })
+        }
     };
 }
 
@@ -277,7 +397,7 @@ macro_rules! impl_scalar {
             }
         }
 
-        /// `px * 1`
+        /// `px / 1`
         impl<T> DivAssign<T> for $ty<T>
         where
             T: Copy + Div<Output = T>,
@@ -291,11 +411,16 @@ macro_rules! impl_scalar {
 }
 
 impl_scalar! {RGB}
+impl_scalar! {BGR}
 impl_scalar! {RGBA}
+impl_scalar! {BGRA}
 
 #[cfg(feature = "argb")]
 impl_scalar! {ARGB}
 
+#[cfg(feature = "argb")]
+impl_scalar! {ABGR}
+
 #[cfg(feature = "grb")]
 impl_scalar! {GRB}
 
@@ -303,6 +428,7 @@ impl_scalar! {Gray}
 impl_scalar! {GrayAlpha}
 
 impl_struct_ops_opaque! {RGB => r g b}
+impl_struct_ops_opaque! {BGR => b g r}
 
 #[cfg(feature = "grb")]
 impl_struct_ops_opaque! {GRB => g r b}
@@ -310,8 +436,131 @@ impl_struct_ops_opaque! {GRB => g r b}
 impl_struct_ops_opaque! {Gray => 0}
 
 impl_struct_ops_alpha! {RGBA => r g b a}
+impl_struct_ops_alpha! {BGRA => b g r a}
 
 #[cfg(feature = "argb")]
 impl_struct_ops_alpha! {ARGB => a r g b}
 
+#[cfg(feature = "argb")]
+impl_struct_ops_alpha! {ABGR => a b g r}
+
 impl_struct_ops_alpha! {GrayAlpha => 0 1}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks every operator on one pixel type against the same operator on its components
+    macro_rules! ops_test {
+        ($name:ident: $ty:ident { $($field:tt: $v:expr),+ }) => {
+            #[test]
+            #[allow(clippy::op_ref)]
+            fn $name() {
+                let a = $ty { $($field: $v as i32),+ };
+                let b = $ty { $($field: $v as i32 / 3 + 1),+ };
+
+                $(
+                    assert_eq!((a + b).$field, a.$field + b.$field);
+                    assert_eq!((a - b).$field, a.$field - b.$field);
+                    assert_eq!((a * b).$field, a.$field * b.$field);
+                    assert_eq!((a / b).$field, a.$field / b.$field);
+                    assert_eq!((-a).$field, -a.$field);
+                    assert_eq!((a + 2).$field, a.$field + 2);
+                    assert_eq!((a - 2).$field, a.$field - 2);
+                    assert_eq!((a * 2).$field, a.$field * 2);
+                    assert_eq!((a / 2).$field, a.$field / 2);
+                )+
+
+                assert_eq!(&a + &b, a + b);
+                assert_eq!(&a - &b, a - b);
+                assert_eq!(&a * &b, a * b);
+                assert_eq!(&a / &b, a / b);
+                assert_eq!(-&a, -a);
+
+                let mut c = a;
+                c += b;
+                c -= b;
+                c *= b;
+                c /= b;
+                assert_eq!(c, a);
+                c += 1;
+                c -= 1;
+                c *= 3;
+                c /= 3;
+                assert_eq!(c, a);
+
+                assert_eq!([a, b].iter().sum::<$ty<i32>>(), a + b);
+                assert_eq!([a, b, a].into_iter().sum::<$ty<i32>>(), a + b + a);
+                assert_eq!([a, b].iter().product::<$ty<i32>>(), a * b);
+                assert_eq!(core::iter::empty::<$ty<i32>>().product::<$ty<i32>>(), $ty { $($field: 1 + 0 * $v),+ });
+
+                let f = $ty { $($field: $v as f32),+ };
+                assert_eq!(f / f, $ty { $($field: 1f32 + 0. * $v as f32),+ });
+            }
+        };
+    }
+
+    ops_test!(rgb: RGB { r: 10, g: 20, b: 30 });
+    ops_test!(bgr: BGR { b: 10, g: 20, r: 30 });
+    ops_test!(gray: Gray { 0: 10 });
+    ops_test!(rgba: RGBA { r: 10, g: 20, b: 30, a: 40 });
+    ops_test!(bgra: BGRA { b: 10, g: 20, r: 30, a: 40 });
+    ops_test!(gray_alpha: GrayAlpha { 0: 10, 1: 40 });
+
+    #[cfg(feature = "grb")]
+    ops_test!(grb: GRB { g: 10, r: 20, b: 30 });
+
+    #[cfg(feature = "argb")]
+    ops_test!(argb: ARGB { a: 40, r: 10, g: 20, b: 30 });
+
+    #[cfg(feature = "argb")]
+    ops_test!(abgr: ABGR { a: 40, b: 10, g: 20, r: 30 });
+
+    #[test]
+    fn overflow() {
+        let a = RGBA::<u8>::new(250, 10, 128, 255);
+        let b = RGBA::<u8>::new(10, 20, 2, 1);
+
+        assert_eq!(a.saturating_add(b), RGBA::new(255, 30, 130, 255));
+        assert_eq!(a.saturating_sub(b), RGBA::new(240, 0, 126, 254));
+        assert_eq!(a.saturating_mul(b), RGBA::new(255, 200, 255, 255));
+        assert_eq!(a.wrapping_add(b), RGBA::new(4, 30, 130, 0));
+        assert_eq!(a.wrapping_sub(b), RGBA::new(240, 246, 126, 254));
+        assert_eq!(a.wrapping_mul(b), RGBA::new(196, 200, 0, 255));
+        assert_eq!(a.checked_add(b), None);
+        assert_eq!(a.checked_sub(b), None);
+        assert_eq!(b.checked_add(b), Some(RGBA::new(20, 40, 4, 2)));
+        assert_eq!(b.checked_mul(b), None);
+
+        let g = GrayAlpha(-100i8, 100u16);
+        assert_eq!(g.saturating_sub(GrayAlpha(100, 200)), GrayAlpha(-128, 0));
+        assert_eq!(
+            BGR {
+                b: 1u16,
+                g: 2,
+                r: 3
+            }
+            .checked_sub(BGR { b: 1, g: 2, r: 3 }),
+            Some(BGR { b: 0, g: 0, r: 0 })
+        );
+    }
+
+    #[test]
+    fn saturating_wrapper() {
+        let mut px = Saturating(RGB::<u8>::new(100, 200, 250));
+        assert_eq!((px + 10).0, RGB::new(110, 210, 255));
+        assert_eq!((px * 2).0, RGB::new(200, 255, 255));
+        assert_eq!(
+            (px - Saturating(RGB::new(150, 0, 0))).0,
+            RGB::new(0, 200, 250)
+        );
+        px += Saturating(RGB::new(200, 0, 0));
+        px -= 5;
+        px *= Saturating(RGB::new(1, 1, 2));
+        assert_eq!(px.0, RGB::new(250, 195, 255));
+
+        // brightening must not panic in debug builds
+        let bright: Vec<_> = (0..=255u8).map(|v| (Saturating(Gray(v)) + 100).0).collect();
+        assert_eq!(bright[200], Gray(255));
+    }
+}
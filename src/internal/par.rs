@@ -0,0 +1,270 @@
+use core::num::NonZeroUsize;
+
+/// How many threads the `ParallelPixels` operations may use, and how to split the work.
+///
+/// The slice is split into one contiguous chunk per thread. Chunk boundaries always fall on
+/// multiples of `row_len`, so with `row_len` set to the image width every thread gets whole rows.
+///
+/// Every pixel is processed exactly once by the same function regardless of the split,
+/// so the result doesn't depend on the number of threads.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Threads {
+    count: NonZeroUsize,
+    row_len: NonZeroUsize,
+    min_chunk_len: usize,
+}
+
+impl Threads {
+    /// Use at most `count` threads (including the calling thread). `0` is treated as `1`.
+    #[inline]
+    pub fn new(count: usize) -> Self {
+        Self {
+            count: NonZeroUsize::new(count).unwrap_or(NonZeroUsize::MIN),
+            row_len: NonZeroUsize::MIN,
+            min_chunk_len: 4096,
+        }
+    }
+
+    /// Use as many threads as `std::thread::available_parallelism()` reports
+    #[inline]
+    pub fn available() -> Self {
+        Self::new(std::thread::available_parallelism().map_or(1, NonZeroUsize::get))
+    }
+
+    /// Only split the slice at multiples of `row_len` pixels (usually the image width)
+    #[inline]
+    pub fn row_len(mut self, row_len: usize) -> Self {
+        self.row_len = NonZeroUsize::new(row_len).unwrap_or(NonZeroUsize::MIN);
+        self
+    }
+
+    /// Don't start a thread for less than `len` pixels. Defaults to 4096.
+    ///
+    /// Set it to `0` to always use all threads.
+    #[inline]
+    pub fn min_chunk_len(mut self, len: usize) -> Self {
+        self.min_chunk_len = len;
+        self
+    }
+
+    /// Number of threads
+    #[inline(always)]
+    pub fn count(&self) -> usize {
+        self.count.get()
+    }
+
+    /// Length of each chunk (the last one may be shorter)
+    fn chunk_len(&self, len: usize) -> usize {
+        let row_len = self.row_len.get();
+        let rows = len.div_ceil(row_len);
+        let rows_per_thread = rows.div_ceil(self.count.get()).max(1);
+        let min_rows = self.min_chunk_len.div_ceil(row_len).max(1);
+        rows_per_thread.max(min_rows) * row_len
+    }
+}
+
+impl Default for Threads {
+    #[inline]
+    fn default() -> Self {
+        Self::available()
+    }
+}
+
+/// Runs `f` on every chunk, the first chunk on the current thread
+fn for_each_chunk<P, F>(pixels: &mut [P], threads: Threads, f: F)
+where
+    P: Send,
+    F: Fn(&mut [P]) + Sync,
+{
+    let chunk_len = threads.chunk_len(pixels.len());
+    if pixels.len() <= chunk_len {
+        return f(pixels);
+    }
+    let f = &f;
+    std::thread::scope(|s| {
+        let mut chunks = pixels.chunks_mut(chunk_len);
+        let first = chunks.next();
+        for chunk in chunks {
+            s.spawn(move || f(chunk));
+        }
+        if let Some(chunk) = first {
+            f(chunk);
+        }
+    });
+}
+
+/// Runs `f` on every pair of chunks at the same position, the first pair on the current thread
+fn for_each_chunk_pair<P, Q, F>(dst: &mut [P], src: &[Q], threads: Threads, f: F)
+where
+    P: Send,
+    Q: Sync,
+    F: Fn(&mut [P], &[Q]) + Sync,
+{
+    assert_eq!(
+        dst.len(),
+        src.len(),
+        "source and destination must have the same length"
+    );
+    let chunk_len = threads.chunk_len(dst.len());
+    if dst.len() <= chunk_len {
+        return f(dst, src);
+    }
+    let f = &f;
+    std::thread::scope(|s| {
+        let mut chunks = dst.chunks_mut(chunk_len).zip(src.chunks(chunk_len));
+        let first = chunks.next();
+        for (d, s_) in chunks {
+            s.spawn(move || f(d, s_));
+        }
+        if let Some((d, s_)) = first {
+            f(d, s_);
+        }
+    });
+}
+
+/// Multi-threaded bulk operations on slices of pixels, using `std::thread::scope`.
+///
+/// ```rust
+/// use cr::{ComponentMap, ParallelPixels, Threads, RGBA8, RGBA16};
+///
+/// let mut frame = vec![RGBA8::new(10, 20, 30, 255); 1920 * 1080];
+/// frame.par_map(Threads::new(4).row_len(1920), |px| px.map(|c| c / 2));
+///
+/// let mut deep = vec![RGBA16::default(); frame.len()];
+/// frame.par_convert_into(&mut deep, Threads::new(4));
+/// assert_eq!(deep[0], RGBA16::new(5, 10, 15, 127));
+/// ```
+pub trait ParallelPixels<P> {
+    /// Replace every pixel with `f(pixel)`
+    fn par_map<F>(&mut self, threads: Threads, f: F)
+    where
+        F: Fn(P) -> P + Sync;
+
+    /// Write `f(pixel)` for every pixel into `dst`.
+    ///
+    /// Panics if `dst` has a different length.
+    fn par_map_into<Q, F>(&self, dst: &mut [Q], threads: Threads, f: F)
+    where
+        Q: Send,
+        F: Fn(P) -> Q + Sync;
+
+    /// Convert every pixel into `dst` using its `From` impl, e.g. `RGB8` to `RGB16` (depth conversion)
+    /// or `BGRA8` to `RGBA8` (swizzling).
+    ///
+    /// Panics if `dst` has a different length.
+    #[inline]
+    fn par_convert_into<Q>(&self, dst: &mut [Q], threads: Threads)
+    where
+        Q: From<P> + Send,
+    {
+        self.par_map_into(dst, threads, Q::from);
+    }
+
+    /// Replace every pixel with `f(pixel, src_pixel)`, where `src_pixel` is at the same index in `src`.
+    /// Use it to blend two images.
+    ///
+    /// Panics if `src` has a different length.
+    fn par_blend<F>(&mut self, src: &[P], threads: Threads, f: F)
+    where
+        F: Fn(P, P) -> P + Sync;
+}
+
+impl<P: Copy + Send + Sync> ParallelPixels<P> for [P] {
+    fn par_map<F>(&mut self, threads: Threads, f: F)
+    where
+        F: Fn(P) -> P + Sync,
+    {
+        for_each_chunk(self, threads, |chunk| {
+            for px in chunk {
+                *px = f(*px);
+            }
+        });
+    }
+
+    fn par_map_into<Q, F>(&self, dst: &mut [Q], threads: Threads, f: F)
+    where
+        Q: Send,
+        F: Fn(P) -> Q + Sync,
+    {
+        for_each_chunk_pair(dst, self, threads, |dst, src| {
+            for (d, s) in dst.iter_mut().zip(src) {
+                *d = f(*s);
+            }
+        });
+    }
+
+    fn par_blend<F>(&mut self, src: &[P], threads: Threads, f: F)
+    where
+        F: Fn(P, P) -> P + Sync,
+    {
+        for_each_chunk_pair(self, src, threads, |dst, src| {
+            for (d, s) in dst.iter_mut().zip(src) {
+                *d = f(*d, *s);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alt::BGRA8;
+    use crate::{ComponentMap, RGB16, RGB8, RGBA8};
+
+    fn pixels(len: usize) -> Vec<RGBA8> {
+        (0..len)
+            .map(|i| RGBA8::new(i as u8, (i >> 8) as u8, (i * 7) as u8, (i * 13) as u8))
+            .collect()
+    }
+
+    #[test]
+    fn chunks() {
+        let t = Threads::new(4).min_chunk_len(0);
+        assert_eq!(t.chunk_len(100), 25);
+        assert_eq!(t.row_len(30).chunk_len(100), 30);
+        assert_eq!(t.row_len(30).chunk_len(0), 30);
+        assert_eq!(Threads::new(0).min_chunk_len(0).chunk_len(100), 100);
+        assert_eq!(Threads::new(4).min_chunk_len(50).chunk_len(100), 50);
+    }
+
+    #[test]
+    fn same_as_single_thread() {
+        let src = pixels(10_007);
+        let invert = |px: RGBA8| px.map(|c| 255 - c);
+        let expected: Vec<_> = src.iter().copied().map(invert).collect();
+
+        for count in [1, 2, 3, 8, 64] {
+            let threads = Threads::new(count).min_chunk_len(0).row_len(101);
+
+            let mut mapped = src.clone();
+            mapped.par_map(threads, invert);
+            assert_eq!(mapped, expected);
+
+            let mut swizzled = vec![BGRA8::default(); src.len()];
+            src.par_convert_into(&mut swizzled, threads);
+            assert!(swizzled
+                .iter()
+                .zip(&src)
+                .all(|(b, r)| RGBA8::from(*b) == *r));
+
+            let rgb: Vec<RGB8> = src.iter().map(|px| px.rgb()).collect();
+            let mut deep = vec![RGB16::default(); src.len()];
+            rgb.par_convert_into(&mut deep, threads);
+            assert!(deep.iter().zip(&rgb).all(|(d, s)| *d == RGB16::from(*s)));
+
+            let mut blended = src.clone();
+            blended.par_blend(&expected, threads, |a, b| {
+                RGBA8::new(a.r / 2 + b.r / 2, a.g, b.b, a.a.max(b.a))
+            });
+            assert_eq!(blended[5000].g, src[5000].g);
+            assert_eq!(blended[5000].b, expected[5000].b);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn length_mismatch() {
+        let mut dst = [RGBA8::default(); 3];
+        pixels(4).par_convert_into(&mut dst[..], Threads::new(2));
+    }
+}
@@ -85,3 +85,273 @@ pub trait ColorComponentMap<DestPixel, SrcComponent, DestComponent> {
     where
         Callback: FnMut(SrcComponent) -> DestComponent;
 }
+
+/// Component types supported by `PixelMath`: `u8`, `u16`, `u32`, `i8`, `i16`, `i32`, `f32` and `f64`
+pub trait MathComponent: Copy + PartialOrd {
+    /// Value of a fully opaque alpha, e.g. `255` for `u8` and `1.0` for floats
+    const OPAQUE: Self;
+
+    /// Lossless for all supported types
+    fn to_f64(self) -> f64;
+
+    /// Rounds to nearest (half up) and saturates for integers
+    fn from_f64(v: f64) -> Self;
+
+    /// `self + (other - self) * t`
+    #[inline]
+    fn lerp(self, other: Self, t: f64) -> Self {
+        let a = self.to_f64();
+        Self::from_f64(a + (other.to_f64() - a) * t)
+    }
+
+    /// `(self + other) / 2`, rounding half up for integers
+    fn avg(self, other: Self) -> Self;
+
+    /// `|self - other|`, saturating for signed integers
+    fn abs_diff(self, other: Self) -> Self;
+}
+
+macro_rules! math_int_impl {
+    ($($t:ty)+) => {$(
+        impl MathComponent for $t {
+            const OPAQUE: Self = <$t>::MAX;
+
+            #[inline(always)]
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            #[inline(always)]
+            fn from_f64(v: f64) -> Self {
+                // 四舍五入 (half up, 与 `avg` 一致); `as` 会饱和 (saturate), NaN 变成 0
+                (v + 0.5).floor() as $t
+            }
+
+            #[inline(always)]
+            fn avg(self, other: Self) -> Self {
+                ((self as i64 + other as i64 + 1) >> 1) as $t
+            }
+
+            #[inline(always)]
+            fn abs_diff(self, other: Self) -> Self {
+                (self as i64 - other as i64).unsigned_abs().min(<$t>::MAX as u64) as $t
+            }
+        }
+    )+};
+}
+
+macro_rules! math_float_impl {
+    ($($t:ty)+) => {$(
+        impl MathComponent for $t {
+            const OPAQUE: Self = 1.;
+
+            #[inline(always)]
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            #[inline(always)]
+            fn from_f64(v: f64) -> Self {
+                v as $t
+            }
+
+            #[inline(always)]
+            fn avg(self, other: Self) -> Self {
+                (self + other) * 0.5
+            }
+
+            #[inline(always)]
+            fn abs_diff(self, other: Self) -> Self {
+                (self - other).abs()
+            }
+        }
+    )+};
+}
+
+math_int_impl! {u8 u16 u32 i8 i16 i32}
+math_float_impl! {f32 f64}
+
+/// Component-wise math on two pixels.
+///
+/// Alpha is treated like any other component, except in `mix`.
+/// `min`, `max` and `clamp` are called `min_each`, `max_each` and `clamp_each`,
+/// because pixels already implement `Ord`, which compares them lexicographically.
+///
+/// ```rust
+/// use cr::{PixelMath, RGB8, RGBA8};
+///
+/// let a = RGB8::new(0, 100, 255);
+/// let b = RGB8::new(255, 101, 0);
+/// assert_eq!(a.lerp(b, 0.5), RGB8::new(128, 101, 128));
+/// assert_eq!(a.avg(b), RGB8::new(128, 101, 128));
+/// assert_eq!(a.abs_diff(b), RGB8::new(255, 1, 255));
+/// assert_eq!(a.clamp_each(10, 200), RGB8::new(10, 100, 200));
+///
+/// // transparent pixels don't darken the mix
+/// let red = RGBA8::new(255, 0, 0, 255);
+/// let clear = RGBA8::new(0, 0, 0, 0);
+/// assert_eq!(red.mix(clear, 0.5), RGBA8::new(255, 0, 0, 128));
+/// ```
+pub trait PixelMath<T: MathComponent>: ComponentSlice<T> + Copy {
+    /// Linear interpolation, `self` at `t = 0` and `other` at `t = 1`
+    #[inline]
+    fn lerp(self, other: Self, t: f64) -> Self {
+        zip_components(self, other, |a, b| MathComponent::lerp(a, b, t))
+    }
+
+    /// Smaller of each pair of components
+    #[inline]
+    fn min_each(self, other: Self) -> Self {
+        zip_components(self, other, |a, b| if b < a { b } else { a })
+    }
+
+    /// Larger of each pair of components
+    #[inline]
+    fn max_each(self, other: Self) -> Self {
+        zip_components(self, other, |a, b| if b > a { b } else { a })
+    }
+
+    /// Every component limited to `lo..=hi`
+    #[inline]
+    fn clamp_each(mut self, lo: T, hi: T) -> Self {
+        for c in self.as_mut_slice() {
+            if *c < lo {
+                *c = lo;
+            } else if *c > hi {
+                *c = hi;
+            }
+        }
+        self
+    }
+
+    /// Absolute difference of each pair of components
+    #[inline]
+    fn abs_diff(self, other: Self) -> Self {
+        zip_components(self, other, MathComponent::abs_diff)
+    }
+
+    /// Average of each pair of components
+    #[inline]
+    fn avg(self, other: Self) -> Self {
+        zip_components(self, other, MathComponent::avg)
+    }
+
+    /// Like `lerp`, but pixels with alpha are interpolated with premultiplied colours,
+    /// so that a transparent pixel's colour doesn't bleed into the result
+    #[inline]
+    fn mix(self, other: Self, t: f64) -> Self {
+        self.lerp(other, t)
+    }
+}
+
+#[inline(always)]
+fn zip_components<P, T>(mut a: P, b: P, mut f: impl FnMut(T, T) -> T) -> P
+where
+    P: ComponentSlice<T>,
+    T: Copy,
+{
+    for (a, &b) in a.as_mut_slice().iter_mut().zip(b.as_slice()) {
+        *a = f(*a, b);
+    }
+    a
+}
+
+/// Interpolates `color` towards `other` in premultiplied space, and returns the new alpha
+#[inline]
+pub(crate) fn mix_premultiplied<T: MathComponent, const N: usize>(
+    color: &mut [T; N],
+    other: [T; N],
+    alpha: T,
+    other_alpha: T,
+    t: f64,
+) -> T {
+    let opaque = T::OPAQUE.to_f64();
+    let (a1, a2) = (alpha.to_f64() / opaque, other_alpha.to_f64() / opaque);
+    let a = a1 + (a2 - a1) * t;
+    for (c, o) in color.iter_mut().zip(other) {
+        *c = if a > 0. {
+            let (c1, c2) = (c.to_f64() * a1, o.to_f64() * a2);
+            T::from_f64((c1 + (c2 - c1) * t) / a)
+        } else {
+            // 完全透明, 颜色没有意义, 退回到普通插值
+            MathComponent::lerp(*c, o, t)
+        };
+    }
+    T::from_f64(a * opaque)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alt::{Gray, GrayAlpha, BGRA};
+    use crate::{RGB, RGBA};
+
+    #[test]
+    fn integer_rounding() {
+        assert_eq!(MathComponent::lerp(0u8, 255, 0.5), 128);
+        assert_eq!(MathComponent::lerp(0u8, 1, 0.49), 0);
+        assert_eq!(MathComponent::lerp(10u8, 20, 2.), 30);
+        assert_eq!(MathComponent::lerp(200u8, 250, 2.), 255);
+        assert_eq!(MathComponent::lerp(-128i8, 127, 0.5), 0);
+        assert_eq!(MathComponent::avg(255u8, 254), 255);
+        assert_eq!(MathComponent::avg(-3i8, -4), -3);
+        assert_eq!(MathComponent::avg(u32::MAX, u32::MAX), u32::MAX);
+        assert_eq!(MathComponent::abs_diff(-128i8, 127), 127);
+        assert_eq!(MathComponent::abs_diff(3u16, 65535), 65532);
+
+        for t in 0..=10 {
+            let t = t as f64 / 10.;
+            for (a, b) in [(0u8, 255u8), (17, 3), (100, 100)] {
+                let expected = (a as f64 + (b as f64 - a as f64) * t).round();
+                assert_eq!(MathComponent::lerp(a, b, t) as f64, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn component_wise() {
+        let a = RGB::new(1f32, 5., -2.);
+        let b = RGB::new(3f32, 1., 2.);
+        assert_eq!(a.lerp(b, 0.25), RGB::new(1.5, 4., -1.));
+        assert_eq!(a.min_each(b), RGB::new(1., 1., -2.));
+        assert_eq!(a.max_each(b), RGB::new(3., 5., 2.));
+        assert_eq!(a.clamp_each(0., 1.), RGB::new(1., 1., 0.));
+        assert_eq!(a.abs_diff(b), RGB::new(2., 4., 4.));
+        assert_eq!(a.avg(b), RGB::new(2., 3., 0.));
+        assert_eq!(Gray(7u16).mix(Gray(8), 0.5), Gray(8));
+        assert_eq!(
+            RGBA::new(0u8, 0, 0, 0).max_each(RGBA::new(1, 0, 2, 3)),
+            RGBA::new(1, 0, 2, 3)
+        );
+    }
+
+    #[test]
+    fn premultiplied_mix() {
+        let red = RGBA::new(255u8, 0, 0, 255);
+        let clear_blue = RGBA::new(0u8, 0, 255, 0);
+        assert_eq!(red.lerp(clear_blue, 0.5), RGBA::new(128, 0, 128, 128));
+        assert_eq!(red.mix(clear_blue, 0.5), RGBA::new(255, 0, 0, 128));
+        assert_eq!(red.mix(clear_blue, 0.), red);
+        assert_eq!(red.mix(clear_blue, 1.), RGBA::new(0, 0, 255, 0));
+        assert_eq!(clear_blue.mix(clear_blue, 0.5), clear_blue);
+
+        // 半透明: 颜色按 alpha 加权
+        let a = GrayAlpha(1f64, 0.25);
+        let b = GrayAlpha(0f64, 0.75);
+        assert_eq!(a.mix(b, 0.5), GrayAlpha(0.25, 0.5));
+
+        let bgra = BGRA {
+            b: 0u8,
+            g: 0,
+            r: 255,
+            a: 255,
+        };
+        let clear = BGRA {
+            b: 0u8,
+            g: 0,
+            r: 0,
+            a: 0,
+        };
+        assert_eq!(bgra.mix(clear, 0.5).r, 255);
+    }
+}
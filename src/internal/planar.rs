@@ -0,0 +1,213 @@
+use crate::alt::*;
+use crate::{RGB, RGBA};
+use core::mem;
+use core::slice;
+
+/// Splits interleaved pixels into one plane per channel, and merges planes back.
+///
+/// Planes are in the order of the components in memory, e.g. `R, G, B, A` for `RGBA`
+/// and `A, R, G, B` for `ARGB`.
+///
+/// ```rust
+/// use cr::{PlanarPixels, RGB8};
+///
+/// let frame = [RGB8::new(1, 2, 3), RGB8::new(4, 5, 6)];
+///
+/// // CHW tensor for ML: one contiguous buffer with all of R, then G, then B
+/// let mut chw = vec![0f32; 3 * frame.len()];
+/// let (r, gb) = chw.split_at_mut(frame.len());
+/// let (g, b) = gb.split_at_mut(frame.len());
+/// frame.to_planes_into([r, g, b]);
+/// assert_eq!(chw, [1., 4., 2., 5., 3., 6.]);
+///
+/// let [r, g, b] = frame.to_planes();
+/// assert_eq!((r, g, b), (vec![1, 4], vec![2, 5], vec![3, 6]));
+/// ```
+pub trait PlanarPixels<T, const N: usize> {
+    /// Copy every channel into its own `Vec`
+    fn to_planes(&self) -> [Vec<T>; N];
+
+    /// Copy every channel into caller-provided planes, converting components with `From`
+    /// (e.g. `u8` to `f32`).
+    ///
+    /// Panics if any plane has a different length than the slice.
+    fn to_planes_into<U: From<T>>(&self, planes: [&mut [U]; N]);
+
+    /// Overwrite every pixel with components from the planes, converting them with `From`.
+    ///
+    /// Panics if any plane has a different length than the slice.
+    fn fill_from_planes<U: Copy>(&mut self, planes: [&[U]; N])
+    where
+        T: From<U>;
+}
+
+/// Creates a `Vec` of interleaved pixels from planes. See `PlanarPixels`.
+pub trait FromPlanes<T, const N: usize> {
+    /// Interleave the planes into a new `Vec` of pixels.
+    ///
+    /// Panics if the planes have different lengths.
+    fn from_planes(planes: [&[T]; N]) -> Self;
+}
+
+#[inline]
+#[track_caller]
+fn check_planes(len: usize, plane_lens: impl Iterator<Item = usize>) {
+    for plane_len in plane_lens {
+        assert_eq!(
+            len, plane_len,
+            "planes must have the same length as the pixels"
+        );
+    }
+}
+
+macro_rules! planar_impl {
+    ($typ:ident, $n:expr) => {
+        impl<T: Copy> PlanarPixels<T, $n> for [$typ<T>] {
+            fn to_planes(&self) -> [Vec<T>; $n] {
+                let components = as_components(self);
+                core::array::from_fn(|ch| components.iter().skip(ch).step_by($n).copied().collect())
+            }
+
+            #[track_caller]
+            fn to_planes_into<U: From<T>>(&self, mut planes: [&mut [U]; $n]) {
+                check_planes(self.len(), planes.iter().map(|p| p.len()));
+                let components = as_components(self);
+                for (i, px) in components.chunks_exact($n).enumerate() {
+                    for (plane, &c) in planes.iter_mut().zip(px) {
+                        plane[i] = U::from(c);
+                    }
+                }
+            }
+
+            #[track_caller]
+            fn fill_from_planes<U: Copy>(&mut self, planes: [&[U]; $n])
+            where
+                T: From<U>,
+            {
+                check_planes(self.len(), planes.iter().map(|p| p.len()));
+                let components = as_components_mut(self);
+                for (i, px) in components.chunks_exact_mut($n).enumerate() {
+                    for (c, plane) in px.iter_mut().zip(&planes) {
+                        *c = T::from(plane[i]);
+                    }
+                }
+            }
+        }
+
+        impl<T: Copy + Default> FromPlanes<T, $n> for Vec<$typ<T>> {
+            #[track_caller]
+            fn from_planes(planes: [&[T]; $n]) -> Self {
+                let mut pixels = vec![$typ::default(); planes[0].len()];
+                pixels.fill_from_planes(planes);
+                pixels
+            }
+        }
+    };
+}
+
+/// Components of all pixels, in memory order
+#[inline(always)]
+fn as_components<P, T>(pixels: &[P]) -> &[T] {
+    // Safety: only used with repr(C) pixels made of `T`
+    unsafe {
+        slice::from_raw_parts(
+            pixels.as_ptr() as *const T,
+            mem::size_of_val(pixels) / mem::size_of::<T>(),
+        )
+    }
+}
+
+#[inline(always)]
+fn as_components_mut<P, T>(pixels: &mut [P]) -> &mut [T] {
+    // Safety: only used with repr(C) pixels made of `T`
+    unsafe {
+        slice::from_raw_parts_mut(
+            pixels.as_mut_ptr() as *mut T,
+            mem::size_of_val(pixels) / mem::size_of::<T>(),
+        )
+    }
+}
+
+planar_impl! {RGB, 3}
+planar_impl! {RGBA, 4}
+planar_impl! {BGR, 3}
+planar_impl! {BGRA, 4}
+
+#[cfg(feature = "grb")]
+planar_impl! {GRB, 3}
+
+planar_impl! {Gray, 1}
+planar_impl! {GrayAlpha, 2}
+
+#[cfg(feature = "argb")]
+planar_impl! {ARGB, 4}
+
+#[cfg(feature = "argb")]
+planar_impl! {ABGR, 4}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RGB8, RGBA16};
+
+    #[test]
+    fn round_trip() {
+        let pixels: Vec<RGBA16> = (0..100u16)
+            .map(|i| RGBA16::new(i, i * 2, i * 3, 65535 - i))
+            .collect();
+        let planes = pixels.to_planes();
+        assert_eq!(planes[3][1], 65534);
+        assert_eq!(planes[2][99], 297);
+
+        let merged = Vec::<RGBA16>::from_planes([&planes[0], &planes[1], &planes[2], &planes[3]]);
+        assert_eq!(merged, pixels);
+    }
+
+    #[test]
+    fn gray_and_bgr() {
+        let ga = [GrayAlpha(1u8, 2), GrayAlpha(3, 4)];
+        let [g, a] = ga.to_planes();
+        assert_eq!((g, a), (vec![1, 3], vec![2, 4]));
+
+        let mut bgr = [BGR8::default(); 2];
+        bgr.fill_from_planes([&[1u8, 2][..], &[3, 4], &[5, 6]]);
+        assert_eq!(bgr[1], BGR { b: 2, g: 4, r: 6 });
+
+        let [gray] = [Gray(7u8)].to_planes();
+        assert_eq!(gray, [7]);
+    }
+
+    #[test]
+    fn converts_components() {
+        let rgb = [RGB8::new(255, 0, 128)];
+        let (mut r, mut g, mut b) = ([0f32], [0f32], [0f32]);
+        rgb.to_planes_into([&mut r, &mut g, &mut b]);
+        assert_eq!((r, g, b), ([255.], [0.], [128.]));
+
+        let mut wide = [RGB::<u16>::default()];
+        wide.fill_from_planes([&[1u8][..], &[2], &[3]]);
+        assert_eq!(wide[0], RGB::new(1, 2, 3));
+    }
+
+    #[test]
+    #[cfg(feature = "argb")]
+    fn alpha_first() {
+        let [a, r, g, b] = [ARGB8 {
+            a: 4,
+            r: 1,
+            g: 2,
+            b: 3,
+        }]
+        .to_planes();
+        assert_eq!((a, r, g, b), (vec![4], vec![1], vec![2], vec![3]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn wrong_length() {
+        let mut r = [0u8; 1];
+        let mut g = [0u8; 2];
+        let mut b = [0u8; 2];
+        [RGB8::default(); 2].to_planes_into([&mut r, &mut g, &mut b]);
+    }
+}
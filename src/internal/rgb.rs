@@ -19,6 +19,7 @@ impl<T> RGB<T> {
 }
 
 impl<T> BGR<T> {
+    /// Arguments are in memory order: B, G, R
     #[inline(always)]
     pub const fn new(b: T, g: T, r: T) -> Self {
         Self { b, g, r }
@@ -105,6 +106,8 @@ macro_rules! impl_rgb {
 
         #[cfg(feature = "as-bytes")]
         impl<T: crate::Pod> ComponentBytes<T> for [$RGB<T>] {}
+
+        impl<T: MathComponent> PixelMath<T> for $RGB<T> {}
     };
 }
 
@@ -200,8 +203,8 @@ impl<T: fmt::LowerHex> fmt::LowerHex for BGR<T> {
 
 #[cfg(test)]
 mod rgb_tests {
+    #[cfg(feature = "grb")]
     use super::*;
-    use std;
 
     #[test]
     #[cfg(feature = "grb")]
@@ -281,6 +281,20 @@ macro_rules! impl_rgba {
 
         #[cfg(feature = "as-bytes")]
         impl<T: crate::Pod> ComponentBytes<T> for [$RGBA<T>] {}
+
+        impl<T: MathComponent> PixelMath<T> for $RGBA<T> {
+            #[inline]
+            fn mix(self, other: Self, t: f64) -> Self {
+                let mut c = [self.r, self.g, self.b];
+                let a = mix_premultiplied(&mut c, [other.r, other.g, other.b], self.a, other.a, t);
+                $RGBA {
+                    r: c[0],
+                    g: c[1],
+                    b: c[2],
+                    a,
+                }
+            }
+        }
     };
 }
 
@@ -1,6 +1,8 @@
 #[allow(unused)]
 #[allow(clippy::upper_case_acronyms)]
 pub mod alt;
+pub mod formats;
+pub mod swizzle;
 
 #[cfg(feature = "as-bytes")]
 pub use bytemuck::Pod;
@@ -9,14 +11,37 @@ pub use bytemuck::Pod;
 pub use bytemuck::Zeroable;
 
 mod internal {
+    pub mod bulk;
     pub mod convert;
+    pub mod dynamic;
+    pub mod filter;
+    pub mod format;
+    pub mod gray;
+    pub mod img;
+    pub mod matrix;
     pub mod ops;
+    pub mod par;
     pub mod pixel;
+    pub mod planar;
     pub mod rgb;
     pub mod rgba;
 }
 
+pub use crate::internal::bulk::*;
+pub use crate::internal::convert::*;
+pub use crate::internal::dynamic::*;
+pub use crate::internal::filter::*;
+pub use crate::internal::format::*;
+pub use crate::internal::gray::*;
+pub use crate::internal::img::*;
+pub use crate::internal::matrix::*;
+pub use crate::internal::ops::*;
+pub use crate::internal::par::*;
+pub use crate::internal::pixel::*;
+pub use crate::internal::planar::*;
+
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(C)]
 pub struct RGB<T> {
     /// Red
     pub r: T,
@@ -27,6 +52,7 @@ pub struct RGB<T> {
 }
 
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(C)]
 pub struct RGBA<T, TA = T> {
     /// Red
     pub r: T,
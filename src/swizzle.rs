@@ -0,0 +1,289 @@
+//! Shader-style channel swizzles.
+//!
+//! Channels are picked with the marker types `R`, `G`, `B` and `A`. The result has as many
+//! components as the pattern: `Gray` for one, `GrayAlpha` for two, `RGB` for three and `RGBA`
+//! for four, filled in that order. Channels can be repeated or left out, and any colour layout
+//! can be the source. Picking a channel the source doesn't have (e.g. `A` from `RGB`)
+//! is a compile error.
+//!
+//! Everything is resolved at compile time and inlined, so a swizzle compiles to plain moves.
+//!
+//! Trait methods can't be called in `const` contexts, so every pixel type also has
+//! `const fn` versions named after the output: `swizzle_gray`, `swizzle_gray_alpha`,
+//! `swizzle_rgb` and `swizzle_rgba`. They take channel indices instead of markers:
+//! 0 is red, 1 green, 2 blue and 3 alpha. `Gray` and `GrayAlpha` give their value for red,
+//! green and blue alike.
+//!
+//! ```rust
+//! use cr::alt::{Gray, GrayAlpha};
+//! use cr::RGBA;
+//!
+//! const PX: RGBA<u8> = RGBA::new(1, 2, 3, 4);
+//! const BGRA: RGBA<u8> = PX.swizzle_rgba::<2, 1, 0, 3>();
+//! assert_eq!(BGRA, RGBA::new(3, 2, 1, 4));
+//! const WHITE: RGBA<u8> = GrayAlpha(255, 128).swizzle_rgba::<0, 0, 0, 3>();
+//! assert_eq!(WHITE, RGBA::new(255, 255, 255, 128));
+//! assert_eq!(Gray(7u8).swizzle_gray_alpha::<0, 0>(), GrayAlpha(7, 7));
+//! ```
+//!
+//! As with the markers, an index the source doesn't have fails to compile:
+//!
+//! ```rust,compile_fail
+//! let _ = cr::RGB::new(1u8, 2, 3).swizzle_gray::<3>();
+//! ```
+//!
+//! ```rust
+//! use cr::alt::{Gray, BGRA};
+//! use cr::swizzle::{Swizzle, A, B, G, R};
+//! use cr::{RGB, RGBA};
+//!
+//! let px = RGBA::new(1u8, 2, 3, 4);
+//! assert_eq!(px.swizzle::<(B, G, R, A)>(), RGBA::new(3, 2, 1, 4));
+//! assert_eq!(px.swizzle::<(R, R, R)>(), RGB::new(1, 1, 1));
+//! assert_eq!(px.swizzle::<(A,)>(), Gray(4));
+//!
+//! let bgra = BGRA { b: 3u8, g: 2, r: 1, a: 4 };
+//! assert_eq!(bgra.swizzle::<(G, B, R, A)>(), RGBA::new(2, 3, 1, 4));
+//! ```
+
+use crate::alt::*;
+use crate::{RGB, RGBA};
+
+/// Red channel
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct R;
+/// Green channel
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct G;
+/// Blue channel
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct B;
+/// Alpha channel
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct A;
+
+/// A channel marker that can be read from pixel `P`
+pub trait Channel<P, T> {
+    /// Value of the channel
+    fn get(px: &P) -> T;
+}
+
+/// A tuple of 1 to 4 channel markers
+pub trait SwizzlePattern<P, T> {
+    /// `Gray`, `GrayAlpha`, `RGB` or `RGBA`
+    type Output;
+
+    /// Build the output from the channels of `px`
+    fn swizzle(px: &P) -> Self::Output;
+}
+
+/// Reorders, duplicates or drops channels. See the module docs.
+pub trait Swizzle<T>: Sized {
+    /// `px.swizzle::<(B, G, R, A)>()`
+    #[inline(always)]
+    fn swizzle<S: SwizzlePattern<Self, T>>(&self) -> S::Output {
+        S::swizzle(self)
+    }
+}
+
+impl<P, T, C0> SwizzlePattern<P, T> for (C0,)
+where
+    C0: Channel<P, T>,
+{
+    type Output = Gray<T>;
+
+    #[inline(always)]
+    fn swizzle(px: &P) -> Gray<T> {
+        Gray(C0::get(px))
+    }
+}
+
+impl<P, T, C0, C1> SwizzlePattern<P, T> for (C0, C1)
+where
+    C0: Channel<P, T>,
+    C1: Channel<P, T>,
+{
+    type Output = GrayAlpha<T>;
+
+    #[inline(always)]
+    fn swizzle(px: &P) -> GrayAlpha<T> {
+        GrayAlpha(C0::get(px), C1::get(px))
+    }
+}
+
+impl<P, T, C0, C1, C2> SwizzlePattern<P, T> for (C0, C1, C2)
+where
+    C0: Channel<P, T>,
+    C1: Channel<P, T>,
+    C2: Channel<P, T>,
+{
+    type Output = RGB<T>;
+
+    #[inline(always)]
+    fn swizzle(px: &P) -> RGB<T> {
+        RGB {
+            r: C0::get(px),
+            g: C1::get(px),
+            b: C2::get(px),
+        }
+    }
+}
+
+impl<P, T, C0, C1, C2, C3> SwizzlePattern<P, T> for (C0, C1, C2, C3)
+where
+    C0: Channel<P, T>,
+    C1: Channel<P, T>,
+    C2: Channel<P, T>,
+    C3: Channel<P, T>,
+{
+    type Output = RGBA<T>;
+
+    #[inline(always)]
+    fn swizzle(px: &P) -> RGBA<T> {
+        RGBA {
+            r: C0::get(px),
+            g: C1::get(px),
+            b: C2::get(px),
+            a: C3::get(px),
+        }
+    }
+}
+
+macro_rules! channel_impl {
+    ($typ:ty; $($index:literal $marker:ident => $field:tt),+) => {
+        impl<T: Copy> Swizzle<T> for $typ {}
+
+        $(
+            impl<T: Copy> Channel<$typ, T> for $marker {
+                #[inline(always)]
+                fn get(px: &$typ) -> T {
+                    px.$field
+                }
+            }
+        )+
+
+        impl<T: Copy> $typ {
+            #[inline(always)]
+            #[allow(clippy::manual_range_patterns)]
+            const fn swizzle_channel<const C: usize>(&self) -> T {
+                const { assert!(matches!(C, $($index)|+), "no such channel in this pixel type") };
+                match C {
+                    $($index => self.$field,)+
+                    _ => unreachable!(),
+                }
+            }
+
+            /// `const` swizzle to one channel. See the module docs.
+            #[inline(always)]
+            pub const fn swizzle_gray<const C0: usize>(&self) -> Gray<T> {
+                Gray(self.swizzle_channel::<C0>())
+            }
+
+            /// `const` swizzle to two channels. See the module docs.
+            #[inline(always)]
+            pub const fn swizzle_gray_alpha<const C0: usize, const C1: usize>(
+                &self,
+            ) -> GrayAlpha<T> {
+                GrayAlpha(self.swizzle_channel::<C0>(), self.swizzle_channel::<C1>())
+            }
+
+            /// `const` swizzle to three channels. See the module docs.
+            #[inline(always)]
+            pub const fn swizzle_rgb<const C0: usize, const C1: usize, const C2: usize>(
+                &self,
+            ) -> RGB<T> {
+                RGB {
+                    r: self.swizzle_channel::<C0>(),
+                    g: self.swizzle_channel::<C1>(),
+                    b: self.swizzle_channel::<C2>(),
+                }
+            }
+
+            /// `const` swizzle to four channels. See the module docs.
+            #[inline(always)]
+            pub const fn swizzle_rgba<
+                const C0: usize,
+                const C1: usize,
+                const C2: usize,
+                const C3: usize,
+            >(
+                &self,
+            ) -> RGBA<T> {
+                RGBA {
+                    r: self.swizzle_channel::<C0>(),
+                    g: self.swizzle_channel::<C1>(),
+                    b: self.swizzle_channel::<C2>(),
+                    a: self.swizzle_channel::<C3>(),
+                }
+            }
+        }
+    };
+}
+
+channel_impl! {RGB<T>; 0 R => r, 1 G => g, 2 B => b}
+channel_impl! {BGR<T>; 0 R => r, 1 G => g, 2 B => b}
+
+#[cfg(feature = "grb")]
+channel_impl! {GRB<T>; 0 R => r, 1 G => g, 2 B => b}
+
+channel_impl! {RGBA<T, T>; 0 R => r, 1 G => g, 2 B => b, 3 A => a}
+channel_impl! {BGRA<T, T>; 0 R => r, 1 G => g, 2 B => b, 3 A => a}
+
+#[cfg(feature = "argb")]
+channel_impl! {ARGB<T, T>; 0 R => r, 1 G => g, 2 B => b, 3 A => a}
+
+#[cfg(feature = "argb")]
+channel_impl! {ABGR<T, T>; 0 R => r, 1 G => g, 2 B => b, 3 A => a}
+
+// 灰度当作 r = g = b
+channel_impl! {Gray<T>; 0 R => 0, 1 G => 0, 2 B => 0}
+channel_impl! {GrayAlpha<T, T>; 0 R => 0, 1 G => 0, 2 B => 0, 3 A => 1}
+
+#[test]
+fn swizzles() {
+    let rgb = RGB::new(1u16, 2, 3);
+    assert_eq!(rgb.swizzle::<(B, G, R)>(), RGB::new(3, 2, 1));
+    assert_eq!(rgb.swizzle::<(G, G)>(), GrayAlpha(2, 2));
+    assert_eq!(rgb.swizzle::<(R, G, B, B)>(), RGBA::new(1, 2, 3, 3));
+
+    let bgr = BGR {
+        b: 3f32,
+        g: 2.,
+        r: 1.,
+    };
+    assert_eq!(bgr.swizzle::<(R, G, B)>(), RGB::new(1., 2., 3.));
+
+    let rgba = RGBA::new(1u8, 2, 3, 4);
+    assert_eq!(rgba.swizzle::<(A, A, A, R)>(), RGBA::new(4, 4, 4, 1));
+    let bgra: BGRA<u8> = rgba.swizzle::<(G, B, R, A)>().into();
+    assert_eq!(
+        bgra,
+        BGRA {
+            b: 1,
+            g: 3,
+            r: 2,
+            a: 4
+        }
+    );
+}
+
+#[test]
+fn const_swizzles() {
+    const RGBA_PX: RGBA<u8> = RGBA::new(1, 2, 3, 4);
+    const SWAPPED: RGB<u8> = RGBA_PX.swizzle_rgb::<2, 1, 0>();
+    const ALPHA: Gray<u8> = RGBA_PX.swizzle_gray::<3>();
+    assert_eq!(SWAPPED, RGBA_PX.swizzle::<(B, G, R)>());
+    assert_eq!(ALPHA, Gray(4));
+
+    let bgr = BGR::new(3u16, 2, 1);
+    assert_eq!(
+        bgr.swizzle_rgba::<0, 0, 1, 2>(),
+        bgr.swizzle::<(R, R, G, B)>()
+    );
+    assert_eq!(bgr.swizzle_gray_alpha::<2, 0>(), GrayAlpha(3, 1));
+
+    let ga = GrayAlpha(5u8, 6);
+    assert_eq!(ga.swizzle::<(R, G, B, A)>(), RGBA::new(5, 5, 5, 6));
+    assert_eq!(ga.swizzle_rgba::<0, 1, 2, 3>(), RGBA::new(5, 5, 5, 6));
+    assert_eq!(Gray(9u8).swizzle::<(G, B)>(), GrayAlpha(9, 9));
+}